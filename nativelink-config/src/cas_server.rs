@@ -96,6 +96,16 @@ pub struct CasStoreConfig {
     /// This store name referenced here may be reused multiple times.
     #[serde(deserialize_with = "convert_string_with_shellexpand")]
     pub cas_store: StoreRefName,
+
+    /// The name of a `dedup` store in the `stores` map whose chunking
+    /// settings are used to serve the `SplitBlob` and `SpliceBlob` methods.
+    /// Chunks are read from and written to `cas_store`, so clients fetch
+    /// them with the usual CAS methods. If not set, these methods are not
+    /// advertised by the capabilities service and return UNIMPLEMENTED.
+    ///
+    /// Default: None
+    #[serde(default, deserialize_with = "convert_optional_string_with_shellexpand")]
+    pub dedup_store: Option<StoreRefName>,
 }
 
 #[derive(Deserialize, Debug, Default)]
@@ -186,6 +196,27 @@ pub struct AdminConfig {
     /// domain is "example.com", you can reach the endpoint with:
     /// <http://example.com/admin>.
     ///
+    /// Endpoints:
+    ///  * `POST {path}/scheduler/{name}/set_drain_worker/{worker_id}/{0|1}`
+    ///    sets if a worker is draining. Undraining a worker also lifts its
+    ///    quarantine.
+    ///  * `POST {path}/scheduler/{name}/unquarantine_worker/{worker_id}`
+    ///    lets a worker the scheduler quarantined (see
+    ///    `SimpleScheduler::worker_quarantine`) take actions again, unless it
+    ///    was also drained.
+    ///  * `GET {path}/scheduler/{name}/autoscaling` returns, as JSON, the
+    ///    number of queued actions and the age of the oldest one, and the
+    ///    number of connected and idle workers, grouped by the values of the
+    ///    scheduler's `exact` and `priority` `supported_platform_properties`.
+    ///    Meant to drive autoscalers such as KEDA.
+    ///  * `GET {path}/scheduler/{name}/operations` returns, as JSON, the
+    ///    queued operations ordered by priority, highest first, followed by
+    ///    the executing ones, with their worker, age, priority and platform
+    ///    properties.
+    ///  * `GET {path}/scheduler/{name}/operations/{operation_id}` returns,
+    ///    as JSON, the `ActionInfo` and current `ActionState` of an
+    ///    operation listed by the endpoint above.
+    ///
     /// Default: "/admin"
     #[serde(default)]
     pub path: String,
@@ -455,6 +486,26 @@ pub enum UploadCacheResultsStrategy {
     failures_only,
 }
 
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Deserialize, Debug, Default, PartialEq, Eq)]
+pub enum InputFileMaterialization {
+    /// Hard link files out of the store. This is the cheapest option, but
+    /// `work_directory` must be on the same filesystem as the store, and an
+    /// action that modifies one of its input files also modifies the file
+    /// in the store.
+    #[default]
+    hardlink,
+
+    /// Give every action its own copy of its input files. A reflink is
+    /// tried first, which is as cheap as a hard link on filesystems that
+    /// support it, like btrfs and XFS. Otherwise the file is copied with
+    /// `copy_file_range`, and if that is not supported either it is hard
+    /// linked. The method that works is remembered for each mount, unless
+    /// it is the hard link. This allows `work_directory` to be on a
+    /// different filesystem, like tmpfs.
+    reflink_or_copy,
+}
+
 #[allow(non_camel_case_types)]
 #[derive(Clone, Deserialize, Debug)]
 pub enum EnvironmentSource {
@@ -633,13 +684,19 @@ pub struct LocalWorkerConfig {
 
     /// The directory work jobs will be executed from. This directory will be fully
     /// managed by the worker service and will be purged on startup.
-    /// This directory and the directory referenced in local_filesystem_store_ref's
+    /// Unless `input_file_materialization` allows copies, this directory and the
+    /// directory referenced in local_filesystem_store_ref's
     /// stores::FilesystemStore::content_path must be on the same filesystem.
     /// Hardlinks will be used when placing files that are accessible to the jobs
     /// that are sourced from local_filesystem_store_ref's content_path.
     #[serde(deserialize_with = "convert_string_with_shellexpand")]
     pub work_directory: String,
 
+    /// How input files are placed into `work_directory`.
+    /// Default: hardlink
+    #[serde(default)]
+    pub input_file_materialization: InputFileMaterialization,
+
     /// Properties of this worker. This configuration will be sent to the scheduler
     /// and used to tell the scheduler to restrict what should be executed on this
     /// worker.
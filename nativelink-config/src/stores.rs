@@ -34,6 +34,20 @@ pub enum ConfigDigestHashFunction {
     /// Use the blake3 hash function.
     /// <https://en.wikipedia.org/wiki/BLAKE_(hash_function)>
     blake3,
+
+    /// Use the sha384 hash function.
+    /// <https://en.wikipedia.org/wiki/SHA-2>
+    sha384,
+
+    /// Use the sha512 hash function.
+    /// <https://en.wikipedia.org/wiki/SHA-2>
+    sha512,
+
+    /// Use the sha256tree hash function. This is sha256 for blobs up to
+    /// 1024 bytes and a merkle tree of sha256 hashes for larger blobs, so
+    /// large blobs can be verified one chunk at a time.
+    /// <https://github.com/bazelbuild/remote-apis/blob/main/build/bazel/remote/execution/v2/remote_execution.proto>
+    sha256tree,
 }
 
 #[allow(non_camel_case_types)]
@@ -158,6 +172,38 @@ pub enum StoreConfig {
     ///
     compression(Box<CompressionStore>),
 
+    /// An encryption store will encrypt the data with AES-256-GCM before
+    /// it is sent to the backend and decrypt it when it is read back.
+    /// Data is encrypted in fixed size chunks, so reading part of an
+    /// object only requires fetching and decrypting the chunks that
+    /// contain the requested range. Keys and sizes seen by clients are
+    /// always those of the unencrypted data.
+    ///
+    /// This is useful when the backend is shared with parties that
+    /// should not be able to read the content, like an S3 bucket that
+    /// other teams have access to.
+    ///
+    /// Note: Never put CompressionStore or DedupStore as the backend of
+    /// this store, since encrypted data does not compress or dedup. Put
+    /// this store as their backend instead.
+    ///
+    /// **Example JSON Config:**
+    /// ```json
+    /// "encryption": {
+    ///     "key_file": "/etc/nativelink/encryption_keys",
+    ///     "active_key_id": 2,
+    ///     "backend": {
+    ///       "experimental_s3_store": {
+    ///         "region": "eu-north-1",
+    ///         "bucket": "shared-build-outputs",
+    ///         "key_prefix": "encrypted/",
+    ///       }
+    ///     }
+    ///   }
+    /// ```
+    ///
+    encryption(Box<EncryptionStore>),
+
     /// A dedup store will take the inputs and run a rolling hash
     /// algorithm on them to slice the input into smaller parts then
     /// run a sha256 algorithm on the slice and if the object doesn't
@@ -512,6 +558,65 @@ pub struct FilesystemStore {
     /// Default: 4096
     #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
     pub block_size: u64,
+
+    /// If set, a low priority background task will rehash every file in
+    /// `content_path` with the default digest function once per this many
+    /// seconds. Files that do not match their digest are evicted from the
+    /// store. This must not be enabled on stores used as an action cache,
+    /// since action cache entries are not keyed by the hash of their
+    /// content.
+    /// Default: 0 (disabled)
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub scrub_interval_s: u64,
+
+    /// If set, files found to be corrupt by the background scrub will be
+    /// hard linked into this directory before being evicted, so they can be
+    /// inspected later. Must be on the same block device as `content_path`.
+    /// Default: None (corrupt files are deleted)
+    #[serde(default, deserialize_with = "convert_optional_string_with_shellexpand")]
+    pub scrub_quarantine_path: Option<String>,
+
+    /// Maximum number of entries in the hash cache. The hash cache
+    /// remembers the digest of files outside of `content_path` that are
+    /// hard links of entries in this store, keyed by their inode, device,
+    /// size, mtime and ctime. Workers record the input files they hard
+    /// link out of the store, so outputs that are unmodified inputs don't
+    /// need to be hashed or uploaded again.
+    /// Default: 0 (disabled)
+    #[serde(default)]
+    pub hash_cache_max_entries: u64,
+
+    /// Additional directories to spread content over, for example one per
+    /// drive. `content_path`, `temp_path` and `eviction_policy` above make
+    /// up the first root. Each blob is placed in a root chosen by its
+    /// hash, and each root evicts by its own `eviction_policy`, so capacity
+    /// is accounted per drive. Roots that fail a health check or hit an
+    /// I/O error are skipped until they pass a health check again. Roots
+    /// that fail to load on startup are skipped until restart.
+    /// When a worker hard links a blob into its work directory, the blob
+    /// is first moved into a root on the same filesystem as the work
+    /// directory if it is not already in one.
+    /// Default: [] (only `content_path` is used)
+    #[serde(default)]
+    pub additional_roots: Vec<FilesystemStoreRoot>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FilesystemStoreRoot {
+    /// Path where this root stores its content. Same as
+    /// `FilesystemStore::content_path`.
+    #[serde(deserialize_with = "convert_string_with_shellexpand")]
+    pub content_path: String,
+
+    /// Temp path of this root. Must be on the same block device as this
+    /// root's `content_path`. Same as `FilesystemStore::temp_path`.
+    #[serde(deserialize_with = "convert_string_with_shellexpand")]
+    pub temp_path: String,
+
+    /// Policy used to evict items out of this root. Same as
+    /// `FilesystemStore::eviction_policy`.
+    pub eviction_policy: Option<EvictionPolicy>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -588,6 +693,20 @@ pub struct DedupStore {
     /// Default: 10
     #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
     pub max_concurrent_fetch_per_get: u32,
+
+    /// How often to remove chunks from the content store that no index
+    /// in the index store refers to anymore. This happens when index
+    /// entries are evicted before their chunks. Both stores must support
+    /// listing their keys and the content store must support removing
+    /// keys (eg: memory and filesystem stores).
+    ///
+    /// Garbage collection only knows about uploads made through this
+    /// store, so it must not be enabled if another process writes to the
+    /// same index and content stores.
+    ///
+    /// Default: 0. Garbage collection is disabled.
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub gc_interval_s: u32,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -604,6 +723,28 @@ pub struct ExistenceCacheStore {
     /// value will cause items to never be removed from the store causing
     /// infinite memory usage.
     pub eviction_policy: Option<EvictionPolicy>,
+
+    /// How long to remember that the backend reported a digest as missing.
+    /// While remembered, `has` requests for the digest are answered without
+    /// asking the backend. Entries are removed as soon as the digest is
+    /// written through this store, so this mostly needs to cover writes
+    /// made by other nodes. Keep this short unless
+    /// `invalidation_redis_store` is set.
+    ///
+    /// Default: 0 (Missing digests are not cached)
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub negative_cache_ttl_s: u32,
+
+    /// A Redis store to subscribe to for cache invalidations. Every key
+    /// published to its `experimental_pub_sub_channel` is removed from the
+    /// existence cache and the negative cache. `RedisStore`s publish every
+    /// key they write or remove, so pointing this at the same Redis and channel as
+    /// the backend lets nodes learn about changes made by other nodes.
+    /// `experimental_pub_sub_channel` must be set.
+    ///
+    /// Default: None
+    #[serde(default)]
+    pub invalidation_redis_store: Option<RedisStore>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -679,6 +820,41 @@ pub enum CompressionAlgorithm {
     lz4(Lz4Config),
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct EncryptionStore {
+    /// The underlying store to wrap around. All data written to the
+    /// backend will be encrypted.
+    pub backend: StoreConfig,
+
+    /// Path to a file containing the encryption keys. Each non-empty
+    /// line that does not start with `#` must be a key ID followed by
+    /// whitespace and a 32 byte key encoded as hex, eg:
+    /// `1 000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f`
+    ///
+    /// The ID of the key that was used is stored with each object, so
+    /// keys can be rotated by adding a new key to this file and changing
+    /// `active_key_id`. Old keys must be kept in the file for as long as
+    /// data encrypted with them needs to be read.
+    #[serde(deserialize_with = "convert_string_with_shellexpand")]
+    pub key_file: String,
+
+    /// ID of the key in `key_file` that will be used to encrypt new data.
+    #[serde(deserialize_with = "convert_numeric_with_shellexpand")]
+    pub active_key_id: u32,
+
+    /// Size of the chunks the data is split into before being encrypted.
+    /// Each chunk adds 16 bytes of overhead. Reading any part of a chunk
+    /// requires reading and decrypting the whole chunk.
+    ///
+    /// Note: Changing this value is safe, every object records the chunk
+    /// size it was written with.
+    ///
+    /// Default: 65536 (64k).
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub chunk_size: u32,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct CompressionStore {
@@ -757,6 +933,19 @@ pub struct S3Store {
     #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
     pub consider_expired_after_s: u32,
 
+    /// If an object is read or checked for existence within this many
+    /// seconds of being considered expired (see `consider_expired_after_s`),
+    /// the object is copied onto itself in the background. This resets
+    /// its `last_modified` time, so objects that are still in use do not
+    /// expire and have to be uploaded again. Each object is refreshed at
+    /// most once in this many seconds.
+    ///
+    /// Objects larger than 5GB cannot be refreshed this way.
+    ///
+    /// Default: 0. Zero means objects are never refreshed.
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub refresh_before_expired_s: u32,
+
     /// The maximum buffer size to retain in case of a retryable error
     /// during upload. Setting this to zero will disable upload buffering;
     /// this means that in the event of a failure during upload, the entire
@@ -850,6 +1039,94 @@ pub struct GrpcStore {
     /// the load over multiple TCP connections.  Default 1.
     #[serde(default)]
     pub connections_per_endpoint: usize,
+
+    /// Stop sending requests to an endpoint for a while after it fails too
+    /// many requests in a row. If every endpoint is ejected, all of them
+    /// are used again.
+    ///
+    /// Default: None. Endpoints are never ejected.
+    #[serde(default)]
+    pub outlier_ejection: Option<OutlierEjection>,
+
+    /// Send a duplicate of a small read to a second endpoint if the first
+    /// endpoint has not responded within the 95th percentile of recent
+    /// read latencies. The first response received is used. Requires more
+    /// than one endpoint and is only used for CAS stores.
+    ///
+    /// Default: None. Reads are never hedged.
+    #[serde(default)]
+    pub hedging: Option<GrpcHedging>,
+
+    /// Combine small reads and writes made at about the same time into
+    /// `BatchReadBlobs` and `BatchUpdateBlobs` requests, and existence
+    /// checks into a single `FindMissingBlobs` request. Reads that are
+    /// batched are not hedged. Only used for CAS stores.
+    ///
+    /// Default: None. Every request is sent on its own.
+    #[serde(default)]
+    pub batching: Option<GrpcBatching>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct OutlierEjection {
+    /// Number of requests in a row that must fail with a transport error
+    /// before the endpoint is ejected.
+    ///
+    /// Default: 5
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub consecutive_failures: u32,
+
+    /// Number of seconds an ejected endpoint is not used for.
+    ///
+    /// Default: 30
+    #[serde(default, deserialize_with = "convert_duration_with_shellexpand")]
+    pub ejection_duration_s: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct GrpcHedging {
+    /// Only reads of blobs up to this size are hedged. Hedged reads are
+    /// buffered in memory until one of the requests completes.
+    ///
+    /// Default: 1mb
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub max_blob_size: u64,
+
+    /// The minimum number of milliseconds to wait before sending the
+    /// duplicate request. This is also the delay used until enough reads
+    /// have completed to know the 95th percentile.
+    ///
+    /// Default: 10
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub min_delay_ms: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct GrpcBatching {
+    /// Only blobs up to this size are read and written in batches. Must
+    /// not be larger than `max_batch_size`.
+    ///
+    /// Default: 64kb
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub max_blob_size: u64,
+
+    /// The maximum total size of the blobs in one batch request. Most
+    /// servers reject gRPC messages larger than 4mb, so leave some room
+    /// for the rest of the request.
+    ///
+    /// Default: 3mb
+    #[serde(default, deserialize_with = "convert_data_size_with_shellexpand")]
+    pub max_batch_size: u64,
+
+    /// The number of milliseconds to wait for more requests to add to a
+    /// batch before sending it.
+    ///
+    /// Default: 5
+    #[serde(default, deserialize_with = "convert_numeric_with_shellexpand")]
+    pub window_ms: u64,
 }
 
 /// The possible error codes that might occur on an upstream request.
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use nativelink_config::schedulers::FairShareConfig;
 use nativelink_config::serde_utils::{
     convert_data_size_with_shellexpand, convert_duration_with_shellexpand,
 };
@@ -65,3 +66,12 @@ fn test_data_size_usize_deserialize() {
     let deserialized: DataSizeEntity = serde_json5::from_str(example).unwrap();
     assert_eq!(deserialized.data_size, 10);
 }
+
+#[test]
+fn test_fair_share_default_weight_deserialize() {
+    let example = r#"
+            {"key": "instance_name"}
+        "#;
+    let deserialized: FairShareConfig = serde_json5::from_str(example).unwrap();
+    assert_eq!(deserialized.default_weight, 1);
+}
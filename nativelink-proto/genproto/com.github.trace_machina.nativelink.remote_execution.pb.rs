@@ -143,6 +143,16 @@ pub struct StartExecute {
     /// / of the ActionResult.
     #[prost(message, optional, tag = "3")]
     pub queued_timestamp: ::core::option::Option<::prost_types::Timestamp>,
+    /// / The `RequestMetadata` the client sent along with the action, if any.
+    #[prost(message, optional, tag = "5")]
+    pub request_metadata: ::core::option::Option<
+        super::super::super::super::super::build::bazel::remote::execution::v2::RequestMetadata,
+    >,
+    /// / If set, the result must not be uploaded to the action cache, even if
+    /// / the action could be cached. Used when the scheduler executes a cache
+    /// / hit again to check that the action is reproducible.
+    #[prost(bool, tag = "6")]
+    pub skip_action_cache_upload: bool,
 }
 /// / This is a special message used to save actions into the CAS that can be used
 /// / by programs like bb_browswer to inspect the history of a build.
@@ -159,6 +169,27 @@ pub struct HistoricalExecuteResponse {
         super::super::super::super::super::build::bazel::remote::execution::v2::ExecuteResponse,
     >,
 }
+/// / Record of an action that produced different outputs when it was executed
+/// / again, which means it is likely not hermetic. Saved by the scheduler when
+/// / it re-executes a sample of action cache hits.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct NonHermeticActionResult {
+    /// / The digest of the action that was executed again.
+    #[prost(message, optional, tag = "1")]
+    pub action_digest: ::core::option::Option<
+        super::super::super::super::super::build::bazel::remote::execution::v2::Digest,
+    >,
+    /// / The result that was stored in the action cache.
+    #[prost(message, optional, tag = "2")]
+    pub cached_result: ::core::option::Option<
+        super::super::super::super::super::build::bazel::remote::execution::v2::ActionResult,
+    >,
+    /// / The result of executing the action again.
+    #[prost(message, optional, tag = "3")]
+    pub rerun_result: ::core::option::Option<
+        super::super::super::super::super::build::bazel::remote::execution::v2::ActionResult,
+    >,
+}
 /// Generated client implementations.
 pub mod worker_api_client {
     #![allow(
@@ -12,18 +12,20 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::num::NonZeroUsize;
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
 
 use async_lock::Mutex;
 use lru::LruCache;
-use nativelink_config::schedulers::WorkerAllocationStrategy;
+use nativelink_config::schedulers::{WorkerAllocationStrategy, WorkerQuarantineConfig};
 use nativelink_error::{error_if, make_err, make_input_err, Code, Error, ResultExt};
 use nativelink_metric::{
     group, MetricFieldData, MetricKind, MetricPublishKnownKindData, MetricsComponent,
     RootMetricsComponent,
 };
-use nativelink_util::action_messages::{OperationId, WorkerId};
+use nativelink_util::action_messages::{ActionInfo, OperationId, WorkerId};
+use nativelink_util::metrics_utils::CounterWithTime;
 use nativelink_util::operation_state_manager::{UpdateOperationType, WorkerStateManager};
 use nativelink_util::platform_properties::PlatformProperties;
 use nativelink_util::spawn;
@@ -33,10 +35,26 @@ use tokio::sync::Notify;
 use tonic::async_trait;
 use tracing::{event, Level};
 
+use crate::autoscaling::WorkerCapacity;
+use crate::leader_election::LeaderElection;
 use crate::platform_property_manager::PlatformPropertyManager;
+use crate::speculative_execution::{CopyUpdate, SpeculativeExecution};
 use crate::worker::{ActionInfoWithProps, Worker, WorkerTimestamp, WorkerUpdate};
 use crate::worker_scheduler::WorkerScheduler;
 
+/// Default number of recently finished actions looked at to decide if a
+/// worker should be quarantined.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_QUARANTINE_FAILURE_WINDOW: usize = 10;
+
+/// Default number of failed actions in the window that quarantine a worker.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_QUARANTINE_MAX_FAILURES: usize = 5;
+
+/// Maximum number of operations for which the workers they failed on are
+/// remembered.
+const MAX_TRACKED_FAILED_OPERATIONS: usize = 10_000;
+
 struct Workers(LruCache<WorkerId, Worker>);
 
 impl Deref for Workers {
@@ -86,6 +104,17 @@ struct ApiWorkerSchedulerImpl {
     worker_change_notify: Arc<Notify>,
     /// A channel to notify that an operation is still alive.
     operation_keep_alive_tx: UnboundedSender<(OperationId, WorkerId)>,
+    /// If set, workers that fail too many actions are drained.
+    worker_quarantine: Option<WorkerQuarantineConfig>,
+    /// Workers each recently retried operation failed on, so the retries
+    /// can be sent elsewhere.
+    failed_workers_by_operation: LruCache<OperationId, Vec<WorkerId>>,
+    #[metric(help = "The number of times a worker was quarantined for failing too many actions.")]
+    workers_quarantined: CounterWithTime,
+    /// If set, actions that run much longer than usual get a copy started
+    /// on another worker.
+    #[metric(group = "speculative_execution")]
+    speculative_execution: Option<SpeculativeExecution>,
 }
 
 impl ApiWorkerSchedulerImpl {
@@ -109,9 +138,20 @@ impl ApiWorkerSchedulerImpl {
         );
         worker.last_update_timestamp = timestamp;
         for operation_id in worker.running_action_infos.keys() {
+            // Copies of an operation keep it alive on behalf of the worker it
+            // is assigned to, killed copies do not keep it alive.
+            let keep_alive_worker_id = match &self.speculative_execution {
+                Some(speculative_execution) => {
+                    speculative_execution.keep_alive_worker_id(operation_id, worker_id)
+                }
+                None => Some(*worker_id),
+            };
+            let Some(keep_alive_worker_id) = keep_alive_worker_id else {
+                continue;
+            };
             if self
                 .operation_keep_alive_tx
-                .send((operation_id.clone(), *worker_id))
+                .send((operation_id.clone(), keep_alive_worker_id))
                 .is_err()
             {
                 event!(
@@ -159,7 +199,8 @@ impl ApiWorkerSchedulerImpl {
         result
     }
 
-    /// Sets if the worker is draining or not.
+    /// Sets if the worker is draining or not. Putting a worker back into
+    /// service also lifts its quarantine.
     async fn set_drain_worker(
         &mut self,
         worker_id: &WorkerId,
@@ -170,26 +211,77 @@ impl ApiWorkerSchedulerImpl {
             .get_mut(worker_id)
             .err_tip(|| format!("Worker {worker_id} doesn't exist in the pool"))?;
         worker.is_draining = is_draining;
+        if !is_draining {
+            worker.is_quarantined = false;
+            worker.clear_action_outcomes();
+        }
+        self.worker_change_notify.notify_one();
+        Ok(())
+    }
+
+    /// Stops giving the worker new actions until it is unquarantined.
+    async fn quarantine_worker(&mut self, worker_id: &WorkerId) -> Result<(), Error> {
+        let worker = self
+            .workers
+            .peek_mut(worker_id)
+            .err_tip(|| format!("Worker {worker_id} doesn't exist in the pool"))?;
+        worker.is_quarantined = true;
+        self.workers_quarantined.inc();
+        self.worker_change_notify.notify_one();
+        Ok(())
+    }
+
+    /// Lifts the quarantine of the worker and forgets its failures. A worker
+    /// that was also drained through `set_drain_worker` stays drained.
+    async fn unquarantine_worker(&mut self, worker_id: &WorkerId) -> Result<(), Error> {
+        let worker = self
+            .workers
+            .peek_mut(worker_id)
+            .err_tip(|| format!("Worker {worker_id} doesn't exist in the pool"))?;
+        worker.is_quarantined = false;
+        worker.clear_action_outcomes();
         self.worker_change_notify.notify_one();
         Ok(())
     }
 
     fn inner_find_worker_for_action(
         &self,
+        operation_id: &OperationId,
         platform_properties: &PlatformProperties,
     ) -> Option<WorkerId> {
-        let mut workers_iter = self.workers.iter();
-        let workers_iter = match self.allocation_strategy {
-            // Use rfind to get the least recently used that satisfies the properties.
-            WorkerAllocationStrategy::least_recently_used => workers_iter.rfind(|(_, w)| {
-                w.can_accept_work() && platform_properties.is_satisfied_by(&w.platform_properties)
-            }),
-            // Use find to get the most recently used that satisfies the properties.
-            WorkerAllocationStrategy::most_recently_used => workers_iter.find(|(_, w)| {
-                w.can_accept_work() && platform_properties.is_satisfied_by(&w.platform_properties)
-            }),
+        let failed_workers = self
+            .failed_workers_by_operation
+            .peek(operation_id)
+            .map_or(&[][..], Vec::as_slice);
+        let find_worker = |skip_failed_workers: bool| {
+            let is_candidate = |w: &Worker| {
+                w.can_accept_work()
+                    && platform_properties.is_satisfied_by(&w.platform_properties)
+                    && !(skip_failed_workers && failed_workers.contains(&w.id))
+            };
+            let mut workers_iter = self.workers.iter();
+            let worker = match self.allocation_strategy {
+                // Use rfind to get the least recently used that satisfies the properties.
+                WorkerAllocationStrategy::least_recently_used => {
+                    workers_iter.rfind(|(_, w)| is_candidate(w))
+                }
+                // Use find to get the most recently used that satisfies the properties.
+                WorkerAllocationStrategy::most_recently_used => {
+                    workers_iter.find(|(_, w)| is_candidate(w))
+                }
+            };
+            worker.map(|(_, w)| w.id)
         };
-        workers_iter.map(|(_, w)| &w.id).copied()
+        if failed_workers.is_empty() {
+            return find_worker(false);
+        }
+        // Skip the workers the action already failed on, unless no other
+        // connected worker has the properties to run it.
+        let has_untried_worker = self.workers.iter().any(|(worker_id, w)| {
+            !failed_workers.contains(worker_id)
+                && platform_properties.is_satisfied_by(&w.platform_properties)
+        });
+        find_worker(has_untried_worker)
     }
 
     async fn update_action(
@@ -203,14 +295,15 @@ impl ApiWorkerSchedulerImpl {
         })?;
 
         // Ensure the worker is supposed to be running the operation.
-        if !worker.running_action_infos.contains_key(operation_id) {
+        let Some(action_info) = worker.running_action_infos.get(operation_id) else {
             let err = make_err!(
                 Code::Internal,
                 "Operation {operation_id} should not be running on worker {worker_id} in SimpleScheduler::update_action"
             );
             return Result::<(), _>::Err(err.clone())
                 .merge(self.immediate_evict_worker(worker_id, err).await);
-        }
+        };
+        let action_info = action_info.inner.clone();
 
         let (is_finished, due_to_backpressure) = match &update {
             UpdateOperationType::UpdateWithActionStage(action_stage) => {
@@ -221,12 +314,28 @@ impl ApiWorkerSchedulerImpl {
                 (true, err.code == Code::ResourceExhausted)
             }
         };
+        let is_error = matches!(update, UpdateOperationType::UpdateWithError(_));
+        // Only errors that point at the worker rather than at the action.
+        let is_internal_failure = matches!(
+            &update,
+            UpdateOperationType::UpdateWithError(err)
+                if matches!(err.code, Code::Internal | Code::Unavailable | Code::Unknown)
+        );
+
+        // When the operation runs on several workers, only the updates that
+        // decide its outcome are reported.
+        let copy_update = match &mut self.speculative_execution {
+            Some(speculative_execution) => {
+                speculative_execution.route_update(operation_id, worker_id, is_finished, is_error)
+            }
+            None => CopyUpdate::Report(*worker_id),
+        };
 
         // Update the operation in the worker state manager.
-        {
+        if let CopyUpdate::Report(reported_worker_id) = copy_update {
             let update_operation_res = self
                 .worker_state_manager
-                .update_operation(operation_id, worker_id, update)
+                .update_operation(operation_id, &reported_worker_id, update)
                 .await
                 .err_tip(|| "in update_operation on SimpleScheduler::update_action");
             if let Err(err) = update_operation_res {
@@ -259,11 +368,171 @@ impl ApiWorkerSchedulerImpl {
             complete_action_res
         };
 
+        if copy_update == CopyUpdate::Ignore {
+            // The copy was killed, so its outcome says nothing about the worker.
+            self.worker_change_notify.notify_one();
+            return complete_action_res;
+        }
+
+        if is_internal_failure {
+            // The action will likely be retried, prefer other workers then.
+            self.failed_workers_by_operation
+                .get_or_insert_mut(operation_id.clone(), Vec::new)
+                .push(*worker_id);
+        } else if !due_to_backpressure {
+            self.failed_workers_by_operation.pop(operation_id);
+        }
+
+        let complete_action_res = complete_action_res.merge(
+            self.record_action_outcome(worker_id, is_internal_failure, due_to_backpressure)
+                .await,
+        );
+
+        if copy_update != CopyUpdate::Drop {
+            self.kill_other_copies(operation_id, worker_id, &action_info, !is_error);
+        }
+
         self.worker_change_notify.notify_one();
 
         complete_action_res
     }
 
+    /// Quarantines the worker if it failed too many of its recent actions.
+    async fn record_action_outcome(
+        &mut self,
+        worker_id: &WorkerId,
+        is_internal_failure: bool,
+        due_to_backpressure: bool,
+    ) -> Result<(), Error> {
+        // A worker being busy says nothing about its health.
+        if due_to_backpressure {
+            return Ok(());
+        }
+        let Some(worker_quarantine) = &self.worker_quarantine else {
+            return Ok(());
+        };
+        let (failure_window, max_failures) = (
+            worker_quarantine.failure_window,
+            worker_quarantine.max_failures,
+        );
+        let Some(worker) = self.workers.peek_mut(worker_id) else {
+            return Ok(());
+        };
+        let failures = worker.record_action_outcome(is_internal_failure, failure_window);
+        if worker.is_quarantined || failures < max_failures {
+            return Ok(());
+        }
+        event!(
+            Level::WARN,
+            ?worker_id,
+            failures,
+            failure_window,
+            "Worker failed too many actions, quarantining it"
+        );
+        self.quarantine_worker(worker_id).await
+    }
+
+    /// Records that the operation finished on the worker and tells the
+    /// workers running other copies of it to stop.
+    fn kill_other_copies(
+        &mut self,
+        operation_id: &OperationId,
+        worker_id: &WorkerId,
+        action_info: &ActionInfo,
+        succeeded: bool,
+    ) {
+        let Some(speculative_execution) = &mut self.speculative_execution else {
+            return;
+        };
+        let losing_worker_ids = speculative_execution.operation_finished(
+            operation_id,
+            worker_id,
+            action_info,
+            succeeded,
+        );
+        for losing_worker_id in losing_worker_ids {
+            let Some(losing_worker) = self.workers.peek_mut(&losing_worker_id) else {
+                continue;
+            };
+            event!(
+                Level::INFO,
+                ?operation_id,
+                ?worker_id,
+                ?losing_worker_id,
+                "Another copy of the operation finished first, killing this one"
+            );
+            // If the worker is gone its copy is gone too.
+            if let Err(err) =
+                losing_worker.notify_update(WorkerUpdate::KillOperation(operation_id.clone()))
+            {
+                event!(
+                    Level::WARN,
+                    ?operation_id,
+                    ?losing_worker_id,
+                    ?err,
+                    "Failed to kill the copy of the operation"
+                );
+            }
+        }
+    }
+
+    /// Starts a copy of each action that runs much longer than usual on an
+    /// idle worker, if there is one.
+    async fn start_speculative_executions(&mut self) -> Result<(), Error> {
+        let Some(speculative_execution) = &self.speculative_execution else {
+            return Ok(());
+        };
+        let now = speculative_execution.now();
+        let mut stragglers = Vec::new();
+        for (worker_id, worker) in self.workers.iter() {
+            for (operation_id, action_info) in &worker.running_action_infos {
+                if speculative_execution.is_straggler(operation_id, &action_info.inner, now) {
+                    stragglers.push((*worker_id, operation_id.clone(), action_info.clone()));
+                }
+            }
+        }
+
+        let mut result = Ok(());
+        for (worker_id, operation_id, action_info) in stragglers {
+            let failed_workers = self
+                .failed_workers_by_operation
+                .peek(&operation_id)
+                .map_or(&[][..], Vec::as_slice);
+            // Only idle workers get copies, so they never delay queued actions.
+            let copy_worker_id = self
+                .workers
+                .iter()
+                .find(|(id, w)| {
+                    **id != worker_id
+                        && w.can_accept_work()
+                        && !w.has_actions()
+                        && !failed_workers.contains(id)
+                        && action_info
+                            .platform_properties
+                            .is_satisfied_by(&w.platform_properties)
+                })
+                .map(|(id, _)| *id);
+            let Some(copy_worker_id) = copy_worker_id else {
+                continue;
+            };
+            event!(
+                Level::INFO,
+                ?operation_id,
+                ?worker_id,
+                ?copy_worker_id,
+                "Operation runs much longer than usual, starting a copy on another worker"
+            );
+            if let Some(speculative_execution) = &mut self.speculative_execution {
+                speculative_execution.copy_started(&operation_id, worker_id, copy_worker_id);
+            }
+            result = result.merge(
+                self.worker_notify_run_action(copy_worker_id, operation_id, action_info)
+                    .await,
+            );
+        }
+        result
+    }
+
     /// Notifies the specified worker to run the given action and handles errors by evicting
     /// the worker if the notification fails.
     async fn worker_notify_run_action(
@@ -273,8 +542,10 @@ impl ApiWorkerSchedulerImpl {
         action_info: ActionInfoWithProps,
     ) -> Result<(), Error> {
         if let Some(worker) = self.workers.get_mut(&worker_id) {
-            let notify_worker_result =
-                worker.notify_update(WorkerUpdate::RunAction((operation_id, action_info.clone())));
+            let notify_worker_result = worker.notify_update(WorkerUpdate::RunAction((
+                operation_id.clone(),
+                action_info.clone(),
+            )));
 
             if notify_worker_result.is_err() {
                 event!(
@@ -293,6 +564,9 @@ impl ApiWorkerSchedulerImpl {
                 return Result::<(), _>::Err(err.clone())
                     .merge(self.immediate_evict_worker(&worker_id, err).await);
             }
+            if let Some(speculative_execution) = &mut self.speculative_execution {
+                speculative_execution.operation_started(&operation_id);
+            }
         } else {
             event!(
                 Level::WARN,
@@ -315,16 +589,27 @@ impl ApiWorkerSchedulerImpl {
         if let Some(mut worker) = self.remove_worker(worker_id) {
             // We don't care if we fail to send message to worker, this is only a best attempt.
             let _ = worker.notify_update(WorkerUpdate::Disconnect);
-            for (operation_id, _) in worker.running_action_infos.drain() {
+            for (operation_id, action_info) in worker.running_action_infos.drain() {
+                // Other copies of the operation may still finish it.
+                let copy_update = match &mut self.speculative_execution {
+                    Some(speculative_execution) => {
+                        speculative_execution.route_update(&operation_id, worker_id, true, true)
+                    }
+                    None => CopyUpdate::Report(*worker_id),
+                };
+                let CopyUpdate::Report(reported_worker_id) = copy_update else {
+                    continue;
+                };
                 result = result.merge(
                     self.worker_state_manager
                         .update_operation(
                             &operation_id,
-                            worker_id,
+                            &reported_worker_id,
                             UpdateOperationType::UpdateWithError(err.clone()),
                         )
                         .await,
                 );
+                self.kill_other_copies(&operation_id, worker_id, &action_info.inner, false);
             }
         }
         // Note: Calling this many time is very cheap, it'll only trigger `do_try_match` once.
@@ -345,17 +630,33 @@ pub struct ApiWorkerScheduler {
         help = "Timeout of how long to evict workers if no response in this given amount of time in seconds."
     )]
     worker_timeout_s: u64,
+
+    /// If set, workers are only accepted while this scheduler is the leader.
+    leader_election: Option<Arc<LeaderElection>>,
     _operation_keep_alive_spawn: JoinHandleDropGuard<()>,
 }
 
 impl ApiWorkerScheduler {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         worker_state_manager: Arc<dyn WorkerStateManager>,
         platform_property_manager: Arc<PlatformPropertyManager>,
         allocation_strategy: WorkerAllocationStrategy,
         worker_change_notify: Arc<Notify>,
         worker_timeout_s: u64,
+        leader_election: Option<Arc<LeaderElection>>,
+        worker_quarantine: Option<WorkerQuarantineConfig>,
+        speculative_execution: Option<SpeculativeExecution>,
     ) -> Arc<Self> {
+        let worker_quarantine = worker_quarantine.map(|mut worker_quarantine| {
+            if worker_quarantine.failure_window == 0 {
+                worker_quarantine.failure_window = DEFAULT_QUARANTINE_FAILURE_WINDOW;
+            }
+            if worker_quarantine.max_failures == 0 {
+                worker_quarantine.max_failures = DEFAULT_QUARANTINE_MAX_FAILURES;
+            }
+            worker_quarantine
+        });
         let (operation_keep_alive_tx, mut operation_keep_alive_rx) = mpsc::unbounded_channel();
         Arc::new(Self {
             inner: Mutex::new(ApiWorkerSchedulerImpl {
@@ -364,9 +665,16 @@ impl ApiWorkerScheduler {
                 allocation_strategy,
                 worker_change_notify,
                 operation_keep_alive_tx,
+                worker_quarantine,
+                failed_workers_by_operation: LruCache::new(
+                    NonZeroUsize::new(MAX_TRACKED_FAILED_OPERATIONS).unwrap(),
+                ),
+                workers_quarantined: CounterWithTime::default(),
+                speculative_execution,
             }),
             platform_property_manager,
             worker_timeout_s,
+            leader_election,
             _operation_keep_alive_spawn: spawn!(
                 "simple_scheduler_operation_keep_alive",
                 async move {
@@ -414,12 +722,35 @@ impl ApiWorkerScheduler {
     // TODO(blaise.bruer) This algorithm is not very efficient. Simple testing using a tree-like
     // structure showed worse performance on a 10_000 worker * 7 properties * 1000 queued tasks
     // simulation of worst cases in a single threaded environment.
+    /// Workers the operation already failed on are only picked if no other
+    /// worker could run it.
     pub async fn find_worker_for_action(
         &self,
+        operation_id: &OperationId,
         platform_properties: &PlatformProperties,
     ) -> Option<WorkerId> {
         let inner = self.inner.lock().await;
-        inner.inner_find_worker_for_action(platform_properties)
+        inner.inner_find_worker_for_action(operation_id, platform_properties)
+    }
+
+    /// Starts a copy of the actions that run much longer than usual on idle
+    /// workers. Does nothing unless speculative execution is enabled.
+    pub async fn start_speculative_executions(&self) -> Result<(), Error> {
+        let mut inner = self.inner.lock().await;
+        inner.start_speculative_executions().await
+    }
+
+    /// Disconnects all workers and requeues the actions they were running.
+    /// Used when this scheduler is no longer the leader, so the workers
+    /// reconnect to the new one.
+    pub async fn remove_all_workers(&self, err: Error) -> Result<(), Error> {
+        let mut inner = self.inner.lock().await;
+        let worker_ids: Vec<WorkerId> = inner.workers.iter().map(|(id, _)| *id).collect();
+        let mut result = Ok(());
+        for worker_id in &worker_ids {
+            result = result.merge(inner.immediate_evict_worker(worker_id, err.clone()).await);
+        }
+        result
     }
 
     /// Checks to see if the worker exists in the worker pool. Should only be used in unit tests.
@@ -449,6 +780,16 @@ impl WorkerScheduler for ApiWorkerScheduler {
     }
 
     async fn add_worker(&self, worker: Worker) -> Result<(), Error> {
+        if let Some(leader_election) = &self.leader_election {
+            // Workers keep retrying to connect, so they end up with the
+            // leader if all schedulers are behind the same address.
+            if !leader_election.is_leader() {
+                return Err(make_err!(
+                    Code::Unavailable,
+                    "This scheduler is a standby and does not accept workers"
+                ));
+            }
+        }
         let mut inner = self.inner.lock().await;
         let worker_id = worker.id;
         let result = inner
@@ -536,6 +877,23 @@ impl WorkerScheduler for ApiWorkerScheduler {
         let mut inner = self.inner.lock().await;
         inner.set_drain_worker(worker_id, is_draining).await
     }
+
+    async fn unquarantine_worker(&self, worker_id: &WorkerId) -> Result<(), Error> {
+        let mut inner = self.inner.lock().await;
+        inner.unquarantine_worker(worker_id).await
+    }
+
+    async fn get_worker_capacity(&self) -> Result<Vec<WorkerCapacity>, Error> {
+        let inner = self.inner.lock().await;
+        Ok(inner
+            .workers
+            .iter()
+            .map(|(_, worker)| WorkerCapacity {
+                platform_properties: worker.unreduced_platform_properties(),
+                is_idle: worker.running_action_infos.is_empty() && worker.can_accept_work(),
+            })
+            .collect())
+    }
 }
 
 impl RootMetricsComponent for ApiWorkerScheduler {}
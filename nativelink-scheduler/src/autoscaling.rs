@@ -0,0 +1,160 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::SystemTime;
+
+use futures::StreamExt;
+use nativelink_config::schedulers::PropertyType;
+use nativelink_error::{Error, ResultExt};
+use nativelink_util::operation_state_manager::{
+    ClientStateManager, OperationFilter, OperationStageFlags,
+};
+use nativelink_util::platform_properties::PlatformProperties;
+use serde::Serialize;
+
+use crate::worker_scheduler::WorkerScheduler;
+
+/// The platform properties of a connected worker and if it could take an
+/// action right now. The properties are not reduced by the actions the
+/// worker is running.
+pub struct WorkerCapacity {
+    pub platform_properties: PlatformProperties,
+    pub is_idle: bool,
+}
+
+/// Platform property values of the `exact` and `priority` keys in
+/// `supported_platform_properties`, actions and workers with the same values
+/// are counted together. `minimum` keys are left out, because actions with
+/// different minimums run on the same workers.
+pub type PlatformShape = BTreeMap<String, String>;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct QueuedShapeSignals {
+    pub platform_properties: PlatformShape,
+    /// Number of actions waiting for a worker.
+    pub queued_actions: u64,
+    /// Seconds the oldest of the queued actions has been waiting.
+    pub oldest_queued_s: f64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct WorkerShapeSignals {
+    pub platform_properties: PlatformShape,
+    /// Number of connected workers.
+    pub workers: u64,
+    /// Number of workers that are not running anything and are neither
+    /// paused nor draining.
+    pub idle_workers: u64,
+}
+
+/// What an autoscaler needs to know to size each worker pool, grouped by
+/// the platform properties the scheduler matches on.
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct AutoscalingSignals {
+    pub queued: Vec<QueuedShapeSignals>,
+    pub workers: Vec<WorkerShapeSignals>,
+}
+
+/// Computes the current `AutoscalingSignals` of a scheduler from the queue
+/// of `action_scheduler` and the workers of `worker_scheduler`.
+pub async fn autoscaling_signals(
+    action_scheduler: &dyn ClientStateManager,
+    worker_scheduler: &dyn WorkerScheduler,
+    now: SystemTime,
+) -> Result<AutoscalingSignals, Error> {
+    let platform_property_manager = worker_scheduler.get_platform_property_manager();
+    let shape_properties: HashSet<&String> = platform_property_manager
+        .get_known_properties()
+        .iter()
+        .filter(|(_, property_type)| **property_type != PropertyType::minimum)
+        .map(|(name, _)| name)
+        .collect();
+
+    let mut queued: HashMap<PlatformShape, (u64, SystemTime)> = HashMap::new();
+    let mut stream = action_scheduler
+        .filter_operations(OperationFilter {
+            stages: OperationStageFlags::Queued,
+            ..Default::default()
+        })
+        .await
+        .err_tip(|| "In autoscaling_signals")?;
+    while let Some(action_state_result) = stream.next().await {
+        let action_info = action_state_result
+            .as_action_info()
+            .await
+            .err_tip(|| "In autoscaling_signals")?;
+        let shape: PlatformShape = action_info
+            .platform_properties
+            .iter()
+            .filter(|(name, _)| shape_properties.contains(*name))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        let (count, oldest) = queued
+            .entry(shape)
+            .or_insert((0, action_info.insert_timestamp));
+        *count += 1;
+        *oldest = (*oldest).min(action_info.insert_timestamp);
+    }
+    drop(stream);
+
+    let mut workers: HashMap<PlatformShape, (u64, u64)> = HashMap::new();
+    for worker in worker_scheduler
+        .get_worker_capacity()
+        .await
+        .err_tip(|| "In autoscaling_signals")?
+    {
+        let shape: PlatformShape = worker
+            .platform_properties
+            .properties
+            .iter()
+            .filter(|(name, _)| shape_properties.contains(*name))
+            .map(|(name, value)| (name.clone(), value.as_str().into_owned()))
+            .collect();
+        let (count, idle) = workers.entry(shape).or_default();
+        *count += 1;
+        *idle += u64::from(worker.is_idle);
+    }
+
+    let mut signals = AutoscalingSignals {
+        queued: queued
+            .into_iter()
+            .map(
+                |(platform_properties, (queued_actions, oldest))| QueuedShapeSignals {
+                    platform_properties,
+                    queued_actions,
+                    oldest_queued_s: now.duration_since(oldest).unwrap_or_default().as_secs_f64(),
+                },
+            )
+            .collect(),
+        workers: workers
+            .into_iter()
+            .map(
+                |(platform_properties, (workers, idle_workers))| WorkerShapeSignals {
+                    platform_properties,
+                    workers,
+                    idle_workers,
+                },
+            )
+            .collect(),
+    };
+    // Keeps the output stable between calls.
+    signals
+        .queued
+        .sort_by(|a, b| a.platform_properties.cmp(&b.platform_properties));
+    signals
+        .workers
+        .sort_by(|a, b| a.platform_properties.cmp(&b.platform_properties));
+    Ok(signals)
+}
@@ -0,0 +1,244 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use nativelink_config::schedulers::CacheHitVerificationConfig;
+use nativelink_error::{make_err, Code, Error, ResultExt};
+use nativelink_metric::MetricsComponent;
+use nativelink_proto::build::bazel::remote::execution::v2::{
+    ActionResult as ProtoActionResult, Digest,
+};
+use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::NonHermeticActionResult;
+use nativelink_store::ac_utils::serialize_and_upload_message;
+use nativelink_util::action_messages::{
+    ActionInfo, ActionStage, ActionUniqueQualifier, OperationId,
+};
+use nativelink_util::background_spawn;
+use nativelink_util::metrics_utils::CounterWithTime;
+use nativelink_util::operation_state_manager::ClientStateManager;
+use nativelink_util::store_trait::{Store, StoreLike};
+use rand::rngs::OsRng;
+use rand::Rng;
+use scopeguard::guard;
+use tracing::{event, Level};
+
+/// What is compared of each output of two results.
+#[derive(PartialEq)]
+enum Output<'a> {
+    File(Option<&'a Digest>, bool),
+    Directory(Option<&'a Digest>),
+    Symlink(&'a str),
+}
+
+/// Returns the exit code and the outputs of `result` keyed by path. These
+/// have to be the same for every execution of a hermetic action.
+fn outputs_of(result: &ProtoActionResult) -> (i32, BTreeMap<&str, Output<'_>>) {
+    let mut outputs = BTreeMap::new();
+    for file in &result.output_files {
+        outputs.insert(
+            file.path.as_str(),
+            Output::File(file.digest.as_ref(), file.is_executable),
+        );
+    }
+    for directory in &result.output_directories {
+        outputs.insert(
+            directory.path.as_str(),
+            Output::Directory(directory.tree_digest.as_ref()),
+        );
+    }
+    for symlink in result
+        .output_symlinks
+        .iter()
+        .chain(&result.output_file_symlinks)
+        .chain(&result.output_directory_symlinks)
+    {
+        outputs.insert(symlink.path.as_str(), Output::Symlink(&symlink.target));
+    }
+    (result.exit_code, outputs)
+}
+
+#[derive(Default, MetricsComponent)]
+struct Metrics {
+    #[metric(help = "The number of cache hits that were executed again.")]
+    started: CounterWithTime,
+    #[metric(help = "The number of cache hits whose outputs matched when executed again.")]
+    matched: CounterWithTime,
+    #[metric(help = "The number of cache hits whose outputs differed when executed again.")]
+    mismatched: CounterWithTime,
+    #[metric(help = "The number of verifications that could not be completed.")]
+    failed: CounterWithTime,
+    #[metric(help = "The number of sampled cache hits skipped due to the concurrency limit.")]
+    skipped: CounterWithTime,
+}
+
+/// Executes a sample of the action cache hits again and compares the outputs
+/// with the cached result, to find actions that are not hermetic.
+#[derive(MetricsComponent)]
+pub struct CacheHitVerifier {
+    config: CacheHitVerificationConfig,
+    ac_store: Store,
+    mismatch_store: Store,
+    action_scheduler: Arc<dyn ClientStateManager>,
+    /// The number of verifications currently running.
+    inflight: AtomicUsize,
+    #[metric]
+    metrics: Metrics,
+}
+
+impl CacheHitVerifier {
+    pub fn new(
+        config: CacheHitVerificationConfig,
+        ac_store: Store,
+        mismatch_store: Store,
+        action_scheduler: Arc<dyn ClientStateManager>,
+    ) -> Self {
+        Self {
+            config,
+            ac_store,
+            mismatch_store,
+            action_scheduler,
+            inflight: AtomicUsize::new(0),
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Called for every cache hit. If the hit is sampled, the action is
+    /// executed again in the background.
+    pub fn on_cache_hit(
+        self: &Arc<Self>,
+        action_info: &Arc<ActionInfo>,
+        cached_result: &ProtoActionResult,
+    ) {
+        if OsRng.gen::<f32>() >= self.config.sample_rate {
+            return;
+        }
+        let max_inflight = self.config.max_concurrent_verifications;
+        let reserved =
+            self.inflight
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |inflight| {
+                    (max_inflight == 0 || inflight < max_inflight).then_some(inflight + 1)
+                });
+        if reserved.is_err() {
+            self.metrics.skipped.inc();
+            return;
+        }
+        self.metrics.started.inc();
+
+        let this = self.clone();
+        let action_info = action_info.clone();
+        let cached_result = cached_result.clone();
+        background_spawn!("cache_hit_verifier_verify", async move {
+            let this = guard(this, |this| {
+                this.inflight.fetch_sub(1, Ordering::AcqRel);
+            });
+            if let Err(err) = this.verify(&action_info, cached_result).await {
+                this.metrics.failed.inc();
+                event!(
+                    Level::WARN,
+                    ?err,
+                    action_digest = %action_info.digest(),
+                    "Could not verify cache hit",
+                );
+            }
+        });
+    }
+
+    async fn verify(
+        &self,
+        action_info: &ActionInfo,
+        cached_result: ProtoActionResult,
+    ) -> Result<(), Error> {
+        let unique_key = match &action_info.unique_qualifier {
+            ActionUniqueQualifier::Cachable(unique_key)
+            | ActionUniqueQualifier::Uncachable(unique_key) => unique_key.clone(),
+        };
+        let mut rerun_info = action_info.clone();
+        // Executed like a request with `skip_cache_lookup`, so it is neither
+        // answered from the cache nor joined with an execution of a client.
+        rerun_info.unique_qualifier = ActionUniqueQualifier::Uncachable(unique_key);
+        // The result must not replace the one being verified.
+        rerun_info.skip_action_cache_upload = true;
+        rerun_info.priority = self.config.priority;
+        rerun_info.insert_timestamp = SystemTime::now();
+
+        let mut action_state_result = self
+            .action_scheduler
+            .add_action(OperationId::default(), Arc::new(rerun_info))
+            .await
+            .err_tip(|| "Adding action in CacheHitVerifier::verify")?;
+        let mut action_state = action_state_result
+            .as_state()
+            .await
+            .err_tip(|| "Getting action state in CacheHitVerifier::verify")?;
+        let mut rerun_result = loop {
+            match &action_state.stage {
+                ActionStage::Completed(action_result) => break action_result.clone(),
+                ActionStage::CompletedFromCache(_) => {
+                    return Err(make_err!(
+                        Code::Internal,
+                        "Action was served from the cache in CacheHitVerifier::verify"
+                    ));
+                }
+                _ => {}
+            }
+            action_state = action_state_result
+                .changed()
+                .await
+                .err_tip(|| "Waiting for action in CacheHitVerifier::verify")?;
+        };
+        // An internal error (eg: a timeout) says nothing about the action
+        // being hermetic or not.
+        if let Some(err) = rerun_result.error.take() {
+            return Err(err).err_tip(|| "Action failed in CacheHitVerifier::verify");
+        }
+        let rerun_result = ProtoActionResult::from(rerun_result);
+
+        if outputs_of(&cached_result) == outputs_of(&rerun_result) {
+            self.metrics.matched.inc();
+            return Ok(());
+        }
+        self.metrics.mismatched.inc();
+
+        let action_digest = action_info.digest();
+        let record_digest = serialize_and_upload_message(
+            &NonHermeticActionResult {
+                action_digest: Some(action_digest.into()),
+                cached_result: Some(cached_result),
+                rerun_result: Some(rerun_result),
+            },
+            self.mismatch_store.as_pin(),
+            &mut action_info.unique_qualifier.digest_function().hasher(),
+        )
+        .await
+        .err_tip(|| "Saving NonHermeticActionResult in CacheHitVerifier::verify")?;
+        event!(
+            Level::WARN,
+            %action_digest,
+            %record_digest,
+            "Action produced different outputs when executed again, it is likely not hermetic",
+        );
+
+        if self.config.evict_on_mismatch {
+            self.ac_store
+                .remove(action_digest)
+                .await
+                .err_tip(|| "Removing action result in CacheHitVerifier::verify")?;
+        }
+        Ok(())
+    }
+}
@@ -40,6 +40,8 @@ use tokio::sync::oneshot;
 use tonic::{Request, Response};
 use tracing::{event, Level};
 
+use crate::cache_hit_verifier::CacheHitVerifier;
+
 /// Actions that are having their cache checked or failed cache lookup and are
 /// being forwarded upstream.  Missing the skip_cache_check actions which are
 /// forwarded directly.
@@ -63,6 +65,10 @@ pub struct CacheLookupScheduler {
     action_scheduler: Arc<dyn ClientStateManager>,
     /// Actions that are currently performing a CacheCheck.
     inflight_cache_checks: Arc<Mutex<CheckActions>>,
+    /// If set, a sample of the cache hits is executed again to find actions
+    /// that are not hermetic.
+    #[metric(group = "cache_hit_verifier")]
+    cache_hit_verifier: Option<Arc<CacheHitVerifier>>,
 }
 
 async fn get_action_from_store(
@@ -143,11 +149,13 @@ impl CacheLookupScheduler {
     pub fn new(
         ac_store: Store,
         action_scheduler: Arc<dyn ClientStateManager>,
+        cache_hit_verifier: Option<Arc<CacheHitVerifier>>,
     ) -> Result<Self, Error> {
         Ok(Self {
             ac_store,
             action_scheduler,
             inflight_cache_checks: Default::default(),
+            cache_hit_verifier,
         })
     }
 
@@ -208,6 +216,7 @@ impl CacheLookupScheduler {
         let ac_store = self.ac_store.clone();
         let action_scheduler = self.action_scheduler.clone();
         let inflight_cache_checks = self.inflight_cache_checks.clone();
+        let cache_hit_verifier = self.cache_hit_verifier.clone();
         // We need this spawn because we are returning a stream and this spawn will populate the stream's data.
         background_spawn!("cache_lookup_scheduler_add_action", async move {
             // If our spawn ever dies, we will remove the action from the inflight_cache_checks map.
@@ -236,6 +245,9 @@ impl CacheLookupScheduler {
             .await;
             match maybe_action_result {
                 Ok(action_result) => {
+                    if let Some(cache_hit_verifier) = &cache_hit_verifier {
+                        cache_hit_verifier.on_cache_hit(&action_info, &action_result);
+                    }
                     let maybe_pending_txs = {
                         let mut inflight_cache_checks = inflight_cache_checks.lock();
                         // We are ready to resolve the in-flight actions. We remove the
@@ -19,13 +19,18 @@ use nativelink_config::schedulers::{ExperimentalSimpleSchedulerBackend, Schedule
 use nativelink_config::stores::EvictionPolicy;
 use nativelink_error::{make_input_err, Error, ResultExt};
 use nativelink_store::redis_store::RedisStore;
+#[cfg(feature = "sql")]
+use nativelink_store::sql_scheduler_store::SqlSchedulerStore;
 use nativelink_store::store_manager::StoreManager;
 use nativelink_util::instant_wrapper::InstantWrapper;
 use nativelink_util::operation_state_manager::ClientStateManager;
+use nativelink_util::store_trait::SchedulerLeaseStore;
 use tokio::sync::Notify;
 
+use crate::cache_hit_verifier::CacheHitVerifier;
 use crate::cache_lookup_scheduler::CacheLookupScheduler;
 use crate::grpc_scheduler::GrpcScheduler;
+use crate::leader_election::LeaderElection;
 use crate::memory_awaited_action_db::MemoryAwaitedActionDb;
 use crate::property_modifier_scheduler::PropertyModifierScheduler;
 use crate::simple_scheduler::SimpleScheduler;
@@ -61,12 +66,40 @@ fn inner_scheduler_factory(
             let ac_store = store_manager
                 .get_store(&config.ac_store)
                 .err_tip(|| format!("'ac_store': '{}' does not exist", config.ac_store))?;
+            if config.verify_cache_hits.is_some() && forwards_to_grpc(&config.scheduler) {
+                return Err(make_input_err!(
+                    "'verify_cache_hits' can not be used with a nested 'grpc' scheduler"
+                ));
+            }
             let (action_scheduler, worker_scheduler) =
                 inner_scheduler_factory(&config.scheduler, store_manager)
                     .err_tip(|| "In nested CacheLookupScheduler construction")?;
+            let action_scheduler =
+                action_scheduler.err_tip(|| "Nested scheduler is not an action scheduler")?;
+            let cache_hit_verifier = config
+                .verify_cache_hits
+                .as_ref()
+                .map(|verify_config| {
+                    let mismatch_store = store_manager
+                        .get_store(&verify_config.mismatch_store)
+                        .err_tip(|| {
+                            format!(
+                                "'mismatch_store': '{}' does not exist",
+                                verify_config.mismatch_store
+                            )
+                        })?;
+                    Ok::<_, Error>(Arc::new(CacheHitVerifier::new(
+                        verify_config.clone(),
+                        ac_store.clone(),
+                        mismatch_store,
+                        action_scheduler.clone(),
+                    )))
+                })
+                .transpose()?;
             let cache_lookup_scheduler = Arc::new(CacheLookupScheduler::new(
                 ac_store,
-                action_scheduler.err_tip(|| "Nested scheduler is not an action scheduler")?,
+                action_scheduler,
+                cache_hit_verifier,
             )?);
             (Some(cache_lookup_scheduler), worker_scheduler)
         }
@@ -85,17 +118,38 @@ fn inner_scheduler_factory(
     Ok(scheduler)
 }
 
+/// Returns whether actions added to the scheduler of `config` are forwarded
+/// to a `GrpcScheduler`.
+fn forwards_to_grpc(config: &SchedulerConfig) -> bool {
+    match config {
+        SchedulerConfig::grpc(_) => true,
+        SchedulerConfig::simple(_) => false,
+        SchedulerConfig::cache_lookup(config) => forwards_to_grpc(&config.scheduler),
+        SchedulerConfig::property_modifier(config) => forwards_to_grpc(&config.scheduler),
+    }
+}
+
 fn simple_scheduler_factory(
     config: &nativelink_config::schedulers::SimpleScheduler,
     store_manager: &StoreManager,
     now_fn: fn() -> SystemTime,
 ) -> Result<SchedulerFactoryResults, Error> {
+    if let Some(fair_share) = &config.fair_share {
+        if fair_share.default_weight == 0 || fair_share.weights.values().any(|&w| w == 0) {
+            return Err(make_input_err!("'fair_share' weights must not be 0"));
+        }
+    }
     match config
         .experimental_backend
         .as_ref()
         .unwrap_or(&ExperimentalSimpleSchedulerBackend::memory)
     {
         ExperimentalSimpleSchedulerBackend::memory => {
+            if config.leader_election.is_some() {
+                return Err(make_input_err!(
+                    "'leader_election' requires the 'redis' or 'sql' experimental_backend"
+                ));
+            }
             let task_change_notify = Arc::new(Notify::new());
             let awaited_action_db = memory_awaited_action_db_factory(
                 config.retain_completed_for_s,
@@ -125,6 +179,7 @@ fn simple_scheduler_factory(
                         "Could not downcast to redis store in RedisAwaitedActionDb::new"
                     )
                 })?;
+            let leader_election = leader_election_factory(config, &store);
             let awaited_action_db = StoreAwaitedActionDb::new(
                 store,
                 task_change_notify.clone(),
@@ -132,13 +187,54 @@ fn simple_scheduler_factory(
                 Default::default,
             )
             .err_tip(|| "In state_manager_factory::redis_state_manager")?;
-            let (action_scheduler, worker_scheduler) =
-                SimpleScheduler::new(config, awaited_action_db, task_change_notify);
+            let (action_scheduler, worker_scheduler) = SimpleScheduler::new_with_leader_election(
+                config,
+                awaited_action_db,
+                task_change_notify,
+                leader_election,
+            );
             Ok((Some(action_scheduler), Some(worker_scheduler)))
         }
+        #[cfg(feature = "sql")]
+        ExperimentalSimpleSchedulerBackend::sql(sql_config) => {
+            let task_change_notify = Arc::new(Notify::new());
+            let store = SqlSchedulerStore::new(sql_config)
+                .err_tip(|| "In state_manager_factory::sql_state_manager")?;
+            let leader_election = leader_election_factory(config, &store);
+            let awaited_action_db = StoreAwaitedActionDb::new(
+                store,
+                task_change_notify.clone(),
+                now_fn,
+                Default::default,
+            )
+            .err_tip(|| "In state_manager_factory::sql_state_manager")?;
+            let (action_scheduler, worker_scheduler) = SimpleScheduler::new_with_leader_election(
+                config,
+                awaited_action_db,
+                task_change_notify,
+                leader_election,
+            );
+            Ok((Some(action_scheduler), Some(worker_scheduler)))
+        }
+        #[cfg(not(feature = "sql"))]
+        ExperimentalSimpleSchedulerBackend::sql(_) => Err(make_input_err!(
+            "The 'sql' experimental_backend requires building with the 'sql' feature"
+        )),
     }
 }
 
+fn leader_election_factory<S: SchedulerLeaseStore>(
+    config: &nativelink_config::schedulers::SimpleScheduler,
+    store: &Arc<S>,
+) -> Option<Arc<LeaderElection>> {
+    config
+        .leader_election
+        .as_ref()
+        .map(|leader_election_config| {
+            Arc::new(LeaderElection::new(store.clone(), leader_election_config))
+        })
+}
+
 pub fn memory_awaited_action_db_factory<I, NowFn>(
     mut retain_completed_for_s: u32,
     task_change_notify: Arc<Notify>,
@@ -0,0 +1,127 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use nativelink_config::schedulers::{FairShareConfig, FairShareKey};
+use nativelink_util::action_messages::ActionInfo;
+use parking_lot::Mutex;
+
+/// Queued actions of one pass of the matching engine, grouped by their
+/// fair-share key. Within a group the original order is kept.
+pub struct FairShareGroups<T> {
+    groups: BTreeMap<String, VecDeque<T>>,
+}
+
+impl<T> Default for FairShareGroups<T> {
+    fn default() -> Self {
+        Self {
+            groups: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> FairShareGroups<T> {
+    pub fn push(&mut self, key: String, item: T) {
+        self.groups.entry(key).or_default().push_back(item);
+    }
+}
+
+/// Hands out queued actions so that each fair-share group receives workers
+/// in proportion to its weight.
+///
+/// Every group tracks a virtual usage, which grows by `1 / weight` each time
+/// one of its actions is assigned to a worker. The next action is always
+/// taken from the non-empty group with the lowest usage, so groups take
+/// turns. Usage is kept between passes of the matching engine, because a
+/// pass often only has room for a handful of actions.
+pub struct FairShare {
+    config: FairShareConfig,
+    usage: Mutex<HashMap<String, f64>>,
+}
+
+impl FairShare {
+    pub fn new(config: FairShareConfig) -> Self {
+        Self {
+            config,
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the fair-share group the action belongs to.
+    pub fn key_for(&self, action_info: &ActionInfo) -> String {
+        match &self.config.key {
+            FairShareKey::instance_name => action_info.instance_name().clone(),
+            FairShareKey::tool_invocation_id => action_info
+                .request_metadata
+                .as_ref()
+                .map(|request_metadata| request_metadata.tool_invocation_id.clone())
+                .unwrap_or_default(),
+            FairShareKey::platform_property(name) => action_info
+                .platform_properties
+                .get(name)
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+
+    fn weight_of(&self, key: &str) -> f64 {
+        let weight = self
+            .config
+            .weights
+            .get(key)
+            .copied()
+            .unwrap_or(self.config.default_weight);
+        f64::from(weight)
+    }
+
+    /// Prepares a new pass over `groups`. Groups that are no longer queued
+    /// are forgotten and groups that just showed up start at the lowest
+    /// usage of the others, so they don't get to make up for lost time.
+    pub fn start_pass<T>(&self, groups: &FairShareGroups<T>) {
+        let mut usage = self.usage.lock();
+        usage.retain(|key, _| groups.groups.contains_key(key));
+        let floor = usage.values().copied().fold(None, |floor: Option<f64>, v| {
+            Some(floor.map_or(v, |floor| floor.min(v)))
+        });
+        for key in groups.groups.keys() {
+            usage.entry(key.clone()).or_insert(floor.unwrap_or(0.0));
+        }
+    }
+
+    /// Removes and returns the next action to try along with its group key.
+    pub fn next<T>(&self, groups: &mut FairShareGroups<T>) -> Option<(String, T)> {
+        let key = {
+            let usage = self.usage.lock();
+            groups
+                .groups
+                .iter()
+                .filter(|(_, items)| !items.is_empty())
+                .map(|(key, _)| (key, usage.get(key).copied().unwrap_or(0.0)))
+                // `min_by` keeps the first of equal elements, so ties go to
+                // the group with the lowest key.
+                .min_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(key, _)| key.clone())?
+        };
+        let items = groups.groups.get_mut(&key)?;
+        let item = items.pop_front()?;
+        Some((key, item))
+    }
+
+    /// Records that an action of the group was assigned to a worker.
+    pub fn charge(&self, key: &str) {
+        let weight = self.weight_of(key);
+        *self.usage.lock().entry(key.to_string()).or_insert(0.0) += 1.0 / weight;
+    }
+}
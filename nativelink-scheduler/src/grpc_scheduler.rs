@@ -25,7 +25,8 @@ use nativelink_metric::{MetricsComponent, RootMetricsComponent};
 use nativelink_proto::build::bazel::remote::execution::v2::capabilities_client::CapabilitiesClient;
 use nativelink_proto::build::bazel::remote::execution::v2::execution_client::ExecutionClient;
 use nativelink_proto::build::bazel::remote::execution::v2::{
-    ExecuteRequest, ExecutionPolicy, GetCapabilitiesRequest, WaitExecutionRequest,
+    ExecuteRequest, ExecutionPolicy, GetCapabilitiesRequest, RequestMetadata, ResultsCachePolicy,
+    WaitExecutionRequest,
 };
 use nativelink_proto::google::longrunning::Operation;
 use nativelink_util::action_messages::{
@@ -36,6 +37,7 @@ use nativelink_util::known_platform_property_provider::KnownPlatformPropertyProv
 use nativelink_util::operation_state_manager::{
     ActionStateResult, ActionStateResultStream, ClientStateManager, OperationFilter,
 };
+use nativelink_util::request_metadata::{make_request, set_request_metadata};
 use nativelink_util::retry::{Retrier, RetryResult};
 use nativelink_util::{background_spawn, tls_utils};
 use parking_lot::Mutex;
@@ -44,7 +46,7 @@ use rand::Rng;
 use tokio::select;
 use tokio::sync::watch;
 use tokio::time::sleep;
-use tonic::{Request, Streaming};
+use tonic::Streaming;
 use tracing::{event, Level};
 
 struct GrpcActionStateResult {
@@ -125,6 +127,7 @@ impl GrpcScheduler {
                 config.max_concurrent_requests,
                 config.retry.to_owned(),
                 jitter_fn,
+                None,
             ),
         })
     }
@@ -257,6 +260,12 @@ impl GrpcScheduler {
         _client_operation_id: OperationId,
         action_info: Arc<ActionInfo>,
     ) -> Result<Box<dyn ActionStateResult>, Error> {
+        // An `ExecuteRequest` has no way to ask the upstream scheduler not to
+        // cache the result, so refuse rather than let it overwrite the cache.
+        error_if!(
+            action_info.skip_action_cache_upload,
+            "GrpcScheduler can not forward skip_action_cache_upload to the upstream scheduler"
+        );
         let execution_policy = if action_info.priority == DEFAULT_EXECUTION_PRIORITY {
             None
         } else {
@@ -264,6 +273,13 @@ impl GrpcScheduler {
                 priority: action_info.priority,
             })
         };
+        let results_cache_policy = if action_info.results_cache_priority == 0 {
+            None
+        } else {
+            Some(ResultsCachePolicy {
+                priority: action_info.results_cache_priority,
+            })
+        };
         let skip_cache_lookup = match action_info.unique_qualifier {
             ActionUniqueQualifier::Cachable(_) => false,
             ActionUniqueQualifier::Uncachable(_) => true,
@@ -273,14 +289,18 @@ impl GrpcScheduler {
             skip_cache_lookup,
             action_digest: Some(action_info.digest().into()),
             execution_policy,
-            // TODO: Get me from the original request, not very important as we ignore it.
-            results_cache_policy: None,
+            results_cache_policy,
             digest_function: action_info
                 .unique_qualifier
                 .digest_function()
                 .proto_digest_func()
                 .into(),
         };
+        // Prefer the metadata stored with the action, since the action might
+        // not be added from within the context of the original request.
+        let request_metadata: Option<RequestMetadata> =
+            action_info.request_metadata.clone().map(Into::into);
+        let request_metadata = &request_metadata;
         let result_stream = self
             .perform_request(request, |request| async move {
                 let channel = self
@@ -288,8 +308,12 @@ impl GrpcScheduler {
                     .connection()
                     .await
                     .err_tip(|| "in add_action()")?;
+                let mut request = make_request(request);
+                if let Some(request_metadata) = request_metadata {
+                    set_request_metadata(&mut request, request_metadata);
+                }
                 ExecutionClient::new(channel)
-                    .execute(Request::new(request))
+                    .execute(request)
                     .await
                     .err_tip(|| "Sending action to upstream scheduler")
             })
@@ -320,7 +344,7 @@ impl GrpcScheduler {
                     .await
                     .err_tip(|| "in find_by_client_operation_id()")?;
                 ExecutionClient::new(channel)
-                    .wait_execution(Request::new(request))
+                    .wait_execution(make_request(request))
                     .await
                     .err_tip(|| "While getting wait_execution stream")
             })
@@ -0,0 +1,184 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use nativelink_config::schedulers::LeaderElectionConfig;
+use nativelink_error::{make_err, Code};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::store_trait::SchedulerLeaseStore;
+use nativelink_util::task::JoinHandleDropGuard;
+use nativelink_util::{background_spawn, spawn};
+use tokio::sync::watch;
+use tokio::time::{sleep, timeout};
+use tracing::{event, Level};
+use uuid::Uuid;
+
+/// Default name of the lease.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_LEASE_NAME: &str = "nativelink_scheduler_leader";
+
+/// Default duration of the lease in seconds.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_LEASE_DURATION_S: u64 = 10;
+
+/// Elects one leader among the schedulers sharing a store by competing for
+/// a lease in it. The lease is renewed in the background for as long as
+/// this struct is alive.
+#[derive(MetricsComponent)]
+pub struct LeaderElection {
+    #[metric(help = "The name of the lease the schedulers compete for.")]
+    lease_name: String,
+    #[metric(help = "The unique name this scheduler holds the lease with.")]
+    holder: String,
+    is_leader: watch::Receiver<bool>,
+    release_lease: Box<dyn Fn() + Send + Sync>,
+    lease_spawn: Option<JoinHandleDropGuard<()>>,
+}
+
+impl LeaderElection {
+    pub fn new<S: SchedulerLeaseStore>(store: Arc<S>, config: &LeaderElectionConfig) -> Self {
+        let lease_name = if config.lease_name.is_empty() {
+            DEFAULT_LEASE_NAME.to_string()
+        } else {
+            config.lease_name.clone()
+        };
+        let mut lease_duration_s = config.lease_duration_s;
+        if lease_duration_s == 0 {
+            lease_duration_s = DEFAULT_LEASE_DURATION_S;
+        }
+        let lease_duration = Duration::from_secs(lease_duration_s);
+        let renew_interval = lease_duration / 3;
+        let holder = Uuid::new_v4().to_string();
+        let (is_leader_tx, is_leader) = watch::channel(false);
+
+        let lease_spawn = {
+            let store = store.clone();
+            let lease_name = lease_name.clone();
+            let holder = holder.clone();
+            spawn!("leader_election_lease", async move {
+                // When the lease was last known to be held by us.
+                let mut held_since: Option<Instant> = None;
+                loop {
+                    // Taken before the request, so we never think we hold the
+                    // lease for longer than the store does.
+                    let attempt_start = Instant::now();
+                    // A hanging store must not keep us leading past the
+                    // expiry of the lease we hold.
+                    let attempt_timeout = held_since.map_or(lease_duration / 2, |held_since| {
+                        (lease_duration / 2)
+                            .min(lease_duration.saturating_sub(held_since.elapsed()))
+                    });
+                    let result = timeout(
+                        attempt_timeout,
+                        store.acquire_or_renew_lease(&lease_name, &holder, lease_duration),
+                    )
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(make_err!(
+                            Code::DeadlineExceeded,
+                            "Timed out after {attempt_timeout:?}"
+                        ))
+                    });
+                    let is_leader = match result {
+                        Ok(acquired) => {
+                            held_since = acquired.then_some(attempt_start);
+                            acquired
+                        }
+                        Err(err) => {
+                            event!(
+                                Level::WARN,
+                                ?err,
+                                %lease_name,
+                                "Could not renew scheduler lease"
+                            );
+                            // Keep leading only while the last renewal is
+                            // certain to outlast the next attempt.
+                            let still_held = held_since.is_some_and(|held_since| {
+                                held_since.elapsed() + renew_interval < lease_duration
+                            });
+                            if !still_held {
+                                held_since = None;
+                            }
+                            still_held
+                        }
+                    };
+                    is_leader_tx.send_if_modified(|was_leader| {
+                        if *was_leader == is_leader {
+                            return false;
+                        }
+                        *was_leader = is_leader;
+                        if is_leader {
+                            event!(Level::INFO, %lease_name, %holder, "Became scheduler leader");
+                        } else {
+                            event!(Level::WARN, %lease_name, %holder, "No longer scheduler leader");
+                        }
+                        true
+                    });
+                    sleep(renew_interval).await;
+                }
+            })
+        };
+
+        // Lets a standby take over right away instead of waiting for the
+        // lease to expire when this scheduler shuts down.
+        let release_lease = {
+            let lease_name = lease_name.clone();
+            let holder = holder.clone();
+            let is_leader = is_leader.clone();
+            Box::new(move || {
+                if !*is_leader.borrow() {
+                    return;
+                }
+                let store = store.clone();
+                let lease_name = lease_name.clone();
+                let holder = holder.clone();
+                background_spawn!("leader_election_release_lease", async move {
+                    if let Err(err) = store.release_lease(&lease_name, &holder).await {
+                        event!(Level::WARN, ?err, %lease_name, "Could not release scheduler lease");
+                    }
+                });
+            })
+        };
+
+        Self {
+            lease_name,
+            holder,
+            is_leader,
+            release_lease,
+            lease_spawn: Some(lease_spawn),
+        }
+    }
+
+    /// Returns true if this scheduler currently holds the lease.
+    pub fn is_leader(&self) -> bool {
+        *self.is_leader.borrow()
+    }
+
+    /// Returns a receiver that is notified every time this scheduler gains
+    /// or loses the lease.
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.is_leader.clone()
+    }
+}
+
+impl Drop for LeaderElection {
+    fn drop(&mut self) {
+        // Stop renewing first, so the lease is not taken again right after
+        // it was released.
+        drop(self.lease_spawn.take());
+        (self.release_lease)();
+    }
+}
@@ -13,15 +13,21 @@
 // limitations under the License.
 
 pub mod api_worker_scheduler;
+pub mod autoscaling;
 pub mod awaited_action_db;
+pub mod cache_hit_verifier;
 pub mod cache_lookup_scheduler;
 pub mod default_scheduler_factory;
+mod fair_share;
 pub mod grpc_scheduler;
+pub mod leader_election;
 pub mod memory_awaited_action_db;
+pub mod operation_summary;
 pub mod platform_property_manager;
 pub mod property_modifier_scheduler;
 pub mod simple_scheduler;
 mod simple_scheduler_state_manager;
+pub mod speculative_execution;
 pub mod store_awaited_action_db;
 pub mod worker;
 pub mod worker_scheduler;
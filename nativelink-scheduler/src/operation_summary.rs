@@ -0,0 +1,166 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use futures::StreamExt;
+use nativelink_error::{Error, ResultExt};
+use nativelink_util::action_messages::{ActionInfo, ActionStage, ActionState, OperationId};
+use nativelink_util::operation_state_manager::{
+    ActionStateResult, ClientStateManager, OperationFilter, OperationStageFlags, OrderDirection,
+};
+use serde::Serialize;
+
+/// Maximum number of operations `list_operations` returns, so a long queue
+/// does not produce an unbounded response.
+pub const MAX_LISTED_OPERATIONS: usize = 10_000;
+
+/// What an operator needs to know about an operation at a glance.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct OperationSummary {
+    /// Id used by the scheduler for the operation. Clients waiting on the
+    /// same action share it.
+    pub operation_id: String,
+    /// One of "CacheCheck", "Queued", "Executing" or "Completed".
+    pub stage: String,
+    pub instance_name: String,
+    pub action_digest: String,
+    /// Worker the operation is assigned to, if any.
+    pub worker_id: Option<String>,
+    /// Seconds since the action was submitted.
+    pub age_s: f64,
+    pub priority: i32,
+    pub platform_properties: BTreeMap<String, String>,
+}
+
+/// Everything the scheduler knows about one operation.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct OperationDetails {
+    #[serde(flatten)]
+    pub summary: OperationSummary,
+    pub action_info: Arc<ActionInfo>,
+    pub action_state: Arc<ActionState>,
+}
+
+const fn stage_name(stage: &ActionStage) -> &'static str {
+    match stage {
+        ActionStage::Unknown => "Unknown",
+        ActionStage::CacheCheck => "CacheCheck",
+        ActionStage::Queued => "Queued",
+        ActionStage::Executing => "Executing",
+        ActionStage::Completed(_) | ActionStage::CompletedFromCache(_) => "Completed",
+    }
+}
+
+async fn operation_details_from_result(
+    action_state_result: &dyn ActionStateResult,
+    now: SystemTime,
+) -> Result<OperationDetails, Error> {
+    let action_state = action_state_result
+        .as_state()
+        .await
+        .err_tip(|| "In operation_details_from_result")?;
+    let action_info = action_state_result
+        .as_action_info()
+        .await
+        .err_tip(|| "In operation_details_from_result")?;
+    let worker_id = action_state_result
+        .as_worker_id()
+        .await
+        .err_tip(|| "In operation_details_from_result")?;
+    Ok(OperationDetails {
+        summary: OperationSummary {
+            operation_id: action_state.client_operation_id.to_string(),
+            stage: stage_name(&action_state.stage).to_string(),
+            instance_name: action_info.instance_name().clone(),
+            action_digest: action_info.digest().to_string(),
+            worker_id: worker_id.map(|worker_id| worker_id.to_string()),
+            age_s: now
+                .duration_since(action_info.insert_timestamp)
+                .unwrap_or_default()
+                .as_secs_f64(),
+            priority: action_info.priority,
+            platform_properties: action_info
+                .platform_properties
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+        },
+        action_info,
+        action_state,
+    })
+}
+
+/// Lists the queued operations ordered by priority, highest first, followed
+/// by the executing operations. This is not necessarily the order the
+/// operations will run in, since fair share and worker matching also decide
+/// that. At most `MAX_LISTED_OPERATIONS` are returned.
+pub async fn list_operations(
+    action_scheduler: &dyn ClientStateManager,
+    now: SystemTime,
+) -> Result<Vec<OperationSummary>, Error> {
+    let filters = [
+        OperationFilter {
+            stages: OperationStageFlags::Queued,
+            order_by_priority_direction: Some(OrderDirection::Desc),
+            ..Default::default()
+        },
+        OperationFilter {
+            stages: OperationStageFlags::Executing,
+            ..Default::default()
+        },
+    ];
+    let mut operations = Vec::new();
+    for filter in filters {
+        let mut stream = action_scheduler
+            .filter_operations(filter)
+            .await
+            .err_tip(|| "In list_operations")?;
+        while let Some(action_state_result) = stream.next().await {
+            if operations.len() >= MAX_LISTED_OPERATIONS {
+                return Ok(operations);
+            }
+            let details = operation_details_from_result(action_state_result.as_ref(), now)
+                .await
+                .err_tip(|| "In list_operations")?;
+            operations.push(details.summary);
+        }
+    }
+    Ok(operations)
+}
+
+/// Returns the details of the operation with the id from `list_operations`,
+/// or None if the scheduler does not know about it.
+pub async fn operation_details(
+    action_scheduler: &dyn ClientStateManager,
+    operation_id: OperationId,
+    now: SystemTime,
+) -> Result<Option<OperationDetails>, Error> {
+    let mut stream = action_scheduler
+        .filter_operations(OperationFilter {
+            operation_id: Some(operation_id),
+            ..Default::default()
+        })
+        .await
+        .err_tip(|| "In operation_details")?;
+    let Some(action_state_result) = stream.next().await else {
+        return Ok(None);
+    };
+    operation_details_from_result(action_state_result.as_ref(), now)
+        .await
+        .err_tip(|| "In operation_details")
+        .map(Some)
+}
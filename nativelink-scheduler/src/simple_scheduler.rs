@@ -12,20 +12,25 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::SystemTime;
 
+use async_lock::Mutex;
 use async_trait::async_trait;
 use futures::Future;
-use nativelink_error::{Code, Error, ResultExt};
+use nativelink_error::{make_err, Code, Error, ResultExt};
 use nativelink_metric::{MetricsComponent, RootMetricsComponent};
-use nativelink_util::action_messages::{ActionInfo, ActionState, OperationId, WorkerId};
+use nativelink_util::action_messages::{
+    ActionInfo, ActionState, ActionUniqueQualifier, OperationId, WorkerId,
+};
 use nativelink_util::instant_wrapper::InstantWrapper;
 use nativelink_util::known_platform_property_provider::KnownPlatformPropertyProvider;
 use nativelink_util::operation_state_manager::{
     ActionStateResult, ActionStateResultStream, ClientStateManager, MatchingEngineStateManager,
     OperationFilter, OperationStageFlags, OrderDirection, UpdateOperationType,
 };
+use nativelink_util::platform_properties::PlatformProperties;
 use nativelink_util::spawn;
 use nativelink_util::task::JoinHandleDropGuard;
 use tokio::sync::Notify;
@@ -34,9 +39,13 @@ use tokio_stream::StreamExt;
 use tracing::{event, Level};
 
 use crate::api_worker_scheduler::ApiWorkerScheduler;
+use crate::autoscaling::WorkerCapacity;
 use crate::awaited_action_db::AwaitedActionDb;
+use crate::fair_share::{FairShare, FairShareGroups};
+use crate::leader_election::LeaderElection;
 use crate::platform_property_manager::PlatformPropertyManager;
 use crate::simple_scheduler_state_manager::SimpleSchedulerStateManager;
+use crate::speculative_execution::SpeculativeExecution;
 use crate::worker::{ActionInfoWithProps, Worker, WorkerTimestamp};
 use crate::worker_scheduler::WorkerScheduler;
 
@@ -48,6 +57,48 @@ const DEFAULT_WORKER_TIMEOUT_S: u64 = 5;
 /// If this changes, remember to change the documentation in the config.
 const DEFAULT_MAX_JOB_RETRIES: usize = 3;
 
+/// How often the matching engine runs even if nothing changed, so queued
+/// actions are failed in time when `max_queued_time_s` or
+/// `unmatchable_action_timeout_s` is set, and slow actions are copied in
+/// time when `speculative_execution` is set.
+const PERIODIC_MATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the cached queue lengths used for `max_queued_actions` and
+/// `max_queued_actions_per_instance` are used before being recounted.
+const QUEUE_LENGTHS_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Mnemonic actions sent without one are counted under.
+const UNKNOWN_MNEMONIC: &str = "unknown";
+
+/// Maximum number of mnemonics counted separately in
+/// `actions_added_by_mnemonic`. Clients can send any mnemonic, so actions
+/// with mnemonics seen after this many are counted under `OTHER_MNEMONIC`.
+const MAX_COUNTED_MNEMONICS: usize = 256;
+
+/// Mnemonic actions are counted under once `MAX_COUNTED_MNEMONICS` is reached.
+const OTHER_MNEMONIC: &str = "other";
+
+/// Number of queued actions, as of the last time they were counted plus the
+/// actions added since then.
+#[derive(Default)]
+struct QueueLengths {
+    refreshed_at: Option<SystemTime>,
+    total: u64,
+    per_instance: HashMap<String, u64>,
+}
+
+/// Workers seen by the matching engine, used to tell if a queued action
+/// could ever be run.
+#[derive(Default)]
+struct WorkerHistory {
+    /// Since when this scheduler has been matching actions. Reset while it
+    /// is a standby, since it has no workers then.
+    observed_since: Option<SystemTime>,
+    /// Distinct platform properties of the connected workers and when a
+    /// worker with them was last seen.
+    seen: Vec<(PlatformProperties, SystemTime)>,
+}
+
 struct SimpleSchedulerActionStateResult {
     client_operation_id: OperationId,
     action_state_result: Box<dyn ActionStateResult>,
@@ -97,6 +148,13 @@ impl ActionStateResult for SimpleSchedulerActionStateResult {
             .await
             .err_tip(|| "In SimpleSchedulerActionStateResult")
     }
+
+    async fn as_worker_id(&self) -> Result<Option<WorkerId>, Error> {
+        self.action_state_result
+            .as_worker_id()
+            .await
+            .err_tip(|| "In SimpleSchedulerActionStateResult")
+    }
 }
 
 /// Engine used to manage the queued/running tasks and relationship with
@@ -121,9 +179,54 @@ pub struct SimpleScheduler {
     #[metric(group = "worker_scheduler")]
     worker_scheduler: Arc<ApiWorkerScheduler>,
 
+    /// If set, queued actions are handed to workers in fair-share order
+    /// instead of strictly by priority.
+    fair_share: Option<FairShare>,
+
+    /// If set, actions are only matched to workers while this scheduler is
+    /// the leader.
+    leader_election: Option<Arc<LeaderElection>>,
+
+    /// Queued actions older than this are failed. Zero disables the limit.
+    #[metric(help = "Maximum time an action may be queued before it is failed.")]
+    max_queued_time: Duration,
+
+    /// Queued actions no worker seen in this long could run are failed.
+    /// Zero disables the check.
+    #[metric(help = "Time after which actions no worker could run are failed.")]
+    unmatchable_action_timeout: Duration,
+
+    #[metric(help = "Maximum number of queued actions before new ones are rejected.")]
+    max_queued_actions: u64,
+
+    #[metric(
+        help = "Maximum number of queued actions of an instance before new ones are rejected."
+    )]
+    max_queued_actions_per_instance: u64,
+
+    /// Cached queue lengths used to enforce the `max_queued_actions*` limits.
+    queue_lengths: Mutex<QueueLengths>,
+
+    /// Number of actions added, by the mnemonic of the `RequestMetadata`
+    /// they were sent with. At most `MAX_COUNTED_MNEMONICS` mnemonics are
+    /// kept apart. Invocation and target ids are left to the tracing spans,
+    /// since there is no bound on how many there are.
+    #[metric(group = "actions_added_by_mnemonic")]
+    actions_added_by_mnemonic: Mutex<HashMap<String, u64>>,
+
+    /// Workers seen recently, used to enforce `unmatchable_action_timeout`.
+    worker_history: Mutex<WorkerHistory>,
+
+    /// Returns the current time.
+    now_fn: Box<dyn Fn() -> SystemTime + Send + Sync>,
+
     /// Background task that tries to match actions to workers. If this struct
     /// is dropped the spawn will be cancelled as well.
     _task_worker_matching_spawn: JoinHandleDropGuard<()>,
+
+    /// Background task that disconnects all workers when this scheduler loses
+    /// the leadership and kicks off matching when it gains it.
+    _leader_election_spawn: Option<JoinHandleDropGuard<()>>,
 }
 
 impl SimpleScheduler {
@@ -139,17 +242,150 @@ impl SimpleScheduler {
         client_operation_id: OperationId,
         action_info: Arc<ActionInfo>,
     ) -> Result<Box<dyn ActionStateResult>, Error> {
+        if self.max_queued_actions != 0 || self.max_queued_actions_per_instance != 0 {
+            self.reserve_queue_slot(&action_info)
+                .await
+                .err_tip(|| "In SimpleScheduler::add_action")?;
+        }
         let action_state_result = self
             .client_state_manager
-            .add_action(client_operation_id.clone(), action_info)
+            .add_action(client_operation_id.clone(), action_info.clone())
             .await
             .err_tip(|| "In SimpleScheduler::add_action")?;
+        let mnemonic = match &action_info.request_metadata {
+            Some(request_metadata) if !request_metadata.action_mnemonic.is_empty() => {
+                request_metadata.action_mnemonic.clone()
+            }
+            _ => UNKNOWN_MNEMONIC.to_string(),
+        };
+        let mut actions_added_by_mnemonic = self.actions_added_by_mnemonic.lock().await;
+        let mnemonic = if actions_added_by_mnemonic.len() < MAX_COUNTED_MNEMONICS
+            || actions_added_by_mnemonic.contains_key(&mnemonic)
+        {
+            mnemonic
+        } else {
+            OTHER_MNEMONIC.to_string()
+        };
+        *actions_added_by_mnemonic.entry(mnemonic).or_default() += 1;
         Ok(Box::new(SimpleSchedulerActionStateResult::new(
             client_operation_id.clone(),
             action_state_result,
         )))
     }
 
+    /// Returns `ResourceExhausted` if the queue is full and the action would
+    /// not join an operation that already exists, otherwise counts the new
+    /// action as queued until the queue lengths are refreshed.
+    async fn reserve_queue_slot(&self, action_info: &ActionInfo) -> Result<(), Error> {
+        let instance_name = action_info.instance_name();
+        let now = (self.now_fn)();
+        let is_fresh = self
+            .queue_lengths
+            .lock()
+            .await
+            .refreshed_at
+            .is_some_and(|refreshed_at| {
+                now.duration_since(refreshed_at)
+                    .is_ok_and(|age| age < QUEUE_LENGTHS_REFRESH_INTERVAL)
+            });
+        if !is_fresh {
+            // Counted without holding the lock, so other actions are not
+            // held up while the queue is walked.
+            let fresh_queue_lengths = self.count_queued_actions(now).await?;
+            let mut queue_lengths = self.queue_lengths.lock().await;
+            // Another action might have counted them more recently.
+            if !queue_lengths
+                .refreshed_at
+                .is_some_and(|refreshed_at| refreshed_at >= now)
+            {
+                *queue_lengths = fresh_queue_lengths;
+            }
+        }
+
+        let queue_full_err = {
+            let mut queue_lengths = self.queue_lengths.lock().await;
+            let instance_queue_length = queue_lengths
+                .per_instance
+                .get(instance_name)
+                .copied()
+                .unwrap_or_default();
+            if self.max_queued_actions != 0 && queue_lengths.total >= self.max_queued_actions {
+                make_err!(
+                    Code::ResourceExhausted,
+                    "Scheduler queue is full, {} actions are queued",
+                    queue_lengths.total
+                )
+            } else if self.max_queued_actions_per_instance != 0
+                && instance_queue_length >= self.max_queued_actions_per_instance
+            {
+                make_err!(
+                    Code::ResourceExhausted,
+                    "Scheduler queue of instance '{instance_name}' is full, {instance_queue_length} actions are queued",
+                )
+            } else {
+                queue_lengths.total += 1;
+                *queue_lengths
+                    .per_instance
+                    .entry(instance_name.to_string())
+                    .or_default() += 1;
+                return Ok(());
+            }
+        };
+        // Joining an operation that already exists does not add to the queue.
+        if self.joins_existing_operation(action_info).await? {
+            return Ok(());
+        }
+        Err(queue_full_err)
+    }
+
+    /// Counts the queued actions, in total and by instance.
+    async fn count_queued_actions(&self, now: SystemTime) -> Result<QueueLengths, Error> {
+        let mut queue_lengths = QueueLengths {
+            refreshed_at: Some(now),
+            ..Default::default()
+        };
+        let mut stream = self
+            .client_state_manager
+            .filter_operations(OperationFilter {
+                stages: OperationStageFlags::Queued,
+                ..Default::default()
+            })
+            .await
+            .err_tip(|| "Failed to get queued operations in count_queued_actions")?;
+        while let Some(action_state_result) = stream.next().await {
+            let action_info = action_state_result
+                .as_action_info()
+                .await
+                .err_tip(|| "Failed to get action_info in count_queued_actions")?;
+            queue_lengths.total += 1;
+            *queue_lengths
+                .per_instance
+                .entry(action_info.instance_name().clone())
+                .or_default() += 1;
+        }
+        Ok(queue_lengths)
+    }
+
+    /// Returns true if adding `action_info` would join an operation that is
+    /// not finished yet instead of creating a new one.
+    async fn joins_existing_operation(&self, action_info: &ActionInfo) -> Result<bool, Error> {
+        let ActionUniqueQualifier::Cachable(unique_key) = &action_info.unique_qualifier else {
+            return Ok(false);
+        };
+        let mut stream = self
+            .client_state_manager
+            .filter_operations(OperationFilter {
+                stages: OperationStageFlags::CacheCheck
+                    | OperationStageFlags::Queued
+                    | OperationStageFlags::Executing,
+                unique_key: Some(unique_key.clone()),
+                ..Default::default()
+            })
+            .await
+            .err_tip(|| "Failed to get operations in joins_existing_operation")?;
+        Ok(stream.next().await.is_some())
+    }
+
     async fn inner_filter_operations(
         &self,
         filter: OperationFilter,
@@ -176,21 +412,135 @@ impl SimpleScheduler {
         self.do_try_match().await
     }
 
+    /// Records the platform properties of the connected workers. Returns the
+    /// properties of the workers seen within `unmatchable_action_timeout`,
+    /// or None if unmatchable actions should not be failed (yet).
+    async fn recent_worker_properties(
+        &self,
+        now: SystemTime,
+    ) -> Result<Option<Vec<PlatformProperties>>, Error> {
+        if self.unmatchable_action_timeout.is_zero() {
+            return Ok(None);
+        }
+        let connected_workers = self
+            .worker_scheduler
+            .get_worker_capacity()
+            .await
+            .err_tip(|| "In SimpleScheduler::recent_worker_properties")?;
+        let mut worker_history = self.worker_history.lock().await;
+        let observed_since = *worker_history.observed_since.get_or_insert(now);
+        for worker in connected_workers {
+            match worker_history
+                .seen
+                .iter_mut()
+                .find(|(platform_properties, _)| *platform_properties == worker.platform_properties)
+            {
+                Some((_, last_seen)) => *last_seen = now,
+                None => worker_history.seen.push((worker.platform_properties, now)),
+            }
+        }
+        let timeout = self.unmatchable_action_timeout;
+        worker_history
+            .seen
+            .retain(|(_, last_seen)| now.duration_since(*last_seen).unwrap_or_default() <= timeout);
+        // Give workers a chance to connect before failing anything.
+        if now.duration_since(observed_since).unwrap_or_default() < timeout {
+            return Ok(None);
+        }
+        Ok(Some(
+            worker_history
+                .seen
+                .iter()
+                .map(|(platform_properties, _)| platform_properties.clone())
+                .collect(),
+        ))
+    }
+
+    /// Fails the queued action if it was queued for too long or none of the
+    /// `recent_worker_properties` could run it. Returns true if it was failed.
+    async fn fail_if_unrunnable(
+        &self,
+        action_state_result: &dyn ActionStateResult,
+        action_info: &ActionInfo,
+        now: SystemTime,
+        recent_worker_properties: Option<&[PlatformProperties]>,
+    ) -> Result<bool, Error> {
+        let queued_time = now
+            .duration_since(action_info.insert_timestamp)
+            .unwrap_or_default();
+        let err = if !self.max_queued_time.is_zero() && queued_time > self.max_queued_time {
+            make_err!(
+                Code::DeadlineExceeded,
+                "Action was queued for more than {} seconds without being run",
+                self.max_queued_time.as_secs()
+            )
+        } else if let Some(recent_worker_properties) = recent_worker_properties {
+            // Properties the scheduler does not know about can never match.
+            let can_run = self
+                .platform_property_manager
+                .make_platform_properties(action_info.platform_properties.clone())
+                .is_ok_and(|platform_properties| {
+                    recent_worker_properties.iter().any(|worker_properties| {
+                        platform_properties.is_satisfied_by(worker_properties)
+                    })
+                });
+            if can_run {
+                return Ok(false);
+            }
+            make_err!(
+                Code::FailedPrecondition,
+                "No worker that can run an action with platform properties {:?} was connected in the last {} seconds",
+                action_info.platform_properties,
+                self.unmatchable_action_timeout.as_secs()
+            )
+        } else {
+            return Ok(false);
+        };
+
+        let operation_id = action_state_result
+            .as_state()
+            .await
+            .err_tip(|| "Failed to get action_state in fail_if_unrunnable")?
+            .client_operation_id
+            .clone();
+        self.matching_engine_state_manager
+            .fail_operation(&operation_id, err)
+            .await
+            .err_tip(|| "Failed to fail operation in fail_if_unrunnable")?;
+        Ok(true)
+    }
+
+    async fn do_try_match(&self) -> Result<(), Error> {
+        let result = self.match_queued_actions().await;
+        // Copies of slow actions only get the workers queued actions left idle.
+        result.merge(
+            self.worker_scheduler
+                .start_speculative_executions()
+                .await
+                .err_tip(|| "Failed to start speculative executions in do_try_match"),
+        )
+    }
+
     // TODO(blaise.bruer) This is an O(n*m) (aka n^2) algorithm. In theory we
     // can create a map of capabilities of each worker and then try and match
     // the actions to the worker using the map lookup (ie. map reduce).
-    async fn do_try_match(&self) -> Result<(), Error> {
+    async fn match_queued_actions(&self) -> Result<(), Error> {
+        if let Some(leader_election) = &self.leader_election {
+            // The leader is in charge of matching, standbys have no workers.
+            if !leader_election.is_leader() {
+                self.worker_history.lock().await.observed_since = None;
+                return Ok(());
+            }
+        }
+
+        /// Returns true if the action was handed to a worker.
         async fn match_action_to_worker(
             action_state_result: &dyn ActionStateResult,
+            action_info: Arc<ActionInfo>,
             workers: &ApiWorkerScheduler,
             matching_engine_state_manager: &dyn MatchingEngineStateManager,
             platform_property_manager: &PlatformPropertyManager,
-        ) -> Result<(), Error> {
-            let action_info = action_state_result
-                .as_action_info()
-                .await
-                .err_tip(|| "Failed to get action_info from as_action_info_result stream")?;
-
+        ) -> Result<bool, Error> {
             // TODO(allada) We should not compute this every time and instead store
             // it with the ActionInfo when we receive it.
             let platform_properties = platform_property_manager
@@ -204,28 +554,28 @@ impl SimpleScheduler {
                 platform_properties,
             };
 
+            // Extract the operation_id from the action_state.
+            let operation_id = {
+                let action_state = action_state_result
+                    .as_state()
+                    .await
+                    .err_tip(|| "Failed to get action_info from as_state_result stream")?;
+                action_state.client_operation_id.clone()
+            };
+
             // Try to find a worker for the action.
             let worker_id = {
                 match workers
-                    .find_worker_for_action(&action_info.platform_properties)
+                    .find_worker_for_action(&operation_id, &action_info.platform_properties)
                     .await
                 {
                     Some(worker_id) => worker_id,
                     // If we could not find a worker for the action,
                     // we have nothing to do.
-                    None => return Ok(()),
+                    None => return Ok(false),
                 }
             };
 
-            // Extract the operation_id from the action_state.
-            let operation_id = {
-                let action_state = action_state_result
-                    .as_state()
-                    .await
-                    .err_tip(|| "Failed to get action_info from as_state_result stream")?;
-                action_state.client_operation_id.clone()
-            };
-
             // Tell the matching engine that the operation is being assigned to a worker.
             let assign_result = matching_engine_state_manager
                 .assign_operation(&operation_id, Ok(&worker_id))
@@ -235,7 +585,7 @@ impl SimpleScheduler {
                 if err.code == Code::Aborted {
                     // If the operation was aborted, it means that the operation was
                     // cancelled due to another operation being assigned to the worker.
-                    return Ok(());
+                    return Ok(false);
                 }
                 // Any other error is a real error.
                 return Err(err);
@@ -248,10 +598,27 @@ impl SimpleScheduler {
                     .await
                     .err_tip(|| {
                         "Failed to run worker_notify_run_action in SimpleScheduler::do_try_match"
-                    })
+                    })?;
             }
+            Ok(true)
         }
 
+        async fn get_action_info(
+            action_state_result: &dyn ActionStateResult,
+        ) -> Result<Arc<ActionInfo>, Error> {
+            action_state_result
+                .as_action_info()
+                .await
+                .err_tip(|| "Failed to get action_info from as_action_info_result stream")
+        }
+
+        let now = (self.now_fn)();
+        let recent_worker_properties = self
+            .recent_worker_properties(now)
+            .await
+            .err_tip(|| "Failed to get worker properties in do_try_match")?;
+        let recent_worker_properties = recent_worker_properties.as_deref();
+
         let mut result = Ok(());
 
         let mut stream = self
@@ -259,16 +626,86 @@ impl SimpleScheduler {
             .await
             .err_tip(|| "Failed to get queued operations in do_try_match")?;
 
+        let Some(fair_share) = &self.fair_share else {
+            while let Some(action_state_result) = stream.next().await {
+                let action_info = match get_action_info(action_state_result.as_ref()).await {
+                    Ok(action_info) => action_info,
+                    Err(err) => {
+                        result = result.merge(Err(err));
+                        continue;
+                    }
+                };
+                let match_result = match self
+                    .fail_if_unrunnable(
+                        action_state_result.as_ref(),
+                        &action_info,
+                        now,
+                        recent_worker_properties,
+                    )
+                    .await
+                {
+                    Ok(true) => Ok(()),
+                    Ok(false) => match_action_to_worker(
+                        action_state_result.as_ref(),
+                        action_info,
+                        self.worker_scheduler.as_ref(),
+                        self.matching_engine_state_manager.as_ref(),
+                        self.platform_property_manager.as_ref(),
+                    )
+                    .await
+                    .map(|_| ()),
+                    Err(err) => Err(err),
+                };
+                result = result.merge(match_result);
+            }
+            return result;
+        };
+
+        // Group the whole queue first, so we can alternate between groups
+        // while still honoring priority and insert time inside each group.
+        let mut groups = FairShareGroups::default();
         while let Some(action_state_result) = stream.next().await {
-            result = result.merge(
-                match_action_to_worker(
+            let action_info = match get_action_info(action_state_result.as_ref()).await {
+                Ok(action_info) => action_info,
+                Err(err) => {
+                    result = result.merge(Err(err));
+                    continue;
+                }
+            };
+            match self
+                .fail_if_unrunnable(
                     action_state_result.as_ref(),
-                    self.worker_scheduler.as_ref(),
-                    self.matching_engine_state_manager.as_ref(),
-                    self.platform_property_manager.as_ref(),
+                    &action_info,
+                    now,
+                    recent_worker_properties,
                 )
-                .await,
-            );
+                .await
+            {
+                Ok(true) => {}
+                Ok(false) => groups.push(
+                    fair_share.key_for(&action_info),
+                    (action_state_result, action_info),
+                ),
+                Err(err) => result = result.merge(Err(err)),
+            }
+        }
+        drop(stream);
+
+        fair_share.start_pass(&groups);
+        while let Some((key, (action_state_result, action_info))) = fair_share.next(&mut groups) {
+            match match_action_to_worker(
+                action_state_result.as_ref(),
+                action_info,
+                self.worker_scheduler.as_ref(),
+                self.matching_engine_state_manager.as_ref(),
+                self.platform_property_manager.as_ref(),
+            )
+            .await
+            {
+                Ok(true) => fair_share.charge(&key),
+                Ok(false) => {}
+                Err(err) => result = result.merge(Err(err)),
+            }
         }
         result
     }
@@ -280,7 +717,18 @@ impl SimpleScheduler {
         awaited_action_db: A,
         task_change_notify: Arc<Notify>,
     ) -> (Arc<Self>, Arc<dyn WorkerScheduler>) {
-        Self::new_with_callback(
+        Self::new_with_leader_election(scheduler_cfg, awaited_action_db, task_change_notify, None)
+    }
+
+    /// Same as `new`, but only matches actions and accepts workers while
+    /// `leader_election` says this scheduler is the leader.
+    pub fn new_with_leader_election<A: AwaitedActionDb>(
+        scheduler_cfg: &nativelink_config::schedulers::SimpleScheduler,
+        awaited_action_db: A,
+        task_change_notify: Arc<Notify>,
+        leader_election: Option<Arc<LeaderElection>>,
+    ) -> (Arc<Self>, Arc<dyn WorkerScheduler>) {
+        Self::new_inner(
             scheduler_cfg,
             awaited_action_db,
             || {
@@ -296,6 +744,7 @@ impl SimpleScheduler {
             },
             task_change_notify,
             SystemTime::now,
+            leader_election,
         )
     }
 
@@ -311,6 +760,30 @@ impl SimpleScheduler {
         on_matching_engine_run: F,
         task_change_notify: Arc<Notify>,
         now_fn: NowFn,
+    ) -> (Arc<Self>, Arc<dyn WorkerScheduler>) {
+        Self::new_inner(
+            scheduler_cfg,
+            awaited_action_db,
+            on_matching_engine_run,
+            task_change_notify,
+            now_fn,
+            None,
+        )
+    }
+
+    fn new_inner<
+        Fut: Future<Output = ()> + Send,
+        F: Fn() -> Fut + Send + Sync + 'static,
+        A: AwaitedActionDb,
+        I: InstantWrapper,
+        NowFn: Fn() -> I + Clone + Send + Unpin + Sync + 'static,
+    >(
+        scheduler_cfg: &nativelink_config::schedulers::SimpleScheduler,
+        awaited_action_db: A,
+        on_matching_engine_run: F,
+        task_change_notify: Arc<Notify>,
+        now_fn: NowFn,
+        leader_election: Option<Arc<LeaderElection>>,
     ) -> (Arc<Self>, Arc<dyn WorkerScheduler>) {
         let platform_property_manager = Arc::new(PlatformPropertyManager::new(
             scheduler_cfg
@@ -329,7 +802,25 @@ impl SimpleScheduler {
             max_job_retries = DEFAULT_MAX_JOB_RETRIES;
         }
 
+        let max_queued_time = Duration::from_secs(scheduler_cfg.max_queued_time_s);
+        let unmatchable_action_timeout =
+            Duration::from_secs(scheduler_cfg.unmatchable_action_timeout_s);
+        // Queued and running actions have to be checked even if nothing else
+        // happens.
+        let periodic_match_interval = (!max_queued_time.is_zero()
+            || !unmatchable_action_timeout.is_zero()
+            || scheduler_cfg.speculative_execution.is_some())
+        .then_some(PERIODIC_MATCH_INTERVAL);
+
         let worker_change_notify = Arc::new(Notify::new());
+        let scheduler_now_fn = {
+            let now_fn = now_fn.clone();
+            Box::new(move || now_fn().now())
+        };
+        let speculative_execution = scheduler_cfg.speculative_execution.as_ref().map(|config| {
+            let now_fn = now_fn.clone();
+            SpeculativeExecution::new(config, Box::new(move || now_fn().now()))
+        });
         let state_manager = SimpleSchedulerStateManager::new(
             max_job_retries,
             Duration::from_secs(worker_timeout_s),
@@ -343,10 +834,46 @@ impl SimpleScheduler {
             scheduler_cfg.allocation_strategy,
             worker_change_notify.clone(),
             worker_timeout_s,
+            leader_election.clone(),
+            scheduler_cfg.worker_quarantine.clone(),
+            speculative_execution,
         );
 
         let worker_scheduler_clone = worker_scheduler.clone();
 
+        let fair_share = scheduler_cfg.fair_share.clone().map(FairShare::new);
+
+        let leader_election_spawn = leader_election.as_ref().map(|leader_election| {
+            let mut is_leader = leader_election.subscribe();
+            let weak_worker_scheduler = Arc::downgrade(&worker_scheduler);
+            let task_change_notify = task_change_notify.clone();
+            spawn!("simple_scheduler_leader_election", async move {
+                while is_leader.changed().await.is_ok() {
+                    let Some(worker_scheduler) = weak_worker_scheduler.upgrade() else {
+                        return;
+                    };
+                    if *is_leader.borrow_and_update() {
+                        // Pick up the actions queued while we were a standby.
+                        task_change_notify.notify_one();
+                        continue;
+                    }
+                    // Another scheduler may be the leader now, so our workers
+                    // must reconnect to it. Their actions are requeued.
+                    let err = make_err!(
+                        Code::Unavailable,
+                        "Scheduler is no longer the leader, disconnecting workers"
+                    );
+                    if let Err(err) = worker_scheduler.remove_all_workers(err).await {
+                        event!(
+                            Level::ERROR,
+                            ?err,
+                            "Error while removing workers after losing leadership"
+                        );
+                    }
+                }
+            })
+        });
+
         let action_scheduler = Arc::new_cyclic(move |weak_self| -> Self {
             let weak_inner = weak_self.clone();
             let task_worker_matching_spawn =
@@ -355,10 +882,21 @@ impl SimpleScheduler {
                     loop {
                         let task_change_fut = task_change_notify.notified();
                         let worker_change_fut = worker_change_notify.notified();
+                        let periodic_match_fut = async {
+                            match periodic_match_interval {
+                                Some(interval) => tokio::time::sleep(interval).await,
+                                None => futures::future::pending().await,
+                            }
+                        };
                         tokio::pin!(task_change_fut);
                         tokio::pin!(worker_change_fut);
-                        // Wait for either of these futures to be ready.
-                        let _ = futures::future::select(task_change_fut, worker_change_fut).await;
+                        tokio::pin!(periodic_match_fut);
+                        // Wait for any of these futures to be ready.
+                        let _ = futures::future::select(
+                            futures::future::select(task_change_fut, worker_change_fut),
+                            periodic_match_fut,
+                        )
+                        .await;
                         let result = match weak_inner.upgrade() {
                             Some(scheduler) => scheduler.do_try_match().await,
                             // If the inner went away it means the scheduler is shutting
@@ -378,7 +916,18 @@ impl SimpleScheduler {
                 client_state_manager: state_manager.clone(),
                 worker_scheduler,
                 platform_property_manager,
+                fair_share,
+                leader_election,
+                max_queued_time,
+                unmatchable_action_timeout,
+                max_queued_actions: scheduler_cfg.max_queued_actions,
+                max_queued_actions_per_instance: scheduler_cfg.max_queued_actions_per_instance,
+                queue_lengths: Mutex::new(QueueLengths::default()),
+                actions_added_by_mnemonic: Mutex::new(HashMap::new()),
+                worker_history: Mutex::new(WorkerHistory::default()),
+                now_fn: scheduler_now_fn,
                 _task_worker_matching_spawn: task_worker_matching_spawn,
+                _leader_election_spawn: leader_election_spawn,
             }
         });
         (action_scheduler, worker_scheduler_clone)
@@ -467,6 +1016,14 @@ impl WorkerScheduler for SimpleScheduler {
             .set_drain_worker(worker_id, is_draining)
             .await
     }
+
+    async fn unquarantine_worker(&self, worker_id: &WorkerId) -> Result<(), Error> {
+        self.worker_scheduler.unquarantine_worker(worker_id).await
+    }
+
+    async fn get_worker_capacity(&self) -> Result<Vec<WorkerCapacity>, Error> {
+        self.worker_scheduler.get_worker_capacity().await
+    }
 }
 
 impl RootMetricsComponent for SimpleScheduler {}
@@ -178,6 +178,10 @@ where
     async fn as_action_info(&self) -> Result<Arc<ActionInfo>, Error> {
         self.inner.as_action_info().await
     }
+
+    async fn as_worker_id(&self) -> Result<Option<WorkerId>, Error> {
+        self.inner.as_worker_id().await
+    }
 }
 
 struct MatchingEngineActionStateResult<U, T, I, NowFn>
@@ -299,6 +303,15 @@ where
             .action_info()
             .clone())
     }
+
+    async fn as_worker_id(&self) -> Result<Option<WorkerId>, Error> {
+        Ok(self
+            .awaited_action_sub
+            .borrow()
+            .await
+            .err_tip(|| "In MatchingEngineActionStateResult::as_worker_id")?
+            .worker_id())
+    }
 }
 
 /// SimpleSchedulerStateManager is responsible for maintaining the state of the scheduler.
@@ -429,6 +442,7 @@ where
         operation_id: &OperationId,
         maybe_worker_id: Option<&WorkerId>,
         update: UpdateOperationType,
+        only_if_queued: bool,
     ) -> Result<(), Error> {
         let mut last_err = None;
         for _ in 0..MAX_UPDATE_RETRIES {
@@ -486,6 +500,12 @@ where
                 ));
             }
 
+            // The operation might have been assigned to a worker since the
+            // caller saw it queued, and then must be left to that worker.
+            if only_if_queued && !matches!(awaited_action.state().stage, ActionStage::Queued) {
+                return Ok(());
+            }
+
             let stage = match &update {
                 UpdateOperationType::KeepAlive => {
                     awaited_action.keep_alive((self.now_fn)().now());
@@ -776,7 +796,7 @@ where
         worker_id: &WorkerId,
         update: UpdateOperationType,
     ) -> Result<(), Error> {
-        self.inner_update_operation(operation_id, Some(worker_id), update)
+        self.inner_update_operation(operation_id, Some(worker_id), update, false)
             .await
     }
 }
@@ -815,7 +835,20 @@ where
             ),
             Err(err) => (None, UpdateOperationType::UpdateWithError(err)),
         };
-        self.inner_update_operation(operation_id, maybe_worker_id, update)
+        self.inner_update_operation(operation_id, maybe_worker_id, update, false)
             .await
     }
+
+    async fn fail_operation(&self, operation_id: &OperationId, err: Error) -> Result<(), Error> {
+        self.inner_update_operation(
+            operation_id,
+            None,
+            UpdateOperationType::UpdateWithActionStage(ActionStage::Completed(ActionResult {
+                error: Some(err),
+                ..ActionResult::default()
+            })),
+            true,
+        )
+        .await
+    }
 }
@@ -0,0 +1,282 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, VecDeque};
+use std::num::NonZeroUsize;
+use std::time::{Duration, SystemTime};
+
+use lru::LruCache;
+use nativelink_config::schedulers::SpeculativeExecutionConfig;
+use nativelink_metric::MetricsComponent;
+use nativelink_util::action_messages::{ActionInfo, OperationId, WorkerId};
+use nativelink_util::metrics_utils::CounterWithTime;
+
+/// Default multiple of the typical duration after which an action is copied.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_DURATION_MULTIPLIER: f64 = 2.0;
+
+/// Default typical duration under which actions are never copied.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_MIN_DURATION_S: u64 = 10;
+
+/// Default number of successful runs of a kind of action seen before it is
+/// copied.
+/// If this changes, remember to change the documentation in the config.
+const DEFAULT_MIN_SAMPLES: usize = 5;
+
+/// Number of recent durations kept for each kind of action.
+const MAX_DURATION_SAMPLES: usize = 20;
+
+/// Maximum number of kinds of actions whose durations are remembered.
+const MAX_TRACKED_ACTION_KINDS: usize = 10_000;
+
+/// What to do with an update a worker sent for an operation.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum CopyUpdate {
+    /// Report the update to the state manager as coming from this worker,
+    /// the one the operation is assigned to.
+    Report(WorkerId),
+    /// The copy failed while another one is still running, so only the
+    /// resources of the worker are freed.
+    Drop,
+    /// The copy lost and was killed, the update is ignored.
+    Ignore,
+}
+
+/// Workers running the same operation.
+struct Copies {
+    /// Worker the state manager has the operation assigned to. Updates of
+    /// the other copies are reported as coming from it.
+    assigned_worker_id: WorkerId,
+    /// Workers still running a copy.
+    running: Vec<WorkerId>,
+    /// Workers that were told to kill their copy and did not report back.
+    killed: Vec<WorkerId>,
+}
+
+/// Keeps track of how long actions usually take and of the workers running
+/// copies of the actions that take longer than that.
+#[derive(MetricsComponent)]
+pub struct SpeculativeExecution {
+    #[metric(help = "Multiple of the typical duration after which an action is copied.")]
+    duration_multiplier: f64,
+
+    #[metric(help = "Actions that typically finish faster than this are never copied.")]
+    min_duration: Duration,
+
+    #[metric(help = "Number of successful runs seen before a kind of action is copied.")]
+    min_samples: usize,
+
+    /// Returns the current time.
+    now_fn: Box<dyn Fn() -> SystemTime + Send + Sync>,
+
+    /// Durations of the recent successful runs, by kind of action.
+    durations: LruCache<String, VecDeque<Duration>>,
+
+    /// When each running operation was handed to its first worker.
+    started_at: HashMap<OperationId, SystemTime>,
+
+    /// Operations that run or ran on more than one worker.
+    copies: HashMap<OperationId, Copies>,
+
+    #[metric(help = "The number of copies of slow actions started on another worker.")]
+    copies_started: CounterWithTime,
+
+    #[metric(help = "The number of actions whose copy finished first.")]
+    copies_won: CounterWithTime,
+}
+
+impl SpeculativeExecution {
+    pub fn new(
+        config: &SpeculativeExecutionConfig,
+        now_fn: Box<dyn Fn() -> SystemTime + Send + Sync>,
+    ) -> Self {
+        let mut duration_multiplier = f64::from(config.duration_multiplier);
+        if duration_multiplier <= 0.0 {
+            duration_multiplier = DEFAULT_DURATION_MULTIPLIER;
+        }
+        let mut min_duration_s = config.min_duration_s;
+        if min_duration_s == 0 {
+            min_duration_s = DEFAULT_MIN_DURATION_S;
+        }
+        let mut min_samples = config.min_samples;
+        if min_samples == 0 {
+            min_samples = DEFAULT_MIN_SAMPLES;
+        }
+        Self {
+            duration_multiplier,
+            min_duration: Duration::from_secs(min_duration_s),
+            min_samples,
+            now_fn,
+            durations: LruCache::new(NonZeroUsize::new(MAX_TRACKED_ACTION_KINDS).unwrap()),
+            started_at: HashMap::new(),
+            copies: HashMap::new(),
+            copies_started: CounterWithTime::default(),
+            copies_won: CounterWithTime::default(),
+        }
+    }
+
+    /// Actions of the same kind are expected to take about as long.
+    fn action_kind(action_info: &ActionInfo) -> String {
+        match &action_info.request_metadata {
+            Some(request_metadata) if !request_metadata.action_mnemonic.is_empty() => {
+                format!("mnemonic:{}", request_metadata.action_mnemonic)
+            }
+            _ => format!("digest:{}", action_info.digest()),
+        }
+    }
+
+    pub(crate) fn now(&self) -> SystemTime {
+        (self.now_fn)()
+    }
+
+    /// Records that the operation was handed to a worker. Starting a copy
+    /// does not reset the time the operation started at.
+    pub(crate) fn operation_started(&mut self, operation_id: &OperationId) {
+        let now = self.now();
+        self.started_at.entry(operation_id.clone()).or_insert(now);
+    }
+
+    /// Returns true if the operation has no copy yet and has been running
+    /// for long enough that it should get one.
+    pub(crate) fn is_straggler(
+        &self,
+        operation_id: &OperationId,
+        action_info: &ActionInfo,
+        now: SystemTime,
+    ) -> bool {
+        if self.copies.contains_key(operation_id) {
+            return false;
+        }
+        let Some(started_at) = self.started_at.get(operation_id) else {
+            return false;
+        };
+        let Some(durations) = self.durations.peek(&Self::action_kind(action_info)) else {
+            return false;
+        };
+        if durations.len() < self.min_samples {
+            return false;
+        }
+        let mut durations: Vec<Duration> = durations.iter().copied().collect();
+        durations.sort_unstable();
+        let typical_duration = durations[durations.len() / 2];
+        if typical_duration < self.min_duration {
+            return false;
+        }
+        now.duration_since(*started_at).unwrap_or_default()
+            > typical_duration.mul_f64(self.duration_multiplier)
+    }
+
+    /// Records that a copy of the operation assigned to `assigned_worker_id`
+    /// is being started on `copy_worker_id`.
+    pub(crate) fn copy_started(
+        &mut self,
+        operation_id: &OperationId,
+        assigned_worker_id: WorkerId,
+        copy_worker_id: WorkerId,
+    ) {
+        self.copies_started.inc();
+        self.copies.insert(
+            operation_id.clone(),
+            Copies {
+                assigned_worker_id,
+                running: vec![assigned_worker_id, copy_worker_id],
+                killed: Vec::new(),
+            },
+        );
+    }
+
+    /// Returns the worker the keep alive of the operation sent by
+    /// `worker_id` should be reported as coming from, if any.
+    pub(crate) fn keep_alive_worker_id(
+        &self,
+        operation_id: &OperationId,
+        worker_id: &WorkerId,
+    ) -> Option<WorkerId> {
+        match self.copies.get(operation_id) {
+            None => Some(*worker_id),
+            Some(copies) if copies.running.contains(worker_id) => Some(copies.assigned_worker_id),
+            Some(_) => None,
+        }
+    }
+
+    /// Decides what to do with an update of the operation sent by the
+    /// worker. `is_finished` and `failed` describe the update.
+    pub(crate) fn route_update(
+        &mut self,
+        operation_id: &OperationId,
+        worker_id: &WorkerId,
+        is_finished: bool,
+        failed: bool,
+    ) -> CopyUpdate {
+        let Some(copies) = self.copies.get_mut(operation_id) else {
+            return CopyUpdate::Report(*worker_id);
+        };
+        if let Some(position) = copies.killed.iter().position(|id| id == worker_id) {
+            if is_finished {
+                copies.killed.swap_remove(position);
+                if copies.killed.is_empty() && copies.running.is_empty() {
+                    self.copies.remove(operation_id);
+                }
+            }
+            return CopyUpdate::Ignore;
+        }
+        if !copies.running.contains(worker_id) {
+            return CopyUpdate::Report(*worker_id);
+        }
+        // The operation only fails once every copy failed.
+        if is_finished && failed && copies.running.len() > 1 {
+            copies.running.retain(|id| id != worker_id);
+            return CopyUpdate::Drop;
+        }
+        CopyUpdate::Report(copies.assigned_worker_id)
+    }
+
+    /// Records that the last update of the operation sent by the worker was
+    /// reported. Returns the workers running the other copies, which should
+    /// be killed.
+    pub(crate) fn operation_finished(
+        &mut self,
+        operation_id: &OperationId,
+        worker_id: &WorkerId,
+        action_info: &ActionInfo,
+        succeeded: bool,
+    ) -> Vec<WorkerId> {
+        let started_at = self.started_at.remove(operation_id);
+        let Some(mut copies) = self.copies.remove(operation_id) else {
+            // Runs that needed a copy would skew the typical duration.
+            if let (true, Some(started_at)) = (succeeded, started_at) {
+                let duration = self.now().duration_since(started_at).unwrap_or_default();
+                let durations = self
+                    .durations
+                    .get_or_insert_mut(Self::action_kind(action_info), VecDeque::new);
+                durations.push_back(duration);
+                while durations.len() > MAX_DURATION_SAMPLES {
+                    durations.pop_front();
+                }
+            }
+            return Vec::new();
+        };
+        if succeeded && *worker_id != copies.assigned_worker_id {
+            self.copies_won.inc();
+        }
+        copies.running.retain(|id| id != worker_id);
+        let losers = copies.running.clone();
+        copies.killed.append(&mut copies.running);
+        if !copies.killed.is_empty() {
+            self.copies.insert(operation_id.clone(), copies);
+        }
+        losers
+    }
+}
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -20,7 +20,7 @@ use std::time::{SystemTime, UNIX_EPOCH};
 use nativelink_error::{make_err, Code, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
 use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::{
-    update_for_worker, ConnectionResult, StartExecute, UpdateForWorker,
+    update_for_worker, ConnectionResult, KillOperationRequest, StartExecute, UpdateForWorker,
 };
 use nativelink_util::action_messages::{ActionInfo, OperationId, WorkerId};
 use nativelink_util::metrics_utils::{CounterWithTime, FuncCounterWrapper};
@@ -50,6 +50,9 @@ pub enum WorkerUpdate {
 
     /// Request that the worker is no longer in the pool and may discard any jobs.
     Disconnect,
+
+    /// Requests that the worker stops running this operation.
+    KillOperation(OperationId),
 }
 
 /// Represents a connection to a worker and used as the medium to
@@ -85,6 +88,14 @@ pub struct Worker {
     #[metric(help = "If the worker is draining.")]
     pub is_draining: bool,
 
+    /// Whether the worker gets no new actions because it failed too many.
+    #[metric(help = "If the worker is quarantined for failing too many actions.")]
+    pub is_quarantined: bool,
+
+    /// If each of the most recently finished actions failed with an internal
+    /// error, oldest first.
+    recent_action_failures: VecDeque<bool>,
+
     /// Stats about the worker.
     #[metric]
     metrics: Arc<Metrics>,
@@ -118,6 +129,23 @@ fn reduce_platform_properties(
     }
 }
 
+/// Gives back the platform properties taken by `reduce_platform_properties`.
+fn restore_platform_properties(
+    parent_props: &mut PlatformProperties,
+    reduction_props: &PlatformProperties,
+) {
+    for (property, prop_value) in &reduction_props.properties {
+        if let PlatformPropertyValue::Minimum(value) = prop_value {
+            let worker_props = &mut parent_props.properties;
+            if let PlatformPropertyValue::Minimum(worker_value) =
+                worker_props.get_mut(property).unwrap()
+            {
+                *worker_value += value;
+            }
+        }
+    }
+}
+
 impl Worker {
     pub fn new(
         id: WorkerId,
@@ -133,12 +161,15 @@ impl Worker {
             last_update_timestamp: timestamp,
             is_paused: false,
             is_draining: false,
+            is_quarantined: false,
+            recent_action_failures: VecDeque::new(),
             metrics: Arc::new(Metrics {
                 connected_timestamp: SystemTime::now()
                     .duration_since(UNIX_EPOCH)
                     .unwrap()
                     .as_secs(),
                 actions_completed: CounterWithTime::default(),
+                actions_failed: CounterWithTime::default(),
                 run_action: FuncCounterWrapper::default(),
                 keep_alive: FuncCounterWrapper::default(),
                 notify_disconnect: CounterWithTime::default(),
@@ -168,6 +199,12 @@ impl Worker {
                 self.metrics.notify_disconnect.inc();
                 send_msg_to_worker(&mut self.tx, update_for_worker::Update::Disconnect(()))
             }
+            WorkerUpdate::KillOperation(operation_id) => send_msg_to_worker(
+                &mut self.tx,
+                update_for_worker::Update::KillOperationRequest(KillOperationRequest {
+                    operation_id: operation_id.to_string(),
+                }),
+            ),
         }
     }
 
@@ -202,6 +239,8 @@ impl Worker {
                     execute_request: Some(action_info_clone.inner.as_ref().into()),
                     operation_id: operation_id_string,
                     queued_timestamp: Some(action_info.inner.insert_timestamp.into()),
+                    request_metadata: action_info.inner.request_metadata.clone().map(Into::into),
+                    skip_action_cache_upload: action_info.inner.skip_action_cache_upload,
                 }),
             )
         })
@@ -214,31 +253,52 @@ impl Worker {
                 self.id, operation_id
             )
         })?;
-        self.restore_platform_properties(&action_info.platform_properties);
+        restore_platform_properties(
+            &mut self.platform_properties,
+            &action_info.platform_properties,
+        );
         self.is_paused = false;
         self.metrics.actions_completed.inc();
         Ok(())
     }
 
+    /// Records if a finished action failed with an internal error and returns
+    /// how many of the last `window` finished actions failed.
+    pub(crate) fn record_action_outcome(&mut self, failed: bool, window: usize) -> usize {
+        if failed {
+            self.metrics.actions_failed.inc();
+        }
+        self.recent_action_failures.push_back(failed);
+        while self.recent_action_failures.len() > window {
+            self.recent_action_failures.pop_front();
+        }
+        self.recent_action_failures
+            .iter()
+            .filter(|failed| **failed)
+            .count()
+    }
+
+    /// Forgets the failures recorded by `record_action_outcome`.
+    pub(crate) fn clear_action_outcomes(&mut self) {
+        self.recent_action_failures.clear();
+    }
+
     pub fn has_actions(&self) -> bool {
         !self.running_action_infos.is_empty()
     }
 
-    fn restore_platform_properties(&mut self, props: &PlatformProperties) {
-        for (property, prop_value) in &props.properties {
-            if let PlatformPropertyValue::Minimum(value) = prop_value {
-                let worker_props = &mut self.platform_properties.properties;
-                if let PlatformPropertyValue::Minimum(worker_value) =
-                    worker_props.get_mut(property).unwrap()
-                {
-                    *worker_value += value;
-                }
-            }
+    /// Returns the platform properties of the worker as if it was not
+    /// running any actions.
+    pub fn unreduced_platform_properties(&self) -> PlatformProperties {
+        let mut platform_properties = self.platform_properties.clone();
+        for action_info in self.running_action_infos.values() {
+            restore_platform_properties(&mut platform_properties, &action_info.platform_properties);
         }
+        platform_properties
     }
 
     pub fn can_accept_work(&self) -> bool {
-        !self.is_paused && !self.is_draining
+        !self.is_paused && !self.is_draining && !self.is_quarantined
     }
 }
 
@@ -262,6 +322,8 @@ struct Metrics {
     connected_timestamp: u64,
     #[metric(help = "The number of actions completed for this worker.")]
     actions_completed: CounterWithTime,
+    #[metric(help = "The number of actions that failed with an internal error on this worker.")]
+    actions_failed: CounterWithTime,
     #[metric(help = "The number of actions started for this worker.")]
     run_action: FuncCounterWrapper,
     #[metric(help = "The number of keep_alive sent to this worker.")]
@@ -18,6 +18,7 @@ use nativelink_metric::RootMetricsComponent;
 use nativelink_util::action_messages::{OperationId, WorkerId};
 use nativelink_util::operation_state_manager::UpdateOperationType;
 
+use crate::autoscaling::WorkerCapacity;
 use crate::platform_property_manager::PlatformPropertyManager;
 use crate::worker::{Worker, WorkerTimestamp};
 
@@ -55,4 +56,12 @@ pub trait WorkerScheduler: Sync + Send + Unpin + RootMetricsComponent + 'static
 
     /// Sets if the worker is draining or not.
     async fn set_drain_worker(&self, worker_id: &WorkerId, is_draining: bool) -> Result<(), Error>;
+
+    /// Puts a worker that was quarantined for failing too many actions back
+    /// into service.
+    async fn unquarantine_worker(&self, worker_id: &WorkerId) -> Result<(), Error>;
+
+    /// Returns the platform properties of every connected worker and if it
+    /// is idle. Used to compute autoscaling signals.
+    async fn get_worker_capacity(&self) -> Result<Vec<WorkerCapacity>, Error>;
 }
@@ -21,15 +21,21 @@ mod utils {
 }
 
 use futures::join;
+use nativelink_config::schedulers::CacheHitVerificationConfig;
 use nativelink_error::Error;
 use nativelink_macro::nativelink_test;
 use nativelink_proto::build::bazel::remote::execution::v2::ActionResult as ProtoActionResult;
+use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::NonHermeticActionResult;
+use nativelink_scheduler::cache_hit_verifier::CacheHitVerifier;
 use nativelink_scheduler::cache_lookup_scheduler::CacheLookupScheduler;
+use nativelink_store::ac_utils::{compute_buf_digest, get_and_decode_digest};
 use nativelink_store::memory_store::MemoryStore;
 use nativelink_util::action_messages::{
-    ActionResult, ActionStage, ActionState, ActionUniqueQualifier, OperationId,
+    ActionResult, ActionStage, ActionState, ActionUniqueQualifier, FileInfo, NameOrPath,
+    OperationId,
 };
 use nativelink_util::common::DigestInfo;
+use nativelink_util::digest_hasher::DigestHasherFunc;
 use nativelink_util::operation_state_manager::{ClientStateManager, OperationFilter};
 use nativelink_util::store_trait::{Store, StoreLike};
 use pretty_assertions::assert_eq;
@@ -51,7 +57,8 @@ fn make_cache_scheduler() -> Result<TestContext, Error> {
     let ac_store = Store::new(MemoryStore::new(
         &nativelink_config::stores::MemoryStore::default(),
     ));
-    let cache_scheduler = CacheLookupScheduler::new(ac_store.clone(), mock_scheduler.clone())?;
+    let cache_scheduler =
+        CacheLookupScheduler::new(ac_store.clone(), mock_scheduler.clone(), None)?;
     Ok(TestContext {
         mock_scheduler,
         ac_store,
@@ -119,3 +126,95 @@ async fn find_by_client_operation_id_call_passed() -> Result<(), Error> {
     );
     Ok(())
 }
+
+#[nativelink_test]
+async fn cache_hit_verification_records_mismatch_and_evicts() -> Result<(), Error> {
+    const ACTION_DIGEST: DigestInfo = DigestInfo::new([9u8; 32], 100);
+    let mock_scheduler = Arc::new(MockActionScheduler::new());
+    let ac_store = Store::new(MemoryStore::new(
+        &nativelink_config::stores::MemoryStore::default(),
+    ));
+    let mismatch_store = Store::new(MemoryStore::new(
+        &nativelink_config::stores::MemoryStore::default(),
+    ));
+    let cache_hit_verifier = Arc::new(CacheHitVerifier::new(
+        CacheHitVerificationConfig {
+            sample_rate: 1.0,
+            mismatch_store: "mismatch_store".to_string(),
+            priority: -5,
+            max_concurrent_verifications: 0,
+            evict_on_mismatch: true,
+        },
+        ac_store.clone(),
+        mismatch_store.clone(),
+        mock_scheduler.clone(),
+    ));
+    let cache_scheduler = CacheLookupScheduler::new(
+        ac_store.clone(),
+        mock_scheduler.clone(),
+        Some(cache_hit_verifier),
+    )?;
+
+    let action_info = make_base_action_info(UNIX_EPOCH, ACTION_DIGEST);
+    let make_result = |output_digest: DigestInfo| ActionResult {
+        output_files: vec![FileInfo {
+            name_or_path: NameOrPath::Path("out.txt".to_string()),
+            digest: output_digest,
+            is_executable: false,
+        }],
+        ..Default::default()
+    };
+    let cached_result = ProtoActionResult::from(make_result(DigestInfo::new([1u8; 32], 5)));
+    let rerun_result = make_result(DigestInfo::new([2u8; 32], 5));
+    ac_store
+        .update_oneshot(action_info.digest(), cached_result.encode_to_vec().into())
+        .await?;
+
+    let action_state_result = cache_scheduler
+        .add_action(OperationId::default(), action_info.clone())
+        .await?;
+    assert_eq!(
+        ActionStage::CompletedFromCache(cached_result.clone()),
+        action_state_result.as_state().await?.stage
+    );
+
+    // The cache hit is executed again with a low priority, skipping the cache.
+    let (rerun_watch_tx, rerun_watch_rx) = watch::channel(Arc::new(ActionState {
+        client_operation_id: OperationId::default(),
+        stage: ActionStage::Completed(rerun_result.clone()),
+        action_digest: ACTION_DIGEST,
+    }));
+    let (_, rerun_info) = mock_scheduler
+        .expect_add_action(Ok(Box::new(TokioWatchActionStateResult::new(
+            OperationId::default(),
+            action_info.clone(),
+            rerun_watch_rx,
+        ))))
+        .await;
+    assert!(matches!(
+        rerun_info.unique_qualifier,
+        ActionUniqueQualifier::Uncachable(_)
+    ));
+    assert_eq!(-5, rerun_info.priority);
+
+    // The outputs differ, so the entry is removed from the action cache.
+    while ac_store.has(ACTION_DIGEST).await?.is_some() {
+        tokio::task::yield_now().await;
+    }
+    drop(rerun_watch_tx);
+
+    let expected_record = NonHermeticActionResult {
+        action_digest: Some(ACTION_DIGEST.into()),
+        cached_result: Some(cached_result),
+        rerun_result: Some(rerun_result.into()),
+    };
+    let record_digest = compute_buf_digest(
+        &expected_record.encode_to_vec(),
+        &mut DigestHasherFunc::Sha256.hasher(),
+    );
+    let record =
+        get_and_decode_digest::<NonHermeticActionResult>(&mismatch_store, record_digest.into())
+            .await?;
+    assert_eq!(expected_record, record);
+    Ok(())
+}
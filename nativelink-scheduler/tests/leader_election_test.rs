@@ -0,0 +1,124 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(feature = "sql")]
+
+use std::future::pending;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use nativelink_config::schedulers::{ExperimentalSqlSchedulerBackend, LeaderElectionConfig};
+use nativelink_error::{make_err, Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_scheduler::leader_election::LeaderElection;
+use nativelink_store::sql_scheduler_store::SqlSchedulerStore;
+use nativelink_util::store_trait::SchedulerLeaseStore;
+use tokio::time::{sleep, timeout};
+
+async fn wait_for_leadership(leader_election: &LeaderElection) -> Result<(), Error> {
+    let mut is_leader = leader_election.subscribe();
+    timeout(
+        Duration::from_secs(5),
+        is_leader.wait_for(|is_leader| *is_leader),
+    )
+    .await
+    .map_err(|_| make_err!(Code::DeadlineExceeded, "Never became leader"))?
+    .map_err(|_| make_err!(Code::Internal, "Leader election was dropped"))?;
+    Ok(())
+}
+
+#[nativelink_test]
+async fn standby_takes_over_when_leader_is_dropped() -> Result<(), Error> {
+    let store = SqlSchedulerStore::new(&ExperimentalSqlSchedulerBackend {
+        database_url: "sqlite::memory:".to_string(),
+        max_connections: 1,
+        ..Default::default()
+    })?;
+    let config = LeaderElectionConfig {
+        lease_duration_s: 1,
+        ..Default::default()
+    };
+
+    let leader = LeaderElection::new(store.clone(), &config);
+    wait_for_leadership(&leader).await?;
+
+    let standby = LeaderElection::new(store.clone(), &config);
+    // Several renewals happen in this time, the lease must stay with the leader.
+    sleep(Duration::from_millis(1500)).await;
+    assert!(leader.is_leader(), "Leader lost its lease");
+    assert!(
+        !standby.is_leader(),
+        "Standby took the lease of a live leader"
+    );
+
+    drop(leader);
+    wait_for_leadership(&standby).await?;
+    Ok(())
+}
+
+/// Grants every lease until `hang` is set, then never answers again.
+#[derive(Default)]
+struct HangingLeaseStore {
+    hang: AtomicBool,
+}
+
+impl SchedulerLeaseStore for HangingLeaseStore {
+    async fn acquire_or_renew_lease(
+        &self,
+        _name: &str,
+        _holder: &str,
+        _lease_duration: Duration,
+    ) -> Result<bool, Error> {
+        if self.hang.load(Ordering::Acquire) {
+            pending::<()>().await;
+        }
+        Ok(true)
+    }
+
+    async fn release_lease(&self, _name: &str, _holder: &str) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[nativelink_test]
+async fn leader_steps_down_when_store_hangs() -> Result<(), Error> {
+    let store = Arc::new(HangingLeaseStore::default());
+    let config = LeaderElectionConfig {
+        lease_duration_s: 1,
+        ..Default::default()
+    };
+
+    let leader = LeaderElection::new(store.clone(), &config);
+    wait_for_leadership(&leader).await?;
+
+    let hang_start = Instant::now();
+    store.hang.store(true, Ordering::Release);
+    let mut is_leader = leader.subscribe();
+    timeout(
+        Duration::from_secs(5),
+        is_leader.wait_for(|is_leader| !*is_leader),
+    )
+    .await
+    .map_err(|_| make_err!(Code::DeadlineExceeded, "Never stepped down"))?
+    .map_err(|_| make_err!(Code::Internal, "Leader election was dropped"))?;
+    // The last renewal started before the store hung, so the lease it got
+    // expires at most a second later. Allow a little scheduling slack.
+    assert!(
+        hang_start.elapsed() < Duration::from_millis(1100),
+        "Stepped down after the lease expired: {:?}",
+        hang_start.elapsed()
+    );
+    Ok(())
+}
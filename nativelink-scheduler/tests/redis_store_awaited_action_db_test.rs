@@ -165,6 +165,9 @@ async fn add_action_smoke_test() -> Result<(), Error> {
                 digest_function: DigestHasherFunc::Sha256,
                 digest: DigestInfo::zero_digest(),
             }),
+            request_metadata: None,
+            results_cache_priority: 0,
+            skip_action_cache_upload: false,
         }),
         MockSystemTime::now().into(),
     );
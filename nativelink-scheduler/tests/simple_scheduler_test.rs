@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::future::Future;
 use std::ops::Bound;
 use std::pin::Pin;
@@ -24,7 +24,10 @@ use async_lock::Mutex;
 use futures::task::Poll;
 use futures::{poll, Stream, StreamExt};
 use mock_instant::{MockClock, SystemTime as MockSystemTime};
-use nativelink_config::schedulers::PropertyType;
+use nativelink_config::schedulers::{
+    FairShareConfig, FairShareKey, PropertyType, SpeculativeExecutionConfig,
+    WorkerAllocationStrategy, WorkerQuarantineConfig,
+};
 use nativelink_error::{make_err, Code, Error, ResultExt};
 use nativelink_macro::nativelink_test;
 use nativelink_metric::MetricsComponent;
@@ -32,17 +35,23 @@ use nativelink_proto::build::bazel::remote::execution::v2::{digest_function, Exe
 use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::{
     update_for_worker, ConnectionResult, StartExecute, UpdateForWorker,
 };
+use nativelink_scheduler::autoscaling::{
+    autoscaling_signals, AutoscalingSignals, QueuedShapeSignals, WorkerShapeSignals,
+};
 use nativelink_scheduler::awaited_action_db::{
     AwaitedAction, AwaitedActionDb, AwaitedActionSubscriber, SortedAwaitedAction,
     SortedAwaitedActionState,
 };
 use nativelink_scheduler::default_scheduler_factory::memory_awaited_action_db_factory;
+use nativelink_scheduler::operation_summary::{
+    list_operations, operation_details, OperationSummary,
+};
 use nativelink_scheduler::simple_scheduler::SimpleScheduler;
 use nativelink_scheduler::worker::Worker;
 use nativelink_scheduler::worker_scheduler::WorkerScheduler;
 use nativelink_util::action_messages::{
     ActionInfo, ActionResult, ActionStage, ActionState, DirectoryInfo, ExecutionMetadata, FileInfo,
-    NameOrPath, OperationId, SymlinkInfo, WorkerId, INTERNAL_ERROR_EXIT_CODE,
+    NameOrPath, OperationId, RequestMetadataInfo, SymlinkInfo, WorkerId, INTERNAL_ERROR_EXIT_CODE,
 };
 use nativelink_util::common::DigestInfo;
 use nativelink_util::instant_wrapper::MockInstantWrapped;
@@ -192,6 +201,8 @@ async fn basic_add_action_with_one_worker_test() -> Result<(), Error> {
                 }),
                 operation_id: "Unknown Generated internally".to_string(),
                 queued_timestamp: Some(insert_timestamp.into()),
+                request_metadata: None,
+                skip_action_cache_upload: false,
             })),
         };
         let msg_for_worker = rx_from_worker.recv().await.unwrap();
@@ -341,6 +352,8 @@ async fn find_executing_action() -> Result<(), Error> {
                 }),
                 operation_id: "Unknown Generated internally".to_string(),
                 queued_timestamp: Some(insert_timestamp.into()),
+                request_metadata: None,
+                skip_action_cache_upload: false,
             })),
         };
         let msg_for_worker = rx_from_worker.recv().await.unwrap();
@@ -412,6 +425,8 @@ async fn remove_worker_reschedules_multiple_running_job_test() -> Result<(), Err
         }),
         operation_id: "WILL BE SET BELOW".to_string(),
         queued_timestamp: Some(insert_timestamp1.into()),
+        request_metadata: None,
+        skip_action_cache_upload: false,
     };
 
     let mut expected_start_execute_for_worker2 = StartExecute {
@@ -423,6 +438,8 @@ async fn remove_worker_reschedules_multiple_running_job_test() -> Result<(), Err
         }),
         operation_id: "WILL BE SET BELOW".to_string(),
         queued_timestamp: Some(insert_timestamp2.into()),
+        request_metadata: None,
+        skip_action_cache_upload: false,
     };
     let operation_id1 = {
         // Worker1 should now see first execution request.
@@ -692,6 +709,8 @@ async fn worker_should_not_queue_if_properties_dont_match_test() -> Result<(), E
                 }),
                 operation_id: "Unknown Generated internally".to_string(),
                 queued_timestamp: Some(insert_timestamp.into()),
+                request_metadata: None,
+                skip_action_cache_upload: false,
             })),
         };
         let msg_for_worker = rx_from_worker2.recv().await.unwrap();
@@ -784,6 +803,8 @@ async fn cacheable_items_join_same_action_queued_test() -> Result<(), Error> {
                 }),
                 operation_id: "Unknown Generated internally".to_string(),
                 queued_timestamp: Some(insert_timestamp1.into()),
+                request_metadata: None,
+                skip_action_cache_upload: false,
             })),
         };
         let msg_for_worker = rx_from_worker.recv().await.unwrap();
@@ -1116,6 +1137,8 @@ async fn worker_timesout_reschedules_running_job_test() -> Result<(), Error> {
         }),
         operation_id: "UNKNOWN HERE, WE WILL SET IT LATER".to_string(),
         queued_timestamp: Some(insert_timestamp.into()),
+        request_metadata: None,
+        skip_action_cache_upload: false,
     };
 
     {
@@ -1563,6 +1586,8 @@ async fn does_not_crash_if_operation_joined_then_relaunched() -> Result<(), Erro
                 }),
                 operation_id: "Unknown Generated internally".to_string(),
                 queued_timestamp: Some(insert_timestamp.into()),
+                request_metadata: None,
+                skip_action_cache_upload: false,
             })),
         };
         let msg_for_worker = rx_from_worker.recv().await.unwrap();
@@ -1886,6 +1911,178 @@ async fn run_jobs_in_the_order_they_were_queued() -> Result<(), Error> {
     Ok(())
 }
 
+#[nativelink_test]
+async fn fair_share_alternates_between_platform_property_values() -> Result<(), Error> {
+    let mut supported_props = HashMap::new();
+    supported_props.insert("prop1".to_string(), PropertyType::minimum);
+    supported_props.insert("pool".to_string(), PropertyType::priority);
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &nativelink_config::schedulers::SimpleScheduler {
+            supported_platform_properties: Some(supported_props),
+            fair_share: Some(FairShareConfig {
+                key: FairShareKey::platform_property("pool".to_string()),
+                weights: HashMap::new(),
+                default_weight: 1,
+            }),
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+    );
+
+    // Use property to restrict each worker to a single action at a time.
+    let mut properties = HashMap::new();
+    properties.insert("prop1".to_string(), PlatformPropertyValue::Minimum(1));
+    properties.insert(
+        "pool".to_string(),
+        PlatformPropertyValue::Priority("any".to_string()),
+    );
+    let platform_properties = PlatformProperties { properties };
+
+    let add_action = |digest: DigestInfo, pool: &str, insert_timestamp| {
+        let action_props = HashMap::from([
+            ("prop1".to_string(), "1".to_string()),
+            ("pool".to_string(), pool.to_string()),
+        ]);
+        setup_action(&scheduler, digest, action_props, insert_timestamp)
+    };
+    // A big build queues up first, then a small one.
+    let mut ci_action_listener1 =
+        add_action(DigestInfo::new([1u8; 32], 512), "ci", make_system_time(1)).await?;
+    let mut ci_action_listener2 =
+        add_action(DigestInfo::new([2u8; 32], 512), "ci", make_system_time(2)).await?;
+    let mut dev_action_listener =
+        add_action(DigestInfo::new([3u8; 32], 512), "dev", make_system_time(3)).await?;
+
+    let mut rx_from_worker1 = setup_new_worker(
+        &scheduler,
+        WorkerId(Uuid::new_v4()),
+        platform_properties.clone(),
+    )
+    .await?;
+    match rx_from_worker1.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => { /* Success */ }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+    assert_eq!(
+        ci_action_listener1.changed().await.unwrap().stage,
+        ActionStage::Executing
+    );
+
+    // The second worker should go to the other pool, even though the big
+    // build still has an older action queued.
+    let mut rx_from_worker2 =
+        setup_new_worker(&scheduler, WorkerId(Uuid::new_v4()), platform_properties).await?;
+    match rx_from_worker2.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => { /* Success */ }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+    assert_eq!(
+        dev_action_listener.changed().await.unwrap().stage,
+        ActionStage::Executing
+    );
+    assert_eq!(
+        ci_action_listener2.changed().await.unwrap().stage,
+        ActionStage::Queued
+    );
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn fair_share_alternates_between_tool_invocations() -> Result<(), Error> {
+    let mut supported_props = HashMap::new();
+    supported_props.insert("prop1".to_string(), PropertyType::minimum);
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &nativelink_config::schedulers::SimpleScheduler {
+            supported_platform_properties: Some(supported_props),
+            fair_share: Some(FairShareConfig {
+                key: FairShareKey::tool_invocation_id,
+                weights: HashMap::new(),
+                default_weight: 1,
+            }),
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+    );
+
+    // Use property to restrict each worker to a single action at a time.
+    let mut properties = HashMap::new();
+    properties.insert("prop1".to_string(), PlatformPropertyValue::Minimum(1));
+    let action_props: HashMap<String, String> = properties
+        .iter()
+        .map(|(k, v)| (k.clone(), v.as_str().into_owned()))
+        .collect();
+    let platform_properties = PlatformProperties { properties };
+
+    let add_action = |digest: DigestInfo, tool_invocation_id: &str, insert_timestamp| {
+        let mut action_info = make_base_action_info(insert_timestamp, digest);
+        let action_info_mut = Arc::make_mut(&mut action_info);
+        action_info_mut.platform_properties = action_props.clone();
+        action_info_mut.request_metadata = Some(RequestMetadataInfo {
+            tool_invocation_id: tool_invocation_id.to_string(),
+            ..Default::default()
+        });
+        scheduler.add_action(OperationId::default(), action_info)
+    };
+    // A big build queues up first, then a small one.
+    let mut ci_action_listener1 =
+        add_action(DigestInfo::new([1u8; 32], 512), "ci", make_system_time(1)).await?;
+    let mut ci_action_listener2 =
+        add_action(DigestInfo::new([2u8; 32], 512), "ci", make_system_time(2)).await?;
+    let mut dev_action_listener =
+        add_action(DigestInfo::new([3u8; 32], 512), "dev", make_system_time(3)).await?;
+
+    let mut rx_from_worker1 = setup_new_worker(
+        &scheduler,
+        WorkerId(Uuid::new_v4()),
+        platform_properties.clone(),
+    )
+    .await?;
+    match rx_from_worker1.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => { /* Success */ }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+    assert_eq!(
+        ci_action_listener1.changed().await.unwrap().stage,
+        ActionStage::Executing
+    );
+
+    // The second worker should go to the other invocation, even though
+    // the big build still has an older action queued.
+    let mut rx_from_worker2 =
+        setup_new_worker(&scheduler, WorkerId(Uuid::new_v4()), platform_properties).await?;
+    match rx_from_worker2.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => { /* Success */ }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+    assert_eq!(
+        dev_action_listener.changed().await.unwrap().stage,
+        ActionStage::Executing
+    );
+    assert_eq!(
+        ci_action_listener2.changed().await.unwrap().stage,
+        ActionStage::Queued
+    );
+
+    Ok(())
+}
+
 #[nativelink_test]
 async fn worker_retries_on_internal_error_and_fails_test() -> Result<(), Error> {
     let worker_id: WorkerId = WorkerId(Uuid::new_v4());
@@ -2222,3 +2419,880 @@ async fn client_reconnect_keeps_action_alive() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[nativelink_test]
+async fn autoscaling_signals_group_by_platform_properties_test() -> Result<(), Error> {
+    let mut prop_defs = HashMap::new();
+    prop_defs.insert("prop".to_string(), PropertyType::exact);
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, worker_scheduler) = SimpleScheduler::new_with_callback(
+        &nativelink_config::schedulers::SimpleScheduler {
+            supported_platform_properties: Some(prop_defs),
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+    );
+    let mut worker_properties = PlatformProperties::default();
+    worker_properties.properties.insert(
+        "prop".to_string(),
+        PlatformPropertyValue::Exact("2".to_string()),
+    );
+    let _rx_from_worker =
+        setup_new_worker(&scheduler, WorkerId(Uuid::new_v4()), worker_properties).await?;
+
+    // Neither action can run on the worker, so both stay queued.
+    let mut platform_properties = HashMap::new();
+    platform_properties.insert("prop".to_string(), "1".to_string());
+    let _action_listener1 = setup_action(
+        &scheduler,
+        DigestInfo::new([1u8; 32], 512),
+        platform_properties.clone(),
+        make_system_time(1),
+    )
+    .await?;
+    let _action_listener2 = setup_action(
+        &scheduler,
+        DigestInfo::new([2u8; 32], 512),
+        platform_properties,
+        make_system_time(5),
+    )
+    .await?;
+
+    let signals = autoscaling_signals(
+        scheduler.as_ref(),
+        worker_scheduler.as_ref(),
+        make_system_time(11),
+    )
+    .await?;
+    assert_eq!(
+        signals,
+        AutoscalingSignals {
+            queued: vec![QueuedShapeSignals {
+                platform_properties: BTreeMap::from([("prop".to_string(), "1".to_string())]),
+                queued_actions: 2,
+                oldest_queued_s: 10.0,
+            }],
+            workers: vec![WorkerShapeSignals {
+                platform_properties: BTreeMap::from([("prop".to_string(), "2".to_string())]),
+                workers: 1,
+                idle_workers: 1,
+            }],
+        }
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn autoscaling_signals_with_busy_minimum_worker_test() -> Result<(), Error> {
+    let mut prop_defs = HashMap::new();
+    prop_defs.insert("os".to_string(), PropertyType::exact);
+    prop_defs.insert("cpu_count".to_string(), PropertyType::minimum);
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, worker_scheduler) = SimpleScheduler::new_with_callback(
+        &nativelink_config::schedulers::SimpleScheduler {
+            supported_platform_properties: Some(prop_defs),
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+    );
+    let mut worker_properties = PlatformProperties::default();
+    worker_properties.properties.insert(
+        "os".to_string(),
+        PlatformPropertyValue::Exact("linux".to_string()),
+    );
+    worker_properties
+        .properties
+        .insert("cpu_count".to_string(), PlatformPropertyValue::Minimum(4));
+    let mut rx_from_worker =
+        setup_new_worker(&scheduler, WorkerId(Uuid::new_v4()), worker_properties).await?;
+
+    // The first action leaves only 1 cpu on the worker, so the second one
+    // stays queued.
+    let mut platform_properties = HashMap::new();
+    platform_properties.insert("os".to_string(), "linux".to_string());
+    platform_properties.insert("cpu_count".to_string(), "3".to_string());
+    let _action_listener1 = setup_action(
+        &scheduler,
+        DigestInfo::new([1u8; 32], 512),
+        platform_properties.clone(),
+        make_system_time(1),
+    )
+    .await?;
+    match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => {}
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+    platform_properties.insert("cpu_count".to_string(), "2".to_string());
+    let _action_listener2 = setup_action(
+        &scheduler,
+        DigestInfo::new([2u8; 32], 512),
+        platform_properties,
+        make_system_time(5),
+    )
+    .await?;
+
+    let signals = autoscaling_signals(
+        scheduler.as_ref(),
+        worker_scheduler.as_ref(),
+        make_system_time(11),
+    )
+    .await?;
+    assert_eq!(
+        signals,
+        AutoscalingSignals {
+            queued: vec![QueuedShapeSignals {
+                platform_properties: BTreeMap::from([("os".to_string(), "linux".to_string())]),
+                queued_actions: 1,
+                oldest_queued_s: 6.0,
+            }],
+            workers: vec![WorkerShapeSignals {
+                platform_properties: BTreeMap::from([("os".to_string(), "linux".to_string())]),
+                workers: 1,
+                idle_workers: 0,
+            }],
+        }
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn max_queued_actions_rejects_actions_when_queue_is_full_test() -> Result<(), Error> {
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &nativelink_config::schedulers::SimpleScheduler {
+            max_queued_actions: 1,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+    );
+
+    let _action_listener1 = setup_action(
+        &scheduler,
+        DigestInfo::new([1u8; 32], 512),
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .await?;
+    let result = setup_action(
+        &scheduler,
+        DigestInfo::new([2u8; 32], 512),
+        HashMap::new(),
+        make_system_time(2),
+    )
+    .await;
+    assert_eq!(
+        result.err().map(|err| err.code),
+        Some(Code::ResourceExhausted)
+    );
+
+    // Once the first action runs and the queue length is refreshed there is
+    // room for another one.
+    let mut rx_from_worker = setup_new_worker(
+        &scheduler,
+        WorkerId(Uuid::new_v4()),
+        PlatformProperties::default(),
+    )
+    .await?;
+    assert!(matches!(
+        rx_from_worker.recv().await.unwrap().update,
+        Some(update_for_worker::Update::StartAction(_))
+    ));
+    MockClock::advance(Duration::from_secs(1));
+    let _action_listener2 = setup_action(
+        &scheduler,
+        DigestInfo::new([2u8; 32], 512),
+        HashMap::new(),
+        make_system_time(2),
+    )
+    .await?;
+    Ok(())
+}
+
+#[nativelink_test]
+async fn max_queued_actions_admits_actions_joining_queued_action_test() -> Result<(), Error> {
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &nativelink_config::schedulers::SimpleScheduler {
+            max_queued_actions: 1,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+    );
+
+    let action_digest = DigestInfo::new([1u8; 32], 512);
+    let _action_listener1 = setup_action(
+        &scheduler,
+        action_digest,
+        HashMap::new(),
+        make_system_time(1),
+    )
+    .await?;
+    // The same action only joins the queued operation, so it is admitted
+    // even though the queue is full.
+    let _action_listener2 = setup_action(
+        &scheduler,
+        action_digest,
+        HashMap::new(),
+        make_system_time(2),
+    )
+    .await?;
+    let result = setup_action(
+        &scheduler,
+        DigestInfo::new([2u8; 32], 512),
+        HashMap::new(),
+        make_system_time(3),
+    )
+    .await;
+    assert_eq!(
+        result.err().map(|err| err.code),
+        Some(Code::ResourceExhausted)
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn max_queued_time_fails_queued_action_test() -> Result<(), Error> {
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &nativelink_config::schedulers::SimpleScheduler {
+            max_queued_time_s: 5,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+    );
+
+    let mut action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([99u8; 32], 512),
+        HashMap::new(),
+        UNIX_EPOCH + MockClock::time(),
+    )
+    .await?;
+
+    MockClock::advance(Duration::from_secs(6));
+    scheduler.do_try_match_for_test().await?;
+
+    let action_state = action_listener.changed().await?;
+    let ActionStage::Completed(action_result) = &action_state.stage else {
+        panic!("Expected Completed, got : {:?}", action_state.stage);
+    };
+    assert_eq!(
+        action_result.error.as_ref().map(|err| err.code),
+        Some(Code::DeadlineExceeded)
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn unmatchable_action_fails_after_timeout_test() -> Result<(), Error> {
+    let mut prop_defs = HashMap::new();
+    prop_defs.insert("prop".to_string(), PropertyType::exact);
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &nativelink_config::schedulers::SimpleScheduler {
+            supported_platform_properties: Some(prop_defs),
+            unmatchable_action_timeout_s: 5,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+    );
+    let mut worker_properties = PlatformProperties::default();
+    worker_properties.properties.insert(
+        "prop".to_string(),
+        PlatformPropertyValue::Exact("2".to_string()),
+    );
+    let _rx_from_worker =
+        setup_new_worker(&scheduler, WorkerId(Uuid::new_v4()), worker_properties).await?;
+
+    let mut platform_properties = HashMap::new();
+    platform_properties.insert("prop".to_string(), "1".to_string());
+    let mut action_listener = setup_action(
+        &scheduler,
+        DigestInfo::new([99u8; 32], 512),
+        platform_properties,
+        make_system_time(1),
+    )
+    .await?;
+
+    // Workers might still be connecting, so the action stays queued.
+    scheduler.do_try_match_for_test().await?;
+    assert_eq!(action_listener.as_state().await?.stage, ActionStage::Queued);
+
+    MockClock::advance(Duration::from_secs(6));
+    scheduler.do_try_match_for_test().await?;
+
+    let action_state = action_listener.changed().await?;
+    let ActionStage::Completed(action_result) = &action_state.stage else {
+        panic!("Expected Completed, got : {:?}", action_state.stage);
+    };
+    assert_eq!(
+        action_result.error.as_ref().map(|err| err.code),
+        Some(Code::FailedPrecondition)
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn action_waiting_for_busy_minimum_worker_is_not_failed_test() -> Result<(), Error> {
+    let mut prop_defs = HashMap::new();
+    prop_defs.insert("cpu_count".to_string(), PropertyType::minimum);
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &nativelink_config::schedulers::SimpleScheduler {
+            supported_platform_properties: Some(prop_defs),
+            unmatchable_action_timeout_s: 5,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+    );
+    let mut worker_properties = PlatformProperties::default();
+    worker_properties
+        .properties
+        .insert("cpu_count".to_string(), PlatformPropertyValue::Minimum(4));
+    let mut rx_from_worker =
+        setup_new_worker(&scheduler, WorkerId(Uuid::new_v4()), worker_properties).await?;
+
+    let _action_listener1 = setup_action(
+        &scheduler,
+        DigestInfo::new([1u8; 32], 512),
+        HashMap::from([("cpu_count".to_string(), "3".to_string())]),
+        make_system_time(1),
+    )
+    .await?;
+    match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => {}
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+
+    // Only 1 cpu is left while the first action runs, but the idle worker
+    // has enough for this one.
+    let mut action_listener2 = setup_action(
+        &scheduler,
+        DigestInfo::new([2u8; 32], 512),
+        HashMap::from([("cpu_count".to_string(), "2".to_string())]),
+        make_system_time(1),
+    )
+    .await?;
+    scheduler.do_try_match_for_test().await?;
+
+    MockClock::advance(Duration::from_secs(6));
+    scheduler.do_try_match_for_test().await?;
+    assert_eq!(
+        action_listener2.as_state().await?.stage,
+        ActionStage::Queued
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn list_operations_shows_queued_then_executing_operations_test() -> Result<(), Error> {
+    let mut prop_defs = HashMap::new();
+    prop_defs.insert("prop".to_string(), PropertyType::exact);
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &nativelink_config::schedulers::SimpleScheduler {
+            supported_platform_properties: Some(prop_defs),
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+    );
+    let worker_id = WorkerId(Uuid::new_v4());
+    let mut worker_properties = PlatformProperties::default();
+    worker_properties.properties.insert(
+        "prop".to_string(),
+        PlatformPropertyValue::Exact("1".to_string()),
+    );
+    let mut rx_from_worker = setup_new_worker(&scheduler, worker_id, worker_properties).await?;
+
+    let executing_digest = DigestInfo::new([1u8; 32], 512);
+    let _action_listener1 = setup_action(
+        &scheduler,
+        executing_digest,
+        HashMap::from([("prop".to_string(), "1".to_string())]),
+        make_system_time(1),
+    )
+    .await?;
+    assert!(matches!(
+        rx_from_worker.recv().await.unwrap().update,
+        Some(update_for_worker::Update::StartAction(_))
+    ));
+    let queued_digest = DigestInfo::new([2u8; 32], 512);
+    let _action_listener2 = setup_action(
+        &scheduler,
+        queued_digest,
+        HashMap::from([("prop".to_string(), "2".to_string())]),
+        make_system_time(5),
+    )
+    .await?;
+
+    let operations = list_operations(scheduler.as_ref(), make_system_time(11)).await?;
+    assert_eq!(operations.len(), 2);
+    let (queued, executing) = (&operations[0], &operations[1]);
+    assert_eq!(
+        queued,
+        &OperationSummary {
+            operation_id: queued.operation_id.clone(),
+            stage: "Queued".to_string(),
+            instance_name: INSTANCE_NAME.to_string(),
+            action_digest: queued_digest.to_string(),
+            worker_id: None,
+            age_s: 6.0,
+            priority: 0,
+            platform_properties: BTreeMap::from([("prop".to_string(), "2".to_string())]),
+        }
+    );
+    assert_eq!(executing.stage, "Executing");
+    assert_eq!(executing.worker_id, Some(worker_id.to_string()));
+    assert_eq!(executing.age_s, 10.0);
+
+    let details = operation_details(
+        scheduler.as_ref(),
+        OperationId::from(queued.operation_id.as_str()),
+        make_system_time(11),
+    )
+    .await?
+    .expect("Operation not found");
+    assert_eq!(&details.summary, queued);
+    assert_eq!(details.action_info.digest(), queued_digest);
+    assert_eq!(details.action_state.stage, ActionStage::Queued);
+
+    assert!(operation_details(
+        scheduler.as_ref(),
+        OperationId::from("unknown_operation_id"),
+        make_system_time(11),
+    )
+    .await?
+    .is_none());
+    Ok(())
+}
+
+#[nativelink_test]
+async fn worker_is_quarantined_after_repeated_failures_test() -> Result<(), Error> {
+    let worker_id: WorkerId = WorkerId(Uuid::new_v4());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &nativelink_config::schedulers::SimpleScheduler {
+            max_job_retries: 10,
+            worker_quarantine: Some(WorkerQuarantineConfig {
+                failure_window: 3,
+                max_failures: 2,
+            }),
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+    );
+    let action_digest = DigestInfo::new([99u8; 32], 512);
+
+    let mut rx_from_worker =
+        setup_new_worker(&scheduler, worker_id, PlatformProperties::default()).await?;
+    let insert_timestamp = make_system_time(1);
+    let mut action_listener =
+        setup_action(&scheduler, action_digest, HashMap::new(), insert_timestamp).await?;
+
+    for _ in 0..2 {
+        // The only worker gets the action again, even if it already failed it.
+        let operation_id = match rx_from_worker.recv().await.unwrap().update {
+            Some(update_for_worker::Update::StartAction(start_execute)) => {
+                OperationId::from(start_execute.operation_id)
+            }
+            v => panic!("Expected StartAction, got : {v:?}"),
+        };
+        assert_eq!(
+            action_listener.changed().await.unwrap().stage,
+            ActionStage::Executing
+        );
+        scheduler
+            .update_action(
+                &worker_id,
+                &operation_id,
+                UpdateOperationType::UpdateWithError(make_err!(Code::Internal, "Broken disk")),
+            )
+            .await?;
+        assert_eq!(
+            action_listener.changed().await.unwrap().stage,
+            ActionStage::Queued
+        );
+        tokio::task::yield_now().await;
+    }
+
+    // The worker is quarantined, so the action stays queued.
+    assert!(
+        rx_from_worker.try_recv().is_err(),
+        "Quarantined worker should not receive actions"
+    );
+
+    scheduler.unquarantine_worker(&worker_id).await?;
+    tokio::task::yield_now().await;
+
+    match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => { /* Success */ }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+    assert_eq!(
+        action_listener.changed().await.unwrap().stage,
+        ActionStage::Executing
+    );
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn unquarantine_keeps_drained_worker_drained_test() -> Result<(), Error> {
+    let worker_id: WorkerId = WorkerId(Uuid::new_v4());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &nativelink_config::schedulers::SimpleScheduler {
+            max_job_retries: 10,
+            worker_quarantine: Some(WorkerQuarantineConfig {
+                failure_window: 3,
+                max_failures: 2,
+            }),
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+    );
+    let action_digest = DigestInfo::new([99u8; 32], 512);
+
+    let mut rx_from_worker =
+        setup_new_worker(&scheduler, worker_id, PlatformProperties::default()).await?;
+    let insert_timestamp = make_system_time(1);
+    let mut action_listener =
+        setup_action(&scheduler, action_digest, HashMap::new(), insert_timestamp).await?;
+
+    // Only the last two errors say something about the health of the worker.
+    for code in [Code::DeadlineExceeded, Code::Internal, Code::Unavailable] {
+        let operation_id = match rx_from_worker.recv().await.unwrap().update {
+            Some(update_for_worker::Update::StartAction(start_execute)) => {
+                OperationId::from(start_execute.operation_id)
+            }
+            v => panic!("Expected StartAction, got : {v:?}"),
+        };
+        assert_eq!(
+            action_listener.changed().await.unwrap().stage,
+            ActionStage::Executing
+        );
+        scheduler
+            .update_action(
+                &worker_id,
+                &operation_id,
+                UpdateOperationType::UpdateWithError(make_err!(code, "Action failed")),
+            )
+            .await?;
+        assert_eq!(
+            action_listener.changed().await.unwrap().stage,
+            ActionStage::Queued
+        );
+        tokio::task::yield_now().await;
+    }
+    assert!(
+        rx_from_worker.try_recv().is_err(),
+        "Quarantined worker should not receive actions"
+    );
+
+    // An operator drains the worker on top of the quarantine.
+    scheduler.set_drain_worker(&worker_id, true).await?;
+    scheduler.unquarantine_worker(&worker_id).await?;
+    tokio::task::yield_now().await;
+    assert!(
+        rx_from_worker.try_recv().is_err(),
+        "Drained worker should not receive actions"
+    );
+
+    scheduler.set_drain_worker(&worker_id, false).await?;
+    tokio::task::yield_now().await;
+    match rx_from_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => { /* Success */ }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+    assert_eq!(
+        action_listener.changed().await.unwrap().stage,
+        ActionStage::Executing
+    );
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn retried_action_avoids_worker_it_failed_on_test() -> Result<(), Error> {
+    let worker_id1: WorkerId = WorkerId(Uuid::new_v4());
+    let worker_id2: WorkerId = WorkerId(Uuid::new_v4());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &nativelink_config::schedulers::SimpleScheduler {
+            max_job_retries: 1,
+            // The worker that just finished the action is the most recently
+            // used one, so it would get the action back if it were not
+            // avoided.
+            allocation_strategy: WorkerAllocationStrategy::most_recently_used,
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+    );
+    let action_digest = DigestInfo::new([99u8; 32], 512);
+
+    let mut rx_from_worker1 =
+        setup_new_worker(&scheduler, worker_id1, PlatformProperties::default()).await?;
+    let mut rx_from_worker2 =
+        setup_new_worker(&scheduler, worker_id2, PlatformProperties::default()).await?;
+    let insert_timestamp = make_system_time(1);
+    let mut action_listener =
+        setup_action(&scheduler, action_digest, HashMap::new(), insert_timestamp).await?;
+
+    let (failed_worker_id, update) = tokio::select! {
+        Some(update) = rx_from_worker1.recv() => (worker_id1, update),
+        Some(update) = rx_from_worker2.recv() => (worker_id2, update),
+    };
+    let (rx_from_failed_worker, rx_from_other_worker) = if failed_worker_id == worker_id1 {
+        (&mut rx_from_worker1, &mut rx_from_worker2)
+    } else {
+        (&mut rx_from_worker2, &mut rx_from_worker1)
+    };
+    let operation_id = match update.update {
+        Some(update_for_worker::Update::StartAction(start_execute)) => {
+            OperationId::from(start_execute.operation_id)
+        }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    };
+    assert_eq!(
+        action_listener.changed().await.unwrap().stage,
+        ActionStage::Executing
+    );
+
+    scheduler
+        .update_action(
+            &failed_worker_id,
+            &operation_id,
+            UpdateOperationType::UpdateWithError(make_err!(Code::Internal, "Broken disk")),
+        )
+        .await?;
+    assert_eq!(
+        action_listener.changed().await.unwrap().stage,
+        ActionStage::Queued
+    );
+    tokio::task::yield_now().await;
+
+    match rx_from_other_worker.recv().await.unwrap().update {
+        Some(update_for_worker::Update::StartAction(_)) => { /* Success */ }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    }
+    assert_eq!(
+        action_listener.changed().await.unwrap().stage,
+        ActionStage::Executing
+    );
+    assert!(
+        rx_from_failed_worker.try_recv().is_err(),
+        "Worker the action failed on should not get it again"
+    );
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn slow_action_is_copied_to_idle_worker_test() -> Result<(), Error> {
+    const ACTION_MNEMONIC: &str = "CppCompile";
+    let worker_id1: WorkerId = WorkerId(Uuid::new_v4());
+    let worker_id2: WorkerId = WorkerId(Uuid::new_v4());
+
+    let task_change_notify = Arc::new(Notify::new());
+    let (scheduler, _worker_scheduler) = SimpleScheduler::new_with_callback(
+        &nativelink_config::schedulers::SimpleScheduler {
+            worker_timeout_s: 100,
+            speculative_execution: Some(SpeculativeExecutionConfig {
+                duration_multiplier: 2.0,
+                min_duration_s: 5,
+                min_samples: 1,
+            }),
+            ..Default::default()
+        },
+        memory_awaited_action_db_factory(
+            0,
+            task_change_notify.clone(),
+            MockInstantWrapped::default,
+        ),
+        || async move {},
+        task_change_notify,
+        MockInstantWrapped::default,
+    );
+    let make_action_info = |action_digest| {
+        let mut action_info = make_base_action_info(make_system_time(1), action_digest);
+        Arc::make_mut(&mut action_info).request_metadata = Some(RequestMetadataInfo {
+            action_mnemonic: ACTION_MNEMONIC.to_string(),
+            ..Default::default()
+        });
+        action_info
+    };
+    let expect_start_action = |update: UpdateForWorker| match update.update {
+        Some(update_for_worker::Update::StartAction(start_execute)) => {
+            OperationId::from(start_execute.operation_id)
+        }
+        v => panic!("Expected StartAction, got : {v:?}"),
+    };
+    let completed = || {
+        UpdateOperationType::UpdateWithActionStage(ActionStage::Completed(ActionResult::default()))
+    };
+
+    let mut rx_from_worker1 =
+        setup_new_worker(&scheduler, worker_id1, PlatformProperties::default()).await?;
+
+    // A first run tells the scheduler how long such actions take.
+    let _first_action_listener = scheduler
+        .add_action(
+            OperationId::default(),
+            make_action_info(DigestInfo::new([1u8; 32], 512)),
+        )
+        .await?;
+    tokio::task::yield_now().await;
+    let operation_id = expect_start_action(rx_from_worker1.recv().await.unwrap());
+    MockClock::advance(Duration::from_secs(10));
+    scheduler
+        .update_action(&worker_id1, &operation_id, completed())
+        .await?;
+
+    let mut action_listener = scheduler
+        .add_action(
+            OperationId::default(),
+            make_action_info(DigestInfo::new([2u8; 32], 512)),
+        )
+        .await?;
+    tokio::task::yield_now().await;
+    let operation_id = expect_start_action(rx_from_worker1.recv().await.unwrap());
+    assert_eq!(
+        action_listener.changed().await.unwrap().stage,
+        ActionStage::Executing
+    );
+    let mut rx_from_worker2 =
+        setup_new_worker(&scheduler, worker_id2, PlatformProperties::default()).await?;
+
+    // Not slow enough to be copied yet.
+    MockClock::advance(Duration::from_secs(15));
+    scheduler.do_try_match_for_test().await?;
+    assert!(
+        rx_from_worker2.try_recv().is_err(),
+        "Action should not have been copied yet"
+    );
+
+    MockClock::advance(Duration::from_secs(10));
+    scheduler.do_try_match_for_test().await?;
+    assert_eq!(
+        expect_start_action(rx_from_worker2.recv().await.unwrap()),
+        operation_id
+    );
+
+    // The copy finishes first, so the original is killed.
+    scheduler
+        .update_action(&worker_id2, &operation_id, completed())
+        .await?;
+    assert!(matches!(
+        action_listener.changed().await.unwrap().stage,
+        ActionStage::Completed(_)
+    ));
+    match rx_from_worker1.recv().await.unwrap().update {
+        Some(update_for_worker::Update::KillOperationRequest(kill_operation_request)) => {
+            assert_eq!(
+                kill_operation_request.operation_id,
+                operation_id.to_string()
+            );
+        }
+        v => panic!("Expected KillOperationRequest, got : {v:?}"),
+    }
+
+    // The killed original reporting back does not affect the operation.
+    scheduler
+        .update_action(
+            &worker_id1,
+            &operation_id,
+            UpdateOperationType::UpdateWithError(make_err!(Code::Aborted, "Killed")),
+        )
+        .await?;
+    assert!(matches!(
+        action_listener.as_state().await.unwrap().stage,
+        ActionStage::Completed(_)
+    ));
+
+    Ok(())
+}
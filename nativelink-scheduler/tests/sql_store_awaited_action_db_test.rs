@@ -0,0 +1,113 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(feature = "sql")]
+
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use nativelink_config::schedulers::ExperimentalSqlSchedulerBackend;
+use nativelink_error::{Error, ResultExt};
+use nativelink_macro::nativelink_test;
+use nativelink_scheduler::awaited_action_db::{AwaitedActionDb, AwaitedActionSubscriber};
+use nativelink_scheduler::store_awaited_action_db::StoreAwaitedActionDb;
+use nativelink_store::sql_scheduler_store::SqlSchedulerStore;
+use nativelink_util::action_messages::{
+    ActionInfo, ActionStage, ActionUniqueKey, ActionUniqueQualifier, OperationId,
+};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::digest_hasher::DigestHasherFunc;
+use pretty_assertions::assert_eq;
+use tokio::sync::Notify;
+
+const INSTANCE_NAME: &str = "instance_name";
+const CLIENT_OPERATION_ID: &str = "my_client_operation_id";
+
+/// Returns the url of a new SQLite database in `TEST_TMPDIR` or the best
+/// effort temp directory if not set.
+fn make_database_url() -> String {
+    let dir = env::var("TEST_TMPDIR").unwrap_or(env::temp_dir().to_str().unwrap().to_string());
+    format!(
+        "sqlite://{dir}/{}.db?mode=rwc",
+        uuid::Uuid::new_v4().as_hyphenated()
+    )
+}
+
+fn make_awaited_action_db(
+    database_url: &str,
+) -> Result<StoreAwaitedActionDb<SqlSchedulerStore, fn() -> OperationId>, Error> {
+    let store = SqlSchedulerStore::new(&ExperimentalSqlSchedulerBackend {
+        database_url: database_url.to_string(),
+        max_connections: 1,
+        ..Default::default()
+    })?;
+    StoreAwaitedActionDb::new(
+        store,
+        Arc::new(Notify::new()),
+        SystemTime::now,
+        OperationId::default,
+    )
+}
+
+#[nativelink_test]
+async fn actions_survive_scheduler_restart() -> Result<(), Error> {
+    let database_url = make_database_url();
+    let action_info = Arc::new(ActionInfo {
+        command_digest: DigestInfo::zero_digest(),
+        input_root_digest: DigestInfo::zero_digest(),
+        timeout: Duration::from_secs(1),
+        platform_properties: HashMap::new(),
+        priority: 0,
+        load_timestamp: SystemTime::UNIX_EPOCH,
+        insert_timestamp: SystemTime::UNIX_EPOCH,
+        unique_qualifier: ActionUniqueQualifier::Cachable(ActionUniqueKey {
+            instance_name: INSTANCE_NAME.to_string(),
+            digest_function: DigestHasherFunc::Sha256,
+            digest: DigestInfo::zero_digest(),
+        }),
+        request_metadata: None,
+        results_cache_priority: 0,
+        skip_action_cache_upload: false,
+    });
+
+    let operation_id = {
+        let awaited_action_db = make_awaited_action_db(&database_url)?;
+        let mut subscription = awaited_action_db
+            .add_action(CLIENT_OPERATION_ID.into(), action_info)
+            .await?;
+        let mut awaited_action = subscription.changed().await?;
+        assert_eq!(awaited_action.state().stage, ActionStage::Queued);
+
+        let mut new_state = awaited_action.state().as_ref().clone();
+        new_state.stage = ActionStage::Executing;
+        awaited_action.set_state(Arc::new(new_state), Some(SystemTime::now()));
+        awaited_action_db
+            .update_awaited_action(awaited_action.clone())
+            .await?;
+        awaited_action.operation_id().clone()
+    };
+
+    // A new scheduler on the same database picks up where the old one left.
+    let awaited_action_db = make_awaited_action_db(&database_url)?;
+    let subscription = awaited_action_db
+        .get_awaited_action_by_id(&CLIENT_OPERATION_ID.into())
+        .await?
+        .err_tip(|| "Expected the client operation to be known after a restart")?;
+    let awaited_action = subscription.borrow().await?;
+    assert_eq!(awaited_action.operation_id(), &operation_id);
+    assert_eq!(awaited_action.state().stage, ActionStage::Executing);
+    Ok(())
+}
@@ -45,6 +45,9 @@ pub fn make_base_action_info(
             digest_function: DigestHasherFunc::Sha256,
             digest: action_digest,
         }),
+        request_metadata: None,
+        results_cache_priority: 0,
+        skip_action_cache_upload: false,
     })
 }
 
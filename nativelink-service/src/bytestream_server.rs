@@ -39,9 +39,7 @@ use nativelink_util::buf_channel::{
     make_buf_channel_pair, DropCloserReadHalf, DropCloserWriteHalf,
 };
 use nativelink_util::common::DigestInfo;
-use nativelink_util::digest_hasher::{
-    default_digest_hasher_func, make_ctx_for_hash_func, DigestHasherFunc,
-};
+use nativelink_util::digest_hasher::make_ctx_for_hash_func;
 use nativelink_util::proto_stream_utils::WriteRequestStreamWrapper;
 use nativelink_util::resource_info::ResourceInfo;
 use nativelink_util::spawn;
@@ -606,10 +604,7 @@ impl ByteStream for ByteStreamServer {
             return Ok(Response::new(Box::pin(stream)));
         }
 
-        let digest_function = resource_info.digest_function.as_deref().map_or_else(
-            || Ok(default_digest_hasher_func()),
-            DigestHasherFunc::try_from,
-        )?;
+        let digest_function = resource_info.digest_hasher_func()?;
 
         let resp = make_ctx_for_hash_func(digest_function)
             .err_tip(|| "In BytestreamServer::read")?
@@ -661,14 +656,7 @@ impl ByteStream for ByteStreamServer {
             return grpc_store.write(stream).await.map_err(Into::into);
         }
 
-        let digest_function = stream
-            .resource_info
-            .digest_function
-            .as_deref()
-            .map_or_else(
-                || Ok(default_digest_hasher_func()),
-                DigestHasherFunc::try_from,
-            )?;
+        let digest_function = stream.resource_info.digest_hasher_func()?;
 
         make_ctx_for_hash_func(digest_function)
             .err_tip(|| "In BytestreamServer::write")?
@@ -12,10 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
-use nativelink_config::cas_server::{CapabilitiesConfig, InstanceName};
+use nativelink_config::cas_server::{CapabilitiesConfig, CasStoreConfig, InstanceName};
 use nativelink_error::{Error, ResultExt};
 use nativelink_proto::build::bazel::remote::execution::v2::capabilities_server::{
     Capabilities, CapabilitiesServer as Server,
@@ -38,12 +38,15 @@ const MAX_BATCH_TOTAL_SIZE: i64 = 64 * 1024;
 #[derive(Debug, Default)]
 pub struct CapabilitiesServer {
     supported_node_properties_for_instance: HashMap<InstanceName, Vec<String>>,
+    /// Instances whose CAS serves `SplitBlob` and `SpliceBlob`.
+    split_splice_instances: HashSet<InstanceName>,
 }
 
 impl CapabilitiesServer {
     pub async fn new(
         config: &HashMap<InstanceName, CapabilitiesConfig>,
         scheduler_map: &HashMap<String, Arc<dyn ClientStateManager>>,
+        cas_config: Option<&HashMap<InstanceName, CasStoreConfig>>,
     ) -> Result<Self, Error> {
         let mut supported_node_properties_for_instance = HashMap::new();
         for (instance_name, cfg) in config {
@@ -78,8 +81,15 @@ impl CapabilitiesServer {
             }
             supported_node_properties_for_instance.insert(instance_name.clone(), properties);
         }
+        let split_splice_instances = cas_config
+            .into_iter()
+            .flatten()
+            .filter(|(_, cas_cfg)| cas_cfg.dedup_store.is_some())
+            .map(|(instance_name, _)| instance_name.clone())
+            .collect();
         Ok(CapabilitiesServer {
             supported_node_properties_for_instance,
+            split_splice_instances,
         })
     }
 
@@ -103,6 +113,7 @@ impl Capabilities for CapabilitiesServer {
         grpc_request: Request<GetCapabilitiesRequest>,
     ) -> Result<Response<ServerCapabilities>, Status> {
         let instance_name = grpc_request.into_inner().instance_name;
+        let split_splice_support = self.split_splice_instances.contains(&instance_name);
         let maybe_supported_node_properties = self
             .supported_node_properties_for_instance
             .get(&instance_name);
@@ -120,6 +131,9 @@ impl Capabilities for CapabilitiesServer {
                 digest_functions: vec![
                     DigestFunction::Sha256.into(),
                     DigestFunction::Blake3.into(),
+                    DigestFunction::Sha384.into(),
+                    DigestFunction::Sha512.into(),
+                    DigestFunction::Sha256tree.into(),
                 ],
             });
 
@@ -128,6 +142,9 @@ impl Capabilities for CapabilitiesServer {
                 digest_functions: vec![
                     DigestFunction::Sha256.into(),
                     DigestFunction::Blake3.into(),
+                    DigestFunction::Sha384.into(),
+                    DigestFunction::Sha512.into(),
+                    DigestFunction::Sha256tree.into(),
                 ],
                 action_cache_update_capabilities: Some(ActionCacheUpdateCapabilities {
                     update_enabled: true,
@@ -137,6 +154,8 @@ impl Capabilities for CapabilitiesServer {
                 symlink_absolute_path_strategy: SymlinkAbsolutePathStrategy::Disallowed.into(),
                 supported_compressors: vec![],
                 supported_batch_update_compressors: vec![],
+                split_blob_support: split_splice_support,
+                splice_blob_support: split_splice_support,
             }),
             execution_capabilities,
             deprecated_api_version: None,
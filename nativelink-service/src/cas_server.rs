@@ -17,10 +17,10 @@ use std::convert::Into;
 use std::pin::Pin;
 
 use bytes::Bytes;
-use futures::stream::{FuturesUnordered, Stream};
-use futures::TryStreamExt;
+use futures::stream::{FuturesUnordered, Stream, StreamExt};
+use futures::{try_join, TryStreamExt};
 use nativelink_config::cas_server::{CasStoreConfig, InstanceName};
-use nativelink_error::{error_if, make_input_err, Code, Error, ResultExt};
+use nativelink_error::{error_if, make_err, make_input_err, Code, Error, ResultExt};
 use nativelink_proto::build::bazel::remote::execution::v2::content_addressable_storage_server::{
     ContentAddressableStorage, ContentAddressableStorageServer as Server,
 };
@@ -28,19 +28,30 @@ use nativelink_proto::build::bazel::remote::execution::v2::{
     batch_read_blobs_response, batch_update_blobs_response, compressor, BatchReadBlobsRequest,
     BatchReadBlobsResponse, BatchUpdateBlobsRequest, BatchUpdateBlobsResponse, Directory,
     FindMissingBlobsRequest, FindMissingBlobsResponse, GetTreeRequest, GetTreeResponse,
+    SpliceBlobRequest, SpliceBlobResponse, SplitBlobRequest, SplitBlobResponse,
 };
 use nativelink_proto::google::rpc::Status as GrpcStatus;
 use nativelink_store::ac_utils::get_and_decode_digest;
+use nativelink_store::dedup_store::DedupStore;
 use nativelink_store::grpc_store::GrpcStore;
 use nativelink_store::store_manager::StoreManager;
+use nativelink_util::buf_channel::make_buf_channel_pair;
 use nativelink_util::common::DigestInfo;
-use nativelink_util::digest_hasher::make_ctx_for_hash_func;
-use nativelink_util::store_trait::{Store, StoreLike};
+use nativelink_util::digest_hasher::{make_ctx_for_hash_func, DigestHasher, DigestHasherFunc};
+use nativelink_util::fastcdc::FastCDC;
+use nativelink_util::store_trait::{Store, StoreKey, StoreLike, UploadSizeInfo};
+use tokio_util::codec::FramedRead;
+use tokio_util::io::StreamReader;
 use tonic::{Request, Response, Status};
 use tracing::{error_span, event, instrument, Level};
 
+/// How many chunks of a blob being split are uploaded at the same time.
+const MAX_CONCURRENT_CHUNK_UPLOADS: usize = 10;
+
 pub struct CasServer {
     stores: HashMap<String, Store>,
+    /// Chunkers of the instances that serve `SplitBlob` and `SpliceBlob`.
+    chunkers: HashMap<String, FastCDC>,
 }
 
 type GetTreeStream = Pin<Box<dyn Stream<Item = Result<GetTreeResponse, Status>> + Send + 'static>>;
@@ -51,13 +62,26 @@ impl CasServer {
         store_manager: &StoreManager,
     ) -> Result<Self, Error> {
         let mut stores = HashMap::with_capacity(config.len());
+        let mut chunkers = HashMap::new();
         for (instance_name, cas_cfg) in config {
             let store = store_manager.get_store(&cas_cfg.cas_store).ok_or_else(|| {
                 make_input_err!("'cas_store': '{}' does not exist", cas_cfg.cas_store)
             })?;
             stores.insert(instance_name.to_string(), store);
+            if let Some(dedup_store_name) = &cas_cfg.dedup_store {
+                let dedup_store = store_manager.get_store(dedup_store_name).ok_or_else(|| {
+                    make_input_err!("'dedup_store': '{dedup_store_name}' does not exist")
+                })?;
+                let chunker = dedup_store
+                    .downcast_ref::<DedupStore>(None)
+                    .ok_or_else(|| {
+                        make_input_err!("'dedup_store': '{dedup_store_name}' is not a dedup store")
+                    })?
+                    .chunker();
+                chunkers.insert(instance_name.to_string(), chunker);
+            }
         }
-        Ok(CasServer { stores })
+        Ok(CasServer { stores, chunkers })
     }
 
     pub fn into_service(self) -> Server<CasServer> {
@@ -297,6 +321,192 @@ impl CasServer {
             })
         }))))
     }
+
+    async fn inner_split_blob(
+        &self,
+        request: SplitBlobRequest,
+    ) -> Result<Response<SplitBlobResponse>, Error> {
+        let instance_name = &request.instance_name;
+
+        let store = self
+            .stores
+            .get(instance_name)
+            .err_tip(|| format!("'instance_name' not configured for '{instance_name}'"))?
+            .clone();
+
+        // If we are a GrpcStore we shortcut here, as this is a special store.
+        if let Some(grpc_store) = store.downcast_ref::<GrpcStore>(None) {
+            return grpc_store.split_blob(Request::new(request)).await;
+        }
+        let chunker = self.chunkers.get(instance_name).ok_or_else(|| {
+            make_err!(
+                Code::Unimplemented,
+                "SplitBlob is not configured for '{instance_name}'"
+            )
+        })?;
+        let blob_digest: DigestInfo = request
+            .blob_digest
+            .err_tip(|| "Expected blob_digest to exist in SplitBlobRequest")?
+            .try_into()
+            .err_tip(|| "In SplitBlobRequest::blob_digest")?;
+        let digest_function = DigestHasherFunc::try_from(request.digest_function)
+            .err_tip(|| "In SplitBlobRequest::digest_function")?;
+
+        let (tx, rx) = make_buf_channel_pair();
+        let store_ref = &store;
+        let read_fut = async move {
+            store_ref
+                .get(blob_digest, tx)
+                .await
+                .err_tip(|| "Failed to read blob in split_blob")
+        };
+        let chunk_fut = async move {
+            let mut bytes_reader = StreamReader::new(rx);
+            FramedRead::new(&mut bytes_reader, chunker.clone())
+                .map(|r| r.err_tip(|| "Failed to decode frame from fast_cdc"))
+                .map_ok(|chunk| async move {
+                    let mut hasher = digest_function.hasher();
+                    hasher.update(&chunk);
+                    let chunk_digest = hasher.finalize_digest();
+                    // Chunks shared with other blobs are likely to exist already.
+                    if store_ref
+                        .has(chunk_digest)
+                        .await
+                        .err_tip(|| "Failed to call .has() in split_blob")?
+                        .is_none()
+                    {
+                        store_ref
+                            .update_oneshot(chunk_digest, chunk)
+                            .await
+                            .err_tip(|| "Failed to upload chunk in split_blob")?;
+                    }
+                    Ok::<_, Error>(chunk_digest)
+                })
+                .try_buffered(MAX_CONCURRENT_CHUNK_UPLOADS)
+                .try_collect::<Vec<_>>()
+                .await
+        };
+        let ((), chunk_digests) = try_join!(read_fut, chunk_fut)?;
+
+        Ok(Response::new(SplitBlobResponse {
+            chunk_digests: chunk_digests.into_iter().map(Into::into).collect(),
+            digest_function: digest_function.proto_digest_func().into(),
+        }))
+    }
+
+    async fn inner_splice_blob(
+        &self,
+        request: SpliceBlobRequest,
+    ) -> Result<Response<SpliceBlobResponse>, Error> {
+        let instance_name = &request.instance_name;
+
+        let store = self
+            .stores
+            .get(instance_name)
+            .err_tip(|| format!("'instance_name' not configured for '{instance_name}'"))?
+            .clone();
+
+        // If we are a GrpcStore we shortcut here, as this is a special store.
+        if let Some(grpc_store) = store.downcast_ref::<GrpcStore>(None) {
+            return grpc_store.splice_blob(Request::new(request)).await;
+        }
+        if !self.chunkers.contains_key(instance_name) {
+            return Err(make_err!(
+                Code::Unimplemented,
+                "SpliceBlob is not configured for '{instance_name}'"
+            ));
+        }
+        let blob_digest: DigestInfo = request
+            .blob_digest
+            .err_tip(|| "Expected blob_digest to exist in SpliceBlobRequest")?
+            .try_into()
+            .err_tip(|| "In SpliceBlobRequest::blob_digest")?;
+        let digest_function = DigestHasherFunc::try_from(request.digest_function)
+            .err_tip(|| "In SpliceBlobRequest::digest_function")?;
+        let chunk_digests = request
+            .chunk_digests
+            .into_iter()
+            .map(DigestInfo::try_from)
+            .collect::<Result<Vec<_>, _>>()
+            .err_tip(|| "In SpliceBlobRequest::chunk_digests")?;
+
+        let chunks_size = chunk_digests
+            .iter()
+            .map(DigestInfo::size_bytes)
+            .fold(0u64, u64::saturating_add);
+        error_if!(
+            chunks_size != blob_digest.size_bytes(),
+            "Chunks add up to {chunks_size} bytes, but blob_digest is {} bytes",
+            blob_digest.size_bytes()
+        );
+
+        // Nothing to do if the blob was uploaded or spliced before.
+        if store
+            .has(blob_digest)
+            .await
+            .err_tip(|| "Failed to call .has() in splice_blob")?
+            .is_some()
+        {
+            return Ok(Response::new(SpliceBlobResponse {
+                blob_digest: Some(blob_digest.into()),
+            }));
+        }
+
+        let chunk_keys: Vec<StoreKey> = chunk_digests.iter().map(|d| (*d).into()).collect();
+        let missing_chunks: Vec<String> = store
+            .has_many(&chunk_keys)
+            .await
+            .err_tip(|| "Failed to call .has_many() in splice_blob")?
+            .into_iter()
+            .zip(&chunk_digests)
+            .filter(|(maybe_size, _)| maybe_size.is_none())
+            .map(|(_, digest)| digest.to_string())
+            .collect();
+        if !missing_chunks.is_empty() {
+            return Err(make_err!(
+                Code::NotFound,
+                "Chunks not found in CAS: {}",
+                missing_chunks.join(", ")
+            ));
+        }
+
+        let (mut tx, rx) = make_buf_channel_pair();
+        let store_ref = &store;
+        let write_fut = store_ref.update(
+            blob_digest,
+            rx,
+            UploadSizeInfo::ExactSize(blob_digest.size_bytes()),
+        );
+        let copy_fut = async move {
+            let mut hasher = digest_function.hasher();
+            for chunk_digest in chunk_digests {
+                let data = store_ref
+                    .get_part_unchunked(chunk_digest, 0, None)
+                    .await
+                    .err_tip(|| format!("Failed to read chunk {chunk_digest} in splice_blob"))?;
+                if data.is_empty() {
+                    continue;
+                }
+                hasher.update(&data);
+                tx.send(data)
+                    .await
+                    .err_tip(|| "Failed to send chunk in splice_blob")?;
+            }
+            let spliced_digest = hasher.finalize_digest();
+            // Returning without sending EOF drops `tx`, which aborts the write.
+            error_if!(
+                spliced_digest != blob_digest,
+                "Spliced chunks have digest {spliced_digest}, expected {blob_digest}"
+            );
+            tx.send_eof()
+                .err_tip(|| "Failed to send EOF in splice_blob")
+        };
+        try_join!(write_fut, copy_fut).err_tip(|| "Failed to write spliced blob")?;
+
+        Ok(Response::new(SpliceBlobResponse {
+            blob_digest: Some(blob_digest.into()),
+        }))
+    }
 }
 
 #[tonic::async_trait]
@@ -401,4 +611,51 @@ impl ContentAddressableStorage for CasServer {
         }
         resp
     }
+    #[allow(clippy::blocks_in_conditions)]
+    #[instrument(
+        err,
+        ret(level = Level::INFO),
+        level = Level::ERROR,
+        skip_all,
+        fields(request = ?grpc_request.get_ref())
+    )]
+    async fn split_blob(
+        &self,
+        grpc_request: Request<SplitBlobRequest>,
+    ) -> Result<Response<SplitBlobResponse>, Status> {
+        let request = grpc_request.into_inner();
+        make_ctx_for_hash_func(request.digest_function)
+            .err_tip(|| "In CasServer::split_blob")?
+            .wrap_async(
+                error_span!("cas_server_split_blob"),
+                self.inner_split_blob(request),
+            )
+            .await
+            .err_tip(|| "Failed on split_blob() command")
+            .map_err(Into::into)
+    }
+
+    #[allow(clippy::blocks_in_conditions)]
+    #[instrument(
+        err,
+        ret(level = Level::INFO),
+        level = Level::ERROR,
+        skip_all,
+        fields(request = ?grpc_request.get_ref())
+    )]
+    async fn splice_blob(
+        &self,
+        grpc_request: Request<SpliceBlobRequest>,
+    ) -> Result<Response<SpliceBlobResponse>, Status> {
+        let request = grpc_request.into_inner();
+        make_ctx_for_hash_func(request.digest_function)
+            .err_tip(|| "In CasServer::splice_blob")?
+            .wrap_async(
+                error_span!("cas_server_splice_blob"),
+                self.inner_splice_blob(request),
+            )
+            .await
+            .err_tip(|| "Failed on splice_blob() command")
+            .map_err(Into::into)
+    }
 }
@@ -40,6 +40,7 @@ use nativelink_util::digest_hasher::{make_ctx_for_hash_func, DigestHasherFunc};
 use nativelink_util::operation_state_manager::{
     ActionStateResult, ClientStateManager, OperationFilter,
 };
+use nativelink_util::request_metadata::active_request_metadata;
 use nativelink_util::store_trait::Store;
 use tonic::{Request, Response, Status};
 use tracing::{error_span, event, instrument, Level};
@@ -133,7 +134,9 @@ impl InstanceInfo {
             digest_function,
             digest: action_digest,
         };
-        let unique_qualifier = if skip_cache_lookup {
+        // Results of `do_not_cache` actions must never be shared, so they are
+        // neither looked up in the cache nor deduplicated with other actions.
+        let unique_qualifier = if skip_cache_lookup || action.do_not_cache {
             ActionUniqueQualifier::Uncachable(action_key)
         } else {
             ActionUniqueQualifier::Cachable(action_key)
@@ -148,6 +151,10 @@ impl InstanceInfo {
             load_timestamp: UNIX_EPOCH,
             insert_timestamp: SystemTime::now(),
             unique_qualifier,
+            request_metadata: active_request_metadata()
+                .map(|request_metadata| request_metadata.as_ref().clone().into()),
+            results_cache_priority: 0,
+            skip_action_cache_upload: false,
         })
     }
 }
@@ -256,7 +263,7 @@ impl ExecutionServer {
 
         let action =
             get_and_decode_digest::<Action>(&instance_info.cas_store, digest.into()).await?;
-        let action_info = instance_info
+        let mut action_info = instance_info
             .build_action_info(
                 instance_name.clone(),
                 digest,
@@ -269,6 +276,7 @@ impl ExecutionServer {
                     .err_tip(|| "Could not convert digest function in inner_execute()")?,
             )
             .await?;
+        action_info.results_cache_priority = request.results_cache_policy.map_or(0, |p| p.priority);
 
         let action_listener = instance_info
             .scheduler
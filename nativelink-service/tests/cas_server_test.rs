@@ -24,7 +24,7 @@ use nativelink_proto::build::bazel::remote::execution::v2::{
     batch_read_blobs_response, batch_update_blobs_request, batch_update_blobs_response, compressor,
     digest_function, BatchReadBlobsRequest, BatchReadBlobsResponse, BatchUpdateBlobsRequest,
     BatchUpdateBlobsResponse, Digest, Directory, DirectoryNode, FindMissingBlobsRequest,
-    GetTreeRequest, GetTreeResponse, NodeProperties,
+    GetTreeRequest, GetTreeResponse, NodeProperties, SpliceBlobRequest, SplitBlobRequest,
 };
 use nativelink_proto::google::rpc::Status as GrpcStatus;
 use nativelink_service::cas_server::CasServer;
@@ -32,7 +32,7 @@ use nativelink_store::ac_utils::serialize_and_upload_message;
 use nativelink_store::default_store_factory::store_factory;
 use nativelink_store::store_manager::StoreManager;
 use nativelink_util::common::DigestInfo;
-use nativelink_util::digest_hasher::DigestHasherFunc;
+use nativelink_util::digest_hasher::{DigestHasher, DigestHasherFunc};
 use nativelink_util::store_trait::{StoreKey, StoreLike};
 use pretty_assertions::assert_eq;
 use prost_types::Timestamp;
@@ -65,6 +65,7 @@ fn make_cas_server(store_manager: &StoreManager) -> Result<CasServer, Error> {
         &hashmap! {
             "foo_instance_name".to_string() => nativelink_config::cas_server::CasStoreConfig{
                 cas_store: "main_cas".to_string(),
+                dedup_store: None,
             }
         },
         store_manager,
@@ -666,3 +667,199 @@ async fn batch_update_blobs_two_items_existence_with_third_missing(
     }
     Ok(())
 }
+
+async fn make_dedup_store_manager() -> Result<Arc<StoreManager>, Error> {
+    let store_manager = make_store_manager().await?;
+    store_manager.add_store(
+        "dedup_cas",
+        store_factory(
+            &nativelink_config::stores::StoreConfig::dedup(Box::new(
+                nativelink_config::stores::DedupStore {
+                    index_store: nativelink_config::stores::StoreConfig::memory(
+                        nativelink_config::stores::MemoryStore::default(),
+                    ),
+                    content_store: nativelink_config::stores::StoreConfig::memory(
+                        nativelink_config::stores::MemoryStore::default(),
+                    ),
+                    min_size: 1024,
+                    normal_size: 4 * 1024,
+                    max_size: 16 * 1024,
+                    max_concurrent_fetch_per_get: 10,
+                    gc_interval_s: 0,
+                },
+            )),
+            &store_manager,
+            None,
+        )
+        .await?,
+    );
+    Ok(store_manager)
+}
+
+fn make_split_splice_cas_server(store_manager: &StoreManager) -> Result<CasServer, Error> {
+    CasServer::new(
+        &hashmap! {
+            "foo_instance_name".to_string() => nativelink_config::cas_server::CasStoreConfig{
+                cas_store: "main_cas".to_string(),
+                dedup_store: Some("dedup_cas".to_string()),
+            }
+        },
+        store_manager,
+    )
+}
+
+/// Deterministic data that does not repeat, so it splits into many chunks.
+fn make_pseudo_random_data(size: usize) -> Vec<u8> {
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    (0..size)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+#[nativelink_test]
+async fn split_and_splice_blob_test() -> Result<(), Box<dyn std::error::Error>> {
+    let store_manager = make_dedup_store_manager().await?;
+    let cas_server = make_split_splice_cas_server(&store_manager)?;
+    let store = store_manager.get_store("main_cas").unwrap();
+
+    let data = make_pseudo_random_data(64 * 1024);
+    let mut hasher = DigestHasherFunc::Sha256.hasher();
+    hasher.update(&data);
+    let blob_digest = hasher.finalize_digest();
+    store
+        .update_oneshot(blob_digest, data.clone().into())
+        .await?;
+
+    let split_response = cas_server
+        .split_blob(Request::new(SplitBlobRequest {
+            instance_name: INSTANCE_NAME.to_string(),
+            blob_digest: Some(blob_digest.into()),
+            digest_function: digest_function::Value::Sha256.into(),
+        }))
+        .await?
+        .into_inner();
+    assert_eq!(
+        split_response.digest_function,
+        i32::from(digest_function::Value::Sha256)
+    );
+    let chunk_digests = split_response.chunk_digests;
+    assert!(chunk_digests.len() > 1, "Expected blob to be split");
+    assert_eq!(
+        chunk_digests.iter().map(|d| d.size_bytes).sum::<i64>(),
+        data.len() as i64
+    );
+    // Every chunk was uploaded to the CAS.
+    let chunk_keys: Vec<StoreKey> = chunk_digests
+        .iter()
+        .map(|d| DigestInfo::try_from(d.clone()).map(Into::into))
+        .collect::<Result<_, _>>()?;
+    assert!(store
+        .has_many(&chunk_keys)
+        .await?
+        .iter()
+        .all(Option::is_some));
+
+    // Splicing the chunks recreates the blob.
+    assert!(store.remove(blob_digest).await?);
+    let splice_response = cas_server
+        .splice_blob(Request::new(SpliceBlobRequest {
+            instance_name: INSTANCE_NAME.to_string(),
+            blob_digest: Some(blob_digest.into()),
+            chunk_digests: chunk_digests.clone(),
+            digest_function: digest_function::Value::Sha256.into(),
+        }))
+        .await?
+        .into_inner();
+    assert_eq!(splice_response.blob_digest, Some(blob_digest.into()));
+    assert_eq!(store.get_part_unchunked(blob_digest, 0, None).await?, data);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn splice_blob_errors_test() -> Result<(), Box<dyn std::error::Error>> {
+    let store_manager = make_dedup_store_manager().await?;
+    let cas_server = make_split_splice_cas_server(&store_manager)?;
+    let store = store_manager.get_store("main_cas").unwrap();
+
+    const VALUE1: &str = "1";
+    const VALUE2: &str = "23";
+    let mut chunk_digests = Vec::new();
+    for value in [VALUE1, VALUE2] {
+        let mut hasher = DigestHasherFunc::Sha256.hasher();
+        hasher.update(value.as_bytes());
+        let digest = hasher.finalize_digest();
+        store.update_oneshot(digest, value.into()).await?;
+        chunk_digests.push(Digest::from(digest));
+    }
+    let splice = |blob_digest: Digest, chunk_digests: Vec<Digest>| {
+        cas_server.splice_blob(Request::new(SpliceBlobRequest {
+            instance_name: INSTANCE_NAME.to_string(),
+            blob_digest: Some(blob_digest),
+            chunk_digests,
+            digest_function: digest_function::Value::Sha256.into(),
+        }))
+    };
+
+    // The chunks do not hash to the blob digest.
+    let result = splice(
+        Digest {
+            hash: HASH1.to_string(),
+            size_bytes: 3,
+        },
+        chunk_digests.clone(),
+    )
+    .await;
+    assert_eq!(result.unwrap_err().code(), Code::InvalidArgument);
+    assert_eq!(
+        store.has(DigestInfo::try_new(HASH1, 3)?).await?,
+        None,
+        "Blob should not have been written"
+    );
+
+    // The chunks do not add up to the blob size.
+    let result = splice(
+        Digest {
+            hash: HASH1.to_string(),
+            size_bytes: 4,
+        },
+        chunk_digests.clone(),
+    )
+    .await;
+    assert_eq!(result.unwrap_err().code(), Code::InvalidArgument);
+
+    // One of the chunks is missing.
+    let missing_chunk = Digest {
+        hash: HASH2.to_string(),
+        size_bytes: 1,
+    };
+    let result = splice(
+        Digest {
+            hash: HASH1.to_string(),
+            size_bytes: 4,
+        },
+        vec![
+            chunk_digests[0].clone(),
+            chunk_digests[1].clone(),
+            missing_chunk,
+        ],
+    )
+    .await;
+    assert_eq!(result.unwrap_err().code(), Code::NotFound);
+
+    // Instances without a dedup store do not support splitting or splicing.
+    let cas_server = make_cas_server(&store_manager)?;
+    let result = cas_server
+        .split_blob(Request::new(SplitBlobRequest {
+            instance_name: INSTANCE_NAME.to_string(),
+            blob_digest: Some(chunk_digests[0].clone()),
+            digest_function: digest_function::Value::Sha256.into(),
+        }))
+        .await;
+    assert_eq!(result.unwrap_err().code(), Code::Unimplemented);
+    Ok(())
+}
@@ -147,6 +147,9 @@ async fn setup_api_server(worker_timeout: u64, now_fn: NowFn) -> Result<TestCont
         WorkerAllocationStrategy::default(),
         tasks_or_worker_change_notify,
         worker_timeout,
+        None,
+        None,
+        None,
     );
 
     let mut schedulers: HashMap<String, Arc<dyn WorkerScheduler>> = HashMap::new();
@@ -390,6 +393,9 @@ pub async fn execution_response_success_test() -> Result<(), Box<dyn std::error:
         load_timestamp: make_system_time(0),
         insert_timestamp: make_system_time(0),
         unique_qualifier,
+        request_metadata: None,
+        results_cache_priority: 0,
+        skip_action_cache_upload: false,
     });
     let expected_operation_id = OperationId::default();
 
@@ -12,10 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use nativelink_util::common::DigestInfo;
+use nativelink_util::common::{DigestInfo, PackedHash};
 use nativelink_util::store_trait::StoreKey;
 
-pub const ZERO_BYTE_DIGESTS: [DigestInfo; 2] = [
+pub const ZERO_BYTE_DIGESTS: [DigestInfo; 4] = [
     // Sha256 hash of zero bytes.
     DigestInfo::new(
         [
@@ -34,6 +34,27 @@ pub const ZERO_BYTE_DIGESTS: [DigestInfo; 2] = [
         ],
         0,
     ),
+    // Sha384 hash of zero bytes.
+    DigestInfo::from_packed_hash(
+        PackedHash::from_static(&[
+            0x38, 0xb0, 0x60, 0xa7, 0x51, 0xac, 0x96, 0x38, 0x4c, 0xd9, 0x32, 0x7e, 0xb1, 0xb1,
+            0xe3, 0x6a, 0x21, 0xfd, 0xb7, 0x11, 0x14, 0xbe, 0x07, 0x43, 0x4c, 0x0c, 0xc7, 0xbf,
+            0x63, 0xf6, 0xe1, 0xda, 0x27, 0x4e, 0xde, 0xbf, 0xe7, 0x6f, 0x65, 0xfb, 0xd5, 0x1a,
+            0xd2, 0xf1, 0x48, 0x98, 0xb9, 0x5b,
+        ]),
+        0,
+    ),
+    // Sha512 hash of zero bytes.
+    DigestInfo::from_packed_hash(
+        PackedHash::from_static(&[
+            0xcf, 0x83, 0xe1, 0x35, 0x7e, 0xef, 0xb8, 0xbd, 0xf1, 0x54, 0x28, 0x50, 0xd6, 0x6d,
+            0x80, 0x07, 0xd6, 0x20, 0xe4, 0x05, 0x0b, 0x57, 0x15, 0xdc, 0x83, 0xf4, 0xa9, 0x21,
+            0xd3, 0x6c, 0xe9, 0xce, 0x47, 0xd0, 0xd1, 0x3c, 0x5d, 0x85, 0xf2, 0xb0, 0xff, 0x83,
+            0x18, 0xd2, 0x87, 0x7e, 0xec, 0x2f, 0x63, 0xb9, 0x31, 0xbd, 0x47, 0x41, 0x7a, 0x81,
+            0xa5, 0x38, 0x32, 0x7a, 0xf9, 0x27, 0xda, 0x3e,
+        ]),
+        0,
+    ),
 ];
 
 #[inline]
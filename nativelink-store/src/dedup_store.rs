@@ -13,20 +13,26 @@
 // limitations under the License.
 
 use std::cmp;
+use std::collections::{HashMap, HashSet};
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
 
 use async_trait::async_trait;
 use bincode::config::{FixintEncoding, WithOtherIntEncoding};
 use bincode::{DefaultOptions, Options};
-use futures::stream::{self, FuturesOrdered, StreamExt, TryStreamExt};
+use bytes::Bytes;
+use futures::stream::{self, FuturesOrdered, Stream, StreamExt, TryStreamExt};
 use nativelink_error::{make_err, Code, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
+use nativelink_util::background_spawn;
 use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
 use nativelink_util::common::DigestInfo;
 use nativelink_util::fastcdc::FastCDC;
 use nativelink_util::health_utils::{default_health_status_indicator, HealthStatusIndicator};
 use nativelink_util::store_trait::{Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo};
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 use tokio_util::codec::FramedRead;
 use tokio_util::io::StreamReader;
@@ -46,6 +52,80 @@ pub struct DedupIndex {
     pub entries: Vec<DigestInfo>,
 }
 
+/// The result of a garbage collection pass.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GarbageCollectionReport {
+    /// Number of chunks referenced by an index or an upload in progress.
+    pub live_chunks: u64,
+    /// Number of chunks removed from the content store.
+    pub removed_chunks: u64,
+    /// Sum of the sizes of the removed chunks.
+    pub reclaimed_bytes: u64,
+}
+
+/// Keeps track of the chunks that uploads refer to before their index is
+/// written, so garbage collection does not remove them.
+#[derive(Default)]
+struct ChunkTracker {
+    /// Chunks referenced by uploads in progress, with the number of uploads
+    /// referencing each of them.
+    in_flight: HashMap<DigestInfo, usize>,
+    /// Set while garbage collection runs. Holds every chunk referenced by
+    /// an upload since garbage collection started.
+    touched_during_gc: Option<HashSet<DigestInfo>>,
+}
+
+/// The chunks referenced by a single upload. They are released from the
+/// `ChunkTracker` when this is dropped.
+struct InFlightChunks<'a> {
+    tracker: &'a Mutex<ChunkTracker>,
+    chunks: Mutex<Vec<DigestInfo>>,
+}
+
+impl<'a> InFlightChunks<'a> {
+    fn new(tracker: &'a Mutex<ChunkTracker>) -> Self {
+        Self {
+            tracker,
+            chunks: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn add(&self, chunk: DigestInfo) {
+        {
+            let mut tracker = self.tracker.lock();
+            *tracker.in_flight.entry(chunk).or_default() += 1;
+            if let Some(touched_during_gc) = &mut tracker.touched_during_gc {
+                touched_during_gc.insert(chunk);
+            }
+        }
+        self.chunks.lock().push(chunk);
+    }
+}
+
+impl Drop for InFlightChunks<'_> {
+    fn drop(&mut self) {
+        let mut tracker = self.tracker.lock();
+        for chunk in self.chunks.get_mut().drain(..) {
+            if let Some(count) = tracker.in_flight.get_mut(&chunk) {
+                *count -= 1;
+                if *count == 0 {
+                    tracker.in_flight.remove(&chunk);
+                }
+            }
+        }
+    }
+}
+
+/// Stops tracking touched chunks when garbage collection ends, even if it
+/// is cancelled.
+struct GarbageCollectionGuard<'a>(&'a Mutex<ChunkTracker>);
+
+impl Drop for GarbageCollectionGuard<'_> {
+    fn drop(&mut self) {
+        self.0.lock().touched_during_gc = None;
+    }
+}
+
 #[derive(MetricsComponent)]
 pub struct DedupStore {
     #[metric(group = "index_store")]
@@ -56,6 +136,28 @@ pub struct DedupStore {
     #[metric(help = "Maximum number of concurrent fetches per get")]
     max_concurrent_fetch_per_get: usize,
     bincode_options: WithOtherIntEncoding<DefaultOptions, FixintEncoding>,
+    chunk_tracker: Mutex<ChunkTracker>,
+    /// Only one garbage collection pass may run at a time.
+    gc_lock: tokio::sync::Mutex<()>,
+    /// Held exclusively by garbage collection while it checks and removes a
+    /// chunk, and shared by uploads while they reference a chunk and check
+    /// if it exists. Keeps an upload from skipping a chunk that is about to
+    /// be removed.
+    sweep_lock: tokio::sync::RwLock<()>,
+    #[metric(help = "Number of bytes written to the store before deduplication")]
+    logical_bytes_written: AtomicU64,
+    #[metric(help = "Number of bytes of chunks uploaded to the content store")]
+    uploaded_chunk_bytes: AtomicU64,
+    #[metric(help = "Bytes written to the store per byte uploaded to the content store")]
+    dedup_ratio: Mutex<f64>,
+    #[metric(help = "Number of garbage collection passes completed")]
+    gc_runs: AtomicU64,
+    #[metric(help = "Number of chunks referenced during the last garbage collection pass")]
+    gc_live_chunks: AtomicU64,
+    #[metric(help = "Number of orphaned chunks removed from the content store")]
+    gc_removed_chunks: AtomicU64,
+    #[metric(help = "Number of bytes reclaimed from the content store")]
+    gc_reclaimed_bytes: AtomicU64,
 }
 
 impl DedupStore {
@@ -84,7 +186,7 @@ impl DedupStore {
         } else {
             config.max_concurrent_fetch_per_get as usize
         };
-        Ok(Arc::new(Self {
+        let store = Arc::new(Self {
             index_store,
             content_store,
             fast_cdc_decoder: FastCDC::new(
@@ -95,7 +197,172 @@ impl DedupStore {
             ),
             max_concurrent_fetch_per_get,
             bincode_options: DefaultOptions::new().with_fixint_encoding(),
-        }))
+            chunk_tracker: Mutex::new(ChunkTracker::default()),
+            gc_lock: tokio::sync::Mutex::new(()),
+            sweep_lock: tokio::sync::RwLock::new(()),
+            logical_bytes_written: AtomicU64::new(0),
+            uploaded_chunk_bytes: AtomicU64::new(0),
+            dedup_ratio: Mutex::new(0.),
+            gc_runs: AtomicU64::new(0),
+            gc_live_chunks: AtomicU64::new(0),
+            gc_removed_chunks: AtomicU64::new(0),
+            gc_reclaimed_bytes: AtomicU64::new(0),
+        });
+        if config.gc_interval_s != 0 {
+            let gc_interval = Duration::from_secs(u64::from(config.gc_interval_s));
+            let weak_store = Arc::downgrade(&store);
+            background_spawn!("dedup_store_gc", async move {
+                Self::gc_loop(weak_store, gc_interval).await;
+            });
+        }
+        Ok(store)
+    }
+
+    /// The content defined chunker this store splits blobs with.  Exposed so
+    /// other services can produce the same chunk boundaries as the store.
+    pub fn chunker(&self) -> FastCDC {
+        self.fast_cdc_decoder.clone()
+    }
+
+    async fn gc_loop(weak_store: Weak<Self>, gc_interval: Duration) {
+        loop {
+            tokio::time::sleep(gc_interval).await;
+            let Some(store) = weak_store.upgrade() else {
+                return;
+            };
+            match store.collect_garbage().await {
+                Ok(report) => event!(
+                    Level::INFO,
+                    ?report,
+                    "Finished garbage collection in dedup store"
+                ),
+                Err(err) => event!(Level::WARN, ?err, "Failed to garbage collect dedup store"),
+            }
+        }
+    }
+
+    /// Removes every chunk from the content store that is not referenced
+    /// by an index in the index store or by an upload in progress.
+    pub async fn collect_garbage(&self) -> Result<GarbageCollectionReport, Error> {
+        let _gc_lock = self.gc_lock.lock().await;
+        {
+            let mut tracker = self.chunk_tracker.lock();
+            let touched_during_gc = tracker.in_flight.keys().copied().collect();
+            tracker.touched_during_gc = Some(touched_during_gc);
+        }
+        let _gc_guard = GarbageCollectionGuard(&self.chunk_tracker);
+
+        // Mark every chunk an index refers to. Any failure other than a
+        // missing index aborts, since removing chunks based on a partial
+        // view could remove live data.
+        let mut index_keys = Vec::new();
+        self.index_store
+            .list(.., |key| {
+                index_keys.push(key.borrow().into_owned());
+                true
+            })
+            .await
+            .err_tip(|| "Failed to list index store in DedupStore::collect_garbage")?;
+        let mut index_stream = self.read_indexes(index_keys);
+        let mut live_chunks = HashSet::new();
+        while let Some(maybe_index) = index_stream.next().await {
+            let Some((key, data)) = maybe_index? else {
+                continue;
+            };
+            match self.bincode_options.deserialize::<DedupIndex>(&data) {
+                Ok(index) => live_chunks.extend(index.entries),
+                Err(err) => event!(
+                    Level::WARN,
+                    ?key,
+                    ?err,
+                    "Failed to deserialize index in dedup store garbage collection",
+                ),
+            }
+        }
+
+        // Sweep every chunk that was not marked.
+        let mut chunk_keys = Vec::new();
+        self.content_store
+            .list(.., |key| {
+                chunk_keys.push(key.borrow().into_digest());
+                true
+            })
+            .await
+            .err_tip(|| "Failed to list content store in DedupStore::collect_garbage")?;
+        let mut report = GarbageCollectionReport::default();
+        for chunk in chunk_keys {
+            if live_chunks.contains(&chunk) {
+                continue;
+            }
+            let _sweep_guard = self.sweep_lock.write().await;
+            {
+                let tracker = self.chunk_tracker.lock();
+                let touched = tracker
+                    .touched_during_gc
+                    .as_ref()
+                    .is_some_and(|touched| touched.contains(&chunk));
+                if touched || tracker.in_flight.contains_key(&chunk) {
+                    live_chunks.insert(chunk);
+                    continue;
+                }
+            }
+            if self
+                .content_store
+                .remove(chunk)
+                .await
+                .err_tip(|| "Failed to remove chunk in DedupStore::collect_garbage")?
+            {
+                report.removed_chunks += 1;
+                report.reclaimed_bytes += chunk.size_bytes();
+            }
+        }
+        report.live_chunks = live_chunks.len() as u64;
+
+        self.gc_runs.fetch_add(1, Ordering::Relaxed);
+        self.gc_live_chunks
+            .store(report.live_chunks, Ordering::Relaxed);
+        self.gc_removed_chunks
+            .fetch_add(report.removed_chunks, Ordering::Relaxed);
+        self.gc_reclaimed_bytes
+            .fetch_add(report.reclaimed_bytes, Ordering::Relaxed);
+        Ok(report)
+    }
+
+    /// Reads the given indexes, skipping the ones that no longer exist.
+    fn read_indexes(
+        &self,
+        index_keys: Vec<StoreKey<'static>>,
+    ) -> impl Stream<Item = Result<Option<(StoreKey<'static>, Bytes)>, Error>> + Send + '_ {
+        stream::iter(index_keys)
+            .map(move |key| async move {
+                match self
+                    .index_store
+                    .get_part_unchunked(key.borrow(), 0, None)
+                    .await
+                {
+                    Ok(data) => Ok(Some((key, data))),
+                    // Evicted since it was listed.
+                    Err(err) if err.code == Code::NotFound => Ok(None),
+                    Err(err) => {
+                        Err(err.append("Failed to read index in DedupStore::collect_garbage"))
+                    }
+                }
+            })
+            .buffer_unordered(self.max_concurrent_fetch_per_get)
+    }
+
+    fn record_written(&self, logical_bytes: u64, uploaded_bytes: u64) {
+        let logical_bytes = self
+            .logical_bytes_written
+            .fetch_add(logical_bytes, Ordering::Relaxed)
+            + logical_bytes;
+        let uploaded_bytes = self
+            .uploaded_chunk_bytes
+            .fetch_add(uploaded_bytes, Ordering::Relaxed)
+            + uploaded_bytes;
+        if uploaded_bytes != 0 {
+            *self.dedup_ratio.lock() = logical_bytes as f64 / uploaded_bytes as f64;
+        }
     }
 
     async fn has(self: Pin<&Self>, key: StoreKey<'_>) -> Result<Option<u64>, Error> {
@@ -187,25 +454,35 @@ impl StoreDriver for DedupStore {
     ) -> Result<(), Error> {
         let mut bytes_reader = StreamReader::new(reader);
         let frame_reader = FramedRead::new(&mut bytes_reader, self.fast_cdc_decoder.clone());
+        // Keeps garbage collection from removing the chunks until the index
+        // referencing them is written.
+        let in_flight_chunks = InFlightChunks::new(&self.chunk_tracker);
+        let in_flight_chunks = &in_flight_chunks;
         let index_entries = frame_reader
             .map(|r| r.err_tip(|| "Failed to decode frame from fast_cdc"))
             .map_ok(|frame| async move {
                 let hash = blake3::hash(&frame[..]).into();
-                let index_entry = DigestInfo::new(hash, frame.len() as u64);
-                if self
-                    .content_store
-                    .has(index_entry)
-                    .await
-                    .err_tip(|| "Failed to call .has() in DedupStore::update()")?
-                    .is_some()
-                {
+                let frame_len = frame.len() as u64;
+                let index_entry = DigestInfo::new(hash, frame_len);
+                let exists = {
+                    let _sweep_guard = self.sweep_lock.read().await;
+                    in_flight_chunks.add(index_entry);
+                    self.content_store
+                        .has(index_entry)
+                        .await
+                        .err_tip(|| "Failed to call .has() in DedupStore::update()")?
+                        .is_some()
+                };
+                if exists {
                     // If our store has this digest, we don't need to upload it.
+                    self.record_written(frame_len, 0);
                     return Result::<_, Error>::Ok(index_entry);
                 }
                 self.content_store
                     .update_oneshot(index_entry, frame)
                     .await
                     .err_tip(|| "Failed to update content store in dedup_store")?;
+                self.record_written(frame_len, frame_len);
                 Ok(index_entry)
             })
             .try_buffered(self.max_concurrent_fetch_per_get)
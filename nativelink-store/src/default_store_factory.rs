@@ -26,6 +26,7 @@ use nativelink_util::store_trait::{Store, StoreDriver};
 use crate::completeness_checking_store::CompletenessCheckingStore;
 use crate::compression_store::CompressionStore;
 use crate::dedup_store::DedupStore;
+use crate::encryption_store::EncryptionStore;
 use crate::existence_cache_store::ExistenceCacheStore;
 use crate::fast_slow_store::FastSlowStore;
 use crate::filesystem_store::FilesystemStore;
@@ -62,6 +63,13 @@ pub fn store_factory<'a>(
                 *config.clone(),
                 store_factory(&config.backend, store_manager, None).await?,
             )?,
+            StoreConfig::encryption(config) => {
+                EncryptionStore::new(
+                    config,
+                    store_factory(&config.backend, store_manager, None).await?,
+                )
+                .await?
+            }
             StoreConfig::dedup(config) => DedupStore::new(
                 config,
                 store_factory(&config.index_store, store_manager, None).await?,
@@ -70,7 +78,7 @@ pub fn store_factory<'a>(
             StoreConfig::existence_cache(config) => ExistenceCacheStore::new(
                 config,
                 store_factory(&config.backend, store_manager, None).await?,
-            ),
+            )?,
             StoreConfig::completeness_checking(config) => CompletenessCheckingStore::new(
                 store_factory(&config.backend, store_manager, None).await?,
                 store_factory(&config.cas_store, store_manager, None).await?,
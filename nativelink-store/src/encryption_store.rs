@@ -0,0 +1,598 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures::future::{try_join_all, FutureExt};
+use nativelink_error::{error_if, make_err, make_input_err, Code, Error, ResultExt};
+use nativelink_metric::MetricsComponent;
+use nativelink_util::buf_channel::{
+    make_buf_channel_pair, DropCloserReadHalf, DropCloserWriteHalf,
+};
+use nativelink_util::common::fs;
+use nativelink_util::health_utils::{default_health_status_indicator, HealthStatusIndicator};
+use nativelink_util::spawn;
+use nativelink_util::store_trait::{Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::hkdf::{Prk, Salt, HKDF_SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::cas_utils::is_zero_digest;
+
+// In the event the stream format changes this number should be incremented to prevent
+// backwards compatibility issues.
+pub const CURRENT_STREAM_FORMAT_VERSION: u8 = 1;
+
+// Default size of the plaintext chunks that are encrypted individually.
+pub const DEFAULT_CHUNK_SIZE: u32 = 64 * 1024;
+
+/// Size of the keys in the key file.
+const KEY_LEN: usize = 32;
+
+/// Size of the random salt every object key is derived with.
+const SALT_LEN: usize = 16;
+
+/// Size of the authentication tag appended to every encrypted chunk.
+const TAG_LEN: u64 = 16;
+
+/// Size of the header: version (u8) | key_id (u32) | chunk_size (u32) | salt.
+pub const HEADER_LEN: u64 = 1 + 4 + 4 + SALT_LEN as u64;
+
+/// Salt used when extracting the master keys. Each object additionally uses
+/// its own random salt when the object key is expanded.
+const HKDF_EXTRACT_SALT: &[u8] = b"nativelink-encryption-store";
+
+// The stream format is:
+//
+//   header | chunk 0 | chunk 1 | ... | chunk N
+//
+// Every chunk is `chunk_size` bytes of plaintext encrypted with AES-256-GCM
+// followed by its 16 byte tag, except the last chunk which may be shorter
+// (or even empty). Since every chunk but the last has the same size, the
+// chunk holding any plaintext offset can be found without reading the
+// chunks before it, which keeps `get_part()` with an offset cheap.
+//
+// Each object is encrypted with its own key derived from the master key in
+// the header and a random salt, so the nonce only needs to be unique within
+// the object. The nonce is the index of the chunk plus a flag marking the
+// final chunk, so chunks cannot be reordered and the stream cannot be
+// truncated on a chunk boundary without failing authentication. The header
+// and the key the object is stored under are used as associated data, so
+// objects cannot be swapped with each other in the inner store either.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Header {
+    version: u8,
+    key_id: u32,
+    chunk_size: u32,
+    salt: [u8; SALT_LEN],
+}
+
+impl Header {
+    fn encode(&self) -> [u8; HEADER_LEN as usize] {
+        let mut data = [0u8; HEADER_LEN as usize];
+        data[0] = self.version;
+        data[1..5].copy_from_slice(&self.key_id.to_le_bytes());
+        data[5..9].copy_from_slice(&self.chunk_size.to_le_bytes());
+        data[9..].copy_from_slice(&self.salt);
+        data
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, Error> {
+        error_if!(
+            data.len() as u64 != HEADER_LEN,
+            "Expected encryption header to be {HEADER_LEN} bytes, got {}",
+            data.len()
+        );
+        let header = Header {
+            version: data[0],
+            key_id: u32::from_le_bytes(data[1..5].try_into().unwrap()),
+            chunk_size: u32::from_le_bytes(data[5..9].try_into().unwrap()),
+            salt: data[9..].try_into().unwrap(),
+        };
+        error_if!(
+            header.version != CURRENT_STREAM_FORMAT_VERSION,
+            "Expected encryption header version to match {CURRENT_STREAM_FORMAT_VERSION} != {}",
+            header.version
+        );
+        error_if!(
+            header.chunk_size == 0,
+            "Encryption header has a chunk size of zero"
+        );
+        Ok(header)
+    }
+}
+
+/// Parses a key file with one `<key_id> <hex key>` entry per line.
+/// Empty lines and lines starting with `#` are ignored.
+fn parse_key_file(data: &str) -> Result<HashMap<u32, Prk>, Error> {
+    let mut keys = HashMap::new();
+    for (line_number, line) in data.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.split_whitespace();
+        let (Some(key_id), Some(hex_key), None) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(make_input_err!(
+                "Expected '<key_id> <hex key>' on line {} of key file",
+                line_number + 1
+            ));
+        };
+        let key_id: u32 = key_id
+            .parse()
+            .map_err(|e| make_input_err!("Invalid key id on line {} : {e:?}", line_number + 1))?;
+        let mut key = [0u8; KEY_LEN];
+        hex::decode_to_slice(hex_key, &mut key).map_err(|e| {
+            make_input_err!(
+                "Key on line {} must be {KEY_LEN} hex encoded bytes : {e:?}",
+                line_number + 1
+            )
+        })?;
+        let prk = Salt::new(HKDF_SHA256, HKDF_EXTRACT_SALT).extract(&key);
+        error_if!(
+            keys.insert(key_id, prk).is_some(),
+            "Key id {key_id} is in the key file more than once"
+        );
+    }
+    Ok(keys)
+}
+
+/// Size of the encrypted stream of `plaintext_size` bytes of data.
+fn encrypted_size(plaintext_size: u64, chunk_size: u32) -> u64 {
+    let chunk_count = plaintext_size.div_ceil(u64::from(chunk_size)).max(1);
+    HEADER_LEN
+        .saturating_add(plaintext_size)
+        .saturating_add(chunk_count.saturating_mul(TAG_LEN))
+}
+
+/// Size of the plaintext of an encrypted stream of `encrypted_size` bytes.
+fn plaintext_size(encrypted_size: u64, chunk_size: u32) -> u64 {
+    let encrypted_chunk_size = u64::from(chunk_size) + TAG_LEN;
+    let payload_size = encrypted_size.saturating_sub(HEADER_LEN);
+    (payload_size / encrypted_chunk_size) * u64::from(chunk_size)
+        + (payload_size % encrypted_chunk_size).saturating_sub(TAG_LEN)
+}
+
+fn chunk_nonce(chunk_index: u64, is_last: bool) -> Nonce {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[..8].copy_from_slice(&chunk_index.to_be_bytes());
+    nonce[NONCE_LEN - 1] = u8::from(is_last);
+    Nonce::assume_unique_for_key(nonce)
+}
+
+/// Key and associated data used to encrypt or decrypt a single object.
+struct ObjectCipher {
+    key: LessSafeKey,
+    aad: Vec<u8>,
+}
+
+impl ObjectCipher {
+    fn seal_chunk(&self, chunk_index: u64, is_last: bool, data: &[u8]) -> Result<Bytes, Error> {
+        let mut buf = BytesMut::with_capacity(data.len() + TAG_LEN as usize);
+        buf.extend_from_slice(data);
+        let tag = self
+            .key
+            .seal_in_place_separate_tag(
+                chunk_nonce(chunk_index, is_last),
+                Aad::from(&self.aad),
+                &mut buf,
+            )
+            .map_err(|_| make_err!(Code::Internal, "Failed to encrypt chunk {chunk_index}"))?;
+        buf.extend_from_slice(tag.as_ref());
+        Ok(buf.freeze())
+    }
+
+    fn open_chunk(&self, chunk_index: u64, is_last: bool, data: &[u8]) -> Option<Bytes> {
+        let mut buf = BytesMut::from(data);
+        let plaintext_len = self
+            .key
+            .open_in_place(
+                chunk_nonce(chunk_index, is_last),
+                Aad::from(&self.aad),
+                &mut buf,
+            )
+            .ok()?
+            .len();
+        buf.truncate(plaintext_len);
+        Some(buf.freeze())
+    }
+}
+
+/// Encrypts all data before passing it to the inner store and decrypts it
+/// on the way out. Keys and sizes seen by clients are of the plaintext.
+#[derive(MetricsComponent)]
+pub struct EncryptionStore {
+    #[metric(group = "inner_store")]
+    inner_store: Store,
+    keys: HashMap<u32, Prk>,
+    #[metric(help = "The id of the key new data is encrypted with")]
+    active_key_id: u32,
+    #[metric(help = "The size of the plaintext chunks new data is encrypted in")]
+    chunk_size: u32,
+    rng: SystemRandom,
+}
+
+impl EncryptionStore {
+    pub async fn new(
+        config: &nativelink_config::stores::EncryptionStore,
+        inner_store: Store,
+    ) -> Result<Arc<Self>, Error> {
+        let key_file = fs::read(&config.key_file)
+            .await
+            .err_tip(|| format!("Failed to read key file {}", config.key_file))?;
+        let key_file = std::str::from_utf8(&key_file)
+            .map_err(|e| make_input_err!("Key file is not utf8 : {e:?}"))?;
+        let keys = parse_key_file(key_file)
+            .err_tip(|| format!("While parsing key file {}", config.key_file))?;
+        error_if!(
+            !keys.contains_key(&config.active_key_id),
+            "Active key id {} is not in key file {}",
+            config.active_key_id,
+            config.key_file
+        );
+        Ok(Arc::new(EncryptionStore {
+            inner_store,
+            keys,
+            active_key_id: config.active_key_id,
+            chunk_size: if config.chunk_size == 0 {
+                DEFAULT_CHUNK_SIZE
+            } else {
+                config.chunk_size
+            },
+            rng: SystemRandom::new(),
+        }))
+    }
+
+    fn object_cipher(&self, header: &Header, key: &StoreKey<'_>) -> Result<ObjectCipher, Error> {
+        let prk = self.keys.get(&header.key_id).ok_or_else(|| {
+            make_err!(
+                Code::Internal,
+                "Key id {} used to encrypt {key:?} is not in the key file",
+                header.key_id
+            )
+        })?;
+        let info = [header.salt.as_slice()];
+        let object_key = prk
+            .expand(&info, &AES_256_GCM)
+            .map_err(|_| make_err!(Code::Internal, "Failed to derive key for {key:?}"))?;
+        let key_str = key.as_str();
+        let mut aad = Vec::with_capacity(HEADER_LEN as usize + key_str.len());
+        aad.extend_from_slice(&header.encode());
+        aad.extend_from_slice(key_str.as_bytes());
+        Ok(ObjectCipher {
+            key: LessSafeKey::new(UnboundKey::from(object_key)),
+            aad,
+        })
+    }
+
+    /// Decrypts the chunks in `rx` starting at `first_chunk_index`, drops
+    /// `skip` bytes of plaintext and writes at most `length` bytes of the
+    /// rest to `writer`. If `bounded` is true `rx` may end right after a
+    /// full chunk that is not the last chunk of the object.
+    #[allow(clippy::too_many_arguments)]
+    async fn decrypt_chunks(
+        &self,
+        key: &StoreKey<'_>,
+        header: &Header,
+        rx: &mut DropCloserReadHalf,
+        writer: &mut DropCloserWriteHalf,
+        first_chunk_index: u64,
+        mut skip: u64,
+        length: Option<u64>,
+        bounded: bool,
+    ) -> Result<(), Error> {
+        let cipher = self.object_cipher(header, key)?;
+        let encrypted_chunk_size = header.chunk_size as usize + TAG_LEN as usize;
+        let mut remaining = length.unwrap_or(u64::MAX);
+        let mut chunk_index = first_chunk_index;
+        loop {
+            let data = rx
+                .consume(Some(encrypted_chunk_size))
+                .await
+                .err_tip(|| "Failed to read chunk in encryption store get_part")?;
+            if data.is_empty() {
+                return Err(make_err!(
+                    Code::DataLoss,
+                    "Encrypted data for {key:?} ended before its last chunk"
+                ));
+            }
+            let is_short = data.len() < encrypted_chunk_size;
+            let at_eof = is_short
+                || rx
+                    .peek()
+                    .await
+                    .err_tip(|| "Failed to peek in encryption store get_part")?
+                    .is_empty();
+            let (plaintext, is_last) = if is_short || (at_eof && !bounded) {
+                (cipher.open_chunk(chunk_index, true, &data), true)
+            } else if !at_eof {
+                (cipher.open_chunk(chunk_index, false, &data), false)
+            } else {
+                // A full chunk at the end of a bounded read may or may not
+                // be the last chunk of the object.
+                match cipher.open_chunk(chunk_index, false, &data) {
+                    Some(plaintext) => (Some(plaintext), false),
+                    None => (cipher.open_chunk(chunk_index, true, &data), true),
+                }
+            };
+            let mut plaintext = plaintext.ok_or_else(|| {
+                make_err!(
+                    Code::DataLoss,
+                    "Failed to authenticate chunk {chunk_index} of {key:?} in encryption store"
+                )
+            })?;
+
+            let plaintext_len = plaintext.len() as u64;
+            if skip >= plaintext_len {
+                skip -= plaintext_len;
+            } else {
+                let _ = plaintext.split_to(skip as usize);
+                skip = 0;
+                plaintext.truncate(remaining.min(plaintext.len() as u64) as usize);
+                remaining -= plaintext.len() as u64;
+                if !plaintext.is_empty() {
+                    writer
+                        .send(plaintext)
+                        .await
+                        .err_tip(|| "Failed to write data in encryption store get_part")?;
+                }
+            }
+            if is_last || remaining == 0 {
+                break;
+            }
+            chunk_index += 1;
+        }
+        writer
+            .send_eof()
+            .err_tip(|| "Failed to send eof in encryption store get_part")
+    }
+}
+
+#[async_trait]
+impl StoreDriver for EncryptionStore {
+    async fn has_with_results(
+        self: Pin<&Self>,
+        digests: &[StoreKey<'_>],
+        results: &mut [Option<u64>],
+    ) -> Result<(), Error> {
+        self.inner_store
+            .has_with_results(digests, results)
+            .await
+            .err_tip(|| "In EncryptionStore::has_with_results")?;
+        // Objects may have been written with another chunk size, so the size
+        // of their plaintext depends on the chunk size in their header.
+        let plaintext_sizes = try_join_all(digests.iter().zip(results.iter()).map(
+            |(key, result)| async move {
+                let Some(size) = *result else {
+                    return Ok(None);
+                };
+                if is_zero_digest(key.borrow()) {
+                    return Ok(Some(0));
+                }
+                let data = match self
+                    .inner_store
+                    .get_part_unchunked(key.borrow(), 0, Some(HEADER_LEN))
+                    .await
+                {
+                    Ok(data) => data,
+                    // Removed since it was found.
+                    Err(err) if err.code == Code::NotFound => return Ok(None),
+                    Err(err) => return Err(err),
+                };
+                let header = Header::decode(&data)?;
+                Ok(Some(plaintext_size(size, header.chunk_size)))
+            },
+        ))
+        .await
+        .err_tip(|| "Failed to read header in EncryptionStore::has_with_results")?;
+        results.copy_from_slice(&plaintext_sizes);
+        Ok(())
+    }
+
+    async fn update(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        mut reader: DropCloserReadHalf,
+        upload_size: UploadSizeInfo,
+    ) -> Result<(), Error> {
+        let mut header = Header {
+            version: CURRENT_STREAM_FORMAT_VERSION,
+            key_id: self.active_key_id,
+            chunk_size: self.chunk_size,
+            salt: [0u8; SALT_LEN],
+        };
+        self.rng
+            .fill(&mut header.salt)
+            .map_err(|_| make_err!(Code::Internal, "Failed to generate salt"))?;
+        let cipher = self.object_cipher(&header, &key)?;
+        let inner_upload_size = match upload_size {
+            UploadSizeInfo::ExactSize(size) => {
+                UploadSizeInfo::ExactSize(encrypted_size(size, self.chunk_size))
+            }
+            UploadSizeInfo::MaxSize(size) => {
+                UploadSizeInfo::MaxSize(encrypted_size(size, self.chunk_size))
+            }
+        };
+
+        let (mut tx, rx) = make_buf_channel_pair();
+
+        let inner_store = self.inner_store.clone();
+        let key = key.into_owned();
+        let update_fut = spawn!("encryption_store_update_spawn", async move {
+            inner_store
+                .update(key, rx, inner_upload_size)
+                .await
+                .err_tip(|| "Inner store update in encryption store failed")
+        })
+        .map(
+            |result| match result.err_tip(|| "Failed to run encryption update spawn") {
+                Ok(inner_result) => {
+                    inner_result.err_tip(|| "Encryption underlying store update failed")
+                }
+                Err(e) => Err(e),
+            },
+        );
+
+        let write_fut = async move {
+            tx.send(Bytes::copy_from_slice(&header.encode()))
+                .await
+                .err_tip(|| "Failed to write encryption header on upload")?;
+
+            let chunk_size = self.chunk_size as usize;
+            let mut chunk_index = 0;
+            loop {
+                let chunk = reader
+                    .consume(Some(chunk_size))
+                    .await
+                    .err_tip(|| "Failed to read chunk in update in encryption store")?;
+                // The last chunk is always written, even if it is empty, so
+                // a truncated stream can be detected on read.
+                let is_last = chunk.len() < chunk_size
+                    || reader
+                        .peek()
+                        .await
+                        .err_tip(|| "Failed to peek in update in encryption store")?
+                        .is_empty();
+                let encrypted_chunk = cipher.seal_chunk(chunk_index, is_last, &chunk)?;
+                tx.send(encrypted_chunk)
+                    .await
+                    .err_tip(|| "Failed to write chunk to inner store in encryption store")?;
+                if is_last {
+                    break;
+                }
+                chunk_index += 1;
+            }
+            tx.send_eof()
+                .err_tip(|| "Failed writing EOF in encryption store update")
+        };
+        let (write_result, update_result) = tokio::join!(write_fut, update_fut);
+        write_result.merge(update_result)
+    }
+
+    async fn get_part(
+        self: Pin<&Self>,
+        key: StoreKey<'_>,
+        writer: &mut DropCloserWriteHalf,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<(), Error> {
+        if is_zero_digest(key.borrow()) {
+            writer
+                .send_eof()
+                .err_tip(|| "Failed to send zero EOF in encryption store get_part")?;
+            return Ok(());
+        }
+
+        // Reads of the whole object are streamed in one request. Otherwise
+        // the header is needed first to find where the requested chunks are.
+        let header = if offset == 0 && length.is_none() {
+            None
+        } else {
+            let data = self
+                .inner_store
+                .get_part_unchunked(key.borrow(), 0, Some(HEADER_LEN))
+                .await
+                .err_tip(|| "Failed to read header in encryption store get_part")?;
+            Some(Header::decode(&data).err_tip(|| "In encryption store get_part")?)
+        };
+        let (first_chunk_index, skip, inner_offset, inner_length) = match header {
+            None => (0, 0, 0, None),
+            Some(header) => {
+                let chunk_size = u64::from(header.chunk_size);
+                // When the offset is on a chunk boundary the chunk before it
+                // is read as well, so there is always at least one chunk to
+                // tell if the offset is at the end of the object.
+                let first_chunk_index = offset.saturating_sub(1) / chunk_size;
+                let skip = offset - first_chunk_index * chunk_size;
+                let inner_offset = HEADER_LEN + first_chunk_index * (chunk_size + TAG_LEN);
+                let inner_length = length.map(|length| {
+                    let end_chunk_index = offset.saturating_add(length).div_ceil(chunk_size);
+                    let chunk_count = end_chunk_index.saturating_sub(first_chunk_index).max(1);
+                    chunk_count.saturating_mul(chunk_size + TAG_LEN)
+                });
+                (first_chunk_index, skip, inner_offset, inner_length)
+            }
+        };
+
+        let (tx, mut rx) = make_buf_channel_pair();
+
+        let inner_store = self.inner_store.clone();
+        let owned_key = key.borrow().into_owned();
+        let get_part_fut = spawn!("encryption_store_get_part_spawn", async move {
+            inner_store
+                .get_part(owned_key, tx, inner_offset, inner_length)
+                .await
+                .err_tip(|| "Inner store get in encryption store failed")
+        })
+        .map(
+            |result| match result.err_tip(|| "Failed to run encryption get spawn") {
+                Ok(inner_result) => {
+                    inner_result.err_tip(|| "Encryption underlying store get failed")
+                }
+                Err(e) => Err(e),
+            },
+        );
+        let read_fut = async {
+            let header = match header {
+                Some(header) => header,
+                None => {
+                    let data = rx
+                        .consume(Some(HEADER_LEN as usize))
+                        .await
+                        .err_tip(|| "Failed to read header in encryption store get_part")?;
+                    Header::decode(&data).err_tip(|| "In encryption store get_part")?
+                }
+            };
+            self.decrypt_chunks(
+                &key,
+                &header,
+                &mut rx,
+                writer,
+                first_chunk_index,
+                skip,
+                length,
+                inner_length.is_some(),
+            )
+            .await
+        };
+
+        let (read_result, get_part_fut_result) = tokio::join!(read_fut, get_part_fut);
+        if let Err(mut e) = read_result {
+            // We may need to propagate the error from reading the data through first.
+            if let Err(err) = get_part_fut_result {
+                e = err.merge(e);
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn inner_store(&self, _digest: Option<StoreKey>) -> &dyn StoreDriver {
+        self
+    }
+
+    fn as_any(&self) -> &(dyn std::any::Any + Sync + Send + 'static) {
+        self
+    }
+
+    fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
+        self
+    }
+}
+
+default_health_status_indicator!(EncryptionStore);
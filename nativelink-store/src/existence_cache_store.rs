@@ -14,7 +14,8 @@
 
 use std::borrow::Cow;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
 use std::time::SystemTime;
 
 use async_trait::async_trait;
@@ -26,7 +27,12 @@ use nativelink_util::common::DigestInfo;
 use nativelink_util::evicting_map::{EvictingMap, LenEntry};
 use nativelink_util::health_utils::{HealthStatus, HealthStatusIndicator};
 use nativelink_util::instant_wrapper::InstantWrapper;
+use nativelink_util::spawn;
 use nativelink_util::store_trait::{Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo};
+use nativelink_util::task::JoinHandleDropGuard;
+use tracing::{event, Level};
+
+use crate::redis_store::{PublishedKeyEvent, PublishedKeySubscription, RedisStore};
 
 #[derive(Clone, Debug)]
 struct ExistanceItem(u64);
@@ -48,11 +54,31 @@ pub struct ExistenceCacheStore<I: InstantWrapper> {
     #[metric(group = "inner_store")]
     inner_store: Store,
     existence_cache: EvictingMap<DigestInfo, ExistanceItem, I>,
+    /// Digests the backend recently reported as missing.
+    negative_cache: Option<EvictingMap<DigestInfo, ExistanceItem, I>>,
+    /// Incremented every time a digest is written or invalidated. Lookups
+    /// that raced with it may hold stale answers and do not cache them.
+    cache_generation: AtomicU64,
+    _invalidation_spawn: Option<JoinHandleDropGuard<()>>,
 }
 
 impl ExistenceCacheStore<SystemTime> {
-    pub fn new(config: &ExistenceCacheStoreConfig, inner_store: Store) -> Arc<Self> {
-        Self::new_with_time(config, inner_store, SystemTime::now())
+    pub fn new(config: &ExistenceCacheStoreConfig, inner_store: Store) -> Result<Arc<Self>, Error> {
+        let invalidation_subscription = config
+            .invalidation_redis_store
+            .as_ref()
+            .map(|redis_config| {
+                RedisStore::new(redis_config)?
+                    .subscribe_to_published_keys()
+                    .err_tip(|| "In ExistenceCacheStore::new")
+            })
+            .transpose()?;
+        Ok(Self::new_with_time_and_subscription(
+            config,
+            inner_store,
+            SystemTime::now(),
+            invalidation_subscription,
+        ))
     }
 }
 
@@ -61,15 +87,79 @@ impl<I: InstantWrapper> ExistenceCacheStore<I> {
         config: &ExistenceCacheStoreConfig,
         inner_store: Store,
         anchor_time: I,
+    ) -> Arc<Self> {
+        Self::new_with_time_and_subscription(config, inner_store, anchor_time, None)
+    }
+
+    fn new_with_time_and_subscription(
+        config: &ExistenceCacheStoreConfig,
+        inner_store: Store,
+        anchor_time: I,
+        invalidation_subscription: Option<PublishedKeySubscription>,
     ) -> Arc<Self> {
         let empty_policy = EvictionPolicy::default();
         let eviction_policy = config.eviction_policy.as_ref().unwrap_or(&empty_policy);
-        Arc::new(Self {
+        let negative_cache = (config.negative_cache_ttl_s != 0).then(|| {
+            EvictingMap::new(
+                &EvictionPolicy {
+                    max_seconds: config.negative_cache_ttl_s,
+                    max_count: eviction_policy.max_count,
+                    ..Default::default()
+                },
+                I::from_secs(anchor_time.unix_timestamp()),
+            )
+        });
+        Arc::new_cyclic(|weak_self| Self {
             inner_store,
             existence_cache: EvictingMap::new(eviction_policy, anchor_time),
+            negative_cache,
+            cache_generation: AtomicU64::new(0),
+            _invalidation_spawn: invalidation_subscription.map(|subscription| {
+                spawn!(
+                    "existence_cache_invalidation",
+                    Self::invalidation_loop(weak_self.clone(), subscription)
+                )
+            }),
         })
     }
 
+    async fn invalidation_loop(weak_self: Weak<Self>, mut subscription: PublishedKeySubscription) {
+        while let Some(event) = subscription.recv().await {
+            let Some(this) = weak_self.upgrade() else {
+                return;
+            };
+            match event {
+                PublishedKeyEvent::Changed(key) => {
+                    // Keys that are not digests can't be in our caches.
+                    let Some((hash, size)) = key.split_once('-') else {
+                        continue;
+                    };
+                    let Ok(size) = size.parse::<u64>() else {
+                        continue;
+                    };
+                    if let Ok(digest) = DigestInfo::try_new(hash, size) {
+                        this.remove_from_cache(&digest).await;
+                    }
+                }
+                PublishedKeyEvent::Lagged => {
+                    event!(
+                        Level::WARN,
+                        "Missed invalidations in ExistenceCacheStore, clearing caches"
+                    );
+                    this.cache_generation.fetch_add(1, Ordering::SeqCst);
+                    this.existence_cache.clear().await;
+                    if let Some(negative_cache) = &this.negative_cache {
+                        negative_cache.clear().await;
+                    }
+                }
+            }
+        }
+        event!(
+            Level::ERROR,
+            "ExistenceCacheStore invalidation subscription ended"
+        );
+    }
+
     pub async fn exists_in_cache(&self, digest: &DigestInfo) -> bool {
         let mut results = [None];
         self.existence_cache
@@ -78,8 +168,23 @@ impl<I: InstantWrapper> ExistenceCacheStore<I> {
         results[0].is_some()
     }
 
+    pub async fn exists_in_negative_cache(&self, digest: &DigestInfo) -> bool {
+        let Some(negative_cache) = &self.negative_cache else {
+            return false;
+        };
+        let mut results = [None];
+        negative_cache
+            .sizes_for_keys([digest], &mut results[..], true /* peek */)
+            .await;
+        results[0].is_some()
+    }
+
     pub async fn remove_from_cache(&self, digest: &DigestInfo) {
+        self.cache_generation.fetch_add(1, Ordering::SeqCst);
         self.existence_cache.remove(digest).await;
+        if let Some(negative_cache) = &self.negative_cache {
+            negative_cache.remove(digest).await;
+        }
     }
 
     async fn inner_has_with_results(
@@ -91,10 +196,21 @@ impl<I: InstantWrapper> ExistenceCacheStore<I> {
             .sizes_for_keys(keys, results, true /* peek */)
             .await;
 
+        // Digests the backend recently reported as missing are answered as
+        // missing without asking the backend again.
+        let mut known_missing = vec![None; keys.len()];
+        if let Some(negative_cache) = &self.negative_cache {
+            negative_cache
+                .sizes_for_keys(keys, &mut known_missing, true /* peek */)
+                .await;
+        }
+
         let not_cached_keys: Vec<_> = keys
             .iter()
-            .zip(results.iter())
-            .filter_map(|(digest, result)| result.map_or_else(|| Some(digest.into()), |_| None))
+            .zip(results.iter().zip(known_missing.iter()))
+            .filter_map(|(digest, (result, missing))| {
+                (result.is_none() && missing.is_none()).then(|| digest.into())
+            })
             .collect();
 
         // Hot path optimization when all keys are cached.
@@ -103,6 +219,7 @@ impl<I: InstantWrapper> ExistenceCacheStore<I> {
         }
 
         // Now query only the items not found in the cache.
+        let cache_generation = self.cache_generation.load(Ordering::SeqCst);
         let mut inner_results = vec![None; not_cached_keys.len()];
         self.inner_store
             .has_with_results(&not_cached_keys, &mut inner_results)
@@ -123,13 +240,36 @@ impl<I: InstantWrapper> ExistenceCacheStore<I> {
             let _ = self.existence_cache.insert_many(inserts).await;
         }
 
+        if let Some(negative_cache) = &self.negative_cache {
+            let inserts = not_cached_keys
+                .iter()
+                .zip(inner_results.iter())
+                .filter(|(_, result)| result.is_none())
+                .map(|(key, _)| (key.borrow().into_digest(), ExistanceItem(0)))
+                .collect::<Vec<_>>();
+            let _ = negative_cache.insert_many(inserts).await;
+        }
+
+        // A digest written or invalidated while the backend was queried may
+        // have been removed from the caches before the answers above were
+        // inserted, so take them out again.
+        if self.cache_generation.load(Ordering::SeqCst) != cache_generation {
+            for key in &not_cached_keys {
+                let digest = key.borrow().into_digest();
+                self.existence_cache.remove(&digest).await;
+                if let Some(negative_cache) = &self.negative_cache {
+                    negative_cache.remove(&digest).await;
+                }
+            }
+        }
+
         // Merge the results from the cache and the query.
         {
             let mut inner_results_iter = inner_results.into_iter();
             // We know at this point that any None in results was queried and will have
             // a result in inner_results_iter, so use this knowledge to fill in the results.
-            for result in results.iter_mut() {
-                if result.is_none() {
+            for (result, missing) in results.iter_mut().zip(known_missing) {
+                if result.is_none() && missing.is_none() {
                     *result = inner_results_iter
                         .next()
                         .expect("has_with_results returned less results than expected");
@@ -186,6 +326,10 @@ impl<I: InstantWrapper> StoreDriver for ExistenceCacheStore<I> {
         }
         let result = self.inner_store.update(digest, reader, size_info).await;
         if result.is_ok() {
+            if let Some(negative_cache) = &self.negative_cache {
+                self.cache_generation.fetch_add(1, Ordering::SeqCst);
+                negative_cache.remove(&digest).await;
+            }
             if let UploadSizeInfo::ExactSize(size) = size_info {
                 let _ = self
                     .existence_cache
@@ -217,6 +361,13 @@ impl<I: InstantWrapper> StoreDriver for ExistenceCacheStore<I> {
         result
     }
 
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        let digest = key.into_digest();
+        let result = self.inner_store.remove(digest).await;
+        self.remove_from_cache(&digest).await;
+        result
+    }
+
     fn inner_store(&self, _digest: Option<StoreKey>) -> &dyn StoreDriver {
         self
     }
@@ -0,0 +1,371 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::iter;
+use std::path::{Path, PathBuf};
+
+use futures::stream::{StreamExt, TryStreamExt};
+use nativelink_error::{make_input_err, Error, ResultExt};
+use nativelink_util::common::{fs, DigestInfo};
+use nativelink_util::digest_hasher::{DigestHasher, DigestHasherFunc};
+use tokio_stream::wrappers::ReadDirStream;
+use tracing::{event, Level};
+
+use crate::filesystem_store::{digest_from_filename, DEFAULT_BLOCK_SIZE};
+
+/// Default number of files that will be checked at the same time.
+pub const DEFAULT_MAX_CONCURRENT_CHECKS: usize = 16;
+
+/// Options that control what `fsck_filesystem_store` checks.
+#[derive(Debug, Clone)]
+pub struct FsckOptions {
+    /// Hash function used to rehash the content of each file. A store may
+    /// hold content of several digest functions, so files that don't match
+    /// are also rehashed with the other functions of the same hash size.
+    pub hasher: DigestHasherFunc,
+
+    /// Check that the size of each file matches the size in its name.
+    pub verify_size: bool,
+
+    /// Check that the hash of each file matches the hash in its name.
+    /// This should be disabled for stores used as an action cache.
+    pub verify_hash: bool,
+
+    /// If set, every problematic file will be moved into this directory.
+    /// Otherwise problems are only reported.
+    pub quarantine_path: Option<PathBuf>,
+
+    /// Maximum number of files that will be checked concurrently.
+    pub max_concurrent_checks: usize,
+}
+
+impl Default for FsckOptions {
+    fn default() -> Self {
+        Self {
+            hasher: DigestHasherFunc::Sha256,
+            verify_size: true,
+            verify_hash: true,
+            quarantine_path: None,
+            max_concurrent_checks: DEFAULT_MAX_CONCURRENT_CHECKS,
+        }
+    }
+}
+
+/// The reason a file was reported by fsck.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsckProblemKind {
+    /// The file name in the content path could not be parsed as a digest.
+    InvalidFileName,
+    /// The size of the file does not match the size of its digest.
+    SizeMismatch { actual_size: u64 },
+    /// The hash of the file does not match the hash of its digest.
+    HashMismatch { actual_digest: DigestInfo },
+    /// The file was left in the temp path by an upload or delete that
+    /// never finished.
+    OrphanedTempFile,
+    /// The file could not be read.
+    ReadFailed(Error),
+}
+
+/// A single file that failed a check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsckProblem {
+    /// Full path of the file.
+    pub path: PathBuf,
+    /// Digest parsed from the file name, if it could be parsed.
+    pub digest: Option<DigestInfo>,
+    /// What is wrong with the file.
+    pub kind: FsckProblemKind,
+}
+
+/// Summary of a content directory check.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FsckReport {
+    /// Number of files in the content path that were checked.
+    pub files_checked: u64,
+    /// Sum of the sizes of all files in the content path that passed.
+    pub data_size: u64,
+    /// Size the passing files are accounted as in the store's eviction
+    /// policy, which rounds every file up to a multiple of `block_size`.
+    pub accounted_size_on_disk: u64,
+    /// Size the passing files actually occupy on disk as reported by the
+    /// filesystem. On non-unix systems this is the same as `data_size`.
+    pub actual_size_on_disk: u64,
+    /// Files that failed a check.
+    pub problems: Vec<FsckProblem>,
+    /// Number of problematic files that were moved to the quarantine path.
+    pub files_quarantined: u64,
+}
+
+impl FsckReport {
+    pub(crate) fn merge(&mut self, other: FsckReport) {
+        self.files_checked += other.files_checked;
+        self.data_size += other.data_size;
+        self.accounted_size_on_disk += other.accounted_size_on_disk;
+        self.actual_size_on_disk += other.actual_size_on_disk;
+        self.problems.extend(other.problems);
+        self.files_quarantined += other.files_quarantined;
+    }
+}
+
+/// Checks every file in the content and temp paths of all the roots of a
+/// `FilesystemStore` that is not running.
+///
+/// Every file in the temp path is reported as orphaned, since a running
+/// store is the only thing that would be using them. If
+/// `FsckOptions::quarantine_path` is set, all reported files are moved
+/// there, so the next start of the store will not load them.
+pub async fn fsck_filesystem_store(
+    config: &nativelink_config::stores::FilesystemStore,
+    options: &FsckOptions,
+) -> Result<FsckReport, Error> {
+    if options.max_concurrent_checks == 0 {
+        return Err(make_input_err!(
+            "max_concurrent_checks must be greater than zero"
+        ));
+    }
+    let block_size = if config.block_size == 0 {
+        DEFAULT_BLOCK_SIZE
+    } else {
+        config.block_size
+    };
+    if let Some(quarantine_path) = &options.quarantine_path {
+        fs::create_dir_all(quarantine_path)
+            .await
+            .err_tip(|| format!("Failed to create quarantine directory {quarantine_path:?}"))?;
+    }
+
+    let roots = std::iter::once((&config.content_path, &config.temp_path)).chain(
+        config
+            .additional_roots
+            .iter()
+            .map(|root| (&root.content_path, &root.temp_path)),
+    );
+    let mut report = FsckReport::default();
+    for (content_path, temp_path) in roots {
+        report.merge(
+            check_content_path(Path::new(content_path), block_size, options)
+                .await
+                .err_tip(|| format!("While checking {content_path} in fsck_filesystem_store"))?,
+        );
+
+        for path in list_files(Path::new(temp_path))
+            .await
+            .err_tip(|| format!("While listing {temp_path} in fsck_filesystem_store"))?
+        {
+            report.problems.push(FsckProblem {
+                path,
+                digest: None,
+                kind: FsckProblemKind::OrphanedTempFile,
+            });
+        }
+    }
+
+    if let Some(quarantine_path) = &options.quarantine_path {
+        for problem in &report.problems {
+            if let FsckProblemKind::ReadFailed(_) = problem.kind {
+                // The file may still be good, so leave it alone.
+                continue;
+            }
+            let to_path = quarantine_file_path(quarantine_path, &problem.path)?;
+            match fs::rename(&problem.path, &to_path).await {
+                Ok(()) => report.files_quarantined += 1,
+                Err(err) => event!(
+                    Level::ERROR,
+                    from_path = ?problem.path,
+                    ?to_path,
+                    ?err,
+                    "Failed to quarantine file",
+                ),
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Checks every file in `content_path` against the digest in its name.
+/// Nothing is modified on disk.
+pub(crate) async fn check_content_path(
+    content_path: &Path,
+    block_size: u64,
+    options: &FsckOptions,
+) -> Result<FsckReport, Error> {
+    let paths = list_files(content_path)
+        .await
+        .err_tip(|| "While listing content path in fsck")?;
+    let reports: Vec<FsckReport> = futures::stream::iter(paths)
+        .map(|path| async move {
+            let mut report = FsckReport::default();
+            check_content_file(&path, block_size, options, &mut report).await;
+            report
+        })
+        .buffer_unordered(options.max_concurrent_checks.max(1))
+        .collect()
+        .await;
+    let mut total = FsckReport::default();
+    for report in reports {
+        total.merge(report);
+    }
+    Ok(total)
+}
+
+/// Checks a single file in the content path and records the result in
+/// `report`.
+pub(crate) async fn check_content_file(
+    path: &Path,
+    block_size: u64,
+    options: &FsckOptions,
+    report: &mut FsckReport,
+) {
+    report.files_checked += 1;
+    let digest = match path
+        .file_name()
+        .and_then(|file_name| file_name.to_str())
+        .err_tip(|| "File name is not utf8")
+        .and_then(digest_from_filename)
+    {
+        Ok(digest) => digest,
+        Err(_) => {
+            report.problems.push(FsckProblem {
+                path: path.to_path_buf(),
+                digest: None,
+                kind: FsckProblemKind::InvalidFileName,
+            });
+            return;
+        }
+    };
+    let kind = match verify_file(path, digest, options).await {
+        Ok(Some(kind)) => kind,
+        Ok(None) => return record_good_file(path, digest, block_size, report).await,
+        Err(err) => FsckProblemKind::ReadFailed(err),
+    };
+    event!(Level::WARN, ?path, ?digest, ?kind, "Fsck found a bad file");
+    report.problems.push(FsckProblem {
+        path: path.to_path_buf(),
+        digest: Some(digest),
+        kind,
+    });
+}
+
+async fn verify_file(
+    path: &Path,
+    digest: DigestInfo,
+    options: &FsckOptions,
+) -> Result<Option<FsckProblemKind>, Error> {
+    let actual_size = fs::metadata(path)
+        .await
+        .err_tip(|| format!("Failed to read metadata of {path:?}"))?
+        .len();
+    if options.verify_size && actual_size != digest.size_bytes() {
+        return Ok(Some(FsckProblemKind::SizeMismatch { actual_size }));
+    }
+    if options.verify_hash {
+        // The digest of the configured hash function is the one reported.
+        let mut first_actual_digest = None;
+        for hasher in candidate_hashers(options.hasher, digest.packed_hash().len()) {
+            let file = fs::open_file(path, u64::MAX)
+                .await
+                .err_tip(|| format!("Failed to open {path:?}"))?;
+            let (actual_digest, _file) = hasher
+                .hasher()
+                .digest_for_file(file, Some(actual_size))
+                .await
+                .err_tip(|| format!("Failed to hash {path:?}"))?;
+            if actual_digest.packed_hash() == digest.packed_hash() {
+                return Ok(None);
+            }
+            first_actual_digest.get_or_insert(actual_digest);
+        }
+        if let Some(actual_digest) = first_actual_digest {
+            return Ok(Some(FsckProblemKind::HashMismatch { actual_digest }));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the hash functions that produce hashes of `hash_size` bytes,
+/// starting with `preferred`. File names don't record the hash function, so
+/// these are the ones the content of a file may have been hashed with.
+fn candidate_hashers(
+    preferred: DigestHasherFunc,
+    hash_size: usize,
+) -> impl Iterator<Item = DigestHasherFunc> {
+    let others = [
+        DigestHasherFunc::Sha256,
+        DigestHasherFunc::Blake3,
+        DigestHasherFunc::Sha384,
+        DigestHasherFunc::Sha512,
+        DigestHasherFunc::Sha256Tree,
+    ]
+    .into_iter()
+    .filter(move |hasher| *hasher != preferred);
+    iter::once(preferred)
+        .chain(others)
+        .filter(move |hasher| hasher.hash_size() == hash_size)
+}
+
+async fn record_good_file(
+    path: &Path,
+    digest: DigestInfo,
+    block_size: u64,
+    report: &mut FsckReport,
+) {
+    let metadata = match fs::metadata(path).await {
+        Ok(metadata) => metadata,
+        Err(err) => {
+            event!(Level::WARN, ?path, ?digest, ?err, "Failed to read metadata");
+            return;
+        }
+    };
+    let data_size = metadata.len();
+    report.data_size += data_size;
+    report.accounted_size_on_disk += data_size.div_ceil(block_size) * block_size;
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::fs::MetadataExt;
+        // `blocks()` is always in units of 512 bytes.
+        report.actual_size_on_disk += metadata.blocks() * 512;
+    }
+    #[cfg(not(target_family = "unix"))]
+    {
+        report.actual_size_on_disk += data_size;
+    }
+}
+
+/// Returns the full paths of all regular files in `dir`.
+async fn list_files(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let (_permit, dir_handle) = fs::read_dir(dir)
+        .await
+        .err_tip(|| format!("Failed opening directory {dir:?}"))?
+        .into_inner();
+    ReadDirStream::new(dir_handle)
+        .map_err(Error::from)
+        .try_filter_map(|dir_entry| async move {
+            let file_type = dir_entry
+                .file_type()
+                .await
+                .err_tip(|| format!("Failed to get file type of {:?}", dir_entry.path()))?;
+            Ok(file_type.is_file().then(|| dir_entry.path()))
+        })
+        .try_collect()
+        .await
+}
+
+/// Returns the path `path` should be moved to inside `quarantine_path`.
+pub(crate) fn quarantine_file_path(quarantine_path: &Path, path: &Path) -> Result<PathBuf, Error> {
+    let file_name = path
+        .file_name()
+        .err_tip(|| format!("Could not get file name of {path:?}"))?;
+    Ok(quarantine_path.join(file_name))
+}
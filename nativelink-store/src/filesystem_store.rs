@@ -13,10 +13,13 @@
 // limitations under the License.
 
 use std::borrow::Cow;
+use std::collections::BTreeSet;
 use std::ffi::{OsStr, OsString};
 use std::fmt::{Debug, Formatter};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Weak};
 use std::time::{Duration, SystemTime};
 
@@ -32,6 +35,7 @@ use nativelink_util::buf_channel::{
     make_buf_channel_pair, DropCloserReadHalf, DropCloserWriteHalf,
 };
 use nativelink_util::common::{fs, DigestInfo};
+use nativelink_util::digest_hasher::{default_digest_hasher_func, DigestHasherFunc};
 use nativelink_util::evicting_map::{EvictingMap, LenEntry};
 use nativelink_util::health_utils::{HealthRegistryBuilder, HealthStatus, HealthStatusIndicator};
 use nativelink_util::store_trait::{StoreDriver, StoreKey, StoreOptimizations, UploadSizeInfo};
@@ -42,11 +46,14 @@ use tokio_stream::wrappers::ReadDirStream;
 use tracing::{event, Level};
 
 use crate::cas_utils::is_zero_digest;
+use crate::filesystem_fsck::{
+    check_content_path, quarantine_file_path, FsckOptions, FsckProblemKind, FsckReport,
+};
 
 // Default size to allocate memory of the buffer when reading files.
 const DEFAULT_BUFF_SIZE: usize = 32 * 1024;
 // Default block size of all major filesystems is 4KB
-const DEFAULT_BLOCK_SIZE: u64 = 4 * 1024;
+pub(crate) const DEFAULT_BLOCK_SIZE: u64 = 4 * 1024;
 
 #[derive(Debug, MetricsComponent)]
 pub struct SharedContext {
@@ -301,13 +308,14 @@ impl Debug for FileEntryImpl {
 
 fn make_temp_digest(digest: &mut DigestInfo) {
     static DELETE_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
-    let mut hash = *digest.packed_hash();
-    hash[24..].clone_from_slice(
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&digest.packed_hash()[..32]);
+    hash[24..32].clone_from_slice(
         &DELETE_FILE_COUNTER
             .fetch_add(1, Ordering::Relaxed)
             .to_le_bytes(),
     );
-    digest.set_packed_hash(*hash);
+    digest.set_packed_hash(hash.into());
 }
 
 impl LenEntry for FileEntryImpl {
@@ -519,16 +527,209 @@ async fn prune_temp_path(temp_path: &str) -> Result<(), Error> {
     Ok(())
 }
 
+/// Copies `src` to the new file `dst` and returns the number of bytes
+/// copied. The source file is closed while a write takes too long, so the
+/// copy never holds two file descriptors while it waits.
+async fn copy_file(src: OsString, dst: OsString, read_buffer_size: usize) -> Result<u64, Error> {
+    let mut src_file = fs::open_file(&src, u64::MAX)
+        .await
+        .err_tip(|| format!("Failed to open {src:?} in copy_file"))?;
+    let dst_file = fs::create_file(&dst)
+        .await
+        .err_tip(|| format!("Failed to create {dst:?} in copy_file"))?;
+    let (_, (mut dst_file, data_size)) = src_file
+        .read_buf_cb(
+            (BytesMut::with_capacity(read_buffer_size), (dst_file, 0)),
+            |(buf, (mut dst_file, data_size))| async move {
+                dst_file
+                    .as_writer()
+                    .await
+                    .err_tip(|| "in filesystem_store::copy_file")?
+                    .write_all(&buf)
+                    .await
+                    .err_tip(|| "Failed to write data in filesystem_store::copy_file")?;
+                let data_size = data_size + buf.len() as u64;
+                Ok((buf, (dst_file, data_size)))
+            },
+        )
+        .await?;
+    dst_file
+        .as_writer()
+        .await
+        .err_tip(|| "in filesystem_store::copy_file")?
+        .sync_all()
+        .await
+        .err_tip(|| format!("Failed to sync {dst:?} in copy_file"))?;
+    Ok(data_size)
+}
+
+/// Identifies a version of a file's content without reading it. Writing
+/// to a file changes its mtime and ctime, and replacing it changes its
+/// inode, so a file with the same identity still has the same content.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct FileIdentity {
+    dev: u64,
+    ino: u64,
+    size: u64,
+    mtime: (i64, i64),
+    ctime: (i64, i64),
+}
+
+impl FileIdentity {
+    #[cfg(target_family = "unix")]
+    fn from_metadata(metadata: &std::fs::Metadata) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt;
+        Some(Self {
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+            size: metadata.size(),
+            mtime: (metadata.mtime(), metadata.mtime_nsec()),
+            ctime: (metadata.ctime(), metadata.ctime_nsec()),
+        })
+    }
+
+    // Windows has no stable equivalent of an inode and ctime, so nothing
+    // is cached there.
+    #[cfg(not(target_family = "unix"))]
+    fn from_metadata(_metadata: &std::fs::Metadata) -> Option<Self> {
+        None
+    }
+}
+
+#[derive(Clone, Debug)]
+struct HashCacheEntry(DigestInfo);
+
+impl LenEntry for HashCacheEntry {
+    #[inline]
+    fn len(&self) -> u64 {
+        self.0.size_bytes()
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(target_family = "unix")]
+fn device_id(metadata: &std::fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.dev())
+}
+
+#[cfg(not(target_family = "unix"))]
+fn device_id(_metadata: &std::fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// A content path and temp path pair, usually one per drive. Every root
+/// has its own eviction map, so capacity is accounted per root.
 #[derive(MetricsComponent)]
-pub struct FilesystemStore<Fe: FileEntry = FileEntryImpl> {
+struct ContentRoot<Fe: FileEntry> {
     #[metric]
     shared_context: Arc<SharedContext>,
     #[metric(group = "evicting_map")]
     evicting_map: Arc<EvictingMap<DigestInfo, Arc<Fe>, SystemTime>>,
+    /// Cleared when a health check fails to write to this root or an I/O
+    /// error happens in it. Unhealthy roots are not read from or written to.
+    healthy: AtomicBool,
+    /// False if the root could not be set up when the store was created.
+    /// Such a root stays unhealthy.
+    loaded: bool,
+    /// Device id of `content_path`, if the platform has one.
+    device: Option<u64>,
+}
+
+impl<Fe: FileEntry> ContentRoot<Fe> {
+    async fn new(
+        content_path: &str,
+        temp_path: &str,
+        eviction_policy: Option<&nativelink_config::stores::EvictionPolicy>,
+        anchor_time: SystemTime,
+        block_size: u64,
+    ) -> Result<Self, Error> {
+        let empty_policy = nativelink_config::stores::EvictionPolicy::default();
+        let eviction_policy = eviction_policy.unwrap_or(&empty_policy);
+        let evicting_map = Arc::new(EvictingMap::new(eviction_policy, anchor_time));
+
+        fs::create_dir_all(temp_path)
+            .await
+            .err_tip(|| format!("Failed to temp directory {temp_path:?}"))?;
+        fs::create_dir_all(content_path)
+            .await
+            .err_tip(|| format!("Failed to content directory {content_path:?}"))?;
+        let device = device_id(
+            &fs::metadata(content_path)
+                .await
+                .err_tip(|| format!("Failed to stat content directory {content_path:?}"))?,
+        );
+
+        let shared_context = Arc::new(SharedContext {
+            active_drop_spawns: AtomicU64::new(0),
+            temp_path: temp_path.to_string(),
+            content_path: content_path.to_string(),
+        });
+
+        add_files_to_cache(
+            evicting_map.as_ref(),
+            &anchor_time,
+            &shared_context,
+            block_size,
+        )
+        .await?;
+        prune_temp_path(&shared_context.temp_path).await?;
+
+        Ok(Self {
+            shared_context,
+            evicting_map,
+            healthy: AtomicBool::new(true),
+            loaded: true,
+            device,
+        })
+    }
+
+    /// Returns a root that is never used, for a root that failed to be set
+    /// up, so it still shows up in metrics and health checks.
+    fn unloaded(content_path: &str, temp_path: &str, anchor_time: SystemTime) -> Self {
+        Self {
+            shared_context: Arc::new(SharedContext {
+                active_drop_spawns: AtomicU64::new(0),
+                temp_path: temp_path.to_string(),
+                content_path: content_path.to_string(),
+            }),
+            evicting_map: Arc::new(EvictingMap::new(
+                &nativelink_config::stores::EvictionPolicy::default(),
+                anchor_time,
+            )),
+            healthy: AtomicBool::new(false),
+            loaded: false,
+            device: None,
+        }
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Acquire)
+    }
+
+    /// Creates and deletes a file in the temp path to make sure the drive
+    /// is still writable.
+    async fn check_writable(&self) -> Result<(), Error> {
+        let path = format!("{}/.health_check", self.shared_context.temp_path);
+        drop(fs::create_file(&path).await?);
+        fs::remove_file(&path).await
+    }
+}
+
+#[derive(MetricsComponent)]
+pub struct FilesystemStore<Fe: FileEntry = FileEntryImpl> {
+    #[metric(group = "roots")]
+    roots: Vec<ContentRoot<Fe>>,
     #[metric(help = "Block size of the configured filesystem")]
     block_size: u64,
     #[metric(help = "Size of the configured read buffer size")]
     read_buffer_size: usize,
+    #[metric(group = "hash_cache")]
+    hash_cache: Option<EvictingMap<(FileIdentity, DigestHasherFunc), HashCacheEntry, SystemTime>>,
     weak_self: Weak<Self>,
     sleep_fn: fn(Duration) -> Sleep,
     rename_fn: fn(&OsStr, &OsStr) -> Result<(), std::io::Error>,
@@ -549,60 +750,338 @@ impl<Fe: FileEntry> FilesystemStore<Fe> {
     ) -> Result<Arc<Self>, Error> {
         let now = SystemTime::now();
 
-        let empty_policy = nativelink_config::stores::EvictionPolicy::default();
-        let eviction_policy = config.eviction_policy.as_ref().unwrap_or(&empty_policy);
-        let evicting_map = Arc::new(EvictingMap::new(eviction_policy, now));
-
-        fs::create_dir_all(&config.temp_path)
-            .await
-            .err_tip(|| format!("Failed to temp directory {:?}", &config.temp_path))?;
-        fs::create_dir_all(&config.content_path)
-            .await
-            .err_tip(|| format!("Failed to content directory {:?}", &config.content_path))?;
-
-        let shared_context = Arc::new(SharedContext {
-            active_drop_spawns: AtomicU64::new(0),
-            temp_path: config.temp_path.clone(),
-            content_path: config.content_path.clone(),
-        });
-
         let block_size = if config.block_size == 0 {
             DEFAULT_BLOCK_SIZE
         } else {
             config.block_size
         };
-        add_files_to_cache(evicting_map.as_ref(), &now, &shared_context, block_size).await?;
-        prune_temp_path(&shared_context.temp_path).await?;
+        let mut roots = Vec::with_capacity(config.additional_roots.len() + 1);
+        roots.push(
+            ContentRoot::new(
+                &config.content_path,
+                &config.temp_path,
+                config.eviction_policy.as_ref(),
+                now,
+                block_size,
+            )
+            .await?,
+        );
+        for root in &config.additional_roots {
+            let content_root = ContentRoot::new(
+                &root.content_path,
+                &root.temp_path,
+                root.eviction_policy.as_ref(),
+                now,
+                block_size,
+            )
+            .await;
+            roots.push(content_root.unwrap_or_else(|err| {
+                event!(
+                    Level::ERROR,
+                    ?err,
+                    content_path = ?root.content_path,
+                    "Failed to load filesystem store root, skipping it",
+                );
+                ContentRoot::unloaded(&root.content_path, &root.temp_path, now)
+            }));
+        }
 
         let read_buffer_size = if config.read_buffer_size == 0 {
             DEFAULT_BUFF_SIZE
         } else {
             config.read_buffer_size as usize
         };
-        Ok(Arc::new_cyclic(|weak_self| Self {
-            shared_context,
-            evicting_map,
+        let hash_cache = (config.hash_cache_max_entries > 0).then(|| {
+            EvictingMap::new(
+                &nativelink_config::stores::EvictionPolicy {
+                    max_count: config.hash_cache_max_entries,
+                    ..Default::default()
+                },
+                now,
+            )
+        });
+        let store = Arc::new_cyclic(|weak_self| Self {
+            roots,
             block_size,
             read_buffer_size,
+            hash_cache,
             weak_self: weak_self.clone(),
             sleep_fn,
             rename_fn,
-        }))
+        });
+        if config.scrub_interval_s > 0 {
+            store.start_background_scrub(
+                Duration::from_secs(config.scrub_interval_s),
+                config.scrub_quarantine_path.as_ref().map(PathBuf::from),
+            );
+        }
+        Ok(store)
+    }
+
+    fn start_background_scrub(&self, interval: Duration, quarantine_path: Option<PathBuf>) {
+        let weak_self = self.weak_self.clone();
+        background_spawn!("filesystem_store_scrub", async move {
+            loop {
+                sleep(interval).await;
+                let Some(store) = weak_self.upgrade() else {
+                    return;
+                };
+                let options = FsckOptions {
+                    hasher: default_digest_hasher_func(),
+                    quarantine_path: quarantine_path.clone(),
+                    // Keep the impact on a live store low.
+                    max_concurrent_checks: 1,
+                    ..Default::default()
+                };
+                match store.scrub(&options).await {
+                    Ok(report) => event!(
+                        Level::INFO,
+                        files_checked = report.files_checked,
+                        problems = report.problems.len(),
+                        data_size = report.data_size,
+                        accounted_size_on_disk = report.accounted_size_on_disk,
+                        actual_size_on_disk = report.actual_size_on_disk,
+                        "Finished filesystem store scrub",
+                    ),
+                    Err(err) => event!(Level::ERROR, ?err, "Filesystem store scrub failed"),
+                }
+            }
+        });
+    }
+
+    /// Checks every file in the content path of every healthy root against
+    /// `options` and evicts the entries whose file does not match its
+    /// digest. If `FsckOptions::quarantine_path` is set, bad files are hard
+    /// linked there before they are evicted.
+    ///
+    /// This is safe to run on a live store. Files in the temp path are not
+    /// checked, since they may belong to in-flight uploads.
+    pub async fn scrub(&self, options: &FsckOptions) -> Result<FsckReport, Error> {
+        if let Some(quarantine_path) = &options.quarantine_path {
+            fs::create_dir_all(quarantine_path)
+                .await
+                .err_tip(|| format!("Failed to create quarantine directory {quarantine_path:?}"))?;
+        }
+        let mut report = FsckReport::default();
+        for root in self.roots.iter().filter(|root| root.is_healthy()) {
+            let mut root_report = check_content_path(
+                Path::new(&root.shared_context.content_path),
+                self.block_size,
+                options,
+            )
+            .await
+            .err_tip(|| "In FilesystemStore::scrub")?;
+            Self::evict_scrub_problems(root, &mut root_report, options).await?;
+            report.merge(root_report);
+        }
+        Ok(report)
+    }
+
+    async fn evict_scrub_problems(
+        root: &ContentRoot<Fe>,
+        report: &mut FsckReport,
+        options: &FsckOptions,
+    ) -> Result<(), Error> {
+        for problem in &report.problems {
+            let Some(digest) = problem.digest else {
+                // Files with invalid names were never loaded into the store.
+                continue;
+            };
+            if let FsckProblemKind::ReadFailed(_) = problem.kind {
+                // Most likely the file was evicted while it was being checked.
+                continue;
+            }
+            if let Some(quarantine_path) = &options.quarantine_path {
+                let to_path = quarantine_file_path(quarantine_path, &problem.path)?;
+                match fs::hard_link(&problem.path, &to_path).await {
+                    Ok(()) => report.files_quarantined += 1,
+                    Err(err) => event!(
+                        Level::ERROR,
+                        from_path = ?problem.path,
+                        ?to_path,
+                        ?err,
+                        "Failed to quarantine file",
+                    ),
+                }
+            }
+            event!(
+                Level::WARN,
+                ?digest,
+                kind = ?problem.kind,
+                "Evicting corrupt file from filesystem store",
+            );
+            root.evicting_map.remove(&digest).await;
+        }
+        Ok(())
     }
 
     pub fn get_arc(&self) -> Option<Arc<Self>> {
         self.weak_self.upgrade()
     }
 
-    pub async fn get_file_entry_for_digest(&self, digest: &DigestInfo) -> Result<Arc<Fe>, Error> {
-        self.evicting_map
-            .get(digest)
+    /// Records that the file at `path` holds the content of `digest`, as
+    /// computed by `hasher`, for example because it was hard linked from
+    /// this store. Does nothing if the hash cache is disabled.
+    pub async fn record_file_digest(
+        &self,
+        path: impl AsRef<Path>,
+        digest: DigestInfo,
+        hasher: DigestHasherFunc,
+    ) -> Result<(), Error> {
+        let Some(hash_cache) = &self.hash_cache else {
+            return Ok(());
+        };
+        let metadata = fs::metadata(path.as_ref())
+            .await
+            .err_tip(|| format!("In record_file_digest for {:?}", path.as_ref()))?;
+        if let Some(identity) = FileIdentity::from_metadata(&metadata) {
+            hash_cache
+                .insert((identity, hasher), HashCacheEntry(digest))
+                .await;
+        }
+        Ok(())
+    }
+
+    /// Returns the digest recorded by `record_file_digest` with `hasher` for
+    /// the file with `metadata`, if the file has not changed since.
+    pub async fn cached_file_digest(
+        &self,
+        metadata: &std::fs::Metadata,
+        hasher: DigestHasherFunc,
+    ) -> Option<DigestInfo> {
+        let identity = FileIdentity::from_metadata(metadata)?;
+        self.hash_cache
+            .as_ref()?
+            .get(&(identity, hasher))
+            .await
+            .map(|entry| entry.0)
+    }
+
+    /// Marks `root` unhealthy after an I/O error in it, so it is skipped
+    /// until a health check finds it writable again. Does nothing if it is
+    /// the only root.
+    fn mark_unhealthy(&self, root: &ContentRoot<Fe>, err: &Error) {
+        if self.roots.len() == 1 {
+            return;
+        }
+        if root.healthy.swap(false, Ordering::AcqRel) {
+            event!(
+                Level::ERROR,
+                ?err,
+                content_path = ?root.shared_context.content_path,
+                "Marking filesystem store root unhealthy after I/O error",
+            );
+        }
+    }
+
+    /// Returns the healthy roots in the order they are searched for
+    /// `digest`, starting with the root its hash maps to.
+    fn roots_for_digest<'a>(
+        &'a self,
+        digest: &DigestInfo,
+    ) -> impl Iterator<Item = &'a ContentRoot<Fe>> + 'a {
+        let mut hash_prefix = [0u8; 8];
+        hash_prefix.copy_from_slice(&digest.packed_hash()[..8]);
+        let start = (u64::from_le_bytes(hash_prefix) % self.roots.len() as u64) as usize;
+        self.roots[start..]
+            .iter()
+            .chain(self.roots[..start].iter())
+            .filter(|root| root.is_healthy())
+    }
+
+    /// Returns the root new content for `digest` should be written to. If
+    /// every root is unhealthy the first root is used, so the write fails
+    /// with the underlying error.
+    fn root_for_update(&self, digest: &DigestInfo) -> &ContentRoot<Fe> {
+        self.roots_for_digest(digest)
+            .next()
+            .unwrap_or(&self.roots[0])
+    }
+
+    async fn find_entry(&self, digest: &DigestInfo) -> Option<(&ContentRoot<Fe>, Arc<Fe>)> {
+        for root in self.roots_for_digest(digest) {
+            if let Some(entry) = root.evicting_map.get(digest).await {
+                return Some((root, entry));
+            }
+        }
+        None
+    }
+
+    async fn get_root_and_entry_for_digest(
+        &self,
+        digest: &DigestInfo,
+    ) -> Result<(&ContentRoot<Fe>, Arc<Fe>), Error> {
+        self.find_entry(digest)
             .await
             .ok_or_else(|| make_err!(Code::NotFound, "{digest} not found in filesystem store"))
     }
 
+    pub async fn get_file_entry_for_digest(&self, digest: &DigestInfo) -> Result<Arc<Fe>, Error> {
+        Ok(self.get_root_and_entry_for_digest(digest).await?.1)
+    }
+
+    /// Same as `get_file_entry_for_digest`, but if the store has a root on
+    /// the same filesystem as `dest_dir` the returned file is in that root,
+    /// so it can be hard linked into `dest_dir`. Files found in other roots
+    /// are moved into it first.
+    pub async fn get_file_entry_for_hard_link(
+        &self,
+        digest: &DigestInfo,
+        dest_dir: impl AsRef<Path>,
+    ) -> Result<Arc<Fe>, Error> {
+        if self.roots.len() == 1 {
+            return self.get_file_entry_for_digest(digest).await;
+        }
+        let dest_device = device_id(
+            &fs::metadata(dest_dir.as_ref())
+                .await
+                .err_tip(|| format!("Failed to stat {:?}", dest_dir.as_ref()))?,
+        );
+        let Some(root) = self
+            .roots
+            .iter()
+            .find(|root| root.is_healthy() && dest_device.is_some() && root.device == dest_device)
+        else {
+            // Hard linking will fail whichever root the file is in.
+            return self.get_file_entry_for_digest(digest).await;
+        };
+        if let Some(entry) = root.evicting_map.get(digest).await {
+            return Ok(entry);
+        }
+
+        let (source_root, source_entry) = self.get_root_and_entry_for_digest(digest).await?;
+        let mut temp_digest = *digest;
+        make_temp_digest(&mut temp_digest);
+        let encoded_file_path = EncodedFilePath {
+            shared_context: root.shared_context.clone(),
+            path_type: PathType::Temp,
+            digest: temp_digest,
+        };
+        let temp_full_path = encoded_file_path.get_file_path().to_os_string();
+        let read_buffer_size = self.read_buffer_size;
+        let data_size = source_entry
+            .get_file_path_locked(move |src| copy_file(src, temp_full_path, read_buffer_size))
+            .await
+            .err_tip(|| format!("Failed to copy {digest} to root on the same filesystem"))?;
+        let entry = Arc::new(Fe::create(
+            data_size,
+            self.block_size,
+            RwLock::new(encoded_file_path),
+        ));
+        self.emplace_file(root, *digest, entry.clone()).await?;
+        // The blob now lives in `root`, so it is only counted there.
+        source_root
+            .evicting_map
+            .remove_if(digest, |map_entry| {
+                Arc::<Fe>::ptr_eq(map_entry, &source_entry)
+            })
+            .await;
+        Ok(entry)
+    }
+
     async fn update_file<'a>(
         self: Pin<&'a Self>,
+        root: &'a ContentRoot<Fe>,
         mut entry: Fe,
         mut resumeable_temp_file: fs::ResumeableFileSlot,
         final_digest: DigestInfo,
@@ -634,7 +1113,8 @@ impl<Fe: FileEntry> FilesystemStore<Fe> {
                 .err_tip(|| "in filesystem_store::update_file")?
                 .write_all_buf(&mut data)
                 .await
-                .err_tip(|| "Failed to write data into filesystem store")?;
+                .err_tip(|| "Failed to write data into filesystem store")
+                .inspect_err(|err| self.mark_unhealthy(root, err))?;
             data_size += data_len as u64;
         }
 
@@ -642,18 +1122,25 @@ impl<Fe: FileEntry> FilesystemStore<Fe> {
             .as_writer()
             .await
             .err_tip(|| "in filesystem_store::update_file")?
-            .as_ref()
             .sync_all()
             .await
-            .err_tip(|| "Failed to sync_data in filesystem store")?;
+            .err_tip(|| "Failed to sync_data in filesystem store")
+            .inspect_err(|err| self.mark_unhealthy(root, err))?;
 
         drop(resumeable_temp_file);
 
         *entry.data_size_mut() = data_size;
-        self.emplace_file(final_digest, Arc::new(entry)).await
+        self.emplace_file(root, final_digest, Arc::new(entry))
+            .await
+            .inspect_err(|err| self.mark_unhealthy(root, err))
     }
 
-    async fn emplace_file(&self, digest: DigestInfo, entry: Arc<Fe>) -> Result<(), Error> {
+    async fn emplace_file(
+        &self,
+        root: &ContentRoot<Fe>,
+        digest: DigestInfo,
+        entry: Arc<Fe>,
+    ) -> Result<(), Error> {
         // This sequence of events is quite ticky to understand due to the amount of triggers that
         // happen, async'ness of it and the locking. So here is a breakdown of what happens:
         // 1. Here will hold a write lock on any file operations of this FileEntry.
@@ -671,7 +1158,7 @@ impl<Fe: FileEntry> FilesystemStore<Fe> {
         // 5. Move the file into place. Since we hold a write lock still anyone that gets our new
         //    FileEntry (which has not yet been placed on disk) will not be able to read the file's
         //    contents until we relese the lock.
-        let evicting_map = self.evicting_map.clone();
+        let evicting_map = root.evicting_map.clone();
         let rename_fn = self.rename_fn;
 
         // We need to guarantee that this will get to the end even if the parent future is dropped.
@@ -736,9 +1223,17 @@ impl<Fe: FileEntry> StoreDriver for FilesystemStore<Fe> {
         // insert them into the cache. In theory it should be able to elide this conversion
         // but it seems to be a bit tricky to get right.
         let keys: Vec<_> = keys.iter().map(|v| v.borrow().into_digest()).collect();
-        self.evicting_map
-            .sizes_for_keys(&keys, results, false /* peek */)
-            .await;
+        let mut root_results = vec![None; keys.len()];
+        for root in self.roots.iter().filter(|root| root.is_healthy()) {
+            root.evicting_map
+                .sizes_for_keys(&keys, &mut root_results, false /* peek */)
+                .await;
+            for (result, root_result) in results.iter_mut().zip(root_results.iter_mut()) {
+                if result.is_none() {
+                    *result = root_result.take();
+                }
+            }
+        }
         // We need to do a special pass to ensure our zero files exist.
         // If our results failed and the result was a zero file, we need to
         // create the file by spec.
@@ -761,6 +1256,50 @@ impl<Fe: FileEntry> StoreDriver for FilesystemStore<Fe> {
         Ok(())
     }
 
+    async fn list(
+        self: Pin<&Self>,
+        range: (Bound<StoreKey<'_>>, Bound<StoreKey<'_>>),
+        handler: &mut (dyn for<'a> FnMut(&'a StoreKey) -> bool + Send + Sync + '_),
+    ) -> Result<u64, Error> {
+        let range = (
+            range.0.map(StoreKey::into_digest),
+            range.1.map(StoreKey::into_digest),
+        );
+        if self.roots.len() == 1 {
+            return Ok(self.roots[0]
+                .evicting_map
+                .range(range, |digest, _| handler(&StoreKey::Digest(*digest)))
+                .await);
+        }
+        // Merge the roots so the handler still sees digests in order.
+        let mut digests = BTreeSet::new();
+        for root in self.roots.iter().filter(|root| root.is_healthy()) {
+            root.evicting_map
+                .range(range.clone(), |digest, _| {
+                    digests.insert(*digest);
+                    true
+                })
+                .await;
+        }
+        let mut continue_count = 0;
+        for digest in digests {
+            if !handler(&StoreKey::Digest(digest)) {
+                break;
+            }
+            continue_count += 1;
+        }
+        Ok(continue_count)
+    }
+
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        let digest = key.into_digest();
+        let mut removed = false;
+        for root in &self.roots {
+            removed |= root.evicting_map.remove(&digest).await;
+        }
+        Ok(removed)
+    }
+
     async fn update(
         self: Pin<&Self>,
         key: StoreKey<'_>,
@@ -771,17 +1310,19 @@ impl<Fe: FileEntry> StoreDriver for FilesystemStore<Fe> {
         let mut temp_digest = digest;
         make_temp_digest(&mut temp_digest);
 
+        let root = self.root_for_update(&digest);
         let (entry, temp_file, temp_full_path) = Fe::make_and_open_file(
             self.block_size,
             EncodedFilePath {
-                shared_context: self.shared_context.clone(),
+                shared_context: root.shared_context.clone(),
                 path_type: PathType::Temp,
                 digest: temp_digest,
             },
         )
-        .await?;
+        .await
+        .inspect_err(|err| self.mark_unhealthy(root, err))?;
 
-        self.update_file(entry, temp_file, digest, reader)
+        self.update_file(root, entry, temp_file, digest, reader)
             .await
             .err_tip(|| format!("While processing with temp file {temp_full_path:?}"))
     }
@@ -798,26 +1339,31 @@ impl<Fe: FileEntry> StoreDriver for FilesystemStore<Fe> {
     ) -> Result<Option<fs::ResumeableFileSlot>, Error> {
         let digest = key.into_digest();
         let path = file.get_path().as_os_str().to_os_string();
+        let metadata = file
+            .as_reader()
+            .await
+            .err_tip(|| format!("While getting metadata for {path:?} in update_with_whole_file"))?
+            .get_ref()
+            .as_ref()
+            .metadata()
+            .await
+            .err_tip(|| format!("While reading metadata for {path:?}"))?;
         let file_size = match upload_size {
             UploadSizeInfo::ExactSize(size) => size,
-            UploadSizeInfo::MaxSize(_) => file
-                .as_reader()
-                .await
-                .err_tip(|| {
-                    format!("While getting metadata for {path:?} in update_with_whole_file")
-                })?
-                .get_ref()
-                .as_ref()
-                .metadata()
-                .await
-                .err_tip(|| format!("While reading metadata for {path:?}"))?
-                .len(),
+            UploadSizeInfo::MaxSize(_) => metadata.len(),
         };
+        // The file is renamed into place, so prefer a root on its filesystem.
+        let file_device = device_id(&metadata);
+        let root = self
+            .roots
+            .iter()
+            .find(|root| root.is_healthy() && file_device.is_some() && root.device == file_device)
+            .unwrap_or_else(|| self.root_for_update(&digest));
         let entry = Fe::create(
             file_size,
             self.block_size,
             RwLock::new(EncodedFilePath {
-                shared_context: self.shared_context.clone(),
+                shared_context: root.shared_context.clone(),
                 path_type: PathType::Custom(path),
                 digest,
             }),
@@ -825,7 +1371,7 @@ impl<Fe: FileEntry> StoreDriver for FilesystemStore<Fe> {
         // We are done with the file, if we hold a reference to the file here, it could
         // result in a deadlock if `emplace_file()` also needs file descriptors.
         drop(file);
-        self.emplace_file(digest, Arc::new(entry))
+        self.emplace_file(root, digest, Arc::new(entry))
             .await
             .err_tip(|| "Could not move file into store in upload_file_to_store, maybe dest is on different volume?")?;
         return Ok(None);
@@ -849,12 +1395,12 @@ impl<Fe: FileEntry> StoreDriver for FilesystemStore<Fe> {
             return Ok(());
         }
 
-        let entry =
-            self.evicting_map.get(&digest).await.ok_or_else(|| {
-                make_err!(Code::NotFound, "{digest} not found in filesystem store")
-            })?;
+        let (root, entry) = self.get_root_and_entry_for_digest(&digest).await?;
         let read_limit = length.unwrap_or(u64::MAX);
-        let mut resumeable_temp_file = entry.read_file_part(offset, read_limit).await?;
+        let mut resumeable_temp_file = entry
+            .read_file_part(offset, read_limit)
+            .await
+            .inspect_err(|err| self.mark_unhealthy(root, err))?;
 
         loop {
             let mut buf = BytesMut::with_capacity(self.read_buffer_size);
@@ -864,7 +1410,8 @@ impl<Fe: FileEntry> StoreDriver for FilesystemStore<Fe> {
                 .err_tip(|| "In FileSystemStore::get_part()")?
                 .read_buf(&mut buf)
                 .await
-                .err_tip(|| "Failed to read data in filesystem store")?;
+                .err_tip(|| "Failed to read data in filesystem store")
+                .inspect_err(|err| self.mark_unhealthy(root, err))?;
             if buf.is_empty() {
                 break; // EOF.
             }
@@ -928,6 +1475,37 @@ impl<Fe: FileEntry> HealthStatusIndicator for FilesystemStore<Fe> {
     }
 
     async fn check_health(&self, namespace: Cow<'static, str>) -> HealthStatus {
-        StoreDriver::check_health(Pin::new(self), namespace).await
+        let mut unhealthy_paths = Vec::new();
+        for root in &self.roots {
+            let result = if root.loaded {
+                root.check_writable().await
+            } else {
+                Err(make_err!(Code::Unavailable, "Root failed to load"))
+            };
+            if let Err(err) = &result {
+                event!(
+                    Level::ERROR,
+                    ?err,
+                    content_path = ?root.shared_context.content_path,
+                    "Filesystem store root failed health check",
+                );
+                unhealthy_paths.push(root.shared_context.content_path.clone());
+            }
+            root.healthy.store(result.is_ok(), Ordering::Release);
+        }
+        if unhealthy_paths.len() == self.roots.len() {
+            return HealthStatus::new_failed(
+                self,
+                format!("No writable roots, failed: {unhealthy_paths:?}").into(),
+            );
+        }
+        let status = StoreDriver::check_health(Pin::new(self), namespace).await;
+        match status {
+            HealthStatus::Ok { .. } if !unhealthy_paths.is_empty() => HealthStatus::new_warning(
+                self,
+                format!("Skipping unhealthy roots: {unhealthy_paths:?}").into(),
+            ),
+            status => status,
+        }
     }
 }
@@ -13,37 +13,45 @@
 // limitations under the License.
 
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::pin::Pin;
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use futures::stream::{unfold, FuturesUnordered};
 use futures::{future, Future, Stream, StreamExt, TryFutureExt, TryStreamExt};
-use nativelink_error::{error_if, make_input_err, Error, ResultExt};
+use nativelink_error::{error_if, make_err, make_input_err, Code, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
 use nativelink_proto::build::bazel::remote::execution::v2::action_cache_client::ActionCacheClient;
 use nativelink_proto::build::bazel::remote::execution::v2::content_addressable_storage_client::ContentAddressableStorageClient;
 use nativelink_proto::build::bazel::remote::execution::v2::{
-    ActionResult, BatchReadBlobsRequest, BatchReadBlobsResponse, BatchUpdateBlobsRequest,
-    BatchUpdateBlobsResponse, FindMissingBlobsRequest, FindMissingBlobsResponse,
-    GetActionResultRequest, GetTreeRequest, GetTreeResponse, UpdateActionResultRequest,
+    batch_update_blobs_request, ActionResult, BatchReadBlobsRequest, BatchReadBlobsResponse,
+    BatchUpdateBlobsRequest, BatchUpdateBlobsResponse, FindMissingBlobsRequest,
+    FindMissingBlobsResponse, GetActionResultRequest, GetTreeRequest, GetTreeResponse,
+    SpliceBlobRequest, SpliceBlobResponse, SplitBlobRequest, SplitBlobResponse,
+    UpdateActionResultRequest,
 };
 use nativelink_proto::google::bytestream::byte_stream_client::ByteStreamClient;
 use nativelink_proto::google::bytestream::{
     QueryWriteStatusRequest, QueryWriteStatusResponse, ReadRequest, ReadResponse, WriteRequest,
     WriteResponse,
 };
+use nativelink_proto::google::rpc::Status as GrpcStatus;
 use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
 use nativelink_util::common::DigestInfo;
-use nativelink_util::connection_manager::ConnectionManager;
+use nativelink_util::connection_manager::{Connection, ConnectionManager};
 use nativelink_util::digest_hasher::{default_digest_hasher_func, ACTIVE_HASHER_FUNC};
 use nativelink_util::health_utils::HealthStatusIndicator;
+use nativelink_util::latency_tracker::LatencyTracker;
 use nativelink_util::origin_context::ActiveOriginContext;
 use nativelink_util::proto_stream_utils::{
     FirstStream, WriteRequestStreamWrapper, WriteState, WriteStateWrapper,
 };
+use nativelink_util::request_batcher::RequestBatcher;
+use nativelink_util::request_metadata::make_request;
 use nativelink_util::resource_info::ResourceInfo;
 use nativelink_util::retry::{Retrier, RetryResult};
 use nativelink_util::store_trait::{StoreDriver, StoreKey, UploadSizeInfo};
@@ -66,7 +74,61 @@ pub struct GrpcStore {
     instance_name: String,
     store_type: nativelink_config::stores::StoreType,
     retrier: Retrier,
+    #[metric(group = "connection_manager")]
     connection_manager: ConnectionManager,
+    #[metric(group = "hedging")]
+    hedging: Option<HedgingState>,
+    batching: Option<BatchingState>,
+}
+
+/// Default for `GrpcHedging::max_blob_size`.
+const DEFAULT_HEDGING_MAX_BLOB_SIZE: u64 = 1024 * 1024;
+
+/// Default for `GrpcHedging::min_delay_ms`.
+const DEFAULT_HEDGING_MIN_DELAY_MS: u64 = 10;
+
+/// The number of recent read latencies used to decide when to hedge.
+const HEDGING_LATENCY_WINDOW: usize = 1000;
+
+/// The number of reads that must complete before their latencies are used
+/// to decide when to hedge.
+const HEDGING_LATENCY_MIN_SAMPLES: usize = 20;
+
+/// Default for `GrpcBatching::max_blob_size`.
+const DEFAULT_BATCHING_MAX_BLOB_SIZE: u64 = 64 * 1024;
+
+/// Default for `GrpcBatching::max_batch_size`.
+const DEFAULT_BATCHING_MAX_BATCH_SIZE: u64 = 3 * 1024 * 1024;
+
+/// Default for `GrpcBatching::window_ms`.
+const DEFAULT_BATCHING_WINDOW_MS: u64 = 5;
+
+/// The approximate size of a digest in a `FindMissingBlobs` request, used
+/// to limit the size of merged requests.
+const FIND_MISSING_DIGEST_SIZE: u64 = 80;
+
+/// Requests are batched per digest function, so each entry carries the
+/// digest function it was made with.
+type DigestFunction = i32;
+
+struct BatchingState {
+    max_blob_size: u64,
+    read_batcher: RequestBatcher<(DigestFunction, DigestInfo), Bytes>,
+    update_batcher: RequestBatcher<(DigestFunction, DigestInfo, Bytes), ()>,
+    find_missing_batcher: RequestBatcher<(DigestFunction, Vec<DigestInfo>), Vec<Option<u64>>>,
+}
+
+#[derive(MetricsComponent)]
+struct HedgingState {
+    #[metric(help = "Reads of blobs up to this size are hedged")]
+    max_blob_size: u64,
+    #[metric(help = "The minimum time to wait before sending a duplicate read")]
+    min_delay: Duration,
+    latencies: LatencyTracker,
+    #[metric(help = "The number of reads a duplicate request was sent for")]
+    hedged_reads: AtomicU64,
+    #[metric(help = "The number of hedged reads where the duplicate request completed first")]
+    hedged_reads_won: AtomicU64,
 }
 
 impl GrpcStore {
@@ -100,9 +162,49 @@ impl GrpcStore {
                 .map_err(|e| make_input_err!("Invalid URI for GrpcStore endpoint : {e:?}"))?;
             endpoints.push(endpoint);
         }
+        error_if!(
+            config.hedging.is_some() && endpoints.len() < 2,
+            "Expected at least 2 endpoints in GrpcStore when hedging is enabled"
+        );
+        let hedging = config.hedging.map(|hedging| HedgingState {
+            max_blob_size: if hedging.max_blob_size == 0 {
+                DEFAULT_HEDGING_MAX_BLOB_SIZE
+            } else {
+                hedging.max_blob_size
+            },
+            min_delay: Duration::from_millis(if hedging.min_delay_ms == 0 {
+                DEFAULT_HEDGING_MIN_DELAY_MS
+            } else {
+                hedging.min_delay_ms
+            }),
+            latencies: LatencyTracker::new(HEDGING_LATENCY_WINDOW, HEDGING_LATENCY_MIN_SAMPLES),
+            hedged_reads: AtomicU64::new(0),
+            hedged_reads_won: AtomicU64::new(0),
+        });
+
+        let batching_config = config
+            .batching
+            .filter(|_| matches!(config.store_type, nativelink_config::stores::StoreType::cas))
+            .map(|batching| {
+                let or_default = |value, default| if value == 0 { default } else { value };
+                (
+                    or_default(batching.max_blob_size, DEFAULT_BATCHING_MAX_BLOB_SIZE),
+                    or_default(batching.max_batch_size, DEFAULT_BATCHING_MAX_BATCH_SIZE),
+                    Duration::from_millis(or_default(
+                        batching.window_ms,
+                        DEFAULT_BATCHING_WINDOW_MS,
+                    )),
+                )
+            });
+        if let Some((max_blob_size, max_batch_size, _)) = batching_config {
+            error_if!(
+                max_blob_size > max_batch_size,
+                "batching.max_blob_size ({max_blob_size}) must not be larger than batching.max_batch_size ({max_batch_size}) in GrpcStore"
+            );
+        }
 
         let jitter_fn = Arc::new(jitter_fn);
-        Ok(Arc::new(GrpcStore {
+        Ok(Arc::new_cyclic(|weak_self: &Weak<Self>| GrpcStore {
             instance_name: config.instance_name.clone(),
             store_type: config.store_type,
             retrier: Retrier::new(
@@ -116,10 +218,186 @@ impl GrpcStore {
                 config.max_concurrent_requests,
                 config.retry.to_owned(),
                 jitter_fn,
+                config.outlier_ejection,
             ),
+            hedging,
+            batching: batching_config.map(|(max_blob_size, max_batch_size, window)| {
+                let read_store = weak_self.clone();
+                let update_store = weak_self.clone();
+                let find_missing_store = weak_self.clone();
+                BatchingState {
+                    max_blob_size,
+                    read_batcher: RequestBatcher::new(window, max_batch_size, move |entries| {
+                        Self::send_read_batch(read_store.clone(), entries)
+                    }),
+                    update_batcher: RequestBatcher::new(window, max_batch_size, move |entries| {
+                        Self::send_update_batch(update_store.clone(), entries)
+                    }),
+                    find_missing_batcher: RequestBatcher::new(
+                        window,
+                        max_batch_size,
+                        move |entries| {
+                            Self::send_find_missing_batch(find_missing_store.clone(), entries)
+                        },
+                    ),
+                }
+            }),
         }))
     }
 
+    /// Sends a batch of reads as one `BatchReadBlobs` request per digest
+    /// function.
+    async fn send_read_batch(
+        weak_self: Weak<Self>,
+        entries: Vec<(DigestFunction, DigestInfo)>,
+    ) -> Result<Vec<Result<Bytes, Error>>, Error> {
+        let store = weak_self
+            .upgrade()
+            .err_tip(|| "GrpcStore was dropped in send_read_batch")?;
+        let mut results = missing_batch_results(entries.len());
+        let groups = group_by_digest_function(entries);
+        let responses = future::try_join_all(groups.into_iter().map(|(digest_function, group)| {
+            let store = &store;
+            async move {
+                let response = store
+                    .batch_read_blobs(make_request(BatchReadBlobsRequest {
+                        instance_name: store.instance_name.clone(),
+                        digests: group.iter().map(|(_, digest)| (*digest).into()).collect(),
+                        acceptable_compressors: Vec::new(),
+                        digest_function,
+                    }))
+                    .await
+                    .err_tip(|| "In GrpcStore::send_read_batch")?
+                    .into_inner();
+                Ok::<_, Error>((group, response))
+            }
+        }))
+        .await?;
+        for (group, response) in responses {
+            let mut blobs = HashMap::with_capacity(response.responses.len());
+            for blob in response.responses {
+                let digest = DigestInfo::try_from(
+                    blob.digest
+                        .err_tip(|| "Missing digest in BatchReadBlobsResponse")?,
+                )?;
+                let result = status_to_result(blob.status).and_then(|()| {
+                    error_if!(
+                        blob.data.len() as u64 != digest.size_bytes(),
+                        "Expected {} bytes for {digest}, got {} in BatchReadBlobsResponse",
+                        digest.size_bytes(),
+                        blob.data.len()
+                    );
+                    Ok(blob.data)
+                });
+                blobs.insert(digest, result);
+            }
+            for (index, digest) in group {
+                if let Some(result) = blobs.get(&digest) {
+                    results[index] = result.clone();
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    /// Sends a batch of writes as one `BatchUpdateBlobs` request per digest
+    /// function.
+    async fn send_update_batch(
+        weak_self: Weak<Self>,
+        entries: Vec<(DigestFunction, DigestInfo, Bytes)>,
+    ) -> Result<Vec<Result<(), Error>>, Error> {
+        let store = weak_self
+            .upgrade()
+            .err_tip(|| "GrpcStore was dropped in send_update_batch")?;
+        let mut results = missing_batch_results(entries.len());
+        let groups = group_by_digest_function(
+            entries
+                .into_iter()
+                .map(|(digest_function, digest, data)| (digest_function, (digest, data))),
+        );
+        let responses = future::try_join_all(groups.into_iter().map(|(digest_function, group)| {
+            let store = &store;
+            async move {
+                let response = store
+                    .batch_update_blobs(make_request(BatchUpdateBlobsRequest {
+                        instance_name: store.instance_name.clone(),
+                        requests: group
+                            .iter()
+                            .map(|(_, (digest, data))| batch_update_blobs_request::Request {
+                                digest: Some((*digest).into()),
+                                data: data.clone(),
+                                compressor: 0,
+                            })
+                            .collect(),
+                        digest_function,
+                    }))
+                    .await
+                    .err_tip(|| "In GrpcStore::send_update_batch")?
+                    .into_inner();
+                Ok::<_, Error>((group, response))
+            }
+        }))
+        .await?;
+        for (group, response) in responses {
+            let mut statuses = HashMap::with_capacity(response.responses.len());
+            for blob in response.responses {
+                let digest = DigestInfo::try_from(
+                    blob.digest
+                        .err_tip(|| "Missing digest in BatchUpdateBlobsResponse")?,
+                )?;
+                statuses.insert(digest, status_to_result(blob.status));
+            }
+            for (index, (digest, _)) in group {
+                if let Some(result) = statuses.get(&digest) {
+                    results[index] = result.clone();
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    /// Sends a batch of existence checks as one `FindMissingBlobs` request
+    /// per digest function.
+    async fn send_find_missing_batch(
+        weak_self: Weak<Self>,
+        entries: Vec<(DigestFunction, Vec<DigestInfo>)>,
+    ) -> Result<Vec<Result<Vec<Option<u64>>, Error>>, Error> {
+        let store = weak_self
+            .upgrade()
+            .err_tip(|| "GrpcStore was dropped in send_find_missing_batch")?;
+        let mut results = missing_batch_results(entries.len());
+        let groups = group_by_digest_function(entries);
+        let responses = future::try_join_all(groups.into_iter().map(|(digest_function, group)| {
+            let store = &store;
+            async move {
+                let response = store
+                    .find_missing_blobs(make_request(FindMissingBlobsRequest {
+                        instance_name: store.instance_name.clone(),
+                        blob_digests: group
+                            .iter()
+                            .flat_map(|(_, digests)| digests.iter().map(|digest| (*digest).into()))
+                            .collect(),
+                        digest_function,
+                    }))
+                    .await
+                    .err_tip(|| "In GrpcStore::send_find_missing_batch")?
+                    .into_inner();
+                Ok::<_, Error>((group, response))
+            }
+        }))
+        .await?;
+        for (group, response) in responses {
+            let missing_digests = sorted_missing_digests(response)?;
+            for (index, digests) in group {
+                results[index] = Ok(digests
+                    .iter()
+                    .map(|digest| size_if_present(&missing_digests, *digest))
+                    .collect());
+            }
+        }
+        Ok(results)
+    }
+
     async fn perform_request<F, Fut, R, I>(&self, input: I, mut request: F) -> Result<R, Error>
     where
         F: FnMut(I) -> Fut + Send + Copy,
@@ -158,7 +436,7 @@ impl GrpcStore {
                 .await
                 .err_tip(|| "in find_missing_blobs")?;
             ContentAddressableStorageClient::new(channel)
-                .find_missing_blobs(Request::new(request))
+                .find_missing_blobs(make_request(request))
                 .await
                 .err_tip(|| "in GrpcStore::find_missing_blobs")
         })
@@ -183,7 +461,7 @@ impl GrpcStore {
                 .await
                 .err_tip(|| "in batch_update_blobs")?;
             ContentAddressableStorageClient::new(channel)
-                .batch_update_blobs(Request::new(request))
+                .batch_update_blobs(make_request(request))
                 .await
                 .err_tip(|| "in GrpcStore::batch_update_blobs")
         })
@@ -208,7 +486,7 @@ impl GrpcStore {
                 .await
                 .err_tip(|| "in batch_read_blobs")?;
             ContentAddressableStorageClient::new(channel)
-                .batch_read_blobs(Request::new(request))
+                .batch_read_blobs(make_request(request))
                 .await
                 .err_tip(|| "in GrpcStore::batch_read_blobs")
         })
@@ -233,13 +511,63 @@ impl GrpcStore {
                 .await
                 .err_tip(|| "in get_tree")?;
             ContentAddressableStorageClient::new(channel)
-                .get_tree(Request::new(request))
+                .get_tree(make_request(request))
                 .await
                 .err_tip(|| "in GrpcStore::get_tree")
         })
         .await
     }
 
+    pub async fn split_blob(
+        &self,
+        grpc_request: Request<SplitBlobRequest>,
+    ) -> Result<Response<SplitBlobResponse>, Error> {
+        error_if!(
+            matches!(self.store_type, nativelink_config::stores::StoreType::ac),
+            "CAS operation on AC store"
+        );
+
+        let mut request = grpc_request.into_inner();
+        request.instance_name.clone_from(&self.instance_name);
+        self.perform_request(request, |request| async move {
+            let channel = self
+                .connection_manager
+                .connection()
+                .await
+                .err_tip(|| "in split_blob")?;
+            ContentAddressableStorageClient::new(channel)
+                .split_blob(make_request(request))
+                .await
+                .err_tip(|| "in GrpcStore::split_blob")
+        })
+        .await
+    }
+
+    pub async fn splice_blob(
+        &self,
+        grpc_request: Request<SpliceBlobRequest>,
+    ) -> Result<Response<SpliceBlobResponse>, Error> {
+        error_if!(
+            matches!(self.store_type, nativelink_config::stores::StoreType::ac),
+            "CAS operation on AC store"
+        );
+
+        let mut request = grpc_request.into_inner();
+        request.instance_name.clone_from(&self.instance_name);
+        self.perform_request(request, |request| async move {
+            let channel = self
+                .connection_manager
+                .connection()
+                .await
+                .err_tip(|| "in splice_blob")?;
+            ContentAddressableStorageClient::new(channel)
+                .splice_blob(make_request(request))
+                .await
+                .err_tip(|| "in GrpcStore::splice_blob")
+        })
+        .await
+    }
+
     fn get_read_request(&self, mut request: ReadRequest) -> Result<ReadRequest, Error> {
         const IS_UPLOAD_FALSE: bool = false;
         let mut resource_info = ResourceInfo::new(&request.resource_name, IS_UPLOAD_FALSE)?;
@@ -260,7 +588,7 @@ impl GrpcStore {
             .await
             .err_tip(|| "in read_internal")?;
         let mut response = ByteStreamClient::new(channel)
-            .read(Request::new(request))
+            .read(make_request(request))
             .await
             .err_tip(|| "in GrpcStore::read")?
             .into_inner();
@@ -271,6 +599,105 @@ impl GrpcStore {
         Ok(FirstStream::new(first_response, response))
     }
 
+    /// Reads the whole response to `request` into memory using `connection`.
+    async fn read_to_bytes(
+        &self,
+        connection: Connection,
+        request: ReadRequest,
+    ) -> Result<Bytes, Error> {
+        let mut response = ByteStreamClient::new(connection)
+            .read(make_request(request))
+            .await
+            .err_tip(|| "in GrpcStore::read_to_bytes")?
+            .into_inner();
+        let mut data = BytesMut::new();
+        while let Some(message) = response
+            .message()
+            .await
+            .err_tip(|| "Fetching chunk in GrpcStore::read_to_bytes")?
+        {
+            data.extend_from_slice(&message.data);
+        }
+        Ok(data.freeze())
+    }
+
+    /// Reads the whole response to `request` into memory.  If the response
+    /// takes longer than most recent reads, the same request is sent to a
+    /// different endpoint and whichever completes first is used.
+    async fn hedged_read(
+        &self,
+        hedging: &HedgingState,
+        request: ReadRequest,
+    ) -> Result<Bytes, Error> {
+        let connection = self
+            .connection_manager
+            .connection()
+            .await
+            .err_tip(|| "in GrpcStore::hedged_read")?;
+        let endpoint_index = connection.endpoint_index();
+        let start = Instant::now();
+        let primary = self.read_to_bytes(connection, request.clone());
+        tokio::pin!(primary);
+
+        let delay = hedging
+            .latencies
+            .percentile(95.)
+            .map_or(hedging.min_delay, |p95| p95.max(hedging.min_delay));
+        tokio::select! {
+            result = &mut primary => {
+                if result.is_ok() {
+                    hedging.latencies.record(start.elapsed());
+                }
+                return result;
+            }
+            () = sleep(delay) => {}
+        }
+
+        let Some(connection) = self
+            .connection_manager
+            .try_connection_excluding(endpoint_index)
+            .await
+            .err_tip(|| "in GrpcStore::hedged_read")?
+        else {
+            // Every other endpoint is busy, so just keep waiting.
+            let result = primary.await;
+            if result.is_ok() {
+                hedging.latencies.record(start.elapsed());
+            }
+            return result;
+        };
+        hedging.hedged_reads.fetch_add(1, Ordering::Relaxed);
+        let hedge = self.read_to_bytes(connection, request);
+        tokio::pin!(hedge);
+
+        // The first successful response wins, the other request is dropped.
+        // Either way the latency is recorded for the primary request, since
+        // that is what decides whether to hedge.
+        let result = tokio::select! {
+            result = &mut primary => match result {
+                Ok(data) => Ok(data),
+                Err(err) => {
+                    let result = hedge.await.map_err(|hedge_err| err.merge(hedge_err));
+                    if result.is_ok() {
+                        hedging.hedged_reads_won.fetch_add(1, Ordering::Relaxed);
+                    }
+                    result
+                }
+            },
+            result = &mut hedge => match result {
+                Ok(data) => {
+                    hedging.hedged_reads_won.fetch_add(1, Ordering::Relaxed);
+                    Ok(data)
+                }
+                Err(err) => primary.await.map_err(|primary_err| primary_err.merge(err)),
+            },
+        };
+        if result.is_ok() {
+            hedging.latencies.record(start.elapsed());
+        }
+        result
+    }
+
     pub async fn read(
         &self,
         grpc_request: impl IntoRequest<ReadRequest>,
@@ -318,7 +745,7 @@ impl GrpcStore {
                     .connection()
                     .and_then(|channel| async {
                         ByteStreamClient::new(channel)
-                            .write(WriteStateWrapper::new(local_state.clone()))
+                            .write(make_request(WriteStateWrapper::new(local_state.clone())))
                             .await
                             .err_tip(|| "in GrpcStore::write")
                     })
@@ -378,7 +805,7 @@ impl GrpcStore {
                 .await
                 .err_tip(|| "in query_write_status")?;
             ByteStreamClient::new(channel)
-                .query_write_status(Request::new(request))
+                .query_write_status(make_request(request))
                 .await
                 .err_tip(|| "in GrpcStore::query_write_status")
         })
@@ -398,7 +825,7 @@ impl GrpcStore {
                 .await
                 .err_tip(|| "in get_action_result")?;
             ActionCacheClient::new(channel)
-                .get_action_result(Request::new(request))
+                .get_action_result(make_request(request))
                 .await
                 .err_tip(|| "in GrpcStore::get_action_result")
         })
@@ -418,7 +845,7 @@ impl GrpcStore {
                 .await
                 .err_tip(|| "in update_action_result")?;
             ActionCacheClient::new(channel)
-                .update_action_result(Request::new(request))
+                .update_action_result(make_request(request))
                 .await
                 .err_tip(|| "in GrpcStore::update_action_result")
         })
@@ -503,6 +930,69 @@ impl GrpcStore {
     }
 }
 
+/// The digest function of the current request.
+fn active_digest_function() -> Result<DigestFunction, Error> {
+    Ok(ActiveOriginContext::get_value(&ACTIVE_HASHER_FUNC)?
+        .map_or_else(default_digest_hasher_func, |v| *v)
+        .proto_digest_func()
+        .into())
+}
+
+/// The results of a batch before any responses are matched to them.
+fn missing_batch_results<T>(len: usize) -> Vec<Result<T, Error>> {
+    (0..len)
+        .map(|_| {
+            Err(make_err!(
+                Code::Internal,
+                "No response for blob in batch response"
+            ))
+        })
+        .collect()
+}
+
+/// Groups batch entries by digest function, keeping the index of each
+/// entry in the batch.
+fn group_by_digest_function<T>(
+    entries: impl IntoIterator<Item = (DigestFunction, T)>,
+) -> HashMap<DigestFunction, Vec<(usize, T)>> {
+    let mut groups: HashMap<DigestFunction, Vec<(usize, T)>> = HashMap::new();
+    for (index, (digest_function, entry)) in entries.into_iter().enumerate() {
+        groups
+            .entry(digest_function)
+            .or_default()
+            .push((index, entry));
+    }
+    groups
+}
+
+fn status_to_result(status: Option<GrpcStatus>) -> Result<(), Error> {
+    match status {
+        Some(status) if status.code != Code::Ok as i32 => Err(status.into()),
+        _ => Ok(()),
+    }
+}
+
+// Since the ordering is not guaranteed in a FindMissingBlobsResponse, the
+// matching has to check all missing blobs against all entries in the unsorted
+// digest list. To optimise this, the missing digests are sorted and then it
+// is efficient to perform a binary search for each digest within the missing
+// list.
+fn sorted_missing_digests(response: FindMissingBlobsResponse) -> Result<Vec<DigestInfo>, Error> {
+    let mut missing_digests = Vec::with_capacity(response.missing_blob_digests.len());
+    for missing_digest in response.missing_blob_digests {
+        missing_digests.push(DigestInfo::try_from(missing_digest)?);
+    }
+    missing_digests.sort_unstable();
+    Ok(missing_digests)
+}
+
+fn size_if_present(sorted_missing_digests: &[DigestInfo], digest: DigestInfo) -> Option<u64> {
+    match sorted_missing_digests.binary_search(&digest) {
+        Ok(_) => None,
+        Err(_) => Some(digest.size_bytes()),
+    }
+}
+
 #[async_trait]
 impl StoreDriver for GrpcStore {
     // NOTE: This function can only be safely used on CAS stores. AC stores may return a size that
@@ -531,42 +1021,39 @@ impl StoreDriver for GrpcStore {
             return Ok(());
         }
 
+        let digest_function =
+            active_digest_function().err_tip(|| "In GrpcStore::has_with_results")?;
+        if let Some(batching) = &self.batching {
+            let digests: Vec<DigestInfo> = keys.iter().map(|k| k.borrow().into_digest()).collect();
+            let size = digests.len() as u64 * FIND_MISSING_DIGEST_SIZE;
+            let sizes = batching
+                .find_missing_batcher
+                .request((digest_function, digests), size)
+                .await
+                .err_tip(|| "In GrpcStore::has_with_results")?;
+            results.copy_from_slice(&sizes);
+            return Ok(());
+        }
+
         let missing_blobs_response = self
-            .find_missing_blobs(Request::new(FindMissingBlobsRequest {
+            .find_missing_blobs(make_request(FindMissingBlobsRequest {
                 instance_name: self.instance_name.clone(),
                 blob_digests: keys
                     .iter()
                     .map(|k| k.borrow().into_digest().into())
                     .collect(),
-                digest_function: ActiveOriginContext::get_value(&ACTIVE_HASHER_FUNC)
-                    .err_tip(|| "In GrpcStore::has_with_results")?
-                    .map_or_else(default_digest_hasher_func, |v| *v)
-                    .proto_digest_func()
-                    .into(),
+                digest_function,
             }))
             .await?
             .into_inner();
 
-        // Since the ordering is not guaranteed above, the matching has to check
-        // all missing blobs against all entries in the unsorted digest list.
-        // To optimise this, the missing digests are sorted and then it is
-        // efficient to perform a binary search for each digest within the
-        // missing list.
-        let mut missing_digests =
-            Vec::with_capacity(missing_blobs_response.missing_blob_digests.len());
-        for missing_digest in missing_blobs_response.missing_blob_digests {
-            missing_digests.push(DigestInfo::try_from(missing_digest)?);
-        }
-        missing_digests.sort_unstable();
+        let missing_digests = sorted_missing_digests(missing_blobs_response)?;
         for (digest, result) in keys
             .iter()
             .map(|v| v.borrow().into_digest())
             .zip(results.iter_mut())
         {
-            match missing_digests.binary_search(&digest) {
-                Ok(_) => *result = None,
-                Err(_) => *result = Some(digest.size_bytes()),
-            }
+            *result = size_if_present(&missing_digests, digest);
         }
 
         Ok(())
@@ -576,13 +1063,30 @@ impl StoreDriver for GrpcStore {
         self: Pin<&Self>,
         key: StoreKey<'_>,
         reader: DropCloserReadHalf,
-        _size_info: UploadSizeInfo,
+        size_info: UploadSizeInfo,
     ) -> Result<(), Error> {
         let digest = key.into_digest();
         if matches!(self.store_type, nativelink_config::stores::StoreType::ac) {
             return self.update_action_result_from_bytes(digest, reader).await;
         }
 
+        if let (Some(batching), UploadSizeInfo::ExactSize(size)) = (&self.batching, size_info) {
+            if size <= batching.max_blob_size {
+                let mut reader = reader;
+                let data = reader
+                    .consume(None)
+                    .await
+                    .err_tip(|| "Reading data in GrpcStore::update()")?;
+                let digest_function =
+                    active_digest_function().err_tip(|| "In GrpcStore::update()")?;
+                return batching
+                    .update_batcher
+                    .request((digest_function, digest, data), size)
+                    .await
+                    .err_tip(|| "In GrpcStore::update()");
+            }
+        }
+
         let mut buf = Uuid::encode_buffer();
         let resource_name = format!(
             "{}/uploads/{}/blobs/{}/{}",
@@ -682,6 +1186,62 @@ impl StoreDriver for GrpcStore {
             digest.size_bytes(),
         );
 
+        if let Some(batching) = &self.batching {
+            if digest.size_bytes() <= batching.max_blob_size {
+                let digest_function =
+                    active_digest_function().err_tip(|| "In GrpcStore::get_part()")?;
+                let data = batching
+                    .read_batcher
+                    .request((digest_function, digest), digest.size_bytes())
+                    .await
+                    .err_tip(|| "In GrpcStore::get_part()")?;
+                let len = data.len() as u64;
+                if offset > len {
+                    return Err(make_err!(
+                        Code::OutOfRange,
+                        "Offset {offset} is past the end of {digest} in GrpcStore::get_part()"
+                    ));
+                }
+                let end = length.map_or(len, |length| offset.saturating_add(length).min(len));
+                if end > offset {
+                    writer
+                        .send(data.slice(offset as usize..end as usize))
+                        .await
+                        .err_tip(|| "While sending in GrpcStore::get_part()")?;
+                }
+                return writer
+                    .send_eof()
+                    .err_tip(|| "Could not send eof in GrpcStore::get_part()");
+            }
+        }
+
+        if let Some(hedging) = &self.hedging {
+            if digest.size_bytes() <= hedging.max_blob_size {
+                let request = ReadRequest {
+                    resource_name,
+                    read_offset: i64::try_from(offset)
+                        .err_tip(|| "Could not convert offset to i64")?,
+                    read_limit: i64::try_from(length.unwrap_or(0))
+                        .err_tip(|| "Could not convert length to i64")?,
+                };
+                let data = self
+                    .perform_request(request, |request| async move {
+                        self.hedged_read(hedging, request).await
+                    })
+                    .await
+                    .err_tip(|| "in GrpcStore::get_part()")?;
+                if !data.is_empty() {
+                    writer
+                        .send(data)
+                        .await
+                        .err_tip(|| "While sending in GrpcStore::get_part()")?;
+                }
+                return writer
+                    .send_eof()
+                    .err_tip(|| "Could not send eof in GrpcStore::get_part()");
+            }
+        }
+
         struct LocalState<'a> {
             resource_name: String,
             writer: &'a mut DropCloserWriteHalf,
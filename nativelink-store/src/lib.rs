@@ -18,8 +18,10 @@ pub mod completeness_checking_store;
 pub mod compression_store;
 pub mod dedup_store;
 pub mod default_store_factory;
+pub mod encryption_store;
 pub mod existence_cache_store;
 pub mod fast_slow_store;
+pub mod filesystem_fsck;
 pub mod filesystem_store;
 pub mod grpc_store;
 pub mod memory_store;
@@ -30,5 +32,8 @@ pub mod ref_store;
 pub mod s3_store;
 pub mod shard_store;
 pub mod size_partitioning_store;
+#[cfg(feature = "sql")]
+pub mod sql_scheduler_store;
 pub mod store_manager;
+pub mod store_migration;
 pub mod verify_store;
@@ -116,6 +116,10 @@ impl StoreDriver for MemoryStore {
         Ok(iterations)
     }
 
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        Ok(self.remove_entry(key).await)
+    }
+
     async fn update(
         self: Pin<&Self>,
         key: StoreKey<'_>,
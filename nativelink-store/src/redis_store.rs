@@ -36,8 +36,8 @@ use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
 use nativelink_util::health_utils::{HealthRegistryBuilder, HealthStatus, HealthStatusIndicator};
 use nativelink_util::spawn;
 use nativelink_util::store_trait::{
-    BoolValue, SchedulerCurrentVersionProvider, SchedulerIndexProvider, SchedulerStore,
-    SchedulerStoreDataProvider, SchedulerStoreDecodeTo, SchedulerStoreKeyProvider,
+    BoolValue, SchedulerCurrentVersionProvider, SchedulerIndexProvider, SchedulerLeaseStore,
+    SchedulerStore, SchedulerStoreDataProvider, SchedulerStoreDecodeTo, SchedulerStoreKeyProvider,
     SchedulerSubscription, SchedulerSubscriptionManager, StoreDriver, StoreKey, UploadSizeInfo,
 };
 use nativelink_util::task::JoinHandleDropGuard;
@@ -207,6 +207,97 @@ impl RedisStore {
     pub fn get_client(&self) -> RedisClient {
         self.client_pool.next().clone()
     }
+
+    /// Subscribe to every key published to `pub_sub_channel`. Keys that do
+    /// not start with this store's `key_prefix` are ignored, and the prefix
+    /// is removed from the ones that do.
+    pub fn subscribe_to_published_keys(&self) -> Result<PublishedKeySubscription, Error> {
+        let Some(pub_sub_channel) = self.pub_sub_channel.clone() else {
+            return Err(make_input_err!(
+                "RedisStore must have a pubsub channel to subscribe to published keys"
+            ));
+        };
+        let subscribe_client = self.subscriber_client.clone();
+        let key_prefix = self.key_prefix.clone();
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        Ok(PublishedKeySubscription {
+            rx,
+            _subscription_spawn: spawn!("redis_published_keys_spawn", async move {
+                let mut rx = subscribe_client.message_rx();
+                loop {
+                    if let Err(e) = subscribe_client.subscribe(&pub_sub_channel).await {
+                        event!(Level::ERROR, "Error subscribing to published keys - {e}");
+                        return;
+                    }
+                    let mut reconnect_rx = subscribe_client.reconnect_rx();
+                    let reconnect_fut = reconnect_rx.recv().fuse();
+                    tokio::pin!(reconnect_fut);
+                    loop {
+                        select! {
+                            msg = rx.recv() => {
+                                let msg = match msg {
+                                    Ok(msg) => msg,
+                                    Err(e) => {
+                                        event!(Level::ERROR, "Error receiving published key, resubscribing - {e}");
+                                        break;
+                                    }
+                                };
+                                if &*msg.channel != pub_sub_channel.as_str() {
+                                    continue;
+                                }
+                                let RedisValue::String(key) = msg.value else {
+                                    event!(Level::ERROR, "Received non-string message in PublishedKeySubscription");
+                                    continue;
+                                };
+                                let Some(key) = key.strip_prefix(key_prefix.as_str()) else {
+                                    continue;
+                                };
+                                if tx.send(PublishedKeyEvent::Changed(key.to_string())).is_err() {
+                                    return;
+                                }
+                            },
+                            _ = &mut reconnect_fut => {
+                                event!(Level::WARN, "Redis reconnected while subscribed to published keys");
+                                break;
+                            }
+                        }
+                    }
+                    // Anything published while we were not listening was lost.
+                    if tx.send(PublishedKeyEvent::Lagged).is_err() {
+                        return;
+                    }
+                    sleep(Duration::from_secs(1)).await;
+                    rx = subscribe_client.message_rx();
+                    rx.resubscribe();
+                }
+            }),
+        })
+    }
+}
+
+/// A notification received by a [`PublishedKeySubscription`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum PublishedKeyEvent {
+    /// A key, without the store's `key_prefix`, was published.
+    Changed(String),
+    /// The subscription was interrupted and notifications may have been
+    /// missed, so any key may have changed.
+    Lagged,
+}
+
+/// A subscription to the keys published to a [`RedisStore`]'s pubsub
+/// channel. The subscription ends when this is dropped.
+pub struct PublishedKeySubscription {
+    rx: tokio::sync::mpsc::UnboundedReceiver<PublishedKeyEvent>,
+    _subscription_spawn: JoinHandleDropGuard<()>,
+}
+
+impl PublishedKeySubscription {
+    /// Wait for the next published key. Returns `None` if the
+    /// subscription could not be established.
+    pub async fn recv(&mut self) -> Option<PublishedKeyEvent> {
+        self.rx.recv().await
+    }
 }
 
 #[async_trait]
@@ -408,6 +499,23 @@ impl StoreDriver for RedisStore {
         Ok(())
     }
 
+    async fn remove(self: Pin<&Self>, key: StoreKey<'_>) -> Result<bool, Error> {
+        let encoded_key = self.encode_key(&key);
+        let client = self.client_pool.next();
+        let removed = client
+            .del::<u64, _>(encoded_key.as_ref())
+            .await
+            .err_tip(|| "In RedisStore::remove")?;
+        // Subscribers caching the key need to know it is gone too.
+        if let Some(pub_sub_channel) = &self.pub_sub_channel {
+            client
+                .publish::<(), _, _>(pub_sub_channel, encoded_key.as_ref())
+                .await
+                .err_tip(|| "While publishing removed key in RedisStore::remove")?;
+        }
+        Ok(removed > 0)
+    }
+
     async fn get_part(
         self: Pin<&Self>,
         key: StoreKey<'_>,
@@ -589,6 +697,33 @@ return new_version
 "#
 );
 
+/// Lua script to acquire a lease or extend it if it is already held.
+/// Args:
+///   KEYS[1]: The key of the lease.
+///   ARGV[1]: The holder trying to acquire the lease.
+///   ARGV[2]: The duration of the lease in milliseconds.
+/// Returns:
+///   1 if the lease is held by the holder, 0 otherwise.
+const LUA_ACQUIRE_LEASE_SCRIPT: &str = r"
+local holder = redis.call('GET', KEYS[1])
+if holder == false or holder == ARGV[1] then
+    redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2])
+    return 1
+end
+return 0
+";
+
+/// Lua script to release a lease only if it is held by the given holder.
+/// Args:
+///   KEYS[1]: The key of the lease.
+///   ARGV[1]: The holder releasing the lease.
+const LUA_RELEASE_LEASE_SCRIPT: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    redis.call('DEL', KEYS[1])
+end
+return 0
+";
+
 /// Compile-time fingerprint of the `FT.CREATE` command used to create the index template.
 /// This is a simple CRC32 checksum of the command string. We don't care about it actually
 /// being a valid CRC32 checksum, just that it's a unique identifier with a low chance of
@@ -1111,3 +1246,39 @@ impl SchedulerStore for RedisStore {
         )?))
     }
 }
+
+impl SchedulerLeaseStore for RedisStore {
+    async fn acquire_or_renew_lease(
+        &self,
+        name: &str,
+        holder: &str,
+        lease_duration: Duration,
+    ) -> Result<bool, Error> {
+        let key = StoreKey::Str(Cow::Borrowed(name));
+        let key = self.encode_key(&key);
+        // The expiry is kept by Redis, so the clocks of the schedulers don't
+        // need to agree.
+        let acquired = Script::from_lua(LUA_ACQUIRE_LEASE_SCRIPT)
+            .evalsha_with_reload::<i64, _, Vec<String>>(
+                self.client_pool.next(),
+                vec![key.as_ref()],
+                vec![holder.to_string(), lease_duration.as_millis().to_string()],
+            )
+            .await
+            .err_tip(|| format!("In RedisStore::acquire_or_renew_lease for {key}"))?;
+        Ok(acquired == 1)
+    }
+
+    async fn release_lease(&self, name: &str, holder: &str) -> Result<(), Error> {
+        let key = StoreKey::Str(Cow::Borrowed(name));
+        let key = self.encode_key(&key);
+        Script::from_lua(LUA_RELEASE_LEASE_SCRIPT)
+            .evalsha_with_reload::<(), _, Vec<String>>(
+                self.client_pool.next(),
+                vec![key.as_ref()],
+                vec![holder.to_string()],
+            )
+            .await
+            .err_tip(|| format!("In RedisStore::release_lease for {key}"))
+    }
+}
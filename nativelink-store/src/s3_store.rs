@@ -13,8 +13,11 @@
 // limitations under the License.
 
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Write as _;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time::Duration;
@@ -27,8 +30,9 @@ use aws_sdk_s3::config::Region;
 use aws_sdk_s3::operation::create_multipart_upload::CreateMultipartUploadOutput;
 use aws_sdk_s3::operation::get_object::GetObjectError;
 use aws_sdk_s3::operation::head_object::HeadObjectError;
-use aws_sdk_s3::primitives::{ByteStream, SdkBody};
+use aws_sdk_s3::primitives::{ByteStream, DateTime, SdkBody};
 use aws_sdk_s3::types::builders::{CompletedMultipartUploadBuilder, CompletedPartBuilder};
+use aws_sdk_s3::types::MetadataDirective;
 use aws_sdk_s3::Client;
 use aws_smithy_runtime::client::http::hyper_014::HyperClientBuilder;
 use bytes::Bytes;
@@ -49,11 +53,12 @@ use nativelink_metric::MetricsComponent;
 use nativelink_util::buf_channel::{
     make_buf_channel_pair, DropCloserReadHalf, DropCloserWriteHalf,
 };
-use nativelink_util::fs;
 use nativelink_util::health_utils::{HealthStatus, HealthStatusIndicator};
 use nativelink_util::instant_wrapper::InstantWrapper;
 use nativelink_util::retry::{Retrier, RetryResult};
 use nativelink_util::store_trait::{StoreDriver, StoreKey, UploadSizeInfo};
+use nativelink_util::{background_spawn, fs};
+use parking_lot::Mutex;
 use rand::rngs::OsRng;
 use rand::Rng;
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
@@ -84,6 +89,13 @@ const DEFAULT_MAX_RETRY_BUFFER_PER_REQUEST: usize = 5 * 1024 * 1024; // 5MB.
 // Note: If you change this, adjust the docs in the config.
 const DEFAULT_MULTIPART_MAX_CONCURRENT_UPLOADS: usize = 10;
 
+// CopyObject cannot copy objects larger than this number. See:
+// https://docs.aws.amazon.com/AmazonS3/latest/API/API_CopyObject.html
+const MAX_COPY_OBJECT_SIZE: u64 = 5 * 1024 * 1024 * 1024; // 5GB.
+
+// Number of tracked refreshes at which stale entries are first pruned.
+const MIN_REFRESH_TIMES_PRUNE_LEN: usize = 1024;
+
 pub struct ConnectionWithPermit<T: Connection + AsyncRead + AsyncWrite + Unpin> {
     connection: T,
     _permit: SemaphorePermit<'static>,
@@ -253,6 +265,12 @@ pub struct S3Store<NowFn> {
     max_retry_buffer_per_request: usize,
     #[metric(help = "The number of concurrent uploads allowed for multipart uploads")]
     multipart_max_concurrent_uploads: usize,
+    #[metric(help = "Objects within this many seconds of expiring are refreshed")]
+    refresh_before_expired_s: i64,
+    /// Time each object was last refreshed, keyed by S3 path.
+    refresh_times: Mutex<HashMap<String, i64>>,
+    #[metric(help = "The number of objects refreshed to keep them from expiring")]
+    objects_refreshed: AtomicU64,
 }
 
 impl<I, NowFn> S3Store<NowFn>
@@ -304,6 +322,16 @@ where
         jitter_fn: Arc<dyn Fn(Duration) -> Duration + Send + Sync>,
         now_fn: NowFn,
     ) -> Result<Arc<Self>, Error> {
+        if config.refresh_before_expired_s != 0
+            && config.refresh_before_expired_s >= config.consider_expired_after_s
+        {
+            return Err(make_err!(
+                Code::InvalidArgument,
+                "refresh_before_expired_s ({}) must be less than consider_expired_after_s ({}) in S3 store",
+                config.refresh_before_expired_s,
+                config.consider_expired_after_s
+            ));
+        }
         Ok(Arc::new(Self {
             s3_client: Arc::new(s3_client),
             now_fn,
@@ -321,6 +349,9 @@ where
             multipart_max_concurrent_uploads: config
                 .multipart_max_concurrent_uploads
                 .map_or(DEFAULT_MULTIPART_MAX_CONCURRENT_UPLOADS, |v| v),
+            refresh_before_expired_s: i64::from(config.refresh_before_expired_s),
+            refresh_times: Mutex::new(HashMap::new()),
+            objects_refreshed: AtomicU64::new(0),
         }))
     }
 
@@ -328,14 +359,81 @@ where
         format!("{}{}", self.key_prefix, key.as_str(),)
     }
 
+    /// Copies the object at `s3_path` onto itself in the background if it
+    /// is about to be considered expired, so that it stays available.
+    fn maybe_refresh_object(
+        &self,
+        s3_path: &str,
+        last_modified: Option<&DateTime>,
+        object_size: Option<u64>,
+    ) {
+        if self.refresh_before_expired_s == 0 {
+            return;
+        }
+        let Some(last_modified) = last_modified else {
+            return;
+        };
+        let now_s = (self.now_fn)().unix_timestamp() as i64;
+        let refresh_at_s =
+            last_modified.secs() + self.consider_expired_after_s - self.refresh_before_expired_s;
+        if now_s < refresh_at_s {
+            return;
+        }
+        if object_size.is_some_and(|size| size > MAX_COPY_OBJECT_SIZE) {
+            return;
+        }
+        {
+            let mut refresh_times = self.refresh_times.lock();
+            if let Some(refreshed_at_s) = refresh_times.get(s3_path) {
+                if now_s < refreshed_at_s + self.refresh_before_expired_s {
+                    return; // Debounce, a refresh was already started recently.
+                }
+            }
+            // Prune stale entries every time the map doubles in size.
+            let len = refresh_times.len();
+            if len >= MIN_REFRESH_TIMES_PRUNE_LEN && len.is_power_of_two() {
+                let refresh_before_expired_s = self.refresh_before_expired_s;
+                refresh_times
+                    .retain(|_, refreshed_at_s| now_s < *refreshed_at_s + refresh_before_expired_s);
+            }
+            refresh_times.insert(s3_path.to_string(), now_s);
+        }
+        self.objects_refreshed.fetch_add(1, Ordering::Relaxed);
+
+        let s3_client = self.s3_client.clone();
+        let bucket = self.bucket.clone();
+        let s3_path = s3_path.to_string();
+        background_spawn!("s3_store_refresh_object", async move {
+            // An object can only be copied onto itself if something about
+            // it changes, so the metadata is replaced (with nothing).
+            let result = s3_client
+                .copy_object()
+                .bucket(&bucket)
+                .key(&s3_path)
+                .copy_source(format!("{bucket}/{}", encode_copy_source_key(&s3_path)))
+                .metadata_directive(MetadataDirective::Replace)
+                .send()
+                .await;
+            if let Err(err) = result {
+                event!(
+                    Level::WARN,
+                    ?err,
+                    s3_path,
+                    "Failed to refresh object in S3Store"
+                );
+            }
+        });
+    }
+
     async fn has(self: Pin<&Self>, digest: &StoreKey<'_>) -> Result<Option<u64>, Error> {
+        let s3_path = &self.make_s3_path(digest.borrow());
         self.retrier
             .retry(unfold((), move |state| async move {
                 let result = self
                     .s3_client
                     .head_object()
                     .bucket(&self.bucket)
-                    .key(self.make_s3_path(digest.borrow()))
+                    .key(s3_path)
                     .send()
                     .await;
 
@@ -353,6 +451,11 @@ where
                             return Some((RetryResult::Ok(None), state));
                         };
                         if length >= 0 {
+                            self.maybe_refresh_object(
+                                s3_path,
+                                head_object_output.last_modified.as_ref(),
+                                Some(length as u64),
+                            );
                             return Some((RetryResult::Ok(Some(length as u64)), state));
                         }
                         Some((
@@ -379,6 +482,19 @@ where
     }
 }
 
+/// Percent-encodes an S3 path for use in the `x-amz-copy-source` header.
+fn encode_copy_source_key(s3_path: &str) -> String {
+    let mut encoded = String::with_capacity(s3_path.len());
+    for byte in s3_path.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~' | b'/') {
+            encoded.push(char::from(byte));
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
+
 #[async_trait]
 impl<I, NowFn> StoreDriver for S3Store<NowFn>
 where
@@ -708,7 +824,20 @@ where
                     .await;
 
                 let mut s3_in_stream = match result {
-                    Ok(head_object_output) => head_object_output.body,
+                    Ok(get_object_output) => {
+                        // The content range is formatted as `bytes {start}-{end}/{size}`.
+                        let object_size = get_object_output
+                            .content_range
+                            .as_deref()
+                            .and_then(|range| range.rsplit('/').next())
+                            .and_then(|size| size.parse().ok());
+                        self.maybe_refresh_object(
+                            s3_path,
+                            get_object_output.last_modified.as_ref(),
+                            object_size,
+                        );
+                        get_object_output.body
+                    }
                     Err(sdk_error) => match sdk_error.into_service_error() {
                         GetObjectError::NoSuchKey(e) => {
                             return Some((
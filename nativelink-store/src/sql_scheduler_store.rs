@@ -0,0 +1,765 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use futures::{stream, Stream};
+use nativelink_config::schedulers::ExperimentalSqlSchedulerBackend;
+use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
+use nativelink_util::spawn;
+use nativelink_util::store_trait::{
+    BoolValue, SchedulerCurrentVersionProvider, SchedulerIndexProvider, SchedulerLeaseStore,
+    SchedulerStore, SchedulerStoreDataProvider, SchedulerStoreDecodeTo, SchedulerStoreKeyProvider,
+    SchedulerSubscription, SchedulerSubscriptionManager,
+};
+use nativelink_util::task::JoinHandleDropGuard;
+use parking_lot::{Mutex, RwLock};
+use patricia_tree::StringPatriciaMap;
+use sqlx::any::{install_default_drivers, AnyPoolOptions};
+use sqlx::{AnyPool, Row};
+use tokio::sync::{watch, OnceCell};
+use tokio::time::sleep;
+use tracing::{event, Level};
+
+/// The default maximum number of connections in the pool.
+const DEFAULT_MAX_CONNECTIONS: u32 = 10;
+/// The default interval to poll for changes made by other schedulers.
+const DEFAULT_POLL_INTERVAL_MS: u64 = 250;
+/// How long entries are kept in the change log. Schedulers that did not
+/// poll for longer than this flag all their subscriptions as changed instead.
+const CHANGE_LOG_RETENTION: Duration = Duration::from_secs(5 * 60);
+/// How long a missing change id is looked for. Ids are handed out when a
+/// change is inserted, but the change only becomes visible once its
+/// transaction commits, so it may show up after changes with higher ids.
+/// Ids that never show up belong to transactions that were rolled back.
+const LATE_CHANGE_WINDOW: Duration = Duration::from_secs(30);
+/// Maximum number of missing change ids looked for. If more are missing,
+/// all subscriptions are flagged as changed instead.
+const MAX_MISSING_CHANGES: usize = 1000;
+/// Maximum number of missing change ids looked up in one query.
+const MISSING_CHANGES_PER_QUERY: usize = 100;
+
+/// Table used to track which migrations have been applied.
+const CREATE_SCHEMA_TABLE: &str =
+    "CREATE TABLE IF NOT EXISTS nativelink_scheduler_schema (version BIGINT NOT NULL)";
+
+/// Migrations for SQLite. Each entry is one schema version and is applied
+/// in a single transaction. Never modify an entry once released, only add
+/// new ones.
+const SQLITE_MIGRATIONS: &[&[&str]] = &[
+    &[
+        "CREATE TABLE nativelink_scheduler_data (
+        key TEXT PRIMARY KEY NOT NULL,
+        version BIGINT NOT NULL,
+        data BLOB NOT NULL
+    )",
+        "CREATE TABLE nativelink_scheduler_index (
+        key TEXT NOT NULL,
+        index_name TEXT NOT NULL,
+        index_value TEXT NOT NULL,
+        PRIMARY KEY (key, index_name)
+    )",
+        "CREATE INDEX nativelink_scheduler_index_value
+        ON nativelink_scheduler_index (index_name, index_value)",
+        "CREATE TABLE nativelink_scheduler_changes (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        key TEXT NOT NULL,
+        changed_at BIGINT NOT NULL
+    )",
+    ],
+    &["CREATE TABLE nativelink_scheduler_leases (
+        name TEXT PRIMARY KEY NOT NULL,
+        holder TEXT NOT NULL,
+        expires_at BIGINT NOT NULL
+    )"],
+];
+
+/// Migrations for Postgres. See `SQLITE_MIGRATIONS`.
+/// Keys and index values use the "C" collation, so they are compared and
+/// sorted bytewise like in SQLite.
+const POSTGRES_MIGRATIONS: &[&[&str]] = &[
+    &[
+        r#"CREATE TABLE nativelink_scheduler_data (
+        key TEXT COLLATE "C" PRIMARY KEY NOT NULL,
+        version BIGINT NOT NULL,
+        data BYTEA NOT NULL
+    )"#,
+        r#"CREATE TABLE nativelink_scheduler_index (
+        key TEXT COLLATE "C" NOT NULL,
+        index_name TEXT NOT NULL,
+        index_value TEXT COLLATE "C" NOT NULL,
+        PRIMARY KEY (key, index_name)
+    )"#,
+        "CREATE INDEX nativelink_scheduler_index_value
+        ON nativelink_scheduler_index (index_name, index_value)",
+        "CREATE TABLE nativelink_scheduler_changes (
+        id BIGSERIAL PRIMARY KEY,
+        key TEXT NOT NULL,
+        changed_at BIGINT NOT NULL
+    )",
+    ],
+    &["CREATE TABLE nativelink_scheduler_leases (
+        name TEXT PRIMARY KEY NOT NULL,
+        holder TEXT NOT NULL,
+        expires_at BIGINT NOT NULL
+    )"],
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SqlDialect {
+    Sqlite,
+    Postgres,
+}
+
+impl SqlDialect {
+    fn from_url(database_url: &str) -> Result<Self, Error> {
+        if database_url.starts_with("sqlite:") {
+            Ok(Self::Sqlite)
+        } else if database_url.starts_with("postgres:") || database_url.starts_with("postgresql:") {
+            Ok(Self::Postgres)
+        } else {
+            Err(make_input_err!(
+                "SqlSchedulerStore only supports 'sqlite:' and 'postgres:' database urls"
+            ))
+        }
+    }
+
+    /// SQL expression for the current time of the database in milliseconds
+    /// since the epoch. Leases use the clock of the database, so the clocks
+    /// of the schedulers don't need to agree.
+    const fn now_millis_sql(self) -> &'static str {
+        match self {
+            Self::Sqlite => "CAST((julianday('now') - 2440587.5) * 86400000 AS BIGINT)",
+            Self::Postgres => "CAST(EXTRACT(EPOCH FROM clock_timestamp()) * 1000 AS BIGINT)",
+        }
+    }
+
+    const fn migrations(self) -> &'static [&'static [&'static str]] {
+        match self {
+            Self::Sqlite => SQLITE_MIGRATIONS,
+            Self::Postgres => POSTGRES_MIGRATIONS,
+        }
+    }
+}
+
+fn sql_err(err: sqlx::Error) -> Error {
+    make_err!(Code::Unavailable, "SQL error: {err:?}")
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| i64::try_from(d.as_millis()).unwrap_or(i64::MAX))
+}
+
+/// Returns a string that sorts after every string starting with `prefix`,
+/// so a prefix search can be done as a range query that uses the indexes.
+fn prefix_upper_bound(prefix: &str) -> String {
+    format!("{prefix}{}", char::MAX)
+}
+
+/// The connection pool, which is only handed out once the schema is up to date.
+struct Database {
+    pool: AnyPool,
+    dialect: SqlDialect,
+    migrated: OnceCell<()>,
+}
+
+impl Database {
+    async fn pool(&self) -> Result<&AnyPool, Error> {
+        self.migrated
+            .get_or_try_init(|| self.migrate())
+            .await
+            .err_tip(|| "In SqlSchedulerStore::migrate")?;
+        Ok(&self.pool)
+    }
+
+    async fn migrate(&self) -> Result<(), Error> {
+        let migrations = self.dialect.migrations();
+        let mut tx = self.pool.begin().await.map_err(sql_err)?;
+        sqlx::query(CREATE_SCHEMA_TABLE)
+            .execute(&mut *tx)
+            .await
+            .map_err(sql_err)?;
+        if self.dialect == SqlDialect::Postgres {
+            // Prevents schedulers that start at the same time from applying
+            // the same migration twice.
+            sqlx::query("LOCK TABLE nativelink_scheduler_schema IN EXCLUSIVE MODE")
+                .execute(&mut *tx)
+                .await
+                .map_err(sql_err)?;
+        }
+        let current_version: i64 =
+            sqlx::query("SELECT COALESCE(MAX(version), 0) FROM nativelink_scheduler_schema")
+                .fetch_one(&mut *tx)
+                .await
+                .and_then(|row| row.try_get(0))
+                .map_err(sql_err)?;
+        let current_version = usize::try_from(current_version)
+            .map_err(|_| make_input_err!("Invalid schema version {current_version}"))?;
+        if current_version > migrations.len() {
+            return Err(make_err!(
+                Code::FailedPrecondition,
+                "Database schema version {current_version} is newer than the supported version {}",
+                migrations.len()
+            ));
+        }
+        for (version, statements) in migrations.iter().enumerate().skip(current_version) {
+            for statement in *statements {
+                sqlx::query(statement)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(sql_err)
+                    .err_tip(|| format!("Applying schema version {}", version + 1))?;
+            }
+            sqlx::query("INSERT INTO nativelink_scheduler_schema (version) VALUES ($1)")
+                .bind(i64::try_from(version + 1).unwrap_or(i64::MAX))
+                .execute(&mut *tx)
+                .await
+                .map_err(sql_err)?;
+        }
+        tx.commit().await.map_err(sql_err)
+    }
+}
+
+/// A store for the scheduler backed by a SQL database. SQLite can be used
+/// for a single scheduler and Postgres for multiple schedulers sharing the
+/// same state.
+pub struct SqlSchedulerStore {
+    database: Arc<Database>,
+    poll_interval: Duration,
+    subscription_manager: Mutex<Option<Arc<SqlSubscriptionManager>>>,
+}
+
+impl SqlSchedulerStore {
+    pub fn new(config: &ExperimentalSqlSchedulerBackend) -> Result<Arc<Self>, Error> {
+        install_default_drivers();
+        let dialect = SqlDialect::from_url(&config.database_url)?;
+        let max_connections = if config.max_connections == 0 {
+            DEFAULT_MAX_CONNECTIONS
+        } else {
+            config.max_connections
+        };
+        let poll_interval_ms = if config.poll_interval_ms == 0 {
+            DEFAULT_POLL_INTERVAL_MS
+        } else {
+            config.poll_interval_ms
+        };
+        // Connections are made on first use, so the scheduler can start
+        // while the database is not reachable yet.
+        let pool = AnyPoolOptions::new()
+            .max_connections(max_connections)
+            .connect_lazy(&config.database_url)
+            .map_err(sql_err)
+            .err_tip(|| "In SqlSchedulerStore::new")?;
+        Ok(Arc::new(Self {
+            database: Arc::new(Database {
+                pool,
+                dialect,
+                migrated: OnceCell::new(),
+            }),
+            poll_interval: Duration::from_millis(poll_interval_ms),
+            subscription_manager: Mutex::new(None),
+        }))
+    }
+}
+
+/// An individual subscription to a key in the database.
+pub struct SqlSubscription {
+    receiver: watch::Receiver<()>,
+}
+
+impl SchedulerSubscription for SqlSubscription {
+    /// Wait for the subscription key to change.
+    async fn changed(&mut self) -> Result<(), Error> {
+        self.receiver
+            .changed()
+            .await
+            .map_err(|_| make_err!(Code::Internal, "In SqlSubscription::changed"))
+    }
+}
+
+type SubscribedKeys = RwLock<StringPatriciaMap<watch::Sender<()>>>;
+
+/// Notifies every subscription to `key` or to a prefix of `key`.
+fn notify_subscribers(subscribed_keys: &SubscribedKeys, key: &str) {
+    subscribed_keys
+        .read()
+        .common_prefix_values(key)
+        .for_each(|sender| sender.send_modify(|()| {}));
+}
+
+fn notify_all_subscribers(subscribed_keys: &SubscribedKeys) {
+    subscribed_keys
+        .read()
+        .values()
+        .for_each(|sender| sender.send_modify(|()| {}));
+}
+
+/// Removes the keys nobody is subscribed to anymore.
+fn remove_unused_subscriptions(subscribed_keys: &SubscribedKeys) {
+    let unused_keys: Vec<String> = subscribed_keys
+        .read()
+        .iter()
+        .filter(|(_, sender)| sender.receiver_count() == 0)
+        .map(|(key, _)| key)
+        .collect();
+    if unused_keys.is_empty() {
+        return;
+    }
+    let mut subscribed_keys = subscribed_keys.write();
+    for key in unused_keys {
+        // Someone might have subscribed again since the read lock was released.
+        if subscribed_keys
+            .get(&key)
+            .is_some_and(|sender| sender.receiver_count() == 0)
+        {
+            subscribed_keys.remove(&key);
+        }
+    }
+}
+
+/// Position in the change log of the changes already picked up.
+#[derive(Default)]
+struct ChangeCursor {
+    /// Id of the latest change picked up. Not known before the first poll.
+    last_id: Option<i64>,
+    /// Ids below `last_id` whose changes were not visible yet, along with
+    /// when they were found missing.
+    missing: BTreeMap<i64, Instant>,
+}
+
+impl ChangeCursor {
+    /// Returns the keys of the changes that became visible since the last
+    /// poll. The first poll only finds the latest change and returns
+    /// nothing. Returns `None` if changes may have been missed.
+    async fn poll(&mut self, database: &Database) -> Result<Option<Vec<String>>, Error> {
+        let pool = database.pool().await?;
+        let Some(mut last_id) = self.last_id else {
+            let latest_id: i64 =
+                sqlx::query("SELECT COALESCE(MAX(id), 0) FROM nativelink_scheduler_changes")
+                    .fetch_one(pool)
+                    .await
+                    .and_then(|row| row.try_get(0))
+                    .map_err(sql_err)?;
+            self.last_id = Some(latest_id);
+            return Ok(Some(Vec::new()));
+        };
+        let now = Instant::now();
+        let mut keys = Vec::new();
+
+        let missing_ids: Vec<i64> = self.missing.keys().copied().collect();
+        for ids in missing_ids.chunks(MISSING_CHANGES_PER_QUERY) {
+            let placeholders: Vec<String> = (1..=ids.len()).map(|i| format!("${i}")).collect();
+            let sql = format!(
+                "SELECT id, key FROM nativelink_scheduler_changes WHERE id IN ({})",
+                placeholders.join(", ")
+            );
+            let rows = ids
+                .iter()
+                .fold(sqlx::query(&sql), |query, id| query.bind(*id))
+                .fetch_all(pool)
+                .await
+                .map_err(sql_err)?;
+            for row in rows {
+                let id: i64 = row.try_get(0).map_err(sql_err)?;
+                if self.missing.remove(&id).is_some() {
+                    keys.push(row.try_get(1).map_err(sql_err)?);
+                }
+            }
+        }
+
+        let rows = sqlx::query(
+            "SELECT id, key FROM nativelink_scheduler_changes WHERE id > $1 ORDER BY id",
+        )
+        .bind(last_id)
+        .fetch_all(pool)
+        .await
+        .map_err(sql_err)?;
+        let mut too_many_missing = false;
+        for row in rows {
+            let id: i64 = row.try_get(0).map_err(sql_err)?;
+            // The ids in between belong to transactions that did not commit
+            // yet or were rolled back.
+            if !usize::try_from(id - last_id - 1).is_ok_and(|gap| gap <= MAX_MISSING_CHANGES) {
+                too_many_missing = true;
+            } else {
+                self.missing.extend((last_id + 1..id).map(|id| (id, now)));
+            }
+            keys.push(row.try_get(1).map_err(sql_err)?);
+            last_id = id;
+        }
+        self.last_id = Some(last_id);
+        self.missing
+            .retain(|_, missing_since| now.duration_since(*missing_since) < LATE_CHANGE_WINDOW);
+        if too_many_missing || self.missing.len() > MAX_MISSING_CHANGES {
+            self.missing.clear();
+            return Ok(None);
+        }
+        Ok(Some(keys))
+    }
+}
+
+pub struct SqlSubscriptionManager {
+    subscribed_keys: Arc<SubscribedKeys>,
+    _poll_spawn: JoinHandleDropGuard<()>,
+}
+
+impl SqlSubscriptionManager {
+    fn new(database: Arc<Database>, poll_interval: Duration) -> Self {
+        let subscribed_keys = Arc::new(RwLock::new(StringPatriciaMap::new()));
+        let subscribed_keys_weak: Weak<SubscribedKeys> = Arc::downgrade(&subscribed_keys);
+        Self {
+            subscribed_keys,
+            // Changes made by this scheduler are published right away, this
+            // picks up the changes made by other schedulers.
+            _poll_spawn: spawn!("sql_subscription_poll_spawn", async move {
+                let mut cursor = ChangeCursor::default();
+                let mut missed_changes = false;
+                let mut last_poll = Instant::now();
+                let mut last_cleanup = Instant::now();
+                loop {
+                    sleep(poll_interval).await;
+                    let Some(subscribed_keys) = subscribed_keys_weak.upgrade() else {
+                        return;
+                    };
+                    // The changes since the last poll may have been removed
+                    // from the change log already.
+                    if last_poll.elapsed() >= CHANGE_LOG_RETENTION {
+                        cursor = ChangeCursor::default();
+                        missed_changes = true;
+                    }
+                    match cursor.poll(&database).await {
+                        Ok(Some(keys)) => {
+                            last_poll = Instant::now();
+                            if missed_changes {
+                                missed_changes = false;
+                                notify_all_subscribers(&subscribed_keys);
+                            }
+                            for key in keys {
+                                notify_subscribers(&subscribed_keys, &key);
+                            }
+                        }
+                        Ok(None) => {
+                            last_poll = Instant::now();
+                            missed_changes = false;
+                            notify_all_subscribers(&subscribed_keys);
+                        }
+                        Err(err) => {
+                            event!(
+                                Level::ERROR,
+                                ?err,
+                                "Error polling for changes in SqlSubscriptionManager"
+                            );
+                            // Changes may have been missed, so once the database
+                            // is reachable again everything is flagged changed
+                            // and polling starts over from the latest change.
+                            cursor = ChangeCursor::default();
+                            missed_changes = true;
+                            continue;
+                        }
+                    }
+                    remove_unused_subscriptions(&subscribed_keys);
+                    if last_cleanup.elapsed() < CHANGE_LOG_RETENTION {
+                        continue;
+                    }
+                    last_cleanup = Instant::now();
+                    let cutoff = now_millis()
+                        - i64::try_from(CHANGE_LOG_RETENTION.as_millis()).unwrap_or(i64::MAX);
+                    let cleanup_result = async {
+                        sqlx::query(
+                            "DELETE FROM nativelink_scheduler_changes WHERE changed_at < $1",
+                        )
+                        .bind(cutoff)
+                        .execute(database.pool().await?)
+                        .await
+                        .map_err(sql_err)
+                    }
+                    .await;
+                    if let Err(err) = cleanup_result {
+                        event!(
+                            Level::WARN,
+                            ?err,
+                            "Error removing old changes in SqlSubscriptionManager"
+                        );
+                    }
+                }
+            }),
+        }
+    }
+}
+
+impl SchedulerSubscriptionManager for SqlSubscriptionManager {
+    type Subscription = SqlSubscription;
+
+    fn notify_for_test(&self, value: String) {
+        notify_subscribers(&self.subscribed_keys, &value);
+    }
+
+    fn subscribe<K>(&self, key: K) -> Result<Self::Subscription, Error>
+    where
+        K: SchedulerStoreKeyProvider,
+    {
+        let key = key.get_key();
+        let key_str = key.as_str();
+        let mut subscribed_keys = self.subscribed_keys.write();
+        let mut receiver = if let Some(sender) = subscribed_keys.get(&key_str) {
+            sender.subscribe()
+        } else {
+            let (sender, receiver) = watch::channel(());
+            subscribed_keys.insert(&key_str, sender);
+            receiver
+        };
+        receiver.mark_changed();
+        Ok(SqlSubscription { receiver })
+    }
+}
+
+impl SchedulerStore for SqlSchedulerStore {
+    type SubscriptionManager = SqlSubscriptionManager;
+
+    fn subscription_manager(&self) -> Result<Arc<SqlSubscriptionManager>, Error> {
+        let mut subscription_manager = self.subscription_manager.lock();
+        Ok(subscription_manager
+            .get_or_insert_with(|| {
+                Arc::new(SqlSubscriptionManager::new(
+                    self.database.clone(),
+                    self.poll_interval,
+                ))
+            })
+            .clone())
+    }
+
+    async fn update_data<T>(&self, data: T) -> Result<Option<u64>, Error>
+    where
+        T: SchedulerStoreDataProvider
+            + SchedulerStoreKeyProvider
+            + SchedulerCurrentVersionProvider
+            + Send,
+    {
+        let key = data.get_key().as_str().into_owned();
+        let indexes = data.get_indexes().err_tip(|| {
+            format!("Err getting index in SqlSchedulerStore::update_data for {key:?}")
+        })?;
+        let current_version = data.current_version();
+        let data = data.try_into_bytes().err_tip(|| {
+            format!(
+                "Could not convert value to bytes in SqlSchedulerStore::update_data for {key:?}"
+            )
+        })?;
+        let pool = self.database.pool().await?;
+        let mut tx = pool.begin().await.map_err(sql_err)?;
+
+        let new_version = if <T as SchedulerStoreKeyProvider>::Versioned::VALUE {
+            let expected_version = i64::try_from(current_version)
+                .map_err(|_| make_input_err!("Invalid version {current_version} for {key:?}"))?;
+            // Same as the Redis store, a key that does not exist yet is at
+            // version 0.
+            let result = if expected_version == 0 {
+                sqlx::query(
+                    "INSERT INTO nativelink_scheduler_data (key, version, data) VALUES ($1, 1, $2)
+                    ON CONFLICT (key) DO UPDATE SET version = 1, data = excluded.data
+                    WHERE nativelink_scheduler_data.version = 0",
+                )
+                .bind(&key)
+                .bind(data.to_vec())
+                .execute(&mut *tx)
+                .await
+            } else {
+                sqlx::query(
+                    "UPDATE nativelink_scheduler_data SET version = version + 1, data = $1
+                    WHERE key = $2 AND version = $3",
+                )
+                .bind(data.to_vec())
+                .bind(&key)
+                .bind(expected_version)
+                .execute(&mut *tx)
+                .await
+            }
+            .map_err(sql_err)
+            .err_tip(|| format!("In SqlSchedulerStore::update_data::versioned for {key:?}"))?;
+            if result.rows_affected() == 0 {
+                return Ok(None);
+            }
+            current_version + 1
+        } else {
+            sqlx::query(
+                "INSERT INTO nativelink_scheduler_data (key, version, data) VALUES ($1, 0, $2)
+                ON CONFLICT (key) DO UPDATE SET version = 0, data = excluded.data",
+            )
+            .bind(&key)
+            .bind(data.to_vec())
+            .execute(&mut *tx)
+            .await
+            .map_err(sql_err)
+            .err_tip(|| format!("In SqlSchedulerStore::update_data::noversion for {key:?}"))?;
+            0 // Always use "0" version since this is not a versioned request.
+        };
+
+        // Indexes are replaced in the same transaction as the data, so they
+        // are always consistent with it.
+        sqlx::query("DELETE FROM nativelink_scheduler_index WHERE key = $1")
+            .bind(&key)
+            .execute(&mut *tx)
+            .await
+            .map_err(sql_err)?;
+        for (name, value) in indexes {
+            let value = String::from_utf8(value.to_vec()).map_err(|e| {
+                make_input_err!("Index {name} of {key:?} is not valid utf8 - {e:?}")
+            })?;
+            sqlx::query(
+                "INSERT INTO nativelink_scheduler_index (key, index_name, index_value)
+                VALUES ($1, $2, $3)",
+            )
+            .bind(&key)
+            .bind(name)
+            .bind(value)
+            .execute(&mut *tx)
+            .await
+            .map_err(sql_err)?;
+        }
+        sqlx::query("INSERT INTO nativelink_scheduler_changes (key, changed_at) VALUES ($1, $2)")
+            .bind(&key)
+            .bind(now_millis())
+            .execute(&mut *tx)
+            .await
+            .map_err(sql_err)?;
+        tx.commit()
+            .await
+            .map_err(sql_err)
+            .err_tip(|| format!("In SqlSchedulerStore::update_data::commit for {key:?}"))?;
+
+        if let Some(subscription_manager) = &*self.subscription_manager.lock() {
+            notify_subscribers(&subscription_manager.subscribed_keys, &key);
+        }
+        Ok(Some(new_version))
+    }
+
+    async fn search_by_index_prefix<K>(
+        &self,
+        index: K,
+    ) -> Result<
+        impl Stream<Item = Result<<K as SchedulerStoreDecodeTo>::DecodeOutput, Error>> + Send,
+        Error,
+    >
+    where
+        K: SchedulerIndexProvider + SchedulerStoreDecodeTo + Send,
+    {
+        let index_value_prefix = index.index_value_prefix();
+        let pool = self.database.pool().await?;
+        let rows = sqlx::query(
+            "SELECT d.version, d.data FROM nativelink_scheduler_index i
+            JOIN nativelink_scheduler_data d ON d.key = i.key
+            WHERE i.index_name = $1
+                AND i.key >= $2 AND i.key < $3
+                AND i.index_value >= $4 AND i.index_value < $5
+            ORDER BY i.index_value",
+        )
+        .bind(K::INDEX_NAME)
+        .bind(K::KEY_PREFIX)
+        .bind(prefix_upper_bound(K::KEY_PREFIX))
+        .bind(index_value_prefix.as_ref())
+        .bind(prefix_upper_bound(&index_value_prefix))
+        .fetch_all(pool)
+        .await
+        .map_err(sql_err)
+        .err_tip(|| {
+            format!(
+                "In SqlSchedulerStore::search_by_index_prefix({}, {index_value_prefix:?})",
+                K::INDEX_NAME
+            )
+        })?;
+        Ok(stream::iter(rows.into_iter().map(|row| {
+            let version = if <K as SchedulerIndexProvider>::Versioned::VALUE {
+                let version: i64 = row.try_get(0).map_err(sql_err)?;
+                u64::try_from(version).unwrap_or(0)
+            } else {
+                0
+            };
+            let data: Vec<u8> = row.try_get(1).map_err(sql_err)?;
+            K::decode(version, Bytes::from(data))
+                .err_tip(|| "In SqlSchedulerStore::search_by_index_prefix::decode")
+        })))
+    }
+
+    async fn get_and_decode<K>(
+        &self,
+        key: K,
+    ) -> Result<Option<<K as SchedulerStoreDecodeTo>::DecodeOutput>, Error>
+    where
+        K: SchedulerStoreKeyProvider + SchedulerStoreDecodeTo + Send,
+    {
+        let key = key.get_key().as_str().into_owned();
+        let pool = self.database.pool().await?;
+        let Some(row) =
+            sqlx::query("SELECT version, data FROM nativelink_scheduler_data WHERE key = $1")
+                .bind(&key)
+                .fetch_optional(pool)
+                .await
+                .map_err(sql_err)
+                .err_tip(|| format!("In SqlSchedulerStore::get_and_decode {key}"))?
+        else {
+            return Ok(None);
+        };
+        let version: i64 = row.try_get(0).map_err(sql_err)?;
+        let data: Vec<u8> = row.try_get(1).map_err(sql_err)?;
+        Ok(Some(
+            K::decode(u64::try_from(version).unwrap_or(0), Bytes::from(data))
+                .err_tip(|| format!("In SqlSchedulerStore::get_and_decode::decode {key}"))?,
+        ))
+    }
+}
+
+impl SchedulerLeaseStore for SqlSchedulerStore {
+    async fn acquire_or_renew_lease(
+        &self,
+        name: &str,
+        holder: &str,
+        lease_duration: Duration,
+    ) -> Result<bool, Error> {
+        let now = self.database.dialect.now_millis_sql();
+        let pool = self.database.pool().await?;
+        let result = sqlx::query(&format!(
+            "INSERT INTO nativelink_scheduler_leases (name, holder, expires_at)
+            VALUES ($1, $2, {now} + $3)
+            ON CONFLICT (name) DO UPDATE
+            SET holder = excluded.holder, expires_at = excluded.expires_at
+            WHERE nativelink_scheduler_leases.holder = excluded.holder
+                OR nativelink_scheduler_leases.expires_at < {now}"
+        ))
+        .bind(name)
+        .bind(holder)
+        .bind(i64::try_from(lease_duration.as_millis()).unwrap_or(i64::MAX))
+        .execute(pool)
+        .await
+        .map_err(sql_err)
+        .err_tip(|| format!("In SqlSchedulerStore::acquire_or_renew_lease for {name}"))?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn release_lease(&self, name: &str, holder: &str) -> Result<(), Error> {
+        let pool = self.database.pool().await?;
+        sqlx::query("DELETE FROM nativelink_scheduler_leases WHERE name = $1 AND holder = $2")
+            .bind(name)
+            .bind(holder)
+            .execute(pool)
+            .await
+            .map_err(sql_err)
+            .err_tip(|| format!("In SqlSchedulerStore::release_lease for {name}"))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,348 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::borrow::Cow;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+
+use futures::join;
+use futures::stream::{self, StreamExt};
+use nativelink_config::stores::{StoreConfig, VerifyStore as VerifyStoreConfig};
+use nativelink_error::{make_input_err, Code, Error, ResultExt};
+use nativelink_util::buf_channel::make_buf_channel_pair;
+use nativelink_util::common::{fs, DigestInfo};
+use nativelink_util::store_trait::{Store, StoreKey, StoreLike, UploadSizeInfo};
+use tokio::io::AsyncWriteExt;
+use tracing::{event, Level};
+
+use crate::verify_store::VerifyStore;
+
+/// Default number of keys copied at the same time.
+pub const DEFAULT_MAX_CONCURRENT_COPIES: usize = 32;
+
+/// Default number of keys listed from the source store per batch.
+/// The checkpoint (if any) is written after each batch.
+pub const DEFAULT_LIST_BATCH_SIZE: usize = 1000;
+
+/// Options that control how `migrate_store` copies data.
+#[derive(Debug, Clone)]
+pub struct MigrationOptions {
+    /// Maximum number of keys that will be copied concurrently.
+    pub max_concurrent_copies: usize,
+
+    /// Number of keys to pull from `Store::list()` at a time. Checkpoints
+    /// are only written on batch boundaries.
+    pub list_batch_size: usize,
+
+    /// If set, keys are listed and counted, but nothing is written to
+    /// the destination store.
+    pub dry_run: bool,
+
+    /// If set, the size and hash of every digest key is verified while
+    /// it is being copied, using the same logic as `VerifyStore`.
+    /// String keys are copied without verification.
+    pub verify: bool,
+
+    /// File used to record the last key that was fully migrated. If the
+    /// file exists when the migration starts, listing resumes after the
+    /// recorded key.
+    pub checkpoint_path: Option<PathBuf>,
+}
+
+impl Default for MigrationOptions {
+    fn default() -> Self {
+        Self {
+            max_concurrent_copies: DEFAULT_MAX_CONCURRENT_COPIES,
+            list_batch_size: DEFAULT_LIST_BATCH_SIZE,
+            dry_run: false,
+            verify: false,
+            checkpoint_path: None,
+        }
+    }
+}
+
+/// Summary of what happened during a call to `migrate_store`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MigrationStats {
+    /// Number of keys returned by the source store listing.
+    pub keys_listed: u64,
+    /// Number of keys written to the destination store.
+    pub keys_copied: u64,
+    /// Number of bytes written to the destination store.
+    pub bytes_copied: u64,
+    /// Number of keys that already existed in the destination store.
+    pub keys_already_present: u64,
+    /// Number of keys that were listed, but disappeared from the source
+    /// store before they could be copied.
+    pub keys_missing_in_source: u64,
+    /// Number of keys that failed to copy or failed verification.
+    pub keys_failed: u64,
+}
+
+enum CopyOutcome {
+    Copied(u64),
+    AlreadyPresent,
+    MissingInSource,
+    DryRun,
+}
+
+/// Copies every key that `source.list()` returns into `destination`.
+///
+/// Keys are processed in listing order in batches of
+/// `MigrationOptions::list_batch_size`. Individual key failures are logged
+/// and counted, but do not stop the migration. Once any batch has a failure,
+/// the checkpoint stops advancing so a resumed run will retry the failed keys.
+pub async fn migrate_store(
+    source: &Store,
+    destination: &Store,
+    options: &MigrationOptions,
+) -> Result<MigrationStats, Error> {
+    error_if_zero(options.max_concurrent_copies, "max_concurrent_copies")?;
+    error_if_zero(options.list_batch_size, "list_batch_size")?;
+
+    let verified_destination = if options.verify {
+        Some(Store::new(VerifyStore::new(
+            &VerifyStoreConfig {
+                // The backend config is not used, the store is passed in directly.
+                backend: StoreConfig::noop,
+                verify_size: true,
+                verify_hash: true,
+            },
+            destination.clone(),
+        )))
+    } else {
+        None
+    };
+
+    let mut start_bound = match &options.checkpoint_path {
+        Some(checkpoint_path) => match read_checkpoint(checkpoint_path).await? {
+            Some(key) => {
+                event!(
+                    Level::INFO,
+                    ?key,
+                    "Resuming store migration from checkpoint"
+                );
+                Bound::Excluded(key)
+            }
+            None => Bound::Unbounded,
+        },
+        None => Bound::Unbounded,
+    };
+
+    let verified_destination = verified_destination.as_ref();
+    let mut stats = MigrationStats::default();
+    let mut checkpoint_enabled = options.checkpoint_path.is_some();
+    loop {
+        let keys = list_batch(source, start_bound, options.list_batch_size)
+            .await
+            .err_tip(|| "While listing source store in migrate_store")?;
+        let Some(last_key) = keys.last().cloned() else {
+            break;
+        };
+        let is_last_batch = keys.len() < options.list_batch_size;
+        stats.keys_listed += keys.len() as u64;
+
+        let results: Vec<(StoreKey<'static>, Result<CopyOutcome, Error>)> = stream::iter(keys)
+            .map(|key| async move {
+                let result = copy_key(
+                    source,
+                    destination,
+                    verified_destination,
+                    key.borrow(),
+                    options.dry_run,
+                )
+                .await;
+                (key, result)
+            })
+            .buffer_unordered(options.max_concurrent_copies)
+            .collect()
+            .await;
+
+        let mut batch_failed = false;
+        for (key, result) in results {
+            match result {
+                Ok(CopyOutcome::Copied(size)) => {
+                    stats.keys_copied += 1;
+                    stats.bytes_copied += size;
+                }
+                Ok(CopyOutcome::AlreadyPresent) => stats.keys_already_present += 1,
+                Ok(CopyOutcome::MissingInSource) => stats.keys_missing_in_source += 1,
+                Ok(CopyOutcome::DryRun) => {}
+                Err(err) => {
+                    event!(Level::ERROR, ?key, ?err, "Failed to migrate key");
+                    stats.keys_failed += 1;
+                    batch_failed = true;
+                }
+            }
+        }
+
+        if batch_failed && checkpoint_enabled {
+            event!(
+                Level::WARN,
+                "Store migration had failures, checkpoint will no longer be advanced"
+            );
+            checkpoint_enabled = false;
+        }
+        if checkpoint_enabled && !options.dry_run {
+            if let Some(checkpoint_path) = &options.checkpoint_path {
+                write_checkpoint(checkpoint_path, &last_key)
+                    .await
+                    .err_tip(|| "In migrate_store")?;
+            }
+        }
+        event!(Level::INFO, ?stats, "Store migration progress");
+
+        if is_last_batch {
+            break;
+        }
+        start_bound = Bound::Excluded(last_key);
+    }
+    Ok(stats)
+}
+
+fn error_if_zero(value: usize, name: &str) -> Result<(), Error> {
+    if value == 0 {
+        return Err(make_input_err!("{name} must be greater than zero"));
+    }
+    Ok(())
+}
+
+/// Lists up to `batch_size` keys from `store` starting at `start_bound`.
+async fn list_batch(
+    store: &Store,
+    start_bound: Bound<StoreKey<'static>>,
+    batch_size: usize,
+) -> Result<Vec<StoreKey<'static>>, Error> {
+    let mut keys = Vec::with_capacity(batch_size);
+    store
+        .list((start_bound, Bound::Unbounded), |key| {
+            keys.push(key.borrow().into_owned());
+            keys.len() < batch_size
+        })
+        .await?;
+    Ok(keys)
+}
+
+async fn copy_key(
+    source: &Store,
+    destination: &Store,
+    maybe_verified_destination: Option<&Store>,
+    key: StoreKey<'_>,
+    dry_run: bool,
+) -> Result<CopyOutcome, Error> {
+    if destination
+        .has(key.borrow())
+        .await
+        .err_tip(|| "Failed to check destination store")?
+        .is_some()
+    {
+        return Ok(CopyOutcome::AlreadyPresent);
+    }
+    // Note: On AC stores the digest size does not match the size of the
+    // data, so we always ask the source how much data there is.
+    let Some(size) = source
+        .has(key.borrow())
+        .await
+        .err_tip(|| "Failed to check source store")?
+    else {
+        return Ok(CopyOutcome::MissingInSource);
+    };
+    if dry_run {
+        return Ok(CopyOutcome::DryRun);
+    }
+
+    let destination = match (&key, maybe_verified_destination) {
+        (StoreKey::Digest(_), Some(verified_destination)) => verified_destination,
+        _ => destination,
+    };
+    let (tx, rx) = make_buf_channel_pair();
+    let (get_result, update_result) = join!(
+        source.get(key.borrow(), tx),
+        destination.update(key.borrow(), rx, UploadSizeInfo::ExactSize(size)),
+    );
+    update_result
+        .err_tip(|| "Failed to write to destination store")
+        .merge(get_result.err_tip(|| "Failed to read from source store"))?;
+    Ok(CopyOutcome::Copied(size))
+}
+
+const CHECKPOINT_DIGEST_PREFIX: &str = "digest:";
+const CHECKPOINT_STR_PREFIX: &str = "str:";
+
+fn encode_checkpoint_key(key: &StoreKey<'_>) -> String {
+    match key {
+        StoreKey::Digest(digest) => format!("{CHECKPOINT_DIGEST_PREFIX}{digest}"),
+        StoreKey::Str(s) => format!("{CHECKPOINT_STR_PREFIX}{s}"),
+    }
+}
+
+fn decode_checkpoint_key(data: &str) -> Result<StoreKey<'static>, Error> {
+    if let Some(digest_str) = data.strip_prefix(CHECKPOINT_DIGEST_PREFIX) {
+        let (hash, size) = digest_str
+            .split_once('-')
+            .ok_or_else(|| make_input_err!("Invalid digest in checkpoint: {digest_str}"))?;
+        let size = size
+            .parse::<u64>()
+            .map_err(|e| make_input_err!("Invalid size in checkpoint: {size} - {e:?}"))?;
+        return Ok(StoreKey::Digest(DigestInfo::try_new(hash, size)?));
+    }
+    if let Some(s) = data.strip_prefix(CHECKPOINT_STR_PREFIX) {
+        return Ok(StoreKey::Str(Cow::Owned(s.to_string())));
+    }
+    Err(make_input_err!("Unknown key format in checkpoint: {data}"))
+}
+
+/// Reads the last migrated key from the checkpoint file, if one exists.
+pub async fn read_checkpoint(path: &Path) -> Result<Option<StoreKey<'static>>, Error> {
+    let data = match fs::read(path).await {
+        Ok(data) => data,
+        Err(err) if err.code == Code::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err).err_tip(|| format!("Could not read checkpoint file {path:?}"));
+        }
+    };
+    let data = String::from_utf8(data)
+        .map_err(|e| make_input_err!("Checkpoint file {path:?} is not utf8 - {e:?}"))?;
+    let data = data.trim_end_matches('\n');
+    if data.is_empty() {
+        return Ok(None);
+    }
+    decode_checkpoint_key(data)
+        .err_tip(|| format!("Could not parse checkpoint file {path:?}"))
+        .map(Some)
+}
+
+/// Atomically replaces the checkpoint file with `key`.
+async fn write_checkpoint(path: &Path, key: &StoreKey<'_>) -> Result<(), Error> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+    {
+        let mut file = fs::create_file(&temp_path)
+            .await
+            .err_tip(|| format!("Could not create checkpoint file {temp_path:?}"))?;
+        let writer = file.as_writer().await?;
+        writer
+            .write_all(format!("{}\n", encode_checkpoint_key(key)).as_bytes())
+            .await
+            .err_tip(|| format!("Could not write checkpoint file {temp_path:?}"))?;
+        writer
+            .sync_all()
+            .await
+            .err_tip(|| format!("Could not sync checkpoint file {temp_path:?}"))?;
+    }
+    fs::rename(&temp_path, path)
+        .await
+        .err_tip(|| format!("Could not move checkpoint file into place {path:?}"))
+}
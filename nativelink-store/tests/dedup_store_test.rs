@@ -35,6 +35,7 @@ fn make_default_config() -> nativelink_config::stores::DedupStore {
         normal_size: 32 * 1024,
         max_size: 128 * 1024,
         max_concurrent_fetch_per_get: 10,
+        gc_interval_s: 0,
     }
 }
 
@@ -180,6 +181,7 @@ async fn check_length_not_set_with_chunk_read_beyond_first_chunk_regression_test
             normal_size: 6,
             max_size: 7,
             max_concurrent_fetch_per_get: 10,
+            gc_interval_s: 0,
         },
         Store::new(MemoryStore::new(
             &nativelink_config::stores::MemoryStore::default(),
@@ -232,6 +234,7 @@ async fn check_chunk_boundary_reads_test() -> Result<(), Error> {
             normal_size: 6,
             max_size: 7,
             max_concurrent_fetch_per_get: 10,
+            gc_interval_s: 0,
         },
         Store::new(MemoryStore::new(
             &nativelink_config::stores::MemoryStore::default(),
@@ -426,3 +429,53 @@ async fn has_with_zero_digest_returns_some_test() -> Result<(), Error> {
     }
     Ok(())
 }
+
+#[nativelink_test]
+async fn garbage_collection_removes_orphaned_chunks_test() -> Result<(), Error> {
+    let index_store = Store::new(MemoryStore::new(
+        &nativelink_config::stores::MemoryStore::default(),
+    ));
+    let content_store = MemoryStore::new(&nativelink_config::stores::MemoryStore::default());
+    let store = DedupStore::new(
+        &make_default_config(),
+        index_store.clone(),
+        Store::new(content_store.clone()),
+    )?;
+
+    let kept_data = make_random_data(MEGABYTE_SZ);
+    let kept_digest = DigestInfo::try_new(VALID_HASH1, MEGABYTE_SZ).unwrap();
+    let mut orphaned_data = vec![0u8; MEGABYTE_SZ];
+    SmallRng::seed_from_u64(2).fill(&mut orphaned_data[..]);
+    let orphaned_digest = DigestInfo::try_new(VALID_HASH2, MEGABYTE_SZ).unwrap();
+    store
+        .update_oneshot(kept_digest, kept_data.clone().into())
+        .await?;
+    store
+        .update_oneshot(orphaned_digest, orphaned_data.into())
+        .await?;
+    let chunks_before_gc = content_store.len_for_test().await;
+
+    // Nothing is removed while every chunk is referenced.
+    let report = store.collect_garbage().await?;
+    assert_eq!(report.removed_chunks, 0);
+    assert_eq!(report.live_chunks, chunks_before_gc as u64);
+
+    // Simulate the index being evicted.
+    assert!(index_store.remove(orphaned_digest).await?);
+    let report = store.collect_garbage().await?;
+    assert!(report.removed_chunks > 0);
+    assert_eq!(report.reclaimed_bytes, MEGABYTE_SZ as u64);
+    assert_eq!(
+        content_store.len_for_test().await,
+        chunks_before_gc - report.removed_chunks as usize
+    );
+    assert_eq!(
+        store.get_part_unchunked(kept_digest, 0, None).await?,
+        kept_data
+    );
+
+    // A second pass has nothing left to do.
+    let report = store.collect_garbage().await?;
+    assert_eq!(report.removed_chunks, 0);
+    Ok(())
+}
@@ -0,0 +1,273 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::env;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_store::encryption_store::{EncryptionStore, HEADER_LEN};
+use nativelink_store::memory_store::MemoryStore;
+use nativelink_util::common::DigestInfo;
+use nativelink_util::store_trait::{Store, StoreLike};
+use pretty_assertions::assert_eq;
+use rand::rngs::SmallRng;
+use rand::{thread_rng, Rng, SeedableRng};
+
+const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
+const VALID_HASH2: &str = "0123456789abcdef000000000000000000020000000000000123456789abcdef";
+const KEY1: &str = "1 000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+const KEY2: &str = "2 202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c3d3e3f";
+const CHUNK_SIZE: u32 = 10;
+const TAG_LEN: usize = 16;
+
+async fn make_key_file(lines: &[&str]) -> String {
+    let dir = format!(
+        "{}/{}",
+        env::var("TEST_TMPDIR").unwrap_or(env::temp_dir().to_str().unwrap().to_string()),
+        thread_rng().gen::<u64>(),
+    );
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    let path = format!("{dir}/keys");
+    let mut contents = "# Test keys.\n\n".to_string();
+    for line in lines {
+        contents.push_str(line);
+        contents.push('\n');
+    }
+    tokio::fs::write(&path, contents).await.unwrap();
+    path
+}
+
+async fn make_store(
+    key_file: &str,
+    active_key_id: u32,
+    inner_store: &Store,
+) -> Result<Arc<EncryptionStore>, Error> {
+    EncryptionStore::new(
+        &nativelink_config::stores::EncryptionStore {
+            backend: nativelink_config::stores::StoreConfig::memory(
+                nativelink_config::stores::MemoryStore::default(),
+            ),
+            key_file: key_file.to_string(),
+            active_key_id,
+            chunk_size: CHUNK_SIZE,
+        },
+        inner_store.clone(),
+    )
+    .await
+}
+
+fn make_inner_store() -> Store {
+    Store::new(MemoryStore::new(
+        &nativelink_config::stores::MemoryStore::default(),
+    ))
+}
+
+fn make_data(size: usize) -> Vec<u8> {
+    let mut rng = SmallRng::seed_from_u64(1);
+    (0..size).map(|_| rng.gen()).collect()
+}
+
+#[nativelink_test]
+async fn partial_reads_test() -> Result<(), Error> {
+    let key_file = make_key_file(&[KEY1]).await;
+    let inner_store = make_inner_store();
+    let store = make_store(&key_file, 1, &inner_store).await?;
+
+    // Include sizes that end exactly on a chunk boundary.
+    for size in [0, 1, 10, 95, 100] {
+        let data = make_data(size);
+        let digest = DigestInfo::try_new(VALID_HASH1, size)?;
+        store
+            .update_oneshot(digest, Bytes::from(data.clone()))
+            .await?;
+
+        for offset in 0..=size {
+            for length in [
+                None,
+                Some(0),
+                Some(1),
+                Some(9),
+                Some(10),
+                Some(25),
+                Some(1000),
+            ] {
+                let end = length.map_or(size, |length| (offset + length).min(size));
+                let result = store
+                    .get_part_unchunked(digest, offset as u64, length.map(|l| l as u64))
+                    .await?;
+                assert_eq!(
+                    &result[..],
+                    &data[offset..end],
+                    "Mismatch for size {size}, offset {offset}, length {length:?}"
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[nativelink_test]
+async fn data_is_encrypted_in_inner_store_test() -> Result<(), Error> {
+    let key_file = make_key_file(&[KEY1]).await;
+    let inner_store = make_inner_store();
+    let store = make_store(&key_file, 1, &inner_store).await?;
+
+    const RAW_INPUT: &str = "this text should never reach the inner store";
+    let digest = DigestInfo::try_new(VALID_HASH1, RAW_INPUT.len())?;
+    store.update_oneshot(digest, RAW_INPUT.into()).await?;
+
+    let chunk_count = RAW_INPUT.len().div_ceil(CHUNK_SIZE as usize);
+    let encrypted_size = HEADER_LEN as usize + RAW_INPUT.len() + chunk_count * TAG_LEN;
+    let encrypted_data = inner_store.get_part_unchunked(digest, 0, None).await?;
+    assert_eq!(encrypted_data.len(), encrypted_size);
+    assert!(!encrypted_data
+        .windows(4)
+        .any(|window| RAW_INPUT.as_bytes().windows(4).any(|w| w == window)));
+
+    assert_eq!(inner_store.has(digest).await?, Some(encrypted_size as u64));
+    assert_eq!(store.has(digest).await?, Some(RAW_INPUT.len() as u64));
+
+    // Every upload uses a new object key, even for the same data.
+    store.update_oneshot(digest, RAW_INPUT.into()).await?;
+    assert_ne!(
+        inner_store.get_part_unchunked(digest, 0, None).await?,
+        encrypted_data
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn has_reports_plaintext_size_after_chunk_size_change_test() -> Result<(), Error> {
+    let key_file = make_key_file(&[KEY1]).await;
+    let inner_store = make_inner_store();
+    let old_store = make_store(&key_file, 1, &inner_store).await?;
+
+    let data = make_data(95);
+    let digest = DigestInfo::try_new(VALID_HASH1, data.len())?;
+    old_store
+        .update_oneshot(digest, Bytes::from(data.clone()))
+        .await?;
+
+    let new_store = EncryptionStore::new(
+        &nativelink_config::stores::EncryptionStore {
+            backend: nativelink_config::stores::StoreConfig::memory(
+                nativelink_config::stores::MemoryStore::default(),
+            ),
+            key_file: key_file.clone(),
+            active_key_id: 1,
+            chunk_size: CHUNK_SIZE * 3,
+        },
+        inner_store.clone(),
+    )
+    .await?;
+    assert_eq!(new_store.has(digest).await?, Some(data.len() as u64));
+    assert_eq!(new_store.get_part_unchunked(digest, 0, None).await?, data);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn key_rotation_test() -> Result<(), Error> {
+    let key_file = make_key_file(&[KEY1, KEY2]).await;
+    let inner_store = make_inner_store();
+    let old_store = make_store(&key_file, 1, &inner_store).await?;
+
+    const OLD_DATA: &str = "written with key 1";
+    const NEW_DATA: &str = "written with key 2";
+    let old_digest = DigestInfo::try_new(VALID_HASH1, OLD_DATA.len())?;
+    let new_digest = DigestInfo::try_new(VALID_HASH2, NEW_DATA.len())?;
+    old_store
+        .update_oneshot(old_digest, OLD_DATA.into())
+        .await?;
+
+    let new_store = make_store(&key_file, 2, &inner_store).await?;
+    new_store
+        .update_oneshot(new_digest, NEW_DATA.into())
+        .await?;
+    assert_eq!(
+        new_store.get_part_unchunked(old_digest, 0, None).await?,
+        OLD_DATA
+    );
+    assert_eq!(
+        new_store.get_part_unchunked(new_digest, 3, None).await?,
+        &NEW_DATA[3..]
+    );
+
+    // Once the old key is removed, data written with it can't be read.
+    let key_file = make_key_file(&[KEY2]).await;
+    let retired_store = make_store(&key_file, 2, &inner_store).await?;
+    assert!(retired_store
+        .get_part_unchunked(old_digest, 0, None)
+        .await
+        .is_err());
+    assert_eq!(
+        retired_store
+            .get_part_unchunked(new_digest, 0, None)
+            .await?,
+        NEW_DATA
+    );
+
+    // The active key must be in the key file.
+    assert!(make_store(&key_file, 1, &inner_store).await.is_err());
+    Ok(())
+}
+
+#[nativelink_test]
+async fn tampered_data_is_rejected_test() -> Result<(), Error> {
+    let key_file = make_key_file(&[KEY1]).await;
+    let inner_store = make_inner_store();
+    let store = make_store(&key_file, 1, &inner_store).await?;
+
+    let data = make_data(30);
+    let digest = DigestInfo::try_new(VALID_HASH1, data.len())?;
+    store
+        .update_oneshot(digest, Bytes::from(data.clone()))
+        .await?;
+    let encrypted_data = inner_store.get_part_unchunked(digest, 0, None).await?;
+
+    // Flipped bit in the last chunk.
+    let mut flipped = encrypted_data.to_vec();
+    *flipped.last_mut().unwrap() ^= 1;
+    inner_store.update_oneshot(digest, flipped.into()).await?;
+    assert!(store.get_part_unchunked(digest, 0, None).await.is_err());
+    assert!(store.get_part_unchunked(digest, 25, None).await.is_err());
+    // Chunks before the bad one can still be read on their own.
+    assert_eq!(
+        store.get_part_unchunked(digest, 0, Some(10)).await?,
+        &data[..10]
+    );
+
+    // Last full chunk removed.
+    let encrypted_chunk_size = CHUNK_SIZE as usize + TAG_LEN;
+    let truncated = encrypted_data.slice(..encrypted_data.len() - encrypted_chunk_size);
+    inner_store.update_oneshot(digest, truncated).await?;
+    assert!(store.get_part_unchunked(digest, 0, None).await.is_err());
+    assert!(store.get_part_unchunked(digest, 15, None).await.is_err());
+
+    // Data moved to a different key.
+    let other_digest = DigestInfo::try_new(VALID_HASH2, data.len())?;
+    inner_store
+        .update_oneshot(other_digest, encrypted_data.clone())
+        .await?;
+    assert!(store
+        .get_part_unchunked(other_digest, 0, None)
+        .await
+        .is_err());
+
+    // The original data still round trips.
+    inner_store.update_oneshot(digest, encrypted_data).await?;
+    assert_eq!(store.get_part_unchunked(digest, 0, None).await?, data);
+    Ok(())
+}
@@ -12,20 +12,27 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+use async_trait::async_trait;
 use mock_instant::MockClock;
 use nativelink_config::stores::{
     EvictionPolicy, ExistenceCacheStore as ExistenceCacheStoreConfig, StoreConfig,
 };
-use nativelink_error::{Error, ResultExt};
+use nativelink_error::{make_err, Code, Error, ResultExt};
 use nativelink_macro::nativelink_test;
+use nativelink_metric::MetricsComponent;
 use nativelink_store::existence_cache_store::ExistenceCacheStore;
 use nativelink_store::memory_store::MemoryStore;
+use nativelink_util::buf_channel::{DropCloserReadHalf, DropCloserWriteHalf};
 use nativelink_util::common::DigestInfo;
+use nativelink_util::health_utils::{default_health_status_indicator, HealthStatusIndicator};
 use nativelink_util::instant_wrapper::MockInstantWrapped;
-use nativelink_util::store_trait::{Store, StoreLike};
+use nativelink_util::store_trait::{Store, StoreDriver, StoreKey, StoreLike, UploadSizeInfo};
 use pretty_assertions::assert_eq;
+use tokio::sync::oneshot;
 
 const VALID_HASH1: &str = "0123456789abcdef000000000000000000010000000000000123456789abcdef";
 
@@ -35,11 +42,13 @@ async fn simple_exist_cache_test() -> Result<(), Error> {
     let config = ExistenceCacheStoreConfig {
         backend: StoreConfig::noop, // Note: Not used.
         eviction_policy: Default::default(),
+        negative_cache_ttl_s: 0,
+        invalidation_redis_store: None,
     };
     let inner_store = Store::new(MemoryStore::new(
         &nativelink_config::stores::MemoryStore::default(),
     ));
-    let store = ExistenceCacheStore::new(&config, inner_store.clone());
+    let store = ExistenceCacheStore::new(&config, inner_store.clone())?;
 
     let digest = DigestInfo::try_new(VALID_HASH1, 3).unwrap();
     store
@@ -75,11 +84,13 @@ async fn update_flags_existance_cache_test() -> Result<(), Error> {
     let config = ExistenceCacheStoreConfig {
         backend: StoreConfig::noop,
         eviction_policy: Default::default(),
+        negative_cache_ttl_s: 0,
+        invalidation_redis_store: None,
     };
     let inner_store = Store::new(MemoryStore::new(
         &nativelink_config::stores::MemoryStore::default(),
     ));
-    let store = ExistenceCacheStore::new(&config, inner_store.clone());
+    let store = ExistenceCacheStore::new(&config, inner_store.clone())?;
 
     let digest = DigestInfo::try_new(VALID_HASH1, 3).unwrap();
     store
@@ -100,6 +111,8 @@ async fn get_part_caches_if_exact_size_set() -> Result<(), Error> {
     let config = ExistenceCacheStoreConfig {
         backend: StoreConfig::noop,
         eviction_policy: Default::default(),
+        negative_cache_ttl_s: 0,
+        invalidation_redis_store: None,
     };
     let inner_store = Store::new(MemoryStore::new(
         &nativelink_config::stores::MemoryStore::default(),
@@ -109,7 +122,7 @@ async fn get_part_caches_if_exact_size_set() -> Result<(), Error> {
         .update_oneshot(digest, VALUE.into())
         .await
         .err_tip(|| "Failed to update store")?;
-    let store = ExistenceCacheStore::new(&config, inner_store.clone());
+    let store = ExistenceCacheStore::new(&config, inner_store.clone())?;
 
     let _ = store
         .get_part_unchunked(digest, 0, None)
@@ -140,6 +153,8 @@ async fn ensure_has_requests_eventually_do_let_evictions_happen() -> Result<(),
                 max_seconds: 10,
                 ..Default::default()
             }),
+            negative_cache_ttl_s: 0,
+            invalidation_redis_store: None,
         },
         Store::new(inner_store.clone()),
         MockInstantWrapped::default(),
@@ -166,3 +181,217 @@ async fn ensure_has_requests_eventually_do_let_evictions_happen() -> Result<(),
 
     Ok(())
 }
+
+#[nativelink_test]
+async fn negative_cache_expires_after_ttl() -> Result<(), Error> {
+    const VALUE: &str = "123";
+    let inner_store = MemoryStore::new(&nativelink_config::stores::MemoryStore::default());
+    let digest = DigestInfo::try_new(VALID_HASH1, 3).unwrap();
+    let store = ExistenceCacheStore::new_with_time(
+        &ExistenceCacheStoreConfig {
+            backend: StoreConfig::noop,
+            eviction_policy: Default::default(),
+            negative_cache_ttl_s: 5,
+            invalidation_redis_store: None,
+        },
+        Store::new(inner_store.clone()),
+        MockInstantWrapped::default(),
+    );
+
+    assert_eq!(store.has(digest).await, Ok(None));
+    assert!(
+        store.exists_in_negative_cache(&digest).await,
+        "Expected digest to exist in negative cache"
+    );
+
+    // Written behind the existence cache's back, so only the TTL can
+    // make the digest visible.
+    inner_store
+        .update_oneshot(digest, VALUE.into())
+        .await
+        .err_tip(|| "Failed to update store")?;
+    assert_eq!(store.has(digest).await, Ok(None));
+
+    MockClock::advance(Duration::from_secs(6));
+    assert_eq!(store.has(digest).await, Ok(Some(VALUE.len() as u64)));
+    assert!(
+        !store.exists_in_negative_cache(&digest).await,
+        "Expected digest to not exist in negative cache"
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn update_invalidates_negative_cache() -> Result<(), Error> {
+    const VALUE: &str = "123";
+    let config = ExistenceCacheStoreConfig {
+        backend: StoreConfig::noop,
+        eviction_policy: Default::default(),
+        negative_cache_ttl_s: 60,
+        invalidation_redis_store: None,
+    };
+    let inner_store = Store::new(MemoryStore::new(
+        &nativelink_config::stores::MemoryStore::default(),
+    ));
+    let store = ExistenceCacheStore::new(&config, inner_store.clone())?;
+
+    let digest = DigestInfo::try_new(VALID_HASH1, 3).unwrap();
+    assert_eq!(store.has(digest).await, Ok(None));
+    assert!(
+        store.exists_in_negative_cache(&digest).await,
+        "Expected digest to exist in negative cache"
+    );
+
+    store
+        .update_oneshot(digest, VALUE.into())
+        .await
+        .err_tip(|| "Failed to update store")?;
+
+    assert!(
+        !store.exists_in_negative_cache(&digest).await,
+        "Expected digest to not exist in negative cache"
+    );
+    assert_eq!(store.has(digest).await, Ok(Some(VALUE.len() as u64)));
+    Ok(())
+}
+
+#[nativelink_test]
+async fn update_during_has_does_not_cache_stale_missing() -> Result<(), Error> {
+    /// Answers the first `has` from the inner store, then waits to be
+    /// released before returning.
+    #[derive(MetricsComponent)]
+    struct SlowHasStore {
+        inner_store: Store,
+        queried_tx: Mutex<Option<oneshot::Sender<()>>>,
+        release_rx: Mutex<Option<oneshot::Receiver<()>>>,
+    }
+
+    #[async_trait]
+    impl StoreDriver for SlowHasStore {
+        async fn has_with_results(
+            self: Pin<&Self>,
+            digests: &[StoreKey<'_>],
+            results: &mut [Option<u64>],
+        ) -> Result<(), Error> {
+            self.inner_store.has_with_results(digests, results).await?;
+            let queried_tx = self.queried_tx.lock().unwrap().take();
+            if let Some(queried_tx) = queried_tx {
+                let release_rx = self.release_rx.lock().unwrap().take().unwrap();
+                queried_tx
+                    .send(())
+                    .map_err(|()| make_err!(Code::Internal, "Failed to send queried"))?;
+                release_rx
+                    .await
+                    .map_err(|e| make_err!(Code::Internal, "{e:?}"))?;
+            }
+            Ok(())
+        }
+
+        async fn update(
+            self: Pin<&Self>,
+            key: StoreKey<'_>,
+            reader: DropCloserReadHalf,
+            size_info: UploadSizeInfo,
+        ) -> Result<(), Error> {
+            self.inner_store.update(key, reader, size_info).await
+        }
+
+        async fn get_part(
+            self: Pin<&Self>,
+            key: StoreKey<'_>,
+            writer: &mut DropCloserWriteHalf,
+            offset: u64,
+            length: Option<u64>,
+        ) -> Result<(), Error> {
+            self.inner_store.get_part(key, writer, offset, length).await
+        }
+
+        fn inner_store(&self, _digest: Option<StoreKey>) -> &'_ dyn StoreDriver {
+            self
+        }
+
+        fn as_any(&self) -> &(dyn std::any::Any + Sync + Send + 'static) {
+            self
+        }
+
+        fn as_any_arc(self: Arc<Self>) -> Arc<dyn std::any::Any + Sync + Send + 'static> {
+            self
+        }
+    }
+
+    default_health_status_indicator!(SlowHasStore);
+
+    const VALUE: &str = "123";
+    let (queried_tx, queried_rx) = oneshot::channel();
+    let (release_tx, release_rx) = oneshot::channel();
+    let inner_store = Store::new(Arc::new(SlowHasStore {
+        inner_store: Store::new(MemoryStore::new(
+            &nativelink_config::stores::MemoryStore::default(),
+        )),
+        queried_tx: Mutex::new(Some(queried_tx)),
+        release_rx: Mutex::new(Some(release_rx)),
+    }));
+    let config = ExistenceCacheStoreConfig {
+        backend: StoreConfig::noop,
+        eviction_policy: Default::default(),
+        negative_cache_ttl_s: 60,
+        invalidation_redis_store: None,
+    };
+    let store = ExistenceCacheStore::new(&config, inner_store)?;
+
+    let digest = DigestInfo::try_new(VALID_HASH1, 3).unwrap();
+    let (has_result, update_result) = tokio::join!(store.has(digest), async {
+        // The backend answered the `has` above before the digest was
+        // written, but the answer is only cached after the write.
+        queried_rx
+            .await
+            .map_err(|e| make_err!(Code::Internal, "{e:?}"))?;
+        store.update_oneshot(digest, VALUE.into()).await?;
+        release_tx
+            .send(())
+            .map_err(|()| make_err!(Code::Internal, "Failed to send release"))
+    });
+    update_result?;
+    assert_eq!(has_result, Ok(None));
+
+    assert!(
+        !store.exists_in_negative_cache(&digest).await,
+        "Expected digest to not exist in negative cache"
+    );
+    assert_eq!(store.has(digest).await, Ok(Some(VALUE.len() as u64)));
+    Ok(())
+}
+
+#[nativelink_test]
+async fn remove_invalidates_existence_cache() -> Result<(), Error> {
+    const VALUE: &str = "123";
+    let config = ExistenceCacheStoreConfig {
+        backend: StoreConfig::noop,
+        eviction_policy: Default::default(),
+        negative_cache_ttl_s: 60,
+        invalidation_redis_store: None,
+    };
+    let inner_store = Store::new(MemoryStore::new(
+        &nativelink_config::stores::MemoryStore::default(),
+    ));
+    let store = ExistenceCacheStore::new(&config, inner_store.clone())?;
+
+    let digest = DigestInfo::try_new(VALID_HASH1, 3).unwrap();
+    store
+        .update_oneshot(digest, VALUE.into())
+        .await
+        .err_tip(|| "Failed to update store")?;
+    assert!(
+        store.exists_in_cache(&digest).await,
+        "Expected digest to exist in cache"
+    );
+
+    assert_eq!(store.remove(digest).await, Ok(true));
+    assert!(
+        !store.exists_in_cache(&digest).await,
+        "Expected digest to not exist in cache"
+    );
+    assert_eq!(store.has(digest).await, Ok(None));
+    assert_eq!(inner_store.has(digest).await, Ok(None));
+    Ok(())
+}
@@ -0,0 +1,272 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_store::filesystem_fsck::{
+    fsck_filesystem_store, FsckOptions, FsckProblem, FsckProblemKind,
+};
+use nativelink_store::filesystem_store::{FileEntryImpl, FilesystemStore};
+use nativelink_util::common::DigestInfo;
+use nativelink_util::digest_hasher::{DigestHasher, DigestHasherFunc};
+use nativelink_util::store_trait::StoreLike;
+use pretty_assertions::assert_eq;
+use rand::{thread_rng, Rng};
+
+const VALUE1: &str = "good data";
+const VALUE2: &str = "more good data";
+const BLOCK_SIZE: u64 = 8;
+
+fn make_temp_path(data: &str) -> String {
+    format!(
+        "{}/{}/{}",
+        env::var("TEST_TMPDIR").unwrap_or(env::temp_dir().to_str().unwrap().to_string()),
+        thread_rng().gen::<u64>(),
+        data
+    )
+}
+
+fn sha256_digest(data: &str) -> DigestInfo {
+    let mut hasher = DigestHasherFunc::Sha256.hasher();
+    hasher.update(data.as_bytes());
+    hasher.finalize_digest()
+}
+
+fn make_config() -> nativelink_config::stores::FilesystemStore {
+    nativelink_config::stores::FilesystemStore {
+        content_path: make_temp_path("content_path"),
+        temp_path: make_temp_path("temp_path"),
+        block_size: BLOCK_SIZE,
+        ..Default::default()
+    }
+}
+
+async fn write_file(dir: &str, name: &str, data: &str) -> PathBuf {
+    tokio::fs::create_dir_all(dir).await.unwrap();
+    let path = Path::new(dir).join(name);
+    tokio::fs::write(&path, data).await.unwrap();
+    path
+}
+
+/// Writes one good file and one of each kind of bad file into the
+/// directories of `config` and returns the expected problems.
+async fn populate_store_dirs(
+    config: &nativelink_config::stores::FilesystemStore,
+) -> Vec<FsckProblem> {
+    let good_digest = sha256_digest(VALUE1);
+    write_file(&config.content_path, &format!("{good_digest}"), VALUE1).await;
+
+    let truncated_digest = sha256_digest(VALUE2);
+    let truncated_path = write_file(
+        &config.content_path,
+        &format!("{truncated_digest}"),
+        &VALUE2[..4],
+    )
+    .await;
+
+    // Same size as the real data, but different content.
+    let corrupt_digest = sha256_digest(VALUE1.to_uppercase().as_str());
+    let corrupt_path = write_file(&config.content_path, &format!("{corrupt_digest}"), VALUE1).await;
+
+    let invalid_name_path = write_file(&config.content_path, "not_a_digest", VALUE1).await;
+    let temp_path = write_file(&config.temp_path, "partial_upload", VALUE1).await;
+
+    let mut problems = vec![
+        FsckProblem {
+            path: truncated_path,
+            digest: Some(truncated_digest),
+            kind: FsckProblemKind::SizeMismatch { actual_size: 4 },
+        },
+        FsckProblem {
+            path: corrupt_path,
+            digest: Some(corrupt_digest),
+            kind: FsckProblemKind::HashMismatch {
+                actual_digest: good_digest,
+            },
+        },
+        FsckProblem {
+            path: invalid_name_path,
+            digest: None,
+            kind: FsckProblemKind::InvalidFileName,
+        },
+        FsckProblem {
+            path: temp_path,
+            digest: None,
+            kind: FsckProblemKind::OrphanedTempFile,
+        },
+    ];
+    problems.sort_by(|a, b| a.path.cmp(&b.path));
+    problems
+}
+
+#[nativelink_test]
+async fn fsck_reports_bad_files_test() -> Result<(), Error> {
+    let config = make_config();
+    let expected_problems = populate_store_dirs(&config).await;
+
+    let mut report = fsck_filesystem_store(&config, &FsckOptions::default()).await?;
+    report.problems.sort_by(|a, b| a.path.cmp(&b.path));
+
+    assert_eq!(report.problems, expected_problems);
+    assert_eq!(report.files_checked, 4);
+    assert_eq!(report.data_size, VALUE1.len() as u64);
+    assert_eq!(
+        report.accounted_size_on_disk,
+        (VALUE1.len() as u64).div_ceil(BLOCK_SIZE) * BLOCK_SIZE
+    );
+    assert_eq!(report.files_quarantined, 0);
+    // Nothing should be touched when only reporting.
+    for problem in &expected_problems {
+        assert!(problem.path.exists(), "{:?} was removed", problem.path);
+    }
+    Ok(())
+}
+
+#[nativelink_test]
+async fn fsck_checks_additional_roots_test() -> Result<(), Error> {
+    let mut config = make_config();
+    let additional_root = nativelink_config::stores::FilesystemStoreRoot {
+        content_path: make_temp_path("content_path"),
+        temp_path: make_temp_path("temp_path"),
+        eviction_policy: None,
+    };
+    config.additional_roots.push(additional_root.clone());
+    let mut expected_problems = populate_store_dirs(&config).await;
+    expected_problems.extend(
+        populate_store_dirs(&nativelink_config::stores::FilesystemStore {
+            content_path: additional_root.content_path,
+            temp_path: additional_root.temp_path,
+            ..Default::default()
+        })
+        .await,
+    );
+    expected_problems.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut report = fsck_filesystem_store(&config, &FsckOptions::default()).await?;
+    report.problems.sort_by(|a, b| a.path.cmp(&b.path));
+
+    assert_eq!(report.problems, expected_problems);
+    assert_eq!(report.files_checked, 8);
+    assert_eq!(report.data_size, 2 * VALUE1.len() as u64);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn fsck_quarantines_bad_files_test() -> Result<(), Error> {
+    let config = make_config();
+    let quarantine_path = PathBuf::from(make_temp_path("quarantine_path"));
+    let expected_problems = populate_store_dirs(&config).await;
+
+    let report = fsck_filesystem_store(
+        &config,
+        &FsckOptions {
+            quarantine_path: Some(quarantine_path.clone()),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    assert_eq!(report.problems.len(), expected_problems.len());
+    assert_eq!(report.files_quarantined, expected_problems.len() as u64);
+    for problem in &expected_problems {
+        assert!(!problem.path.exists(), "{:?} was not moved", problem.path);
+        assert!(quarantine_path
+            .join(problem.path.file_name().unwrap())
+            .exists());
+    }
+    let good_path = Path::new(&config.content_path).join(format!("{}", sha256_digest(VALUE1)));
+    assert!(good_path.exists());
+    Ok(())
+}
+
+#[nativelink_test]
+async fn size_only_check_ignores_hash_test() -> Result<(), Error> {
+    let config = make_config();
+    // Action cache entries are not keyed by the hash of their content.
+    let digest = DigestInfo::try_new(
+        "0123456789abcdef000000000000000000000000000000000123456789abcdef",
+        VALUE1.len(),
+    )?;
+    write_file(&config.content_path, &format!("{digest}"), VALUE1).await;
+    tokio::fs::create_dir_all(&config.temp_path).await.unwrap();
+
+    let report = fsck_filesystem_store(
+        &config,
+        &FsckOptions {
+            verify_hash: false,
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    assert_eq!(report.problems, vec![]);
+    assert_eq!(report.files_checked, 1);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn scrub_evicts_corrupt_files_test() -> Result<(), Error> {
+    let config = make_config();
+    let store = FilesystemStore::<FileEntryImpl>::new(&config).await?;
+    let good_digest = sha256_digest(VALUE1);
+    let corrupt_digest = sha256_digest(VALUE2);
+    store.update_oneshot(good_digest, VALUE1.into()).await?;
+    store.update_oneshot(corrupt_digest, VALUE2.into()).await?;
+
+    // Simulate bit rot on a file the store already knows about.
+    let corrupt_path = Path::new(&config.content_path).join(format!("{corrupt_digest}"));
+    tokio::fs::write(&corrupt_path, VALUE2.to_uppercase())
+        .await
+        .unwrap();
+
+    let report = store.scrub(&FsckOptions::default()).await?;
+
+    assert_eq!(
+        report.problems,
+        vec![FsckProblem {
+            path: corrupt_path,
+            digest: Some(corrupt_digest),
+            kind: FsckProblemKind::HashMismatch {
+                actual_digest: sha256_digest(VALUE2.to_uppercase().as_str()),
+            },
+        }]
+    );
+    assert!(store.has(good_digest).await?.is_some());
+    assert_eq!(store.has(corrupt_digest).await?, None);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn scrub_keeps_files_of_other_digest_functions_test() -> Result<(), Error> {
+    let config = make_config();
+    let store = FilesystemStore::<FileEntryImpl>::new(&config).await?;
+    let mut hasher = DigestHasherFunc::Blake3.hasher();
+    hasher.update(VALUE1.as_bytes());
+    let blake3_digest = hasher.finalize_digest();
+    store.update_oneshot(blake3_digest, VALUE1.into()).await?;
+
+    let report = store
+        .scrub(&FsckOptions {
+            hasher: DigestHasherFunc::Sha256,
+            ..Default::default()
+        })
+        .await?;
+
+    assert_eq!(report.problems, vec![]);
+    assert!(store.has(blake3_digest).await?.is_some());
+    Ok(())
+}
@@ -36,7 +36,9 @@ use nativelink_store::filesystem_store::{
 };
 use nativelink_util::buf_channel::make_buf_channel_pair;
 use nativelink_util::common::{fs, DigestInfo};
+use nativelink_util::digest_hasher::DigestHasherFunc;
 use nativelink_util::evicting_map::LenEntry;
+use nativelink_util::health_utils::{HealthStatus, HealthStatusIndicator};
 use nativelink_util::origin_context::ContextAwareFuture;
 use nativelink_util::store_trait::{Store, StoreLike, UploadSizeInfo};
 use nativelink_util::{background_spawn, spawn};
@@ -402,6 +404,7 @@ async fn file_continues_to_stream_on_content_replace_test() -> Result<(), Error>
                 }),
                 block_size: 1,
                 read_buffer_size: 1,
+                ..Default::default()
             },
         )
         .await?,
@@ -525,6 +528,7 @@ async fn file_gets_cleans_up_on_cache_eviction() -> Result<(), Error> {
                 }),
                 block_size: 1,
                 read_buffer_size: 1,
+                ..Default::default()
             },
         )
         .await?,
@@ -1490,3 +1494,170 @@ async fn update_with_whole_file_uses_same_inode() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[nativelink_test]
+async fn hash_cache_forgets_modified_files() -> Result<(), Error> {
+    let temp_path = make_temp_path("temp_path");
+    let store =
+        FilesystemStore::<FileEntryImpl>::new(&nativelink_config::stores::FilesystemStore {
+            content_path: make_temp_path("content_path"),
+            temp_path: temp_path.clone(),
+            hash_cache_max_entries: 10,
+            ..Default::default()
+        })
+        .await?;
+
+    let digest = DigestInfo::try_new(HASH1, VALUE1.len())?;
+    let file_path = format!("{temp_path}/output_file");
+    std::fs::write(&file_path, VALUE1)?;
+
+    store
+        .record_file_digest(&file_path, digest, DigestHasherFunc::Sha256)
+        .await?;
+    assert_eq!(
+        store
+            .cached_file_digest(&fs::metadata(&file_path).await?, DigestHasherFunc::Sha256)
+            .await,
+        Some(digest),
+        "Expected digest of untouched file to be cached"
+    );
+    assert_eq!(
+        store
+            .cached_file_digest(&fs::metadata(&file_path).await?, DigestHasherFunc::Blake3)
+            .await,
+        None,
+        "Expected digest to only be cached for the digest function it was made with"
+    );
+
+    // Changes the size as well, in case mtime has a coarse resolution.
+    std::fs::write(&file_path, format!("{VALUE2}{VALUE2}"))?;
+    assert_eq!(
+        store
+            .cached_file_digest(&fs::metadata(&file_path).await?, DigestHasherFunc::Sha256)
+            .await,
+        None,
+        "Expected modified file to not be cached"
+    );
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn additional_roots_spread_content_by_hash() -> Result<(), Error> {
+    const EVEN_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000001";
+    const ODD_HASH: &str = "0100000000000000000000000000000000000000000000000000000000000001";
+    let content_path0 = make_temp_path("content_path0");
+    let content_path1 = make_temp_path("content_path1");
+    let temp_path1 = make_temp_path("temp_path1");
+    let store =
+        FilesystemStore::<FileEntryImpl>::new(&nativelink_config::stores::FilesystemStore {
+            content_path: content_path0.clone(),
+            temp_path: make_temp_path("temp_path0"),
+            additional_roots: vec![nativelink_config::stores::FilesystemStoreRoot {
+                content_path: content_path1.clone(),
+                temp_path: temp_path1.clone(),
+                eviction_policy: None,
+            }],
+            ..Default::default()
+        })
+        .await?;
+
+    let even_digest = DigestInfo::try_new(EVEN_HASH, VALUE1.len())?;
+    let odd_digest = DigestInfo::try_new(ODD_HASH, VALUE2.len())?;
+    store.update_oneshot(even_digest, VALUE1.into()).await?;
+    store.update_oneshot(odd_digest, VALUE2.into()).await?;
+
+    assert!(Path::new(&format!("{content_path0}/{even_digest}")).exists());
+    assert!(Path::new(&format!("{content_path1}/{odd_digest}")).exists());
+
+    // Both roots are on the same filesystem, so the file is copied into
+    // the first root, which is the first one found to match.
+    let hard_link_entry = store
+        .get_file_entry_for_hard_link(&odd_digest, &content_path0)
+        .await?;
+    hard_link_entry
+        .get_file_path_locked(|path| async move {
+            assert_eq!(read_file_contents(&path).await?, VALUE2.as_bytes());
+            Ok(())
+        })
+        .await?;
+    assert!(Path::new(&format!("{content_path0}/{odd_digest}")).exists());
+    // The blob was moved, so it is only counted in the first root.
+    assert!(!Path::new(&format!("{content_path1}/{odd_digest}")).exists());
+    assert_eq!(store.has(odd_digest).await?, Some(VALUE2.len() as u64));
+
+    // A root that can't be written to is marked unhealthy and skipped.
+    fs::remove_dir_all(&temp_path1).await?;
+    let status = HealthStatusIndicator::check_health(store.as_ref(), "test".into()).await;
+    assert!(
+        matches!(status, HealthStatus::Warning { .. }),
+        "Expected warning, got {status:?}"
+    );
+    assert_eq!(store.has(even_digest).await?, Some(VALUE1.len() as u64));
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn additional_root_that_fails_to_load_is_skipped() -> Result<(), Error> {
+    const ODD_HASH: &str = "0100000000000000000000000000000000000000000000000000000000000001";
+    // A content path under a regular file can never be created.
+    let blocking_file = make_temp_path("blocking_file");
+    std::fs::create_dir_all(Path::new(&blocking_file).parent().unwrap())?;
+    std::fs::write(&blocking_file, b"")?;
+    let store =
+        FilesystemStore::<FileEntryImpl>::new(&nativelink_config::stores::FilesystemStore {
+            content_path: make_temp_path("content_path0"),
+            temp_path: make_temp_path("temp_path0"),
+            additional_roots: vec![nativelink_config::stores::FilesystemStoreRoot {
+                content_path: format!("{blocking_file}/content_path1"),
+                temp_path: format!("{blocking_file}/temp_path1"),
+                eviction_policy: None,
+            }],
+            ..Default::default()
+        })
+        .await?;
+
+    // The hash maps to the root that failed to load, so it goes to the
+    // first root instead.
+    let digest = DigestInfo::try_new(ODD_HASH, VALUE1.len())?;
+    store.update_oneshot(digest, VALUE1.into()).await?;
+    assert_eq!(store.has(digest).await?, Some(VALUE1.len() as u64));
+
+    let status = HealthStatusIndicator::check_health(store.as_ref(), "test".into()).await;
+    assert!(
+        matches!(status, HealthStatus::Warning { .. }),
+        "Expected warning, got {status:?}"
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn additional_root_is_marked_unhealthy_on_write_error() -> Result<(), Error> {
+    const ODD_HASH: &str = "0100000000000000000000000000000000000000000000000000000000000001";
+    let temp_path1 = make_temp_path("temp_path1");
+    let store =
+        FilesystemStore::<FileEntryImpl>::new(&nativelink_config::stores::FilesystemStore {
+            content_path: make_temp_path("content_path0"),
+            temp_path: make_temp_path("temp_path0"),
+            additional_roots: vec![nativelink_config::stores::FilesystemStoreRoot {
+                content_path: make_temp_path("content_path1"),
+                temp_path: temp_path1.clone(),
+                eviction_policy: None,
+            }],
+            ..Default::default()
+        })
+        .await?;
+
+    fs::remove_dir_all(&temp_path1).await?;
+    let digest = DigestInfo::try_new(ODD_HASH, VALUE1.len())?;
+    assert!(
+        store.update_oneshot(digest, VALUE1.into()).await.is_err(),
+        "Expected write to the broken root to fail"
+    );
+
+    // No health check ran, but the root is already skipped.
+    store.update_oneshot(digest, VALUE1.into()).await?;
+    assert_eq!(store.has(digest).await?, Some(VALUE1.len() as u64));
+    Ok(())
+}
@@ -640,6 +640,55 @@ async fn zero_len_items_exist_check() -> Result<(), Error> {
     Ok(())
 }
 
+#[nativelink_test]
+async fn remove_publishes_key() -> Result<(), Error> {
+    let mocks = Arc::new(MockRedisBackend::new());
+
+    let digest = DigestInfo::try_new(VALID_HASH1, 2)?;
+    let packed_hash_hex = format!("{digest}");
+    let pub_sub_channel = "pub_sub_channel";
+
+    mocks
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("DEL"),
+                subcommand: None,
+                args: vec![RedisValue::Bytes(packed_hash_hex.clone().into())],
+            },
+            Ok(RedisValue::Integer(1)),
+        )
+        .expect(
+            MockCommand {
+                cmd: Str::from_static("PUBLISH"),
+                subcommand: None,
+                args: vec![
+                    RedisValue::String(Str::from_static(pub_sub_channel)),
+                    RedisValue::String(packed_hash_hex.into()),
+                ],
+            },
+            Ok(RedisValue::Integer(0)),
+        );
+
+    let store = {
+        let mut builder = Builder::default_centralized();
+        builder.set_config(RedisConfig {
+            mocks: Some(Arc::clone(&mocks) as Arc<dyn Mocks>),
+            ..Default::default()
+        });
+
+        RedisStore::new_from_builder_and_parts(
+            builder,
+            Some(pub_sub_channel.to_string()),
+            mock_uuid_generator,
+            String::new(),
+        )?
+    };
+
+    assert_eq!(store.remove(digest).await, Ok(true));
+
+    Ok(())
+}
+
 // Prevent regressions to https://reviewable.io/reviews/TraceMachina/nativelink/1188#-O2pu9LV5ux4ILuT6MND
 #[nativelink_test]
 async fn dont_loop_forever_on_empty() -> Result<(), Error> {
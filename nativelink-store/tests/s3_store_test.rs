@@ -734,3 +734,82 @@ async fn has_with_expired_result() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[nativelink_test]
+async fn has_refreshes_objects_near_expiry() -> Result<(), Error> {
+    const CAS_ENTRY_SIZE: usize = 10;
+    let head_event = || {
+        ReplayEvent::new(
+            http::Request::builder().body(SdkBody::empty()).unwrap(),
+            http::Response::builder()
+                .header(header::CONTENT_LENGTH, "512")
+                .header(header::LAST_MODIFIED, "Thu, 01 Jan 1970 00:00:00 GMT")
+                .body(SdkBody::empty())
+                .unwrap(),
+        )
+    };
+    let mock_client = StaticReplayClient::new(vec![
+        head_event(),
+        head_event(),
+        ReplayEvent::new(
+            http::Request::builder().body(SdkBody::empty()).unwrap(),
+            http::Response::builder()
+                .status(StatusCode::OK)
+                .body(SdkBody::from(
+                    "<CopyObjectResult><ETag>\"etag\"</ETag></CopyObjectResult>",
+                ))
+                .unwrap(),
+        ),
+        head_event(),
+    ]);
+    let test_config = Builder::new()
+        .behavior_version(BehaviorVersion::v2024_03_28())
+        .region(Region::from_static(REGION))
+        .http_client(mock_client.clone())
+        .build();
+    let s3_client = aws_sdk_s3::Client::from_conf(test_config);
+    let store = S3Store::new_with_client_and_jitter(
+        &nativelink_config::stores::S3Store {
+            bucket: BUCKET_NAME.to_string(),
+            consider_expired_after_s: 2 * 24 * 60 * 60, // 2 days.
+            refresh_before_expired_s: 24 * 60 * 60,     // 1 day.
+            ..Default::default()
+        },
+        s3_client,
+        Arc::new(move |_delay| Duration::from_secs(0)),
+        MockInstantWrapped::default,
+    )?;
+
+    // Time starts at 1970-01-01 00:00:00.
+    let digest = DigestInfo::try_new(VALID_HASH1, CAS_ENTRY_SIZE).unwrap();
+    {
+        MockClock::advance(Duration::from_secs(12 * 60 * 60)); // 12 hours.
+        assert_eq!(store.has(digest).await, Ok(Some(512)));
+        // Too far from expiring to be refreshed.
+        assert_eq!(mock_client.actual_requests().count(), 1);
+    }
+    {
+        MockClock::advance(Duration::from_secs(24 * 60 * 60)); // 1 day.
+        assert_eq!(store.has(digest).await, Ok(Some(512)));
+        // The refresh happens in the background.
+        while mock_client.actual_requests().count() < 3 {
+            tokio::task::yield_now().await;
+        }
+        let copy_request = mock_client.actual_requests().nth(2).unwrap();
+        assert_eq!(
+            copy_request.headers().get("x-amz-copy-source"),
+            Some(format!("{BUCKET_NAME}/{VALID_HASH1}-{CAS_ENTRY_SIZE}").as_str())
+        );
+        assert_eq!(
+            copy_request.headers().get("x-amz-metadata-directive"),
+            Some("REPLACE")
+        );
+    }
+    {
+        // The object was just refreshed, so it is not refreshed again.
+        assert_eq!(store.has(digest).await, Ok(Some(512)));
+        assert_eq!(mock_client.actual_requests().count(), 4);
+    }
+
+    Ok(())
+}
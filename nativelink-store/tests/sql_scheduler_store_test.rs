@@ -0,0 +1,190 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(feature = "sql")]
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::StreamExt;
+use nativelink_config::schedulers::ExperimentalSqlSchedulerBackend;
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_store::sql_scheduler_store::SqlSchedulerStore;
+use nativelink_util::store_trait::{
+    SchedulerCurrentVersionProvider, SchedulerIndexProvider, SchedulerStore,
+    SchedulerStoreDataProvider, SchedulerStoreDecodeTo, SchedulerStoreKeyProvider,
+    SchedulerSubscription, SchedulerSubscriptionManager, StoreKey, TrueValue,
+};
+use pretty_assertions::assert_eq;
+
+const KEY_PREFIX: &str = "test_";
+const INDEX_NAME: &str = "sort_key";
+
+struct TestData {
+    id: &'static str,
+    sort_key: &'static str,
+    version: u64,
+    payload: &'static str,
+}
+
+impl SchedulerStoreKeyProvider for TestData {
+    type Versioned = TrueValue;
+    fn get_key(&self) -> StoreKey<'static> {
+        StoreKey::Str(Cow::Owned(format!("{KEY_PREFIX}{}", self.id)))
+    }
+}
+
+impl SchedulerStoreDataProvider for TestData {
+    fn try_into_bytes(self) -> Result<Bytes, Error> {
+        Ok(Bytes::from(self.payload))
+    }
+    fn get_indexes(&self) -> Result<Vec<(&'static str, Bytes)>, Error> {
+        Ok(vec![(INDEX_NAME, Bytes::from(self.sort_key))])
+    }
+}
+
+impl SchedulerCurrentVersionProvider for TestData {
+    fn current_version(&self) -> u64 {
+        self.version
+    }
+}
+
+struct TestKey(&'static str);
+
+impl SchedulerStoreKeyProvider for TestKey {
+    type Versioned = TrueValue;
+    fn get_key(&self) -> StoreKey<'static> {
+        StoreKey::Str(Cow::Owned(format!("{KEY_PREFIX}{}", self.0)))
+    }
+}
+
+impl SchedulerStoreDecodeTo for TestKey {
+    type DecodeOutput = (u64, Bytes);
+    fn decode(version: u64, data: Bytes) -> Result<Self::DecodeOutput, Error> {
+        Ok((version, data))
+    }
+}
+
+struct TestIndex(&'static str);
+
+impl SchedulerIndexProvider for TestIndex {
+    const KEY_PREFIX: &'static str = KEY_PREFIX;
+    const INDEX_NAME: &'static str = INDEX_NAME;
+    type Versioned = TrueValue;
+    fn index_value_prefix(&self) -> Cow<'_, str> {
+        Cow::Borrowed(self.0)
+    }
+}
+
+impl SchedulerStoreDecodeTo for TestIndex {
+    type DecodeOutput = (u64, Bytes);
+    fn decode(version: u64, data: Bytes) -> Result<Self::DecodeOutput, Error> {
+        Ok((version, data))
+    }
+}
+
+fn make_store() -> Result<Arc<SqlSchedulerStore>, Error> {
+    SqlSchedulerStore::new(&ExperimentalSqlSchedulerBackend {
+        database_url: "sqlite::memory:".to_string(),
+        max_connections: 1,
+        ..Default::default()
+    })
+}
+
+#[nativelink_test]
+async fn versioned_update_only_applies_to_current_version() -> Result<(), Error> {
+    let store = make_store()?;
+    let data = |version, payload| TestData {
+        id: "a",
+        sort_key: "a",
+        version,
+        payload,
+    };
+
+    assert_eq!(store.update_data(data(0, "first")).await?, Some(1));
+    // Stale writers must not overwrite newer data.
+    assert_eq!(store.update_data(data(0, "stale")).await?, None);
+    assert_eq!(store.update_data(data(1, "second")).await?, Some(2));
+    assert_eq!(store.update_data(data(1, "stale")).await?, None);
+
+    assert_eq!(
+        store.get_and_decode(TestKey("a")).await?,
+        Some((2, Bytes::from("second")))
+    );
+    assert_eq!(store.get_and_decode(TestKey("missing")).await?, None);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn search_by_index_prefix_returns_sorted_matches() -> Result<(), Error> {
+    let store = make_store()?;
+    for (id, sort_key) in [("a", "b_2"), ("b", "b_1"), ("c", "c_1")] {
+        store
+            .update_data(TestData {
+                id,
+                sort_key,
+                version: 0,
+                payload: id,
+            })
+            .await?;
+    }
+    // Moving an entry to another index value must drop the old value.
+    store
+        .update_data(TestData {
+            id: "c",
+            sort_key: "b_0",
+            version: 1,
+            payload: "c",
+        })
+        .await?;
+
+    let results: Vec<(u64, Bytes)> = store
+        .search_by_index_prefix(TestIndex("b_"))
+        .await?
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<_, _>>()?;
+    assert_eq!(
+        results,
+        vec![
+            (2, Bytes::from("c")),
+            (1, Bytes::from("b")),
+            (1, Bytes::from("a")),
+        ]
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn subscription_is_notified_on_update() -> Result<(), Error> {
+    let store = make_store()?;
+    let subscription_manager = store.subscription_manager()?;
+    let mut subscription = subscription_manager.subscribe(TestKey("a"))?;
+    // New subscriptions always start out as changed.
+    subscription.changed().await?;
+
+    store
+        .update_data(TestData {
+            id: "a",
+            sort_key: "a",
+            version: 0,
+            payload: "a",
+        })
+        .await?;
+    subscription.changed().await?;
+    Ok(())
+}
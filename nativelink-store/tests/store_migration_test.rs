@@ -0,0 +1,240 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::env;
+use std::path::PathBuf;
+
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_store::memory_store::MemoryStore;
+use nativelink_store::store_migration::{
+    migrate_store, read_checkpoint, MigrationOptions, MigrationStats,
+};
+use nativelink_util::common::{fs, DigestInfo};
+use nativelink_util::digest_hasher::{DigestHasher, DigestHasherFunc};
+use nativelink_util::store_trait::{Store, StoreKey, StoreLike};
+use pretty_assertions::assert_eq;
+use rand::{thread_rng, Rng};
+
+const HASH1: &str = "0000000000000000000000000000000000000000000000000000000000000001";
+const HASH2: &str = "0000000000000000000000000000000000000000000000000000000000000002";
+const HASH3: &str = "0000000000000000000000000000000000000000000000000000000000000003";
+
+/// Get temporary path from either `TEST_TMPDIR` or best effort temp directory if
+/// not set.
+async fn make_temp_path(data: &str) -> PathBuf {
+    let dir = format!(
+        "{}/{}",
+        env::var("TEST_TMPDIR").unwrap_or(env::temp_dir().to_str().unwrap().to_string()),
+        thread_rng().gen::<u64>(),
+    );
+    fs::create_dir_all(&dir).await.unwrap();
+    PathBuf::from(format!("{dir}/{data}"))
+}
+
+fn make_memory_store() -> Store {
+    Store::new(MemoryStore::new(
+        &nativelink_config::stores::MemoryStore::default(),
+    ))
+}
+
+fn sha256_digest(data: &str) -> DigestInfo {
+    let mut hasher = DigestHasherFunc::Sha256.hasher();
+    hasher.update(data.as_bytes());
+    hasher.finalize_digest()
+}
+
+#[nativelink_test]
+async fn copies_all_keys_test() -> Result<(), Error> {
+    const VALUE1: &str = "foo";
+    const VALUE2: &str = "barbaz";
+    let source = make_memory_store();
+    let destination = make_memory_store();
+    let digest1 = DigestInfo::try_new(HASH1, VALUE1.len())?;
+    let digest2 = DigestInfo::try_new(HASH2, VALUE2.len())?;
+    source.update_oneshot(digest1, VALUE1.into()).await?;
+    source.update_oneshot(digest2, VALUE2.into()).await?;
+    source.update_oneshot("str_key", VALUE1.into()).await?;
+
+    let stats = migrate_store(&source, &destination, &MigrationOptions::default()).await?;
+
+    assert_eq!(
+        stats,
+        MigrationStats {
+            keys_listed: 3,
+            keys_copied: 3,
+            bytes_copied: (VALUE1.len() * 2 + VALUE2.len()) as u64,
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        destination.get_part_unchunked(digest1, 0, None).await?,
+        VALUE1.as_bytes()
+    );
+    assert_eq!(
+        destination.get_part_unchunked(digest2, 0, None).await?,
+        VALUE2.as_bytes()
+    );
+    assert_eq!(
+        destination.get_part_unchunked("str_key", 0, None).await?,
+        VALUE1.as_bytes()
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn dry_run_does_not_write_test() -> Result<(), Error> {
+    const VALUE1: &str = "foo";
+    let source = make_memory_store();
+    let destination = make_memory_store();
+    let digest1 = DigestInfo::try_new(HASH1, VALUE1.len())?;
+    source.update_oneshot(digest1, VALUE1.into()).await?;
+
+    let stats = migrate_store(
+        &source,
+        &destination,
+        &MigrationOptions {
+            dry_run: true,
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    assert_eq!(
+        stats,
+        MigrationStats {
+            keys_listed: 1,
+            ..Default::default()
+        }
+    );
+    assert_eq!(destination.has(digest1).await?, None);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn skips_keys_already_in_destination_test() -> Result<(), Error> {
+    const VALUE1: &str = "foo";
+    const VALUE2: &str = "barbaz";
+    let source = make_memory_store();
+    let destination = make_memory_store();
+    let digest1 = DigestInfo::try_new(HASH1, VALUE1.len())?;
+    let digest2 = DigestInfo::try_new(HASH2, VALUE2.len())?;
+    source.update_oneshot(digest1, VALUE1.into()).await?;
+    source.update_oneshot(digest2, VALUE2.into()).await?;
+    destination.update_oneshot(digest1, VALUE1.into()).await?;
+
+    let stats = migrate_store(&source, &destination, &MigrationOptions::default()).await?;
+
+    assert_eq!(
+        stats,
+        MigrationStats {
+            keys_listed: 2,
+            keys_copied: 1,
+            bytes_copied: VALUE2.len() as u64,
+            keys_already_present: 1,
+            ..Default::default()
+        }
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn verify_rejects_corrupt_data_test() -> Result<(), Error> {
+    const VALUE1: &str = "foo";
+    const VALUE2: &str = "bar";
+    let source = make_memory_store();
+    let destination = make_memory_store();
+    let good_digest = sha256_digest(VALUE1);
+    // Same size as the real data, but the content does not match the hash.
+    let corrupt_digest = sha256_digest(VALUE2);
+    source.update_oneshot(good_digest, VALUE1.into()).await?;
+    source.update_oneshot(corrupt_digest, VALUE1.into()).await?;
+
+    let stats = migrate_store(
+        &source,
+        &destination,
+        &MigrationOptions {
+            verify: true,
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    assert_eq!(
+        stats,
+        MigrationStats {
+            keys_listed: 2,
+            keys_copied: 1,
+            bytes_copied: VALUE1.len() as u64,
+            keys_failed: 1,
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        destination.has(good_digest).await?,
+        Some(VALUE1.len() as u64)
+    );
+    assert_eq!(destination.has(corrupt_digest).await?, None);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn resumes_from_checkpoint_test() -> Result<(), Error> {
+    const VALUE1: &str = "foo";
+    let checkpoint_path = make_temp_path("checkpoint").await;
+    let source = make_memory_store();
+    let digest1 = DigestInfo::try_new(HASH1, VALUE1.len())?;
+    let digest2 = DigestInfo::try_new(HASH2, VALUE1.len())?;
+    let digest3 = DigestInfo::try_new(HASH3, VALUE1.len())?;
+    source.update_oneshot(digest1, VALUE1.into()).await?;
+    source.update_oneshot(digest2, VALUE1.into()).await?;
+
+    let options = MigrationOptions {
+        list_batch_size: 1,
+        checkpoint_path: Some(checkpoint_path.clone()),
+        ..Default::default()
+    };
+    let first_destination = make_memory_store();
+    let stats = migrate_store(&source, &first_destination, &options).await?;
+    assert_eq!(stats.keys_copied, 2);
+    assert_eq!(
+        read_checkpoint(&checkpoint_path).await?,
+        Some(StoreKey::Digest(digest2))
+    );
+
+    // A second run must only pick up keys after the checkpoint.
+    source.update_oneshot(digest3, VALUE1.into()).await?;
+    let second_destination = make_memory_store();
+    let stats = migrate_store(&source, &second_destination, &options).await?;
+    assert_eq!(
+        stats,
+        MigrationStats {
+            keys_listed: 1,
+            keys_copied: 1,
+            bytes_copied: VALUE1.len() as u64,
+            ..Default::default()
+        }
+    );
+    assert_eq!(second_destination.has(digest1).await?, None);
+    assert_eq!(second_destination.has(digest2).await?, None);
+    assert_eq!(
+        second_destination.has(digest3).await?,
+        Some(VALUE1.len() as u64)
+    );
+    assert_eq!(
+        read_checkpoint(&checkpoint_path).await?,
+        Some(StoreKey::Digest(digest3))
+    );
+    Ok(())
+}
@@ -305,6 +305,40 @@ async fn verify_blake3_hash_true_fails_on_update() -> Result<(), Error> {
     Ok(())
 }
 
+#[nativelink_test]
+async fn verify_sha512_hash_true_suceeds_on_update() -> Result<(), Error> {
+    let inner_store = MemoryStore::new(&nativelink_config::stores::MemoryStore::default());
+    let store = VerifyStore::new(
+        &nativelink_config::stores::VerifyStore {
+            backend: nativelink_config::stores::StoreConfig::memory(
+                nativelink_config::stores::MemoryStore::default(),
+            ),
+            verify_size: false,
+            verify_hash: true,
+        },
+        Store::new(inner_store.clone()),
+    );
+
+    /// This value is sha512("123").
+    const HASH: &str = "3c9909afec25354d551dae21590bb26e38d53f2173b8d3dc3eee4c047e7ab1c1eb8b85103e3be7ba613b31bb5c9c36214dc9f14a42fd7a2fdb84856bca5c44c2";
+    const VALUE: &str = "123";
+    let digest = DigestInfo::try_new(HASH, 3).unwrap();
+    let result = make_ctx_for_hash_func(DigestHasherFunc::Sha512)?
+        .wrap_async(
+            info_span!("update_oneshot"),
+            store.update_oneshot(digest, VALUE.into()),
+        )
+        .await;
+
+    assert_eq!(result, Ok(()), "Expected success, got: {:?}", result);
+    assert_eq!(
+        inner_store.has(digest).await,
+        Ok(Some(VALUE.len() as u64)),
+        "Expected data to exist in store after update"
+    );
+    Ok(())
+}
+
 // A potential bug could happen if the down stream component ignores the EOF but will
 // stop receiving data when the expected size is reached. We should ensure this edge
 // case is double protected.
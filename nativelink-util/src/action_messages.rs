@@ -25,7 +25,7 @@ use nativelink_metric::{
 use nativelink_proto::build::bazel::remote::execution::v2::{
     execution_stage, Action, ActionResult as ProtoActionResult, ExecuteOperationMetadata,
     ExecuteRequest, ExecuteResponse, ExecutedActionMetadata, FileNode, LogFile, OutputDirectory,
-    OutputFile, OutputSymlink, SymlinkNode,
+    OutputFile, OutputSymlink, RequestMetadata, ResultsCachePolicy, SymlinkNode, ToolDetails,
 };
 use nativelink_proto::google::longrunning::operation::Result as LongRunningResult;
 use nativelink_proto::google::longrunning::Operation;
@@ -312,6 +312,21 @@ pub struct ActionInfo {
     /// This is primarily used to join actions/operations together using this key.
     #[metric(help = "Info used to uniquely identify this ActionInfo and if it is cachable.")]
     pub unique_qualifier: ActionUniqueQualifier,
+    /// The `RequestMetadata` the client sent along with the request, if any.
+    #[metric(group = "request_metadata")]
+    #[serde(default)]
+    pub request_metadata: Option<RequestMetadataInfo>,
+    /// Priority the client asked the result to be cached with, as given in
+    /// the `ResultsCachePolicy` of the request. Only stores that support
+    /// priorities, like a `GrpcStore`, make use of it.
+    #[metric(help = "Priority the client asked the result to be cached with.")]
+    #[serde(default)]
+    pub results_cache_priority: i32,
+    /// Set if the result must not be written to the action cache, eg: when
+    /// a cache hit is executed again to check that it is reproducible.
+    #[metric(help = "If the result must not be written to the action cache.")]
+    #[serde(default)]
+    pub skip_action_cache_upload: bool,
 }
 
 impl ActionInfo {
@@ -341,7 +356,9 @@ impl ActionInfo {
                 .err_tip(|| "Expected action_digest to exist on ExecuteRequest")?
                 .try_into()?,
         };
-        let unique_qualifier = if execute_request.skip_cache_lookup {
+        // Results of `do_not_cache` actions must never be shared, so they are
+        // handled the same as if the cache lookup was skipped.
+        let unique_qualifier = if execute_request.skip_cache_lookup || action.do_not_cache {
             ActionUniqueQualifier::Uncachable(unique_key)
         } else {
             ActionUniqueQualifier::Cachable(unique_key)
@@ -375,6 +392,12 @@ impl ActionInfo {
             load_timestamp,
             insert_timestamp: queued_timestamp,
             unique_qualifier,
+            request_metadata: None,
+            results_cache_priority: execute_request
+                .results_cache_policy
+                .unwrap_or_default()
+                .priority,
+            skip_action_cache_upload: false,
         })
     }
 }
@@ -390,13 +413,80 @@ impl From<&ActionInfo> for ExecuteRequest {
             instance_name: unique_qualifier.instance_name.clone(),
             action_digest: Some(digest),
             skip_cache_lookup,
-            execution_policy: None,     // Not used in the worker.
-            results_cache_policy: None, // Not used in the worker.
+            execution_policy: None, // Not used in the worker.
+            // Zero is the server default, so it does not need to be sent.
+            results_cache_policy: (val.results_cache_priority != 0).then_some(ResultsCachePolicy {
+                priority: val.results_cache_priority,
+            }),
             digest_function: unique_qualifier.digest_function.proto_digest_func().into(),
         }
     }
 }
 
+/// Information about the client invocation an action belongs to. This is a
+/// copy of the REv2 `RequestMetadata` the client sent with the request, so it
+/// can be stored and published along with the `ActionInfo`.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize, MetricsComponent)]
+pub struct RequestMetadataInfo {
+    /// Name of the tool that sent the request, eg: "bazel".
+    #[metric(help = "Name of the tool that sent the request.")]
+    pub tool_name: String,
+    /// Version of the tool that sent the request.
+    #[metric(help = "Version of the tool that sent the request.")]
+    pub tool_version: String,
+    /// Identifier of the action, as chosen by the tool.
+    #[metric(help = "Identifier of the action, as chosen by the tool.")]
+    pub action_id: String,
+    /// Identifier of the tool invocation (eg: one `bazel build`).
+    #[metric(help = "Identifier of the tool invocation.")]
+    pub tool_invocation_id: String,
+    /// Identifier shared by related tool invocations.
+    #[metric(help = "Identifier shared by related tool invocations.")]
+    pub correlated_invocations_id: String,
+    /// Mnemonic of the action, eg: "CppCompile".
+    #[metric(help = "Mnemonic of the action.")]
+    pub action_mnemonic: String,
+    /// Label of the target that produced the action.
+    #[metric(help = "Label of the target that produced the action.")]
+    pub target_id: String,
+    /// Identifier of the configuration the target was built in.
+    #[metric(help = "Identifier of the configuration the target was built in.")]
+    pub configuration_id: String,
+}
+
+impl From<RequestMetadata> for RequestMetadataInfo {
+    fn from(val: RequestMetadata) -> Self {
+        let tool_details = val.tool_details.unwrap_or_default();
+        Self {
+            tool_name: tool_details.tool_name,
+            tool_version: tool_details.tool_version,
+            action_id: val.action_id,
+            tool_invocation_id: val.tool_invocation_id,
+            correlated_invocations_id: val.correlated_invocations_id,
+            action_mnemonic: val.action_mnemonic,
+            target_id: val.target_id,
+            configuration_id: val.configuration_id,
+        }
+    }
+}
+
+impl From<RequestMetadataInfo> for RequestMetadata {
+    fn from(val: RequestMetadataInfo) -> Self {
+        Self {
+            tool_details: Some(ToolDetails {
+                tool_name: val.tool_name,
+                tool_version: val.tool_version,
+            }),
+            action_id: val.action_id,
+            tool_invocation_id: val.tool_invocation_id,
+            correlated_invocations_id: val.correlated_invocations_id,
+            action_mnemonic: val.action_mnemonic,
+            target_id: val.target_id,
+            configuration_id: val.configuration_id,
+        }
+    }
+}
+
 /// Simple utility struct to determine if a string is representing a full path or
 /// just the name of the file.
 /// This is in order to be able to reuse the same struct instead of building different
@@ -12,12 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::borrow::Cow;
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::fmt;
 use std::hash::Hash;
 use std::io::{Cursor, Write};
-use std::ops::{Deref, DerefMut};
+use std::ops::Deref;
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use nativelink_error::{make_input_err, Error, ResultExt};
@@ -25,6 +26,7 @@ use nativelink_metric::{
     MetricFieldData, MetricKind, MetricPublishKnownKindData, MetricsComponent,
 };
 use nativelink_proto::build::bazel::remote::execution::v2::Digest;
+use parking_lot::{const_mutex, Mutex};
 use prost::Message;
 use serde::de::Visitor;
 use serde::ser::Error as _;
@@ -57,7 +59,14 @@ impl DigestInfo {
     pub const fn new(packed_hash: [u8; 32], size_bytes: u64) -> Self {
         DigestInfo {
             size_bytes,
-            packed_hash: PackedHash(packed_hash),
+            packed_hash: PackedHash(PackedHashRepr::Inline(packed_hash)),
+        }
+    }
+
+    pub const fn from_packed_hash(packed_hash: PackedHash, size_bytes: u64) -> Self {
+        DigestInfo {
+            size_bytes,
+            packed_hash,
         }
     }
 
@@ -96,8 +105,8 @@ impl DigestInfo {
         &self.packed_hash
     }
 
-    pub fn set_packed_hash(&mut self, packed_hash: [u8; 32]) {
-        self.packed_hash = PackedHash(packed_hash);
+    pub fn set_packed_hash(&mut self, packed_hash: PackedHash) {
+        self.packed_hash = packed_hash;
     }
 
     pub const fn size_bytes(&self) -> u64 {
@@ -126,17 +135,17 @@ const fn count_digits(mut num: u64) -> usize {
 struct DigestStackStringifier<'a> {
     digest: &'a DigestInfo,
     /// Buffer that can hold the string representation of the `DigestInfo`.
-    /// - Hex is '2 * sizeof(PackedHash)'.
+    /// - Hex is at most '2 * MAX_PACKED_HASH_SIZE'.
     /// - Digits can be at most `count_digits(u64::MAX)`.
     /// - We also have a hyphen separator.
-    buf: [u8; std::mem::size_of::<PackedHash>() * 2 + count_digits(u64::MAX) + 1],
+    buf: [u8; MAX_PACKED_HASH_SIZE * 2 + count_digits(u64::MAX) + 1],
 }
 
 impl<'a> DigestStackStringifier<'a> {
     const fn new(digest: &'a DigestInfo) -> Self {
         DigestStackStringifier {
             digest,
-            buf: [b'-'; std::mem::size_of::<PackedHash>() * 2 + count_digits(u64::MAX) + 1],
+            buf: [b'-'; MAX_PACKED_HASH_SIZE * 2 + count_digits(u64::MAX) + 1],
         }
     }
 
@@ -151,8 +160,9 @@ impl<'a> DigestStackStringifier<'a> {
                     self.digest
                 )
             })?;
+            let hex = &hex[..self.digest.packed_hash.len() * 2];
             cursor
-                .write_all(&hex)
+                .write_all(hex)
                 .err_tip(|| format!("Could not write hex to buffer - {hex:?} - {hex:?}",))?;
             // Note: We already have a hyphen at this point because we
             // initialized the buffer with hyphens.
@@ -344,31 +354,102 @@ impl From<&DigestInfo> for Digest {
     }
 }
 
-#[derive(Serialize, Deserialize, Default, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
-pub struct PackedHash([u8; 32]);
+/// Size of the largest hash we support (SHA-512).
+const MAX_PACKED_HASH_SIZE: usize = 64;
+
+/// Size of the hashes produced by SHA-256, SHA256TREE and BLAKE3.
+const SIZE_OF_SHA256_HASH: usize = 32;
+
+/// Raw hash in packed form. The 32 byte hashes of SHA-256, SHA256TREE and
+/// BLAKE3 are stored inline. The larger SHA-384 and SHA-512 hashes are
+/// interned instead, so `DigestInfo` stays small and `Copy`.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct PackedHash(PackedHashRepr);
+
+// Every hash has exactly one representation, which depends only on its
+// length, so the derived traits compare the content of the hashes.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+enum PackedHashRepr {
+    Inline([u8; SIZE_OF_SHA256_HASH]),
+    Interned(&'static [u8]),
+}
+
+/// Returns a copy of `hash` that lives for the rest of the program. Each
+/// distinct hash is only copied once.
+fn intern_hash(hash: &[u8]) -> &'static [u8] {
+    static INTERNED_HASHES: Mutex<BTreeSet<&'static [u8]>> = const_mutex(BTreeSet::new());
+    let mut interned_hashes = INTERNED_HASHES.lock();
+    if let Some(interned_hash) = interned_hashes.get(hash) {
+        return *interned_hash;
+    }
+    let interned_hash: &'static [u8] = Box::leak(Box::<[u8]>::from(hash));
+    interned_hashes.insert(interned_hash);
+    interned_hash
+}
 
-const SIZE_OF_PACKED_HASH: usize = 32;
 impl PackedHash {
     const fn new() -> Self {
-        PackedHash([0; SIZE_OF_PACKED_HASH])
+        PackedHash(PackedHashRepr::Inline([0; SIZE_OF_SHA256_HASH]))
+    }
+
+    pub fn from_array<const N: usize>(hash: [u8; N]) -> Self {
+        Self::from_slice(&hash)
+    }
+
+    /// Same as `from_array`, but usable in constants.
+    pub const fn from_static(hash: &'static [u8]) -> Self {
+        assert!(
+            hash.len() <= MAX_PACKED_HASH_SIZE,
+            "Hash is too large for PackedHash"
+        );
+        if hash.len() != SIZE_OF_SHA256_HASH {
+            return PackedHash(PackedHashRepr::Interned(hash));
+        }
+        let mut packed_hash = [0u8; SIZE_OF_SHA256_HASH];
+        let mut i = 0;
+        while i < SIZE_OF_SHA256_HASH {
+            packed_hash[i] = hash[i];
+            i += 1;
+        }
+        PackedHash(PackedHashRepr::Inline(packed_hash))
+    }
+
+    fn from_slice(hash: &[u8]) -> Self {
+        assert!(
+            hash.len() <= MAX_PACKED_HASH_SIZE,
+            "Hash is too large for PackedHash"
+        );
+        match <[u8; SIZE_OF_SHA256_HASH]>::try_from(hash) {
+            Ok(hash) => PackedHash(PackedHashRepr::Inline(hash)),
+            Err(_) => PackedHash(PackedHashRepr::Interned(intern_hash(hash))),
+        }
     }
 
     fn from_hex(hash: &str) -> Result<Self, Error> {
-        let mut packed_hash = [0u8; 32];
-        hex::decode_to_slice(hash, &mut packed_hash)
-            .map_err(|e| make_input_err!("Invalid sha256 hash: {hash} - {e:?}"))?;
-        Ok(PackedHash(packed_hash))
+        let len = hash.len() / 2;
+        // SHA-256, SHA256TREE and BLAKE3 use 32 bytes, SHA-384 uses 48 bytes
+        // and SHA-512 uses 64 bytes.
+        if hash.len() % 2 != 0 || !matches!(len, 32 | 48 | 64) {
+            return Err(make_input_err!(
+                "Invalid hash: {hash} - expected 64, 96 or 128 hex characters"
+            ));
+        }
+        let mut packed_hash = [0u8; MAX_PACKED_HASH_SIZE];
+        hex::decode_to_slice(hash, &mut packed_hash[..len])
+            .map_err(|e| make_input_err!("Invalid hash: {hash} - {e:?}"))?;
+        Ok(Self::from_slice(&packed_hash[..len]))
     }
 
-    /// Converts the packed hash into a hex string.
+    /// Converts the packed hash into a hex string. Only the first
+    /// `2 * self.len()` bytes of the result are used.
     #[inline]
-    fn to_hex(self) -> Result<[u8; SIZE_OF_PACKED_HASH * 2], fmt::Error> {
-        let mut hash = [0u8; SIZE_OF_PACKED_HASH * 2];
-        hex::encode_to_slice(self.0, &mut hash).map_err(|e| {
+    fn to_hex(self) -> Result<[u8; MAX_PACKED_HASH_SIZE * 2], fmt::Error> {
+        let mut hash = [0u8; MAX_PACKED_HASH_SIZE * 2];
+        hex::encode_to_slice(&*self, &mut hash[..self.len() * 2]).map_err(|e| {
             event!(
                 Level::ERROR,
                 "Could not convert PackedHash to hex - {e:?} - {:?}",
-                self.0
+                &*self
             );
             fmt::Error
         })?;
@@ -376,28 +457,78 @@ impl PackedHash {
     }
 }
 
+impl Default for PackedHash {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<[u8; 32]> for PackedHash {
+    fn from(hash: [u8; 32]) -> Self {
+        Self::from_array(hash)
+    }
+}
+
+impl From<[u8; 48]> for PackedHash {
+    fn from(hash: [u8; 48]) -> Self {
+        Self::from_array(hash)
+    }
+}
+
+impl From<[u8; 64]> for PackedHash {
+    fn from(hash: [u8; 64]) -> Self {
+        Self::from_array(hash)
+    }
+}
+
 impl fmt::Display for PackedHash {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let hash = self.to_hex()?;
-        match std::str::from_utf8(&hash) {
+        match std::str::from_utf8(&hash[..self.len() * 2]) {
             Ok(hash) => f.write_str(hash)?,
-            Err(_) => f.write_str(&format!("Could not convert hash to utf8 {:?}", self.0))?,
+            Err(_) => f.write_str(&format!("Could not convert hash to utf8 {:?}", &**self))?,
         }
         Ok(())
     }
 }
 
-impl Deref for PackedHash {
-    type Target = [u8; 32];
+impl fmt::Debug for PackedHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PackedHash")
+            .field(&self.to_string())
+            .finish()
+    }
+}
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+/// Serialized as a hex string, like the hash part of a `DigestInfo`.
+impl Serialize for PackedHash {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for PackedHash {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let hash = Cow::<str>::deserialize(deserializer)?;
+        PackedHash::from_hex(&hash)
+            .map_err(|e| serde::de::Error::custom(format!("Could not create PackedHash: {e:?}")))
     }
 }
 
-impl DerefMut for PackedHash {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+impl Deref for PackedHash {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        match &self.0 {
+            PackedHashRepr::Inline(hash) => hash,
+            PackedHashRepr::Interned(hash) => hash,
+        }
     }
 }
 
@@ -12,16 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::task::{Context, Poll};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use futures::stream::{unfold, FuturesUnordered, StreamExt};
 use futures::Future;
-use nativelink_config::stores::Retry;
+use nativelink_config::stores::{OutlierEjection, Retry};
 use nativelink_error::{make_err, Code, Error};
+use nativelink_metric::MetricsComponent;
 use tokio::sync::{mpsc, oneshot};
 use tonic::transport::{channel, Channel, Endpoint};
 use tracing::{event, Level};
@@ -31,9 +33,64 @@ use crate::retry::{self, Retrier, RetryResult};
 
 /// A helper utility that enables management of a suite of connections to an
 /// upstream gRPC endpoint using Tonic.
+#[derive(MetricsComponent)]
 pub struct ConnectionManager {
     // The channel to request connections from the worker.
-    worker_tx: mpsc::Sender<oneshot::Sender<Connection>>,
+    worker_tx: mpsc::Sender<WorkerRequest>,
+    #[metric(group = "endpoints")]
+    endpoint_stats: HashMap<String, Arc<EndpointStats>>,
+}
+
+/// The requests that can be made to the ConnectionManagerWorker.
+enum WorkerRequest {
+    /// Wait for a Connection to any endpoint.
+    Connection(oneshot::Sender<Connection>),
+    /// Provide a Connection to an endpoint other than the given one, or
+    /// None if there is no such Connection available right now.
+    TryConnectionExcluding(EndpointIndex, oneshot::Sender<Option<Connection>>),
+}
+
+/// Weight of a new sample in `EndpointStats::latency_ewma_us`.
+const LATENCY_EWMA_WEIGHT: f64 = 0.2;
+
+/// The health of a single endpoint, shared between the worker and the
+/// Connections to the endpoint.
+#[derive(Default, MetricsComponent)]
+pub struct EndpointStats {
+    #[metric(help = "The number of connections that were handed out for this endpoint")]
+    selected: AtomicU64,
+    #[metric(help = "The number of requests that failed with a transport error")]
+    failures: AtomicU64,
+    #[metric(help = "The number of requests that failed in a row")]
+    consecutive_failures: AtomicU64,
+    #[metric(help = "The number of times the endpoint was ejected for failing too often")]
+    ejections: AtomicU64,
+    #[metric(help = "Set to 1 while the endpoint is ejected")]
+    ejected: AtomicU64,
+    #[metric(help = "Moving average of the time until response headers are received in us")]
+    latency_ewma_us: AtomicU64,
+}
+
+impl EndpointStats {
+    fn record_success(&self, latency: Duration) {
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+        let sample = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+        let _ = self
+            .latency_ewma_us
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |ewma| {
+                Some(if ewma == 0 {
+                    sample
+                } else {
+                    (ewma as f64 * (1. - LATENCY_EWMA_WEIGHT) + sample as f64 * LATENCY_EWMA_WEIGHT)
+                        as u64
+                })
+            });
+    }
+
+    fn record_failure(&self) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+        self.consecutive_failures.fetch_add(1, Ordering::Relaxed);
+    }
 }
 
 /// The index into ConnectionManagerWorker::endpoints.
@@ -85,13 +142,24 @@ struct EstablishedChannel {
     identifier: ChannelIdentifier,
 }
 
+/// An Endpoint and the state the worker keeps about it.
+struct EndpointState {
+    /// The identifier of the last connection attempt to the endpoint.
+    connection_index: ConnectionIndex,
+    /// The Endpoint to establish Channels to.
+    endpoint: Endpoint,
+    /// The health of the endpoint.
+    stats: Arc<EndpointStats>,
+    /// If set, the endpoint is not used until this time.
+    ejected_until: Option<Instant>,
+}
+
 /// The context of the worker used to manage all of the connections.  This
 /// handles reconnecting to endpoints on errors and multiple connections to a
 /// given endpoint.
 struct ConnectionManagerWorker {
-    /// The endpoints to establish Channels and the identifier of the last
-    /// connection attempt to that endpoint.
-    endpoints: Vec<(ConnectionIndex, Endpoint)>,
+    /// The endpoints to establish Channels to.
+    endpoints: Vec<EndpointState>,
     /// The channel used to communicate between a Connection and the worker.
     connection_tx: mpsc::UnboundedSender<ConnectionRequest>,
     /// The number of connections that are currently allowed to be made.
@@ -105,6 +173,9 @@ struct ConnectionManagerWorker {
     /// The retry configuration for connecting to an Endpoint, on failure will
     /// restart the retrier after a 1 second delay.
     retrier: Retrier,
+    /// If set, endpoints that fail too many requests in a row are not used
+    /// for a while.
+    outlier_ejection: Option<OutlierEjection>,
 }
 
 /// The maximum number of queued requests to obtain a connection from the
@@ -112,16 +183,24 @@ struct ConnectionManagerWorker {
 /// keep this small since it has to wait for a response anyway.
 const WORKER_BACKLOG: usize = 8;
 
+/// Default for `OutlierEjection::consecutive_failures`.
+const DEFAULT_EJECTION_CONSECUTIVE_FAILURES: u32 = 5;
+
+/// Default for `OutlierEjection::ejection_duration_s`.
+const DEFAULT_EJECTION_DURATION_S: u32 = 30;
+
 impl ConnectionManager {
     /// Create a connection manager that creates a balance list between a given
     /// set of Endpoints.  This will restrict the number of concurrent requests
-    /// and automatically re-connect upon transport error.
+    /// and automatically re-connect upon transport error.  If
+    /// `outlier_ejection` is set, endpoints that keep failing are avoided.
     pub fn new(
         endpoints: impl IntoIterator<Item = Endpoint>,
         mut connections_per_endpoint: usize,
         mut max_concurrent_requests: usize,
         retry: Retry,
         jitter_fn: retry::JitterFn,
+        outlier_ejection: Option<OutlierEjection>,
     ) -> Self {
         let (worker_tx, worker_rx) = mpsc::channel(WORKER_BACKLOG);
         // The connection messages always come from sync contexts (e.g. drop)
@@ -129,7 +208,25 @@ impl ConnectionManager {
         // which defeats the object since there would be no backpressure
         // applied. Therefore it makes sense for this to be unbounded.
         let (connection_tx, connection_rx) = mpsc::unbounded_channel();
-        let endpoints = Vec::from_iter(endpoints.into_iter().map(|endpoint| (0, endpoint)));
+        let endpoints = Vec::from_iter(endpoints.into_iter().map(|endpoint| EndpointState {
+            connection_index: 0,
+            endpoint,
+            stats: Arc::new(EndpointStats::default()),
+            ejected_until: None,
+        }));
+        let endpoint_stats = endpoints
+            .iter()
+            .map(|state| (state.endpoint.uri().to_string(), state.stats.clone()))
+            .collect();
+        let outlier_ejection = outlier_ejection.map(|mut outlier_ejection| {
+            if outlier_ejection.consecutive_failures == 0 {
+                outlier_ejection.consecutive_failures = DEFAULT_EJECTION_CONSECUTIVE_FAILURES;
+            }
+            if outlier_ejection.ejection_duration_s == 0 {
+                outlier_ejection.ejection_duration_s = DEFAULT_EJECTION_DURATION_S;
+            }
+            outlier_ejection
+        });
         if max_concurrent_requests == 0 {
             max_concurrent_requests = usize::MAX;
         }
@@ -148,13 +245,17 @@ impl ConnectionManager {
                 jitter_fn,
                 retry,
             ),
+            outlier_ejection,
         };
         background_spawn!("connection_manager_worker_spawn", async move {
             worker
                 .service_requests(connections_per_endpoint, worker_rx, connection_rx)
                 .await;
         });
-        Self { worker_tx }
+        Self {
+            worker_tx,
+            endpoint_stats,
+        }
     }
 
     /// Get a Connection that can be used as a tonic::Channel, except it
@@ -163,19 +264,49 @@ impl ConnectionManager {
     pub async fn connection(&self) -> Result<Connection, Error> {
         let (tx, rx) = oneshot::channel();
         self.worker_tx
-            .send(tx)
+            .send(WorkerRequest::Connection(tx))
             .await
             .map_err(|err| make_err!(Code::Unavailable, "Requesting a new connection: {err:?}"))?;
         rx.await
             .map_err(|err| make_err!(Code::Unavailable, "Waiting for a new connection: {err:?}"))
     }
+
+    /// Get a Connection to an endpoint other than `endpoint_index` (as
+    /// returned by `Connection::endpoint_index`), if one is available right
+    /// away.  This does not wait for the concurrency limit or for a Channel
+    /// to become available.
+    pub async fn try_connection_excluding(
+        &self,
+        endpoint_index: usize,
+    ) -> Result<Option<Connection>, Error> {
+        let (tx, rx) = oneshot::channel();
+        self.worker_tx
+            .send(WorkerRequest::TryConnectionExcluding(endpoint_index, tx))
+            .await
+            .map_err(|err| make_err!(Code::Unavailable, "Requesting a new connection: {err:?}"))?;
+        rx.await
+            .map_err(|err| make_err!(Code::Unavailable, "Waiting for a new connection: {err:?}"))
+    }
+
+    /// The number of endpoints connections are made to.
+    pub fn endpoint_count(&self) -> usize {
+        self.endpoint_stats.len()
+    }
+}
+
+/// Resolves at `deadline`, or never if there is none.
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => futures::future::pending().await,
+    }
 }
 
 impl ConnectionManagerWorker {
     async fn service_requests(
         mut self,
         connections_per_endpoint: usize,
-        mut worker_rx: mpsc::Receiver<oneshot::Sender<Connection>>,
+        mut worker_rx: mpsc::Receiver<WorkerRequest>,
         mut connection_rx: mpsc::UnboundedReceiver<ConnectionRequest>,
     ) {
         // Make the initial set of connections, connection failures will be
@@ -192,6 +323,11 @@ impl ConnectionManagerWorker {
         // state while `await`-ing.  This is enforced through the use of
         // non-async functions to do all of the work.
         loop {
+            let next_ejection_expiry = self
+                .endpoints
+                .iter()
+                .filter_map(|state| state.ejected_until)
+                .min();
             tokio::select! {
                 request = worker_rx.recv() => {
                     let Some(request) = request else {
@@ -211,6 +347,10 @@ impl ConnectionManagerWorker {
                         self.handle_connected(connection_result);
                     }
                 }
+                () = sleep_until(next_ejection_expiry) => {
+                    self.expire_ejections(Instant::now());
+                    self.maybe_available_connection();
+                }
             }
         }
     }
@@ -242,7 +382,11 @@ impl ConnectionManagerWorker {
     }
 
     fn connect_endpoint(&mut self, endpoint_index: usize, connection_index: Option<usize>) {
-        let Some((current_connection_index, endpoint)) = self.endpoints.get_mut(endpoint_index)
+        let Some(EndpointState {
+            connection_index: current_connection_index,
+            endpoint,
+            ..
+        }) = self.endpoints.get_mut(endpoint_index)
         else {
             // Unknown endpoint, this should never happen.
             event!(
@@ -309,40 +453,122 @@ impl ConnectionManagerWorker {
     }
 
     // This must never be made async otherwise the select may cancel it.
-    fn handle_worker(&mut self, tx: oneshot::Sender<Connection>) {
-        if let Some(channel) = (self.available_connections > 0)
-            .then_some(())
-            .and_then(|_| self.available_channels.pop_front())
-        {
-            self.provide_channel(channel, tx);
-        } else {
-            self.waiting_connections.push_back(tx);
+    fn handle_worker(&mut self, request: WorkerRequest) {
+        match request {
+            WorkerRequest::Connection(tx) => {
+                // Requests are served in order, so only skip the queue if
+                // there is nobody waiting already.
+                if let Some(channel) = (self.available_connections > 0
+                    && self.waiting_connections.is_empty())
+                .then_some(())
+                .and_then(|()| self.take_channel(None))
+                {
+                    let _ = tx.send(self.provide_channel(channel));
+                } else {
+                    self.waiting_connections.push_back(tx);
+                }
+            }
+            WorkerRequest::TryConnectionExcluding(endpoint_index, tx) => {
+                let connection = (self.available_connections > 0)
+                    .then_some(())
+                    .and_then(|()| self.take_channel(Some(endpoint_index)))
+                    .map(|channel| self.provide_channel(channel));
+                let _ = tx.send(connection);
+            }
         }
     }
 
-    fn provide_channel(&mut self, channel: EstablishedChannel, tx: oneshot::Sender<Connection>) {
+    /// Removes the first available Channel that should be used from
+    /// `available_channels`.  Channels to ejected endpoints are only used if
+    /// every endpoint is ejected.
+    fn take_channel(
+        &mut self,
+        excluded_endpoint: Option<EndpointIndex>,
+    ) -> Option<EstablishedChannel> {
+        self.expire_ejections(Instant::now());
+        let endpoints = &self.endpoints;
+        let all_ejected = endpoints.iter().all(|state| state.ejected_until.is_some());
+        let position = self.available_channels.iter().position(|channel| {
+            let endpoint_index = channel.identifier.endpoint_index;
+            Some(endpoint_index) != excluded_endpoint
+                && (all_ejected
+                    || endpoints
+                        .get(endpoint_index)
+                        .is_some_and(|state| state.ejected_until.is_none()))
+        })?;
+        self.available_channels.remove(position)
+    }
+
+    fn provide_channel(&mut self, channel: EstablishedChannel) -> Connection {
         // We decrement here because we create Connection, this will signal when
         // it is Dropped and therefore increment this again.
         self.available_connections -= 1;
-        let _ = tx.send(Connection {
+        let stats = self.endpoints[channel.identifier.endpoint_index]
+            .stats
+            .clone();
+        stats.selected.fetch_add(1, Ordering::Relaxed);
+        Connection {
             connection_tx: self.connection_tx.clone(),
             pending_channel: Some(channel.channel.clone()),
             channel,
-        });
+            stats,
+        }
     }
 
     fn maybe_available_connection(&mut self) {
-        while self.available_connections > 0
-            && !self.waiting_connections.is_empty()
-            && !self.available_channels.is_empty()
+        while self.available_connections > 0 && !self.waiting_connections.is_empty() {
+            let Some(channel) = self.take_channel(None) else {
+                break;
+            };
+            if let Some(tx) = self.waiting_connections.pop_front() {
+                let _ = tx.send(self.provide_channel(channel));
+            } else {
+                // This should never happen, but better than an unwrap.
+                self.available_channels.push_front(channel);
+            }
+        }
+    }
+
+    /// Ejects the endpoint if it failed too many requests in a row.
+    fn maybe_eject_endpoint(&mut self, endpoint_index: EndpointIndex) {
+        let Some(outlier_ejection) = self.outlier_ejection else {
+            return;
+        };
+        let Some(state) = self.endpoints.get_mut(endpoint_index) else {
+            return;
+        };
+        if state.ejected_until.is_some()
+            || state.stats.consecutive_failures.load(Ordering::Relaxed)
+                < u64::from(outlier_ejection.consecutive_failures)
         {
-            if let Some(channel) = self.available_channels.pop_front() {
-                if let Some(tx) = self.waiting_connections.pop_front() {
-                    self.provide_channel(channel, tx);
-                } else {
-                    // This should never happen, but better than an unwrap.
-                    self.available_channels.push_front(channel);
-                }
+            return;
+        }
+        event!(
+            Level::WARN,
+            endpoint = ?state.endpoint.uri(),
+            ejection_duration_s = outlier_ejection.ejection_duration_s,
+            "Ejecting endpoint after too many failed requests"
+        );
+        state.ejected_until = Some(
+            Instant::now() + Duration::from_secs(u64::from(outlier_ejection.ejection_duration_s)),
+        );
+        // Give the endpoint a fresh start when it is used again.
+        state.stats.consecutive_failures.store(0, Ordering::Relaxed);
+        state.stats.ejections.fetch_add(1, Ordering::Relaxed);
+        state.stats.ejected.store(1, Ordering::Relaxed);
+    }
+
+    /// Makes endpoints whose ejection has expired available again.
+    fn expire_ejections(&mut self, now: Instant) {
+        for state in &mut self.endpoints {
+            if state.ejected_until.is_some_and(|until| until <= now) {
+                event!(
+                    Level::INFO,
+                    endpoint = ?state.endpoint.uri(),
+                    "Ejected endpoint is being used again"
+                );
+                state.ejected_until = None;
+                state.stats.ejected.store(0, Ordering::Relaxed);
             }
         }
     }
@@ -364,6 +590,7 @@ impl ConnectionManagerWorker {
             // Handle a transport error on a connection by making it unavailable
             // for use and establishing a new connection to the endpoint.
             ConnectionRequest::Error((identifier, was_pending)) => {
+                self.maybe_eject_endpoint(identifier.endpoint_index);
                 let should_reconnect = if was_pending {
                     true
                 } else {
@@ -397,6 +624,16 @@ pub struct Connection {
     pending_channel: Option<Channel>,
     /// The identifier to send to connection_tx.
     channel: EstablishedChannel,
+    /// The health of the endpoint the channel is to.
+    stats: Arc<EndpointStats>,
+}
+
+impl Connection {
+    /// The index of the endpoint this Connection is to, in the order the
+    /// endpoints were given to `ConnectionManager::new`.
+    pub fn endpoint_index(&self) -> usize {
+        self.channel.identifier.endpoint_index
+    }
 }
 
 impl Drop for Connection {
@@ -424,6 +661,10 @@ pub struct ResponseFuture {
     connection_tx: mpsc::UnboundedSender<ConnectionRequest>,
     /// The identifier to send to connection_tx on a transport error.
     identifier: ChannelIdentifier,
+    /// The health of the endpoint the request was sent to.
+    stats: Arc<EndpointStats>,
+    /// When the request was made.
+    start: Instant,
 }
 
 /// This is mostly copied from tonic::transport::channel except it wraps it
@@ -453,6 +694,7 @@ impl tonic::codegen::Service<tonic::codegen::http::Request<tonic::body::BoxBody>
                         ?err,
                         "Error while creating connection on channel"
                     );
+                    self.stats.record_failure();
                     let _ = self.connection_tx.send(ConnectionRequest::Error((
                         self.channel.identifier,
                         self.pending_channel.take().is_some(),
@@ -471,6 +713,8 @@ impl tonic::codegen::Service<tonic::codegen::http::Request<tonic::body::BoxBody>
             inner: self.channel.channel.call(request),
             connection_tx: self.connection_tx.clone(),
             identifier: self.channel.identifier,
+            stats: self.stats.clone(),
+            start: Instant::now(),
         }
     }
 }
@@ -483,10 +727,15 @@ impl Future for ResponseFuture {
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let result = Pin::new(&mut self.inner).poll(cx);
-        if let Poll::Ready(Err(_)) = &result {
-            let _ = self
-                .connection_tx
-                .send(ConnectionRequest::Error((self.identifier, false)));
+        match &result {
+            Poll::Ready(Ok(_)) => self.stats.record_success(self.start.elapsed()),
+            Poll::Ready(Err(_)) => {
+                self.stats.record_failure();
+                let _ = self
+                    .connection_tx
+                    .send(ConnectionRequest::Error((self.identifier, false)));
+            }
+            Poll::Pending => {}
         }
         result
     }
@@ -24,10 +24,11 @@ use nativelink_metric::{
 };
 use nativelink_proto::build::bazel::remote::execution::v2::digest_function::Value as ProtoDigestFunction;
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
+use sha2::digest::generic_array::GenericArray;
+use sha2::{Digest, Sha256, Sha384, Sha512};
 use tokio::io::{AsyncRead, AsyncReadExt};
 
-use crate::common::DigestInfo;
+use crate::common::{DigestInfo, PackedHash};
 use crate::origin_context::{ActiveOriginContext, OriginContext};
 use crate::{fs, make_symbol, spawn_blocking};
 
@@ -69,6 +70,9 @@ pub fn set_default_digest_hasher_func(hasher: DigestHasherFunc) -> Result<(), Er
 pub enum DigestHasherFunc {
     Sha256,
     Blake3,
+    Sha384,
+    Sha512,
+    Sha256Tree,
 }
 
 impl MetricsComponent for DigestHasherFunc {
@@ -86,11 +90,24 @@ impl DigestHasherFunc {
         self.into()
     }
 
+    /// Number of bytes in the hashes this function produces.
+    #[must_use]
+    pub const fn hash_size(&self) -> usize {
+        match self {
+            Self::Sha256 | Self::Blake3 | Self::Sha256Tree => 32,
+            Self::Sha384 => 48,
+            Self::Sha512 => 64,
+        }
+    }
+
     #[must_use]
     pub const fn proto_digest_func(&self) -> ProtoDigestFunction {
         match self {
             Self::Sha256 => ProtoDigestFunction::Sha256,
             Self::Blake3 => ProtoDigestFunction::Blake3,
+            Self::Sha384 => ProtoDigestFunction::Sha384,
+            Self::Sha512 => ProtoDigestFunction::Sha512,
+            Self::Sha256Tree => ProtoDigestFunction::Sha256tree,
         }
     }
 }
@@ -100,6 +117,9 @@ impl From<ConfigDigestHashFunction> for DigestHasherFunc {
         match value {
             ConfigDigestHashFunction::sha256 => Self::Sha256,
             ConfigDigestHashFunction::blake3 => Self::Blake3,
+            ConfigDigestHashFunction::sha384 => Self::Sha384,
+            ConfigDigestHashFunction::sha512 => Self::Sha512,
+            ConfigDigestHashFunction::sha256tree => Self::Sha256Tree,
         }
     }
 }
@@ -111,6 +131,9 @@ impl TryFrom<ProtoDigestFunction> for DigestHasherFunc {
         match value {
             ProtoDigestFunction::Sha256 => Ok(Self::Sha256),
             ProtoDigestFunction::Blake3 => Ok(Self::Blake3),
+            ProtoDigestFunction::Sha384 => Ok(Self::Sha384),
+            ProtoDigestFunction::Sha512 => Ok(Self::Sha512),
+            ProtoDigestFunction::Sha256tree => Ok(Self::Sha256Tree),
             v => Err(make_input_err!(
                 "Unknown or unsupported digest function for proto conversion {v:?}"
             )),
@@ -125,6 +148,9 @@ impl TryFrom<&str> for DigestHasherFunc {
         match value.to_uppercase().as_str() {
             "SHA256" => Ok(Self::Sha256),
             "BLAKE3" => Ok(Self::Blake3),
+            "SHA384" => Ok(Self::Sha384),
+            "SHA512" => Ok(Self::Sha512),
+            "SHA256TREE" => Ok(Self::Sha256Tree),
             v => Err(make_input_err!(
                 "Unknown or unsupported digest function for string conversion: {v:?}"
             )),
@@ -137,6 +163,9 @@ impl std::fmt::Display for DigestHasherFunc {
         match self {
             DigestHasherFunc::Sha256 => write!(f, "SHA256"),
             DigestHasherFunc::Blake3 => write!(f, "BLAKE3"),
+            DigestHasherFunc::Sha384 => write!(f, "SHA384"),
+            DigestHasherFunc::Sha512 => write!(f, "SHA512"),
+            DigestHasherFunc::Sha256Tree => write!(f, "SHA256TREE"),
         }
     }
 }
@@ -152,6 +181,9 @@ impl TryFrom<i32> for DigestHasherFunc {
         match ProtoDigestFunction::try_from(value) {
             Ok(ProtoDigestFunction::Sha256) => Ok(Self::Sha256),
             Ok(ProtoDigestFunction::Blake3) => Ok(Self::Blake3),
+            Ok(ProtoDigestFunction::Sha384) => Ok(Self::Sha384),
+            Ok(ProtoDigestFunction::Sha512) => Ok(Self::Sha512),
+            Ok(ProtoDigestFunction::Sha256tree) => Ok(Self::Sha256Tree),
             value => Err(make_input_err!(
                 "Unknown or unsupported digest function for int conversion: {:?}",
                 value.map(|v| v.as_str_name())
@@ -165,6 +197,11 @@ impl From<&DigestHasherFunc> for DigestHasherImpl {
         let hash_func_impl = match value {
             DigestHasherFunc::Sha256 => DigestHasherFuncImpl::Sha256(Sha256::new()),
             DigestHasherFunc::Blake3 => DigestHasherFuncImpl::Blake3(Box::new(Blake3Hasher::new())),
+            DigestHasherFunc::Sha384 => DigestHasherFuncImpl::Sha384(Sha384::new()),
+            DigestHasherFunc::Sha512 => DigestHasherFuncImpl::Sha512(Sha512::new()),
+            DigestHasherFunc::Sha256Tree => {
+                DigestHasherFuncImpl::Sha256Tree(Sha256TreeHasher::default())
+            }
         };
         Self {
             hashed_size: 0,
@@ -217,6 +254,108 @@ pub trait DigestHasher {
 pub enum DigestHasherFuncImpl {
     Sha256(Sha256),
     Blake3(Box<Blake3Hasher>), // Box because Blake3Hasher is 1.3kb in size.
+    Sha384(Sha384),
+    Sha512(Sha512),
+    Sha256Tree(Sha256TreeHasher),
+}
+
+/// Blobs up to this size are hashed with plain SHA-256 by SHA256TREE, and
+/// larger blobs are split into chunks of this size.
+const SHA256TREE_CHUNK_SIZE: usize = 1024;
+
+/// Initial state of the SHA-256 block cipher when SHA256TREE combines two
+/// hashes. These are the leading fractional parts of the square roots of
+/// the 9th to the 16th prime number.
+const SHA256TREE_PARENT_IV: [u32; 8] = [
+    0xcbbb_9d5d,
+    0x629a_292a,
+    0x9159_015a,
+    0x152f_ecd8,
+    0x6733_2667,
+    0x8eb4_4a87,
+    0xdb0c_2e0d,
+    0x47b5_481d,
+];
+
+/// Incremental implementation of the REv2 SHA256TREE digest function.
+/// Each chunk is hashed with SHA-256 as soon as the next one starts and
+/// complete subtrees are combined right away, so only one hash per tree
+/// level is kept in memory.
+#[derive(Default)]
+pub struct Sha256TreeHasher {
+    /// Hasher of the chunk currently being filled.
+    chunk_hasher: Sha256,
+    /// Number of bytes in the chunk currently being filled.
+    chunk_len: usize,
+    /// Number of chunks that have been completed.
+    completed_chunks: u64,
+    /// Hashes of complete subtrees, largest first.
+    subtree_hashes: Vec<[u8; 32]>,
+}
+
+impl Sha256TreeHasher {
+    fn update(&mut self, mut input: &[u8]) {
+        while !input.is_empty() {
+            // A full chunk is only completed once we know more data
+            // follows it, because a blob of exactly one chunk is not a tree.
+            if self.chunk_len == SHA256TREE_CHUNK_SIZE {
+                self.complete_chunk();
+            }
+            let len = input.len().min(SHA256TREE_CHUNK_SIZE - self.chunk_len);
+            sha2::digest::Update::update(&mut self.chunk_hasher, &input[..len]);
+            self.chunk_len += len;
+            input = &input[len..];
+        }
+    }
+
+    fn complete_chunk(&mut self) {
+        let mut hash: [u8; 32] = self.chunk_hasher.finalize_reset().into();
+        self.chunk_len = 0;
+        self.completed_chunks += 1;
+        // Every trailing zero bit of the chunk count is a subtree that
+        // this chunk completes.
+        let mut completed_chunks = self.completed_chunks;
+        while completed_chunks & 1 == 0 {
+            let left = self
+                .subtree_hashes
+                .pop()
+                .expect("SHA256TREE subtree stack should not be empty");
+            hash = sha256tree_parent(&left, &hash);
+            completed_chunks >>= 1;
+        }
+        self.subtree_hashes.push(hash);
+    }
+
+    fn finalize_reset(&mut self) -> [u8; 32] {
+        let mut hash: [u8; 32] = self.chunk_hasher.finalize_reset().into();
+        while let Some(left) = self.subtree_hashes.pop() {
+            hash = sha256tree_parent(&left, &hash);
+        }
+        self.chunk_len = 0;
+        self.completed_chunks = 0;
+        hash
+    }
+}
+
+/// Combines the hashes of two subtrees with a single invocation of the
+/// SHA-256 block cipher.
+fn sha256tree_parent(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut block = [0u8; 64];
+    block[..32].copy_from_slice(left);
+    block[32..].copy_from_slice(right);
+    let mut state = SHA256TREE_PARENT_IV;
+    sha2::compress256(&mut state, &[GenericArray::clone_from_slice(&block)]);
+    let mut hash = [0u8; 32];
+    for ((out, word), iv) in hash
+        .chunks_exact_mut(4)
+        .zip(state)
+        .zip(SHA256TREE_PARENT_IV)
+    {
+        // `compress256` adds the initial state to the cipher output, but
+        // SHA256TREE does not, so take it back out.
+        out.copy_from_slice(&word.wrapping_sub(iv).to_be_bytes());
+    }
+    hash
 }
 
 /// The individual implementation of the hash function.
@@ -249,16 +388,30 @@ impl DigestHasher for DigestHasherImpl {
             DigestHasherFuncImpl::Blake3(h) => {
                 Blake3Hasher::update(h, input);
             }
+            DigestHasherFuncImpl::Sha384(h) => sha2::digest::Update::update(h, input),
+            DigestHasherFuncImpl::Sha512(h) => sha2::digest::Update::update(h, input),
+            DigestHasherFuncImpl::Sha256Tree(h) => h.update(input),
         }
     }
 
     #[inline]
     fn finalize_digest(&mut self) -> DigestInfo {
-        let hash = match &mut self.hash_func_impl {
-            DigestHasherFuncImpl::Sha256(h) => h.finalize_reset().into(),
-            DigestHasherFuncImpl::Blake3(h) => h.finalize().into(),
+        let hash: PackedHash = match &mut self.hash_func_impl {
+            DigestHasherFuncImpl::Sha256(h) => <[u8; 32]>::from(h.finalize_reset()).into(),
+            DigestHasherFuncImpl::Blake3(h) => <[u8; 32]>::from(h.finalize()).into(),
+            DigestHasherFuncImpl::Sha384(h) => {
+                let mut hash = [0u8; 48];
+                hash.copy_from_slice(&h.finalize_reset());
+                hash.into()
+            }
+            DigestHasherFuncImpl::Sha512(h) => {
+                let mut hash = [0u8; 64];
+                hash.copy_from_slice(&h.finalize_reset());
+                hash.into()
+            }
+            DigestHasherFuncImpl::Sha256Tree(h) => h.finalize_reset().into(),
         };
-        DigestInfo::new(hash, self.hashed_size)
+        DigestInfo::from_packed_hash(hash, self.hashed_size)
     }
 
     async fn digest_for_file(
@@ -281,7 +434,10 @@ impl DigestHasher for DigestHasherImpl {
             }
         }
         match self.hash_func_impl {
-            DigestHasherFuncImpl::Sha256(_) => self.hash_file(file).await,
+            DigestHasherFuncImpl::Sha256(_)
+            | DigestHasherFuncImpl::Sha384(_)
+            | DigestHasherFuncImpl::Sha512(_)
+            | DigestHasherFuncImpl::Sha256Tree(_) => self.hash_file(file).await,
             DigestHasherFuncImpl::Blake3(mut hasher) => {
                 spawn_blocking!("digest_for_file", move || {
                     hasher.update_mmap(file.get_path()).map_err(|e| {
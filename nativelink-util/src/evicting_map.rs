@@ -461,6 +461,20 @@ where
         false
     }
 
+    /// Removes every item from the map. The items are not counted as
+    /// evicted.
+    pub async fn clear(&self) {
+        let mut state = self.state.lock().await;
+        if let Some(btree) = &mut state.btree {
+            btree.clear();
+        }
+        while let Some((_, eviction_item)) = state.lru.pop_lru() {
+            state.sum_store_size -= eviction_item.data.len();
+            // Note: See comment in `unref()` requring global lock of insert/remove.
+            eviction_item.data.unref().await;
+        }
+    }
+
     /// Same as remove(), but allows for a conditional to be applied to the entry before removal
     /// in an atomic fashion.
     pub async fn remove_if<Q, F: FnOnce(&T) -> bool>(&self, key: &Q, cond: F) -> bool
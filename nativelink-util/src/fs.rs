@@ -12,18 +12,21 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
 use std::fs::Metadata;
 use std::io::IoSlice;
+use std::mem::ManuallyDrop;
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::OnceLock;
+use std::sync::{Arc, OnceLock};
 use std::task::{Context, Poll};
 use std::time::Duration;
 
 use bytes::BytesMut;
 use futures::Future;
 use nativelink_error::{make_err, Code, Error, ResultExt};
+use parking_lot::Mutex;
 /// We wrap all tokio::fs items in our own wrapper so we can limit the number of outstanding
 /// open files at any given time. This will greatly reduce the chance we'll hit open file limit
 /// issues.
@@ -35,6 +38,7 @@ use tokio::sync::{Semaphore, SemaphorePermit};
 use tokio::time::timeout;
 use tracing::{event, Level};
 
+use crate::fs_uring::{self, UringFile};
 use crate::spawn_blocking;
 
 /// Default read buffer size when reading to/from disk.
@@ -96,7 +100,6 @@ impl ResumeableFileSlot {
         };
         file_slot
             .get_mut()
-            .inner
             .stream_position()
             .await
             .err_tip(|| "Failed to get file position in digest_for_file")
@@ -108,7 +111,6 @@ impl ResumeableFileSlot {
         };
         let position = file_slot
             .get_mut()
-            .inner
             .stream_position()
             .await
             .err_tip(|| format!("Failed to get file position {:?}", self.path))?;
@@ -132,12 +134,8 @@ impl ResumeableFileSlot {
             .open(&self.path)
             .await
             .err_tip(|| format!("Could not open after resume {:?}", self.path))?;
-        let mut file_slot = FileSlot {
-            _permit: permit,
-            inner,
-        };
+        let mut file_slot = FileSlot::new(permit, inner);
         file_slot
-            .inner
             .seek(SeekFrom::Start(stream_position))
             .await
             .err_tip(|| {
@@ -209,10 +207,53 @@ impl ResumeableFileSlot {
 #[derive(Debug)]
 pub struct FileSlot {
     // We hold the permit because once it is dropped it goes back into the queue.
-    _permit: SemaphorePermit<'static>,
-    inner: tokio::fs::File,
+    // Both are only taken out when the slot is dropped.
+    _permit: ManuallyDrop<SemaphorePermit<'static>>,
+    inner: ManuallyDrop<tokio::fs::File>,
+    // If set, reads, writes, seeks and syncs go through io_uring instead of
+    // `inner`. `inner` is still used for everything else, like metadata.
+    uring: Option<UringFile>,
+}
+
+impl FileSlot {
+    fn new(permit: SemaphorePermit<'static>, inner: tokio::fs::File) -> Self {
+        let uring = UringFile::new(&inner);
+        Self {
+            _permit: ManuallyDrop::new(permit),
+            inner: ManuallyDrop::new(inner),
+            uring,
+        }
+    }
+
+    /// Waits for all data and metadata of the file to reach the disk.
+    pub async fn sync_all(&mut self) -> Result<(), Error> {
+        let result = match &mut self.uring {
+            Some(uring) => uring.sync_all().await,
+            None => self.inner.sync_all().await,
+        };
+        result.err_tip(|| "Failed to sync file")
+    }
 }
 
+impl Drop for FileSlot {
+    fn drop(&mut self) {
+        // SAFETY: Neither field is used again.
+        let owner = unsafe {
+            (
+                ManuallyDrop::take(&mut self._permit),
+                ManuallyDrop::take(&mut self.inner),
+            )
+        };
+        // io_uring ops borrow the file descriptor of `inner`, so the file is
+        // only closed, and its permit released, once none of them can use it.
+        if let Some(uring) = self.uring.take() {
+            uring.close(Box::new(owner));
+        }
+    }
+}
+
+/// Reads and writes done directly on the `tokio::fs::File` do not go through
+/// io_uring and do not move the position the slot tracks when it uses io_uring.
 impl AsRef<tokio::fs::File> for FileSlot {
     fn as_ref(&self) -> &tokio::fs::File {
         &self.inner
@@ -231,20 +272,29 @@ impl AsyncRead for FileSlot {
         cx: &mut Context<'_>,
         buf: &mut ReadBuf<'_>,
     ) -> Poll<Result<(), tokio::io::Error>> {
-        Pin::new(&mut self.inner).poll_read(cx, buf)
+        if let Some(uring) = &mut self.uring {
+            return uring.poll_read(cx, buf);
+        }
+        Pin::new(&mut *self.inner).poll_read(cx, buf)
     }
 }
 
 impl AsyncSeek for FileSlot {
     fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> Result<(), tokio::io::Error> {
-        Pin::new(&mut self.inner).start_seek(position)
+        if let Some(uring) = &mut self.uring {
+            return uring.start_seek(position);
+        }
+        Pin::new(&mut *self.inner).start_seek(position)
     }
 
     fn poll_complete(
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Result<u64, tokio::io::Error>> {
-        Pin::new(&mut self.inner).poll_complete(cx)
+        if let Some(uring) = &mut self.uring {
+            return uring.poll_complete(cx);
+        }
+        Pin::new(&mut *self.inner).poll_complete(cx)
     }
 }
 
@@ -254,21 +304,30 @@ impl AsyncWrite for FileSlot {
         cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<Result<usize, tokio::io::Error>> {
-        Pin::new(&mut self.inner).poll_write(cx, buf)
+        if let Some(uring) = &mut self.uring {
+            return uring.poll_write(cx, buf);
+        }
+        Pin::new(&mut *self.inner).poll_write(cx, buf)
     }
 
     fn poll_flush(
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Result<(), tokio::io::Error>> {
-        Pin::new(&mut self.inner).poll_flush(cx)
+        if let Some(uring) = &mut self.uring {
+            return uring.poll_flush(cx);
+        }
+        Pin::new(&mut *self.inner).poll_flush(cx)
     }
 
     fn poll_shutdown(
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Result<(), tokio::io::Error>> {
-        Pin::new(&mut self.inner).poll_shutdown(cx)
+        if let Some(uring) = &mut self.uring {
+            return uring.poll_flush(cx);
+        }
+        Pin::new(&mut *self.inner).poll_shutdown(cx)
     }
 
     fn poll_write_vectored(
@@ -276,11 +335,18 @@ impl AsyncWrite for FileSlot {
         cx: &mut Context<'_>,
         bufs: &[IoSlice<'_>],
     ) -> Poll<Result<usize, tokio::io::Error>> {
-        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+        if let Some(uring) = &mut self.uring {
+            let buf = bufs
+                .iter()
+                .find(|buf| !buf.is_empty())
+                .map_or(&[][..], |buf| &**buf);
+            return uring.poll_write(cx, buf);
+        }
+        Pin::new(&mut *self.inner).poll_write_vectored(cx, bufs)
     }
 
     fn is_write_vectored(&self) -> bool {
-        self.inner.is_write_vectored()
+        self.uring.is_none() && self.inner.is_write_vectored()
     }
 }
 
@@ -328,6 +394,12 @@ pub fn get_open_files_for_test() -> usize {
     TOTAL_FILE_SEMAPHORES.load(Ordering::Acquire) - OPEN_FILE_SEMAPHORE.available_permits()
 }
 
+/// Makes files fall back to blocking I/O as if the io_uring driver had
+/// stopped. Does nothing if io_uring is not used.
+pub fn set_io_uring_stopped_for_test(stopped: bool) {
+    fs_uring::set_stopped_for_test(stopped);
+}
+
 /// How long a file descriptor can be open without being used before it is closed.
 static IDLE_FILE_DESCRIPTOR_TIMEOUT: OnceLock<Duration> = OnceLock::new();
 
@@ -354,11 +426,7 @@ pub async fn open_file(path: impl AsRef<Path>, limit: u64) -> Result<ResumeableF
     })
     .await?;
     Ok(ResumeableFileSlot::new_with_take(
-        FileSlot {
-            _permit: permit,
-            inner: tokio::fs::File::from_std(os_file),
-        }
-        .take(limit),
+        FileSlot::new(permit, tokio::fs::File::from_std(os_file)).take(limit),
         path,
         false, /* is_write */
     ))
@@ -381,10 +449,7 @@ pub async fn create_file(path: impl AsRef<Path>) -> Result<ResumeableFileSlot, E
     })
     .await?;
     Ok(ResumeableFileSlot::new(
-        FileSlot {
-            _permit: permit,
-            inner: tokio::fs::File::from_std(os_file),
-        },
+        FileSlot::new(permit, tokio::fs::File::from_std(os_file)),
         path,
         true, /* is_write */
     ))
@@ -396,6 +461,189 @@ pub async fn hard_link(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> Result<(
     call_with_permit(move |_| std::fs::hard_link(src, dst).map_err(Into::<Error>::into)).await
 }
 
+/// Copies the contents of `src` to `dst`, returning the number of bytes
+/// copied. `dst` is created or truncated.
+pub async fn copy(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> Result<u64, Error> {
+    let src = src.as_ref().to_owned();
+    let dst = dst.as_ref().to_owned();
+    call_with_permit(move |_| std::fs::copy(src, dst).map_err(Into::<Error>::into)).await
+}
+
+/// Ways of placing a file at a new path, in the order `FileCloner` tries
+/// them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileCloneMethod {
+    /// Shares the data blocks of the source file through `FICLONE`. Only
+    /// works within one filesystem, and only on filesystems like btrfs and
+    /// XFS that support it.
+    Reflink,
+    /// Copies the data. On Linux this uses `copy_file_range`, which lets
+    /// the filesystem avoid copying through userspace.
+    Copy,
+    /// Hard links the file, so both paths share one inode.
+    HardLink,
+}
+
+impl FileCloneMethod {
+    fn next(self) -> Option<Self> {
+        match self {
+            Self::Reflink => Some(Self::Copy),
+            Self::Copy => Some(Self::HardLink),
+            Self::HardLink => None,
+        }
+    }
+
+    fn clone_file(self, src: &Path, dst: &Path) -> std::io::Result<()> {
+        match self {
+            Self::Reflink => reflink(src, dst),
+            Self::Copy => copy_new_file(src, dst),
+            Self::HardLink => std::fs::hard_link(src, dst),
+        }
+    }
+}
+
+/// Copies `src` to `dst`, which must not exist yet.
+fn copy_new_file(src: &Path, dst: &Path) -> std::io::Result<()> {
+    let mut src_file = std::fs::File::open(src)?;
+    let mut dst_file = std::fs::File::options()
+        .write(true)
+        .create_new(true)
+        .open(dst)?;
+    let result = std::io::copy(&mut src_file, &mut dst_file)
+        .and_then(|_| dst_file.set_permissions(src_file.metadata()?.permissions()));
+    if result.is_err() {
+        // `dst` was created above, so don't leave a partial copy behind.
+        drop(dst_file);
+        let _ = std::fs::remove_file(dst);
+    }
+    result
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(src: &Path, dst: &Path) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+    // `_IOW(0x94, 9, int)` from linux/fs.h.
+    const FICLONE: u32 = 0x4004_9409;
+
+    let src_file = std::fs::File::open(src)?;
+    let dst_file = std::fs::File::options()
+        .write(true)
+        .create_new(true)
+        .open(dst)?;
+    // SAFETY: Both file descriptors stay open for the duration of the call
+    // and FICLONE does not read or write any memory of this process.
+    let result = unsafe { libc::ioctl(dst_file.as_raw_fd(), FICLONE as _, src_file.as_raw_fd()) };
+    let result = if result == 0 {
+        // Like a copy, the clone gets the permissions of the source.
+        src_file
+            .metadata()
+            .and_then(|metadata| dst_file.set_permissions(metadata.permissions()))
+    } else {
+        Err(std::io::Error::last_os_error())
+    };
+    if result.is_err() {
+        drop(dst_file);
+        let _ = std::fs::remove_file(dst);
+    }
+    result
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_src: &Path, _dst: &Path) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Returns true if `err` means the method is not supported between the
+/// two paths, rather than that something is wrong with the paths.
+fn is_unsupported(err: &std::io::Error) -> bool {
+    #[cfg(target_family = "unix")]
+    if let Some(errno) = err.raw_os_error() {
+        return matches!(
+            errno,
+            libc::EXDEV | libc::EOPNOTSUPP | libc::ENOTTY | libc::ENOSYS
+        );
+    }
+    err.kind() == std::io::ErrorKind::Unsupported
+}
+
+/// Returns the devices of `src` and of the directory `dst` will be put in.
+#[cfg(target_family = "unix")]
+fn device_pair(src: &Path, dst: &Path) -> std::io::Result<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    let dst_dir = dst.parent().unwrap_or(dst);
+    Ok((
+        std::fs::metadata(src)?.dev(),
+        std::fs::metadata(dst_dir)?.dev(),
+    ))
+}
+
+#[cfg(not(target_family = "unix"))]
+fn device_pair(_src: &Path, _dst: &Path) -> std::io::Result<(u64, u64)> {
+    Ok((0, 0))
+}
+
+/// Places files at new paths with the first `FileCloneMethod` that works,
+/// starting from a configured method. A method is only skipped if it is
+/// unsupported, and the method that worked is remembered for each pair of
+/// source and destination filesystems, so unsupported methods are only
+/// tried once per pair of mounts. Falling back to hard links is never
+/// remembered, so files are only shared with the source when no other
+/// method works for that very file.
+#[derive(Debug)]
+pub struct FileCloner {
+    first_method: FileCloneMethod,
+    methods: Arc<Mutex<HashMap<(u64, u64), FileCloneMethod>>>,
+}
+
+impl FileCloner {
+    pub fn new(first_method: FileCloneMethod) -> Self {
+        Self {
+            first_method,
+            methods: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Places the content of `src` at `dst`, returning the method used.
+    pub async fn clone_file(
+        &self,
+        src: impl AsRef<Path>,
+        dst: impl AsRef<Path>,
+    ) -> Result<FileCloneMethod, Error> {
+        let src = src.as_ref().to_owned();
+        let dst = dst.as_ref().to_owned();
+        let first_method = self.first_method;
+        let methods = self.methods.clone();
+        call_with_permit(move |_| {
+            if first_method == FileCloneMethod::HardLink {
+                // Nothing to fall back to, so skip looking up the devices.
+                std::fs::hard_link(&src, &dst)
+                    .err_tip(|| format!("Could not hard link {src:?} to {dst:?}"))?;
+                return Ok(FileCloneMethod::HardLink);
+            }
+            let devices = device_pair(&src, &dst)
+                .err_tip(|| format!("Could not get devices of {src:?} and {dst:?}"))?;
+            let mut method = *methods.lock().get(&devices).unwrap_or(&first_method);
+            loop {
+                match method.clone_file(&src, &dst) {
+                    Ok(()) => break,
+                    Err(err) => match method.next() {
+                        Some(next_method) if is_unsupported(&err) => method = next_method,
+                        _ => {
+                            return Err(err)
+                                .err_tip(|| format!("Could not {method:?} {src:?} to {dst:?}"))
+                        }
+                    },
+                }
+            }
+            if method != FileCloneMethod::HardLink {
+                methods.lock().insert(devices, method);
+            }
+            Ok(method)
+        })
+        .await
+    }
+}
+
 pub async fn set_permissions(
     src: impl AsRef<Path>,
     perm: std::fs::Permissions,
@@ -471,6 +719,9 @@ pub async fn read_dir(path: impl AsRef<Path>) -> Result<ReadDir, Error> {
 }
 
 pub async fn rename(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<(), Error> {
+    if let Some(result) = fs_uring::rename(from.as_ref(), to.as_ref()).await {
+        return result.map_err(Into::<Error>::into);
+    }
     let from = from.as_ref().to_owned();
     let to = to.as_ref().to_owned();
     call_with_permit(move |_| std::fs::rename(from, to).map_err(Into::<Error>::into)).await
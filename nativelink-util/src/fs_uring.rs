@@ -0,0 +1,666 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Optional io_uring backend for `crate::fs`. When the `io_uring` feature is
+//! enabled on Linux, file reads, writes, fsyncs and renames are submitted to
+//! a single io_uring driven by a dedicated thread instead of going through
+//! tokio's blocking thread pool. If the ring can not be created, for
+//! example because the kernel is too old or io_uring is blocked by seccomp,
+//! or if the driver thread stops, everything falls back to the blocking
+//! thread pool.
+
+pub(crate) use imp::*;
+
+#[cfg(all(feature = "io_uring", target_os = "linux"))]
+mod imp {
+    use std::collections::{HashMap, VecDeque};
+    use std::ffi::{CString, OsStr};
+    use std::fmt::{Debug, Formatter};
+    use std::future::Future;
+    use std::io::SeekFrom;
+    use std::mem::ManuallyDrop;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::{channel, Receiver, SendError, Sender, TryRecvError};
+    use std::sync::{Arc, OnceLock};
+    use std::task::{ready, Context, Poll};
+
+    use io_uring::{opcode, squeue, types, IoUring};
+    use parking_lot::Mutex;
+    use tokio::io::ReadBuf;
+    use tokio::sync::oneshot;
+    use tracing::{event, Level};
+
+    use crate::spawn_blocking;
+    use crate::task::JoinHandleDropGuard;
+
+    /// Number of submission queue entries of the ring.
+    const RING_ENTRIES: u32 = 256;
+    /// Most ops submitted to the ring at once. One entry is kept for the
+    /// wake read, and the completion queue is twice as large as the
+    /// submission queue, so it can never overflow.
+    const MAX_IN_FLIGHT: usize = RING_ENTRIES as usize - 1;
+    /// Largest read submitted at once.
+    const MAX_READ_SIZE: usize = 1024 * 1024;
+    /// `user_data` of the read that wakes the driver when an op is queued.
+    const WAKE_TOKEN: u64 = u64::MAX;
+
+    /// Takes over whatever owns the file descriptor of a `UringFile` when
+    /// the `UringFile` is closed. Every op on the file holds a reference, so
+    /// the file descriptor stays open until no op can use it anymore.
+    #[derive(Default)]
+    struct FdOwner(Mutex<Option<Box<dyn Send>>>);
+
+    /// A file descriptor borrowed from the `tokio::fs::File` a `UringFile`
+    /// was created for.
+    #[derive(Clone)]
+    struct BorrowedFile {
+        fd: RawFd,
+        owner: Arc<FdOwner>,
+    }
+
+    impl BorrowedFile {
+        /// Returns a `std::fs::File` that does not close the file descriptor
+        /// when dropped.
+        fn as_file(&self) -> ManuallyDrop<std::fs::File> {
+            // SAFETY: `owner` keeps the file descriptor open, and the file is
+            // never dropped, so it does not close it.
+            ManuallyDrop::new(unsafe { std::fs::File::from_raw_fd(self.fd) })
+        }
+    }
+
+    enum OpKind {
+        Read {
+            file: BorrowedFile,
+            buf: Vec<u8>,
+            offset: u64,
+        },
+        Write {
+            file: BorrowedFile,
+            buf: Vec<u8>,
+            offset: u64,
+        },
+        Fsync {
+            file: BorrowedFile,
+        },
+        Rename {
+            from: CString,
+            to: CString,
+        },
+    }
+
+    impl OpKind {
+        /// Builds the submission entry. The entry points into buffers owned
+        /// by `self`, so `self` must be kept alive until it completes.
+        fn entry(&mut self) -> squeue::Entry {
+            match self {
+                Self::Read { file, buf, offset } => {
+                    opcode::Read::new(types::Fd(file.fd), buf.as_mut_ptr(), buf.len() as u32)
+                        .offset(*offset)
+                        .build()
+                }
+                Self::Write { file, buf, offset } => {
+                    opcode::Write::new(types::Fd(file.fd), buf.as_ptr(), buf.len() as u32)
+                        .offset(*offset)
+                        .build()
+                }
+                Self::Fsync { file } => opcode::Fsync::new(types::Fd(file.fd)).build(),
+                Self::Rename { from, to } => opcode::RenameAt::new(
+                    types::Fd(libc::AT_FDCWD),
+                    from.as_ptr(),
+                    types::Fd(libc::AT_FDCWD),
+                    to.as_ptr(),
+                )
+                .build(),
+            }
+        }
+
+        fn into_buf(self) -> Vec<u8> {
+            match self {
+                Self::Read { buf, .. } | Self::Write { buf, .. } => buf,
+                Self::Fsync { .. } | Self::Rename { .. } => Vec::new(),
+            }
+        }
+
+        /// Leaks the memory the kernel may still use and drops the rest.
+        fn leak(self) {
+            match self {
+                Self::Read { buf, .. } | Self::Write { buf, .. } => std::mem::forget(buf),
+                Self::Rename { from, to } => std::mem::forget((from, to)),
+                Self::Fsync { .. } => {}
+            }
+        }
+
+        /// Does the same as the submission entry with blocking calls.
+        fn run_blocking(mut self) -> OpResult {
+            use std::os::unix::fs::FileExt;
+            let result = match &mut self {
+                Self::Read { file, buf, offset } => file.as_file().read_at(buf, *offset),
+                Self::Write { file, buf, offset } => file.as_file().write_at(buf, *offset),
+                Self::Fsync { file } => file.as_file().sync_all().map(|()| 0),
+                Self::Rename { from, to } => std::fs::rename(
+                    OsStr::from_bytes(from.as_bytes()),
+                    OsStr::from_bytes(to.as_bytes()),
+                )
+                .map(|()| 0),
+            };
+            OpResult {
+                result,
+                buf: self.into_buf(),
+            }
+        }
+    }
+
+    struct OpResult {
+        result: std::io::Result<usize>,
+        buf: Vec<u8>,
+    }
+
+    struct Op {
+        kind: OpKind,
+        tx: oneshot::Sender<OpResult>,
+    }
+
+    /// The result of a submitted op.
+    enum Completion {
+        Ring(oneshot::Receiver<OpResult>),
+        Blocking(JoinHandleDropGuard<OpResult>),
+    }
+
+    impl Completion {
+        /// Returns `None` if the driver stopped before the op completed. The
+        /// op must then be submitted again, which runs it on the blocking
+        /// thread pool.
+        fn poll(&mut self, cx: &mut Context<'_>) -> Poll<Option<OpResult>> {
+            match self {
+                Self::Ring(rx) => Poll::Ready(ready!(Pin::new(rx).poll(cx)).ok()),
+                Self::Blocking(handle) => Poll::Ready(Some(
+                    ready!(Pin::new(handle).poll(cx)).unwrap_or_else(|err| OpResult {
+                        result: Err(std::io::Error::other(err)),
+                        buf: Vec::new(),
+                    }),
+                )),
+            }
+        }
+    }
+
+    struct Driver {
+        ops_tx: Sender<Op>,
+        wake_fd: OwnedFd,
+        /// Set when the driver thread exits.
+        stopped: Arc<AtomicBool>,
+    }
+
+    impl Driver {
+        /// Returns the process wide driver, or `None` if io_uring is not
+        /// available.
+        fn get() -> Option<&'static Driver> {
+            static DRIVER: OnceLock<Option<Driver>> = OnceLock::new();
+            DRIVER
+                .get_or_init(|| match Driver::start() {
+                    Ok(driver) => Some(driver),
+                    Err(err) => {
+                        event!(
+                            Level::WARN,
+                            ?err,
+                            "io_uring is not available, falling back to blocking file I/O",
+                        );
+                        None
+                    }
+                })
+                .as_ref()
+        }
+
+        fn start() -> std::io::Result<Self> {
+            let ring = IoUring::new(RING_ENTRIES)?;
+            // SAFETY: `eventfd` has no memory safety requirements.
+            let wake_fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+            if wake_fd < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            // SAFETY: `wake_fd` was just created and is owned by nothing else.
+            let wake_fd = unsafe { OwnedFd::from_raw_fd(wake_fd) };
+            let (ops_tx, ops_rx) = channel();
+            let raw_wake_fd = wake_fd.as_raw_fd();
+            let stopped = Arc::new(AtomicBool::new(false));
+            let driver_stopped = stopped.clone();
+            // The ring is driven with blocking calls, so it needs a thread of
+            // its own rather than one of tokio's.
+            #[allow(clippy::disallowed_methods)]
+            std::thread::Builder::new()
+                .name("nativelink-io-uring".to_string())
+                .spawn(move || run_driver(ring, ops_rx, raw_wake_fd, &driver_stopped))?;
+            Ok(Self {
+                ops_tx,
+                wake_fd,
+                stopped,
+            })
+        }
+
+        fn submit(&self, kind: OpKind) -> Completion {
+            if self.stopped.load(Ordering::Acquire) {
+                return Completion::Blocking(spawn_blocking!("io_uring_fallback", move || {
+                    kind.run_blocking()
+                }));
+            }
+            let (tx, rx) = oneshot::channel();
+            if let Err(SendError(op)) = self.ops_tx.send(Op { kind, tx }) {
+                return Completion::Blocking(spawn_blocking!("io_uring_fallback", move || {
+                    op.kind.run_blocking()
+                }));
+            }
+            let one: u64 = 1;
+            // SAFETY: Writes 8 bytes from a valid u64 to an eventfd we own.
+            unsafe {
+                libc::write(
+                    self.wake_fd.as_raw_fd(),
+                    std::ptr::addr_of!(one).cast(),
+                    std::mem::size_of::<u64>(),
+                );
+            }
+            Completion::Ring(rx)
+        }
+    }
+
+    fn run_driver(mut ring: IoUring, ops_rx: Receiver<Op>, wake_fd: RawFd, stopped: &AtomicBool) {
+        let mut in_flight: HashMap<u64, Op> = HashMap::new();
+        // Ops received while the ring was full.
+        let mut backlog: VecDeque<Op> = VecDeque::new();
+        let mut next_id: u64 = 0;
+        // Leaked, since the kernel may still write to it if the ring fails.
+        let wake_buf: &'static mut [u8; 8] = Box::leak(Box::new([0; 8]));
+        let mut wake_armed = false;
+        let result: std::io::Result<()> = (|| loop {
+            if !wake_armed {
+                let entry = opcode::Read::new(
+                    types::Fd(wake_fd),
+                    wake_buf.as_mut_ptr(),
+                    wake_buf.len() as u32,
+                )
+                .build()
+                .user_data(WAKE_TOKEN);
+                // SAFETY: `wake_buf` is never freed.
+                wake_armed = unsafe { ring.submission().push(&entry).is_ok() };
+            }
+            while in_flight.len() < MAX_IN_FLIGHT {
+                let mut op = match backlog.pop_front() {
+                    Some(op) => op,
+                    None => match ops_rx.try_recv() {
+                        Ok(op) => op,
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => return Ok(()),
+                    },
+                };
+                let id = next_id;
+                next_id = next_id.wrapping_add(1) % WAKE_TOKEN;
+                let entry = op.kind.entry().user_data(id);
+                // SAFETY: `op` owns every buffer the entry points to, and is
+                // kept in `in_flight` until the entry completes. Moving `op`
+                // does not move the heap allocations of its buffers.
+                if unsafe { ring.submission().push(&entry).is_err() } {
+                    // Entries the kernel did not take yet are still queued.
+                    backlog.push_front(op);
+                    break;
+                }
+                in_flight.insert(id, op);
+            }
+            match ring.submit_and_wait(1) {
+                Ok(_) => {}
+                Err(err) if matches!(err.raw_os_error(), Some(libc::EINTR | libc::EBUSY)) => {}
+                Err(err) => return Err(err),
+            }
+            let completions: Vec<(u64, i32)> = ring
+                .completion()
+                .map(|entry| (entry.user_data(), entry.result()))
+                .collect();
+            for (id, result) in completions {
+                if id == WAKE_TOKEN {
+                    wake_armed = false;
+                    continue;
+                }
+                let Some(op) = in_flight.remove(&id) else {
+                    continue;
+                };
+                let result = if result < 0 {
+                    Err(std::io::Error::from_raw_os_error(-result))
+                } else {
+                    Ok(result as usize)
+                };
+                // The receiver is gone if the caller stopped waiting.
+                let _ = op.tx.send(OpResult {
+                    result,
+                    buf: op.kind.into_buf(),
+                });
+            }
+        })();
+        if let Err(err) = result {
+            event!(
+                Level::ERROR,
+                ?err,
+                "io_uring driver failed, falling back to blocking file I/O",
+            );
+        }
+        // Set before any sender is dropped, so the ops submitted again are
+        // run on the blocking thread pool.
+        stopped.store(true, Ordering::Release);
+        for (_, Op { kind, tx }) in in_flight {
+            // The kernel may still use the buffers of ops in flight, so they
+            // are leaked rather than freed.
+            kind.leak();
+            drop(tx);
+        }
+        // The ops in `backlog` and `ops_rx` are dropped when this returns.
+    }
+
+    enum Pending {
+        Read {
+            rx: Completion,
+            offset: u64,
+        },
+        Write {
+            rx: Completion,
+            offset: u64,
+            /// Address and length of the caller's buffer, to tell if the
+            /// caller is polling the same write again.
+            data: (usize, usize),
+        },
+    }
+
+    /// A file that does its I/O through io_uring. It reads and writes at an
+    /// explicit offset, so it tracks its own position. It borrows the file
+    /// descriptor of a `tokio::fs::File`, which has to be handed over with
+    /// `close` rather than dropped.
+    pub(crate) struct UringFile {
+        driver: &'static Driver,
+        file: BorrowedFile,
+        position: u64,
+        pending: Option<Pending>,
+    }
+
+    impl Debug for UringFile {
+        fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+            f.debug_struct("UringFile")
+                .field("fd", &self.file.fd)
+                .field("position", &self.position)
+                .finish()
+        }
+    }
+
+    impl UringFile {
+        /// Returns a `UringFile` for the same open file as `file`, or `None`
+        /// if io_uring is not available.
+        pub(crate) fn new(file: &tokio::fs::File) -> Option<Self> {
+            let driver = Driver::get()?;
+            Some(Self {
+                driver,
+                file: BorrowedFile {
+                    fd: file.as_raw_fd(),
+                    owner: Arc::default(),
+                },
+                position: 0,
+                pending: None,
+            })
+        }
+
+        /// Takes `owner`, which owns the file descriptor, and drops it once
+        /// no op submitted for the file can still use the file descriptor.
+        pub(crate) fn close(self, owner: Box<dyn Send>) {
+            *self.file.owner.0.lock() = Some(owner);
+        }
+
+        /// Returns `None` if the op has to be submitted again.
+        fn poll_rx(
+            rx: &mut Completion,
+            cx: &mut Context<'_>,
+        ) -> Poll<Option<std::io::Result<(usize, Vec<u8>)>>> {
+            let result = ready!(rx.poll(cx));
+            Poll::Ready(result.map(|OpResult { result, buf }| result.map(|size| (size, buf))))
+        }
+
+        /// Waits for a write whose caller stopped polling it. It does not
+        /// move the position, so its data will be overwritten by the next
+        /// write.
+        fn poll_abandoned_write(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            if let Some(Pending::Write { rx, .. }) = &mut self.pending {
+                let result = ready!(Self::poll_rx(rx, cx));
+                self.pending = None;
+                if let Some(result) = result {
+                    result?;
+                }
+            }
+            Poll::Ready(Ok(()))
+        }
+
+        pub(crate) fn poll_read(
+            &mut self,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            ready!(self.poll_abandoned_write(cx))?;
+            let (size, data) = loop {
+                let is_same_read = matches!(
+                    &self.pending,
+                    Some(Pending::Read { offset, .. }) if *offset == self.position
+                );
+                if !is_same_read {
+                    // A read at another position was abandoned by a seek.
+                    let len = buf.remaining().min(MAX_READ_SIZE);
+                    if len == 0 {
+                        return Poll::Ready(Ok(()));
+                    }
+                    let rx = self.driver.submit(OpKind::Read {
+                        file: self.file.clone(),
+                        buf: vec![0; len],
+                        offset: self.position,
+                    });
+                    self.pending = Some(Pending::Read {
+                        rx,
+                        offset: self.position,
+                    });
+                }
+                let Some(Pending::Read { rx, .. }) = &mut self.pending else {
+                    unreachable!();
+                };
+                let result = ready!(Self::poll_rx(rx, cx));
+                self.pending = None;
+                if let Some(result) = result {
+                    break result?;
+                }
+            };
+            // The caller may have passed a smaller buffer since the read was
+            // submitted. Whatever does not fit is read again next time.
+            let size = size.min(buf.remaining());
+            buf.put_slice(&data[..size]);
+            self.position += size as u64;
+            Poll::Ready(Ok(()))
+        }
+
+        pub(crate) fn poll_write(
+            &mut self,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            let data = (buf.as_ptr() as usize, buf.len());
+            let (size, _) = loop {
+                let is_same_write = matches!(
+                    &self.pending,
+                    Some(Pending::Write { offset, data: pending_data, .. })
+                        if *offset == self.position && *pending_data == data
+                );
+                if !is_same_write {
+                    ready!(self.poll_abandoned_write(cx))?;
+                    if buf.is_empty() {
+                        return Poll::Ready(Ok(0));
+                    }
+                    let rx = self.driver.submit(OpKind::Write {
+                        file: self.file.clone(),
+                        buf: buf.to_vec(),
+                        offset: self.position,
+                    });
+                    self.pending = Some(Pending::Write {
+                        rx,
+                        offset: self.position,
+                        data,
+                    });
+                }
+                let Some(Pending::Write { rx, .. }) = &mut self.pending else {
+                    unreachable!();
+                };
+                let result = ready!(Self::poll_rx(rx, cx));
+                self.pending = None;
+                if let Some(result) = result {
+                    break result?;
+                }
+            };
+            self.position += size as u64;
+            Poll::Ready(Ok(size))
+        }
+
+        pub(crate) fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            self.poll_abandoned_write(cx)
+        }
+
+        pub(crate) fn start_seek(&mut self, position: SeekFrom) -> std::io::Result<()> {
+            if let Some(Pending::Write { .. }) = self.pending {
+                return Err(std::io::Error::other(
+                    "other file operation is pending, call poll_complete before start_seek",
+                ));
+            }
+            let (base, delta) = match position {
+                SeekFrom::Start(position) => {
+                    self.position = position;
+                    return Ok(());
+                }
+                SeekFrom::Current(delta) => (self.position, delta),
+                SeekFrom::End(delta) => (self.file.as_file().metadata()?.len(), delta),
+            };
+            self.position = base.checked_add_signed(delta).ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "invalid seek to a negative or overflowing position",
+                )
+            })?;
+            Ok(())
+        }
+
+        pub(crate) fn poll_complete(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+            ready!(self.poll_abandoned_write(cx))?;
+            Poll::Ready(Ok(self.position))
+        }
+
+        pub(crate) async fn sync_all(&mut self) -> std::io::Result<()> {
+            std::future::poll_fn(|cx| self.poll_abandoned_write(cx)).await?;
+            loop {
+                let mut rx = self.driver.submit(OpKind::Fsync {
+                    file: self.file.clone(),
+                });
+                if let Some(result) = std::future::poll_fn(|cx| Self::poll_rx(&mut rx, cx)).await {
+                    return result.map(|_| ());
+                }
+            }
+        }
+    }
+
+    /// Renames `from` to `to` through io_uring. Returns `None` if io_uring
+    /// or its rename operation is not available, or if the driver stopped
+    /// before the rename completed.
+    pub(crate) async fn rename(from: &Path, to: &Path) -> Option<std::io::Result<()>> {
+        let driver = Driver::get()?;
+        let from = CString::new(from.as_os_str().as_bytes()).ok()?;
+        let to = CString::new(to.as_os_str().as_bytes()).ok()?;
+        let mut rx = driver.submit(OpKind::Rename { from, to });
+        let OpResult { result, .. } = std::future::poll_fn(|cx| rx.poll(cx)).await?;
+        match result {
+            // Kernels before 5.11 don't support renameat in io_uring.
+            Err(err) if matches!(err.raw_os_error(), Some(libc::EINVAL | libc::EOPNOTSUPP)) => None,
+            result => Some(result.map(|_| ())),
+        }
+    }
+
+    /// Makes new ops run on the blocking thread pool, as if the driver
+    /// thread had stopped.
+    pub(crate) fn set_stopped_for_test(stopped: bool) {
+        if let Some(driver) = Driver::get() {
+            driver.stopped.store(stopped, Ordering::Release);
+        }
+    }
+}
+
+#[cfg(not(all(feature = "io_uring", target_os = "linux")))]
+mod imp {
+    use std::io::SeekFrom;
+    use std::path::Path;
+    use std::task::{Context, Poll};
+
+    use tokio::io::ReadBuf;
+
+    /// Stand-in for the io_uring backed file when the backend is not
+    /// compiled in. It can not be constructed.
+    #[derive(Debug)]
+    pub(crate) enum UringFile {}
+
+    impl UringFile {
+        pub(crate) fn new(_file: &tokio::fs::File) -> Option<Self> {
+            None
+        }
+
+        pub(crate) fn close(self, _owner: Box<dyn Send>) {
+            match self {}
+        }
+
+        pub(crate) fn poll_read(
+            &mut self,
+            _cx: &mut Context<'_>,
+            _buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            match *self {}
+        }
+
+        pub(crate) fn poll_write(
+            &mut self,
+            _cx: &mut Context<'_>,
+            _buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            match *self {}
+        }
+
+        pub(crate) fn poll_flush(&mut self, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            match *self {}
+        }
+
+        pub(crate) fn start_seek(&mut self, _position: SeekFrom) -> std::io::Result<()> {
+            match *self {}
+        }
+
+        pub(crate) fn poll_complete(
+            &mut self,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::io::Result<u64>> {
+            match *self {}
+        }
+
+        pub(crate) async fn sync_all(&mut self) -> std::io::Result<()> {
+            match *self {}
+        }
+    }
+
+    pub(crate) async fn rename(_from: &Path, _to: &Path) -> Option<std::io::Result<()>> {
+        None
+    }
+
+    pub(crate) fn set_stopped_for_test(_stopped: bool) {}
+}
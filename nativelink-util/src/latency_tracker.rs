@@ -0,0 +1,92 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+/// Tracks the latencies of the most recent requests of some kind, so that
+/// percentiles of them can be queried cheaply.
+pub struct LatencyTracker {
+    /// The number of samples to keep.
+    window_size: usize,
+    /// The minimum number of samples needed before a percentile is known.
+    min_samples: usize,
+    state: Mutex<LatencyTrackerState>,
+}
+
+struct LatencyTrackerState {
+    /// The most recent samples in the order they were recorded.
+    samples: VecDeque<Duration>,
+    /// A sorted copy of `samples` from the last time it was sorted.
+    sorted: Vec<Duration>,
+    /// The number of samples recorded since `sorted` was updated.
+    samples_since_sort: usize,
+}
+
+impl LatencyTracker {
+    /// Creates a tracker that keeps the last `window_size` samples and
+    /// only reports percentiles once `min_samples` have been recorded.
+    pub fn new(window_size: usize, min_samples: usize) -> Self {
+        let window_size = window_size.max(1);
+        Self {
+            window_size,
+            min_samples: min_samples.clamp(1, window_size),
+            state: Mutex::new(LatencyTrackerState {
+                samples: VecDeque::with_capacity(window_size),
+                sorted: Vec::with_capacity(window_size),
+                samples_since_sort: 0,
+            }),
+        }
+    }
+
+    /// Records the latency of a request.
+    pub fn record(&self, latency: Duration) {
+        let mut state = self.state.lock();
+        if state.samples.len() == self.window_size {
+            state.samples.pop_front();
+        }
+        state.samples.push_back(latency);
+        state.samples_since_sort += 1;
+    }
+
+    /// Returns the latency that `percentile` percent of the recorded
+    /// samples are at or below, or `None` if too few samples have been
+    /// recorded. `percentile` is clamped to between 0 and 100.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        let mut state = self.state.lock();
+        if state.samples.len() < self.min_samples {
+            return None;
+        }
+        // Sorting the whole window on every query would be wasteful, so the
+        // sorted copy is only refreshed once a twentieth of it is stale.
+        if state.sorted.len() < self.min_samples
+            || state.samples_since_sort * 20 >= self.window_size
+        {
+            let LatencyTrackerState {
+                samples, sorted, ..
+            } = &mut *state;
+            sorted.clear();
+            sorted.extend(samples.iter());
+            sorted.sort_unstable();
+            state.samples_since_sort = 0;
+        }
+        let fraction = percentile.clamp(0., 100.) / 100.;
+        let index = ((state.sorted.len() as f64 * fraction).ceil() as usize)
+            .clamp(1, state.sorted.len())
+            - 1;
+        Some(state.sorted[index])
+    }
+}
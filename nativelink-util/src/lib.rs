@@ -22,14 +22,18 @@ pub mod digest_hasher;
 pub mod evicting_map;
 pub mod fastcdc;
 pub mod fs;
+mod fs_uring;
 pub mod health_utils;
 pub mod instant_wrapper;
 pub mod known_platform_property_provider;
+pub mod latency_tracker;
 pub mod metrics_utils;
 pub mod operation_state_manager;
 pub mod origin_context;
 pub mod platform_properties;
 pub mod proto_stream_utils;
+pub mod request_batcher;
+pub mod request_metadata;
 pub mod resource_info;
 pub mod retry;
 pub mod store_trait;
@@ -53,6 +53,11 @@ pub trait ActionStateResult: Send + Sync + 'static {
     async fn changed(&mut self) -> Result<Arc<ActionState>, Error>;
     // Provide result as action info. This behavior will not be supported by all implementations.
     async fn as_action_info(&self) -> Result<Arc<ActionInfo>, Error>;
+    // Provides the worker the action is assigned to. Implementations that do
+    // not know about workers always return None.
+    async fn as_worker_id(&self) -> Result<Option<WorkerId>, Error> {
+        Ok(None)
+    }
 }
 
 /// The direction in which the results are ordered.
@@ -162,4 +167,9 @@ pub trait MatchingEngineStateManager: Sync + Send + MetricsComponent {
         operation_id: &OperationId,
         worker_id_or_reason_for_unsassign: Result<&WorkerId, Error>,
     ) -> Result<(), Error>;
+
+    /// Completes a queued operation with the given error without retrying
+    /// it. Used when the operation can never be assigned to a worker. Does
+    /// nothing if the operation is not queued anymore.
+    async fn fail_operation(&self, operation_id: &OperationId, err: Error) -> Result<(), Error>;
 }
@@ -0,0 +1,229 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::Future;
+use nativelink_error::{make_err, Code, Error, ResultExt};
+use tokio::sync::{mpsc, oneshot};
+use tracing::error_span;
+
+use crate::background_spawn;
+use crate::origin_context::{ActiveOriginContext, OriginContext};
+
+/// A request waiting to be sent as part of a batch.
+struct PendingRequest<E, R> {
+    entry: E,
+    size: u64,
+    reply: oneshot::Sender<Result<R, Error>>,
+    /// The context of the caller, the batch is sent from it.
+    context: Option<Arc<OriginContext>>,
+}
+
+/// The requests of one context that are waiting for the batching window
+/// to end.
+struct PendingBatch<E, R> {
+    context: Option<Arc<OriginContext>>,
+    requests: Vec<PendingRequest<E, R>>,
+    size: u64,
+}
+
+fn is_same_context(a: &Option<Arc<OriginContext>>, b: &Option<Arc<OriginContext>>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// Coalesces requests made at about the same time into batches.  The first
+/// request of a batch waits for up to the batching window for more requests
+/// to join it, then the whole batch is handed to the function given to
+/// `RequestBatcher::new`.  A batch is sent early if it would grow past the
+/// maximum batch size.  Only requests made from the same `OriginContext`
+/// are batched together, and the batch is sent from that context, so
+/// values like the active digest function reach the function that sends it.
+pub struct RequestBatcher<E, R> {
+    request_tx: mpsc::UnboundedSender<PendingRequest<E, R>>,
+}
+
+impl<E, R> RequestBatcher<E, R>
+where
+    E: Send + 'static,
+    R: Send + 'static,
+{
+    /// Creates a batcher that waits up to `window` for requests to batch
+    /// together and sends batches of up to `max_batch_size` total size.
+    /// `send_batch` is called with the entries of each batch and must
+    /// return one result per entry in the same order.
+    pub fn new<F, Fut>(window: Duration, max_batch_size: u64, send_batch: F) -> Self
+    where
+        F: Fn(Vec<E>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Vec<Result<R, Error>>, Error>> + Send + 'static,
+    {
+        let (request_tx, request_rx) = mpsc::unbounded_channel();
+        background_spawn!(
+            "request_batcher",
+            collect_batches(request_rx, window, max_batch_size, Arc::new(send_batch))
+        );
+        Self { request_tx }
+    }
+
+    /// Adds `entry` to the next batch and waits for its result.  `size` is
+    /// the amount `entry` counts towards the maximum batch size.  An entry
+    /// larger than the maximum batch size is sent in a batch on its own.
+    pub async fn request(&self, entry: E, size: u64) -> Result<R, Error> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.request_tx
+            .send(PendingRequest {
+                entry,
+                size,
+                reply,
+                context: ActiveOriginContext::get(),
+            })
+            .map_err(|_| make_err!(Code::Internal, "RequestBatcher is no longer running"))?;
+        reply_rx
+            .await
+            .map_err(|_| make_err!(Code::Internal, "Batch was dropped before it was sent"))?
+    }
+}
+
+async fn collect_batches<E, R, F, Fut>(
+    mut request_rx: mpsc::UnboundedReceiver<PendingRequest<E, R>>,
+    window: Duration,
+    max_batch_size: u64,
+    send_batch: Arc<F>,
+) where
+    E: Send + 'static,
+    R: Send + 'static,
+    F: Fn(Vec<E>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Vec<Result<R, Error>>, Error>> + Send + 'static,
+{
+    loop {
+        let Some(first_request) = request_rx.recv().await else {
+            // The batcher was dropped and all requests were sent.
+            return;
+        };
+        let mut batches = Vec::new();
+        add_request(&mut batches, first_request, max_batch_size, &send_batch);
+        let deadline = tokio::time::sleep(window);
+        tokio::pin!(deadline);
+        loop {
+            tokio::select! {
+                () = &mut deadline => break,
+                maybe_request = request_rx.recv() => {
+                    let Some(request) = maybe_request else {
+                        break;
+                    };
+                    add_request(&mut batches, request, max_batch_size, &send_batch);
+                }
+            }
+        }
+        for batch in batches {
+            spawn_send_batch(batch, &send_batch);
+        }
+    }
+}
+
+/// Adds `request` to the batch of its context. Batches that would grow past
+/// `max_batch_size` are sent first, full batches are sent right away.
+fn add_request<E, R, F, Fut>(
+    batches: &mut Vec<PendingBatch<E, R>>,
+    request: PendingRequest<E, R>,
+    max_batch_size: u64,
+    send_batch: &Arc<F>,
+) where
+    E: Send + 'static,
+    R: Send + 'static,
+    F: Fn(Vec<E>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Vec<Result<R, Error>>, Error>> + Send + 'static,
+{
+    let mut index = batches
+        .iter()
+        .position(|batch| is_same_context(&batch.context, &request.context));
+    if let Some(i) = index {
+        if batches[i].size.saturating_add(request.size) > max_batch_size {
+            spawn_send_batch(batches.swap_remove(i), send_batch);
+            index = None;
+        }
+    }
+    let i = index.unwrap_or_else(|| {
+        batches.push(PendingBatch {
+            context: request.context.clone(),
+            requests: Vec::new(),
+            size: 0,
+        });
+        batches.len() - 1
+    });
+    let batch = &mut batches[i];
+    batch.size = batch.size.saturating_add(request.size);
+    batch.requests.push(request);
+    if batch.size >= max_batch_size {
+        spawn_send_batch(batches.swap_remove(i), send_batch);
+    }
+}
+
+fn spawn_send_batch<E, R, F, Fut>(batch: PendingBatch<E, R>, send_batch: &Arc<F>)
+where
+    E: Send + 'static,
+    R: Send + 'static,
+    F: Fn(Vec<E>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Vec<Result<R, Error>>, Error>> + Send + 'static,
+{
+    background_spawn!(
+        span: error_span!("request_batcher_send_batch", batch_size = batch.size),
+        ctx: batch.context,
+        fut: send_pending_batch(batch.requests, send_batch.clone())
+    );
+}
+
+async fn send_pending_batch<E, R, F, Fut>(batch: Vec<PendingRequest<E, R>>, send_batch: Arc<F>)
+where
+    F: Fn(Vec<E>) -> Fut,
+    Fut: Future<Output = Result<Vec<Result<R, Error>>, Error>>,
+{
+    let batch_len = batch.len();
+    let (entries, replies): (Vec<_>, Vec<_>) = batch
+        .into_iter()
+        .map(|request| (request.entry, request.reply))
+        .unzip();
+    let results = send_batch(entries)
+        .await
+        .err_tip(|| "In RequestBatcher::send_batch")
+        .and_then(|results| {
+            if results.len() == batch_len {
+                Ok(results)
+            } else {
+                Err(make_err!(
+                    Code::Internal,
+                    "Expected {batch_len} results for batch, got {}",
+                    results.len()
+                ))
+            }
+        });
+    match results {
+        Ok(results) => {
+            for (reply, result) in replies.into_iter().zip(results) {
+                // The requester may have gone away, which is fine.
+                let _ = reply.send(result);
+            }
+        }
+        Err(err) => {
+            for reply in replies {
+                let _ = reply.send(Err(err.clone()));
+            }
+        }
+    }
+}
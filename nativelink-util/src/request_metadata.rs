@@ -0,0 +1,86 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use nativelink_error::{Error, ResultExt};
+use nativelink_proto::build::bazel::remote::execution::v2::RequestMetadata;
+use prost::Message;
+use tonic::codegen::http::{HeaderMap, HeaderName};
+use tonic::metadata::{BinaryMetadataValue, MetadataMap};
+use tonic::Request;
+
+use crate::make_symbol;
+use crate::origin_context::{ActiveOriginContext, OriginContext};
+
+/// Header REv2 clients use to send a serialized `RequestMetadata` with
+/// every call.
+pub const REQUEST_METADATA_HEADER: &str = "build.bazel.remote.execution.v2.requestmetadata-bin";
+
+// The symbol can be used to retrieve the `RequestMetadata` the client sent
+// from an `OriginContext`.
+make_symbol!(ACTIVE_REQUEST_METADATA, RequestMetadata);
+
+/// Decodes the `RequestMetadata` the client sent in `headers`, if any.
+/// Malformed metadata is ignored, since it is purely informational.
+pub fn request_metadata_from_headers(headers: &HeaderMap) -> Option<RequestMetadata> {
+    let value = headers.get(REQUEST_METADATA_HEADER)?;
+    // Only the one header is copied, so the base64 decoding of tonic can be
+    // used without cloning the whole map.
+    let mut single_header = HeaderMap::with_capacity(1);
+    single_header.insert(
+        HeaderName::from_static(REQUEST_METADATA_HEADER),
+        value.clone(),
+    );
+    let bytes = MetadataMap::from_headers(single_header)
+        .get_bin(REQUEST_METADATA_HEADER)?
+        .to_bytes()
+        .ok()?;
+    RequestMetadata::decode(bytes).ok()
+}
+
+/// Utility function to make a context with the given `RequestMetadata` set.
+pub fn make_ctx_for_request_metadata(
+    request_metadata: RequestMetadata,
+) -> Result<Arc<OriginContext>, Error> {
+    let mut new_ctx = ActiveOriginContext::fork().err_tip(|| "In make_ctx_for_request_metadata")?;
+    new_ctx.set_value(&ACTIVE_REQUEST_METADATA, Arc::new(request_metadata));
+    Ok(Arc::new(new_ctx))
+}
+
+/// Returns the `RequestMetadata` of the active context, if any.
+pub fn active_request_metadata() -> Option<Arc<RequestMetadata>> {
+    ActiveOriginContext::get_value(&ACTIVE_REQUEST_METADATA)
+        .ok()
+        .flatten()
+}
+
+/// Wraps `message` in a request that carries the `RequestMetadata` of the
+/// active context, so upstream services can attribute the call to the same
+/// client invocation.
+pub fn make_request<T>(message: T) -> Request<T> {
+    let mut request = Request::new(message);
+    if let Some(request_metadata) = active_request_metadata() {
+        set_request_metadata(&mut request, &request_metadata);
+    }
+    request
+}
+
+/// Sets the `RequestMetadata` header of an outgoing request.
+pub fn set_request_metadata<T>(request: &mut Request<T>, request_metadata: &RequestMetadata) {
+    request.metadata_mut().insert_bin(
+        REQUEST_METADATA_HEADER,
+        BinaryMetadataValue::from_bytes(&request_metadata.encode_to_vec()),
+    );
+}
@@ -17,6 +17,8 @@ use std::convert::AsRef;
 
 use nativelink_error::{error_if, make_input_err, Error, ResultExt};
 
+use crate::digest_hasher::{default_digest_hasher_func, DigestHasherFunc};
+
 const ERROR_MSG: &str = concat!(
     "Expected resource_name to be of pattern ",
     "'{?instance_name/}(?uploads/{uuid}/)blobs/{?/digest_function}{/hash}/{size}{?/optional_metadata}' or ",
@@ -149,6 +151,20 @@ impl<'a> ResourceInfo<'a> {
         Ok(output)
     }
 
+    /// Returns the digest function of the resource. If the resource name
+    /// does not have one, SHA-384 and SHA-512 are recognized by the length
+    /// of the hash, otherwise the default digest function is used.
+    pub fn digest_hasher_func(&self) -> Result<DigestHasherFunc, Error> {
+        if let Some(digest_function) = self.digest_function.as_deref() {
+            return DigestHasherFunc::try_from(digest_function);
+        }
+        Ok(match self.hash.len() {
+            96 => DigestHasherFunc::Sha384,
+            128 => DigestHasherFunc::Sha512,
+            _ => default_digest_hasher_func(),
+        })
+    }
+
     /// Returns a new ResourceInfo with all fields owned.
     pub fn to_owned(&self) -> ResourceInfo<'static> {
         ResourceInfo {
@@ -258,7 +274,6 @@ fn recursive_parse<'a>(
             State::Hash => {
                 output.hash = Cow::Borrowed(part);
                 *bytes_processed += part.len() + SLASH_SIZE;
-                // TODO(allada) Set the digest_function if it is not set based on the hash size.
                 return Ok(State::Size);
             }
             State::Size => {
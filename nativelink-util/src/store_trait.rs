@@ -20,6 +20,7 @@ use std::ops::{Bound, Deref, RangeBounds};
 use std::pin::Pin;
 use std::ptr::addr_eq;
 use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 
 use async_trait::async_trait;
 use bytes::{Bytes, BytesMut};
@@ -440,6 +441,17 @@ pub trait StoreLike: Send + Sync + Sized + Unpin + 'static {
         }
     }
 
+    /// Removes the key from the store. Returns true if the key was in the
+    /// store. Stores that can not remove individual keys return an
+    /// `Unimplemented` error.
+    #[inline]
+    fn remove<'a>(
+        &'a self,
+        key: impl Into<StoreKey<'a>>,
+    ) -> impl Future<Output = Result<bool, Error>> + Send + 'a {
+        self.as_store_driver_pin().remove(key.into())
+    }
+
     /// Sends the data to the store.
     #[inline]
     fn update<'a>(
@@ -582,6 +594,14 @@ pub trait StoreDriver:
         ))
     }
 
+    /// See: [`StoreLike::remove`] for details.
+    async fn remove(self: Pin<&Self>, _key: StoreKey<'_>) -> Result<bool, Error> {
+        Err(make_err!(
+            Code::Unimplemented,
+            "Store::remove() not implemented for this store"
+        ))
+    }
+
     /// See: [`StoreLike::update`] for details.
     async fn update(
         self: Pin<&Self>,
@@ -846,6 +866,27 @@ pub trait SchedulerStore: Send + Sync + 'static {
         K: SchedulerStoreKeyProvider + SchedulerStoreDecodeTo + Send;
 }
 
+/// A store that can hold leases, used to elect one leader among multiple
+/// schedulers sharing the same store.
+pub trait SchedulerLeaseStore: Send + Sync + 'static {
+    /// Acquires the lease `name` for `holder`, or extends it if `holder`
+    /// already holds it. Returns false if another holder has a lease that
+    /// has not expired yet.
+    fn acquire_or_renew_lease(
+        &self,
+        name: &str,
+        holder: &str,
+        lease_duration: Duration,
+    ) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    /// Releases the lease `name` if it is held by `holder`.
+    fn release_lease(
+        &self,
+        name: &str,
+        holder: &str,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+}
+
 /// A type that is used to let the scheduler store know what
 /// index is beign requested.
 pub trait SchedulerIndexProvider {
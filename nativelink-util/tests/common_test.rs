@@ -14,8 +14,8 @@
 
 use nativelink_error::{make_input_err, Error};
 use nativelink_macro::nativelink_test;
-use nativelink_util::common::DigestInfo;
-use pretty_assertions::assert_eq;
+use nativelink_util::common::{DigestInfo, PackedHash};
+use pretty_assertions::{assert_eq, assert_ne};
 
 const MIN_DIGEST: &str = "0000000000000000000000000000000000000000000000000000000000000000-0";
 const MAX_SAFE_DIGEST: &str =
@@ -130,3 +130,38 @@ async fn digest_info_deserialize_test() -> Result<(), Error> {
     }
     Ok(())
 }
+
+#[nativelink_test]
+async fn digest_info_sha384_and_sha512_test() -> Result<(), Error> {
+    for hash in ["ab".repeat(48), "cd".repeat(64)] {
+        let digest = DigestInfo::try_new(&hash, 123)?;
+        assert_eq!(digest.packed_hash().len() * 2, hash.len());
+        assert_eq!(format!("{digest}"), format!("{hash}-123"));
+        assert_eq!(
+            serde_json::from_str::<DigestInfo>(&serde_json::to_string(&digest).unwrap()).unwrap(),
+            digest
+        );
+    }
+    // Hashes of any other length are rejected.
+    assert!(DigestInfo::try_new(&"ab".repeat(40), 123).is_err());
+    Ok(())
+}
+
+#[nativelink_test]
+async fn digest_info_only_stores_sha256_sized_hashes_inline_test() -> Result<(), Error> {
+    // The larger hashes must not make every `DigestInfo` larger.
+    assert!(std::mem::size_of::<DigestInfo>() <= 48);
+
+    let hash = "cd".repeat(64);
+    let digest = DigestInfo::try_new(&hash, 123)?;
+    assert_eq!(
+        digest,
+        DigestInfo::from_packed_hash(PackedHash::from_array([0xcd; 64]), 123)
+    );
+    assert_ne!(digest, DigestInfo::try_new(&"ab".repeat(64), 123)?);
+    assert_eq!(
+        digest.cmp(&DigestInfo::try_new(&"ab".repeat(64), 123)?),
+        std::cmp::Ordering::Greater
+    );
+    Ok(())
+}
@@ -0,0 +1,117 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_util::digest_hasher::{DigestHasher, DigestHasherFunc};
+use pretty_assertions::assert_eq;
+
+const HELLO_WORLD: &[u8] = b"hello world";
+
+/// Deterministic, non-repeating (within a chunk) test data.
+fn make_data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+fn hash(func: DigestHasherFunc, data: &[u8]) -> String {
+    let mut hasher = func.hasher();
+    hasher.update(data);
+    hasher.finalize_digest().to_string()
+}
+
+#[nativelink_test]
+async fn sha384_and_sha512_test() -> Result<(), Error> {
+    assert_eq!(
+        hash(DigestHasherFunc::Sha384, HELLO_WORLD),
+        "fdbd8e75a67f29f701a4e040385e2e23986303ea10239211af907fcbb83578b3e417cb71ce646efd0819dd8c088de1bd-11"
+    );
+    assert_eq!(
+        hash(DigestHasherFunc::Sha512, HELLO_WORLD),
+        "309ecc489c12d6eb4cc40f50c902f2b4d0ed77ee511a7c7a9bcd3ca86d4cd86f989dd35bc5ff499670da34255b45b0cfd830e81f605dcf7dc5542e93ae9cd76f-11"
+    );
+    Ok(())
+}
+
+#[nativelink_test]
+async fn sha256tree_matches_sha256_for_small_blobs_test() -> Result<(), Error> {
+    for len in [0, 1, 1023, 1024] {
+        let data = make_data(len);
+        assert_eq!(
+            hash(DigestHasherFunc::Sha256Tree, &data),
+            hash(DigestHasherFunc::Sha256, &data),
+            "Mismatch for blob of {len} bytes"
+        );
+    }
+    Ok(())
+}
+
+#[nativelink_test]
+async fn sha256tree_large_blobs_test() -> Result<(), Error> {
+    const EXPECTED: [(usize, &str); 5] = [
+        (
+            1025,
+            "36c0998b21839ef74300b9de47d96d1f62323dc81f2b4231e98ce70cd6ffe750",
+        ),
+        (
+            2048,
+            "b584996386f01793751c5cf0c39561f51b7e9924b818943b3cb2f6928cea0fa9",
+        ),
+        (
+            3000,
+            "44f5e533e39f82e0a0ad5743238bc2b49fb3452fdecd6109496fb787f0f20aef",
+        ),
+        (
+            4097,
+            "c3ec942c1b8f4580320d3a06bcf4f8fe1f5db2be797ab67061ea4c2a95f208f2",
+        ),
+        (
+            10000,
+            "f6711f95e9a1a4e8e178a779369bc0bca1a1e524f52dd9696c3a9b5514cb14b0",
+        ),
+    ];
+    for (len, expected_hash) in EXPECTED {
+        let data = make_data(len);
+        assert_eq!(
+            hash(DigestHasherFunc::Sha256Tree, &data),
+            format!("{expected_hash}-{len}"),
+        );
+
+        // Feeding the data in pieces that don't line up with the chunk
+        // boundaries must not change the result.
+        let mut hasher = DigestHasherFunc::Sha256Tree.hasher();
+        for piece in data.chunks(333) {
+            hasher.update(piece);
+        }
+        assert_eq!(
+            hasher.finalize_digest().to_string(),
+            format!("{expected_hash}-{len}"),
+        );
+    }
+    Ok(())
+}
+
+#[nativelink_test]
+async fn digest_hasher_func_string_conversion_test() -> Result<(), Error> {
+    for func in [
+        DigestHasherFunc::Sha256,
+        DigestHasherFunc::Blake3,
+        DigestHasherFunc::Sha384,
+        DigestHasherFunc::Sha512,
+        DigestHasherFunc::Sha256Tree,
+    ] {
+        assert_eq!(DigestHasherFunc::try_from(func.to_string().as_str())?, func);
+        assert_eq!(DigestHasherFunc::try_from(func.proto_digest_func())?, func);
+    }
+    Ok(())
+}
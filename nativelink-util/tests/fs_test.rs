@@ -178,3 +178,166 @@ async fn resumeable_file_slot_read_close_read_with_take_and_seek_test() -> Resul
     }
     Ok(())
 }
+
+#[nativelink_test]
+async fn file_cloner_gives_independent_copy_test() -> Result<(), Error> {
+    let _permit = TEST_EXCLUSIVE_SEMAPHORE.acquire().await; // One test at a time.
+    let src = make_temp_path("src_file.txt").await;
+    let dst = make_temp_path("dst_file.txt").await;
+    std::fs::write(&src, b"Hello")?;
+
+    let cloner = fs::FileCloner::new(fs::FileCloneMethod::Reflink);
+    let method = cloner.clone_file(&src, &dst).await?;
+    assert!(
+        method != fs::FileCloneMethod::HardLink,
+        "Expected reflink or copy, got {method:?}"
+    );
+    assert_eq!(fs::read(&dst).await?, b"Hello");
+
+    // Modifying the clone must not modify the source.
+    std::fs::write(&dst, b"Goodbye")?;
+    assert_eq!(fs::read(&src).await?, b"Hello");
+    Ok(())
+}
+
+#[cfg(target_family = "unix")]
+#[nativelink_test]
+async fn file_cloner_keeps_permissions_test() -> Result<(), Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let _permit = TEST_EXCLUSIVE_SEMAPHORE.acquire().await; // One test at a time.
+    for method in [fs::FileCloneMethod::Reflink, fs::FileCloneMethod::Copy] {
+        let src = make_temp_path("src_file.txt").await;
+        let dst = make_temp_path("dst_file.txt").await;
+        std::fs::write(&src, b"Hello")?;
+        std::fs::set_permissions(&src, std::fs::Permissions::from_mode(0o751))?;
+
+        fs::FileCloner::new(method).clone_file(&src, &dst).await?;
+        assert_eq!(
+            std::fs::metadata(&dst)?.permissions().mode() & 0o777,
+            0o751,
+            "Expected {method:?} to keep the permissions of the source"
+        );
+    }
+    Ok(())
+}
+
+#[nativelink_test]
+async fn file_cloner_copy_keeps_existing_destination_test() -> Result<(), Error> {
+    let _permit = TEST_EXCLUSIVE_SEMAPHORE.acquire().await; // One test at a time.
+    let src = make_temp_path("src_file.txt").await;
+    let dst = make_temp_path("dst_file.txt").await;
+    std::fs::write(&src, b"Hello")?;
+    std::fs::write(&dst, b"Goodbye")?;
+
+    let cloner = fs::FileCloner::new(fs::FileCloneMethod::Copy);
+    assert!(
+        cloner.clone_file(&src, &dst).await.is_err(),
+        "Expected copy over an existing file to fail"
+    );
+    // The file was not created by the cloner, so it must be left alone.
+    assert_eq!(fs::read(&dst).await?, b"Goodbye");
+    Ok(())
+}
+
+#[cfg(feature = "io_uring")]
+#[nativelink_test]
+async fn io_uring_write_seek_read_test() -> Result<(), Error> {
+    let _permit = TEST_EXCLUSIVE_SEMAPHORE.acquire().await; // One test at a time.
+    let filename = make_temp_path("test_file.txt").await;
+    // Larger than what is read at once, so reads need several ops.
+    let mut data = vec![0u8; 3 * 1024 * 1024 + 7];
+    thread_rng().fill(&mut data[..]);
+
+    let mut file = fs::create_file(&filename).await?;
+    file.as_writer().await?.write_all(&data).await?;
+    file.as_writer().await?.sync_all().await?;
+
+    file.as_writer().await?.seek(SeekFrom::Start(0)).await?;
+    let mut contents = Vec::new();
+    file.as_reader().await?.read_to_end(&mut contents).await?;
+    assert_eq!(contents, data);
+
+    let offset = 1024 * 1024 + 3;
+    file.as_writer()
+        .await?
+        .seek(SeekFrom::Start(offset as u64))
+        .await?;
+    let mut contents = [0u8; 16];
+    file.as_reader().await?.read_exact(&mut contents).await?;
+    assert_eq!(contents[..], data[offset..offset + 16]);
+    Ok(())
+}
+
+#[cfg(feature = "io_uring")]
+#[nativelink_test]
+async fn io_uring_rename_test() -> Result<(), Error> {
+    let _permit = TEST_EXCLUSIVE_SEMAPHORE.acquire().await; // One test at a time.
+    let from = make_temp_path("from_file.txt").await;
+    let to = make_temp_path("to_file.txt").await;
+    std::fs::write(&from, b"Hello")?;
+
+    fs::rename(&from, &to).await?;
+    assert_eq!(fs::read(&to).await?, b"Hello");
+    assert!(
+        std::fs::metadata(&from).is_err(),
+        "Expected {from:?} to be gone"
+    );
+    Ok(())
+}
+
+#[cfg(feature = "io_uring")]
+#[nativelink_test]
+async fn io_uring_falls_back_when_driver_stopped_test() -> Result<(), Error> {
+    let _permit = TEST_EXCLUSIVE_SEMAPHORE.acquire().await; // One test at a time.
+    let filename = make_temp_path("test_file.txt").await;
+    let renamed = make_temp_path("renamed_file.txt").await;
+
+    let mut file = fs::create_file(&filename).await?;
+    file.as_writer().await?.write_all(b"Hello").await?;
+    fs::set_io_uring_stopped_for_test(true);
+    let result = async {
+        file.as_writer().await?.write_all(b"Goodbye").await?;
+        file.as_writer().await?.sync_all().await?;
+        file.as_writer().await?.seek(SeekFrom::Start(0)).await?;
+        let mut contents = String::new();
+        file.as_reader()
+            .await?
+            .read_to_string(&mut contents)
+            .await?;
+        fs::rename(&filename, &renamed).await?;
+        Ok::<_, Error>(contents)
+    }
+    .await;
+    fs::set_io_uring_stopped_for_test(false);
+
+    assert_eq!(result?, "HelloGoodbye");
+    assert_eq!(fs::read(&renamed).await?, b"HelloGoodbye");
+    Ok(())
+}
+
+#[cfg(feature = "io_uring")]
+#[nativelink_test]
+async fn io_uring_file_uses_one_file_descriptor_test() -> Result<(), Error> {
+    let _permit = TEST_EXCLUSIVE_SEMAPHORE.acquire().await; // One test at a time.
+    let filename = make_temp_path("test_file.txt").await;
+    let open_fds = || std::fs::read_dir("/proc/self/fd").map(Iterator::count);
+    // Makes sure the ring is set up before counting.
+    fs::create_file(&filename)
+        .await?
+        .as_writer()
+        .await?
+        .write_all(b"Hello")
+        .await?;
+
+    let fds_before = open_fds()?;
+    {
+        let mut file = fs::create_file(&filename).await?;
+        file.as_writer().await?.write_all(b"Goodbye").await?;
+        assert_eq!(fs::get_open_files_for_test(), 1);
+        assert_eq!(open_fds()?, fds_before + 1);
+    }
+    assert_eq!(fs::get_open_files_for_test(), 0);
+    assert_eq!(open_fds()?, fds_before);
+    Ok(())
+}
@@ -0,0 +1,51 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_util::latency_tracker::LatencyTracker;
+use pretty_assertions::assert_eq;
+
+#[nativelink_test]
+async fn percentile_needs_min_samples_test() -> Result<(), Error> {
+    let tracker = LatencyTracker::new(100, 10);
+    for i in 1..10 {
+        tracker.record(Duration::from_millis(i));
+    }
+    assert_eq!(tracker.percentile(95.), None);
+    tracker.record(Duration::from_millis(10));
+    assert_eq!(tracker.percentile(95.), Some(Duration::from_millis(10)));
+    assert_eq!(tracker.percentile(50.), Some(Duration::from_millis(5)));
+    assert_eq!(tracker.percentile(0.), Some(Duration::from_millis(1)));
+    Ok(())
+}
+
+#[nativelink_test]
+async fn percentile_of_recent_samples_test() -> Result<(), Error> {
+    let tracker = LatencyTracker::new(100, 10);
+    // Record in reverse order to make sure samples are sorted.
+    for i in (1..=100).rev() {
+        tracker.record(Duration::from_millis(i));
+    }
+    assert_eq!(tracker.percentile(95.), Some(Duration::from_millis(95)));
+
+    // Old samples fall out of the window.
+    for _ in 0..100 {
+        tracker.record(Duration::from_millis(1000));
+    }
+    assert_eq!(tracker.percentile(50.), Some(Duration::from_millis(1000)));
+    Ok(())
+}
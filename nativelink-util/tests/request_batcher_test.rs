@@ -0,0 +1,159 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::join;
+use nativelink_error::{make_err, make_input_err, Code, Error};
+use nativelink_macro::nativelink_test;
+use nativelink_util::digest_hasher::{
+    make_ctx_for_hash_func, DigestHasherFunc, ACTIVE_HASHER_FUNC,
+};
+use nativelink_util::origin_context::ActiveOriginContext;
+use nativelink_util::request_batcher::RequestBatcher;
+use parking_lot::Mutex;
+use pretty_assertions::assert_eq;
+use tracing::error_span;
+
+const WINDOW: Duration = Duration::from_millis(50);
+
+type Batches = Arc<Mutex<Vec<Vec<u64>>>>;
+
+/// Makes a batcher that doubles each entry and records the batches sent.
+fn make_doubling_batcher(max_batch_size: u64) -> (RequestBatcher<u64, u64>, Batches) {
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let batches_clone = batches.clone();
+    let batcher = RequestBatcher::new(WINDOW, max_batch_size, move |entries: Vec<u64>| {
+        batches_clone.lock().push(entries.clone());
+        async move { Ok(entries.into_iter().map(|entry| Ok(entry * 2)).collect()) }
+    });
+    (batcher, batches)
+}
+
+#[nativelink_test]
+async fn concurrent_requests_are_batched_test() -> Result<(), Error> {
+    let (batcher, batches) = make_doubling_batcher(1000);
+    let (a, b, c) = join!(
+        batcher.request(1, 1),
+        batcher.request(2, 1),
+        batcher.request(3, 1),
+    );
+    assert_eq!((a?, b?, c?), (2, 4, 6));
+    assert_eq!(*batches.lock(), vec![vec![1, 2, 3]]);
+
+    // A later request is sent in a new batch.
+    assert_eq!(batcher.request(4, 1).await?, 8);
+    assert_eq!(*batches.lock(), vec![vec![1, 2, 3], vec![4]]);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn batch_is_split_at_max_size_test() -> Result<(), Error> {
+    let (batcher, batches) = make_doubling_batcher(10);
+    let (a, b, c, d) = join!(
+        batcher.request(1, 4),
+        batcher.request(2, 4),
+        batcher.request(3, 4),
+        // Larger than a whole batch, so it is sent on its own.
+        batcher.request(4, 20),
+    );
+    assert_eq!((a?, b?, c?, d?), (2, 4, 6, 8));
+    assert_eq!(*batches.lock(), vec![vec![1, 2], vec![3], vec![4]]);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn batch_errors_are_returned_test() -> Result<(), Error> {
+    let batcher = RequestBatcher::new(WINDOW, 1000, |entries: Vec<u64>| async move {
+        Ok(entries
+            .into_iter()
+            .map(|entry| {
+                if entry % 2 == 0 {
+                    Ok(entry)
+                } else {
+                    Err(make_input_err!("odd entry {entry}"))
+                }
+            })
+            .collect())
+    });
+    let (a, b) = join!(batcher.request(1, 1), batcher.request(2, 1));
+    assert_eq!(a.unwrap_err().code, Code::InvalidArgument);
+    assert_eq!(b?, 2);
+
+    // An error for the whole batch is returned to every request.
+    let batcher = RequestBatcher::new(WINDOW, 1000, |_entries: Vec<u64>| async move {
+        Err::<Vec<Result<u64, Error>>, _>(make_err!(Code::Unavailable, "upstream is down"))
+    });
+    let (a, b) = join!(batcher.request(1, 1), batcher.request(2, 1));
+    assert_eq!(a.unwrap_err().code, Code::Unavailable);
+    assert_eq!(b.unwrap_err().code, Code::Unavailable);
+
+    // Returning the wrong number of results is an error too.
+    let batcher = RequestBatcher::new(
+        WINDOW,
+        1000,
+        |_entries: Vec<u64>| async move { Ok(vec![Ok(1)]) },
+    );
+    let (a, b) = join!(batcher.request(1, 1), batcher.request(2, 1));
+    assert_eq!(a.unwrap_err().code, Code::Internal);
+    assert_eq!(b.unwrap_err().code, Code::Internal);
+    Ok(())
+}
+
+#[nativelink_test]
+async fn batches_are_sent_from_the_context_of_their_requests_test() -> Result<(), Error> {
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let batches_clone = batches.clone();
+    let batcher = Arc::new(RequestBatcher::new(
+        WINDOW,
+        1000,
+        move |entries: Vec<u64>| {
+            let hasher = ActiveOriginContext::get_value(&ACTIVE_HASHER_FUNC)
+                .ok()
+                .flatten()
+                .map(|hasher| *hasher);
+            batches_clone.lock().push((hasher, entries.clone()));
+            async move { Ok(entries.into_iter().map(Ok).collect()) }
+        },
+    ));
+
+    let request_with_hasher = |entry: u64, hasher: DigestHasherFunc| {
+        let batcher = batcher.clone();
+        async move {
+            make_ctx_for_hash_func(hasher)?
+                .wrap_async(error_span!("test"), batcher.request(entry, 1))
+                .await
+        }
+    };
+    let (a, b, c) = join!(
+        request_with_hasher(1, DigestHasherFunc::Blake3),
+        request_with_hasher(2, DigestHasherFunc::Sha256),
+        request_with_hasher(3, DigestHasherFunc::Blake3),
+    );
+    assert_eq!((a?, b?, c?), (1, 2, 3));
+
+    // Requests of different contexts are never batched together.
+    let mut batches = batches.lock().clone();
+    batches.sort();
+    assert_eq!(
+        batches,
+        vec![
+            (Some(DigestHasherFunc::Sha256), vec![2]),
+            (Some(DigestHasherFunc::Blake3), vec![1]),
+            (Some(DigestHasherFunc::Blake3), vec![3]),
+        ]
+    );
+    Ok(())
+}
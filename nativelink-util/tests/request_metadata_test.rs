@@ -0,0 +1,53 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use nativelink_error::Error;
+use nativelink_macro::nativelink_test;
+use nativelink_proto::build::bazel::remote::execution::v2::{RequestMetadata, ToolDetails};
+use nativelink_util::request_metadata::{
+    make_ctx_for_request_metadata, make_request, request_metadata_from_headers,
+    REQUEST_METADATA_HEADER,
+};
+use pretty_assertions::assert_eq;
+use tracing::error_span;
+
+#[nativelink_test]
+async fn request_metadata_is_forwarded_from_context_test() -> Result<(), Error> {
+    let request_metadata = RequestMetadata {
+        tool_details: Some(ToolDetails {
+            tool_name: "bazel".to_string(),
+            tool_version: "7.3.1".to_string(),
+        }),
+        action_id: "action_id".to_string(),
+        tool_invocation_id: "tool_invocation_id".to_string(),
+        correlated_invocations_id: "correlated_invocations_id".to_string(),
+        action_mnemonic: "CppCompile".to_string(),
+        target_id: "//foo:bar".to_string(),
+        configuration_id: "configuration_id".to_string(),
+    };
+
+    // Without metadata in the context nothing is forwarded.
+    let request = make_request(());
+    assert_eq!(request.metadata().get_bin(REQUEST_METADATA_HEADER), None);
+
+    let request = make_ctx_for_request_metadata(request_metadata.clone())?
+        .wrap_async(error_span!("test"), async { make_request(()) })
+        .await;
+    let headers = request.into_parts().0.into_headers();
+    assert_eq!(
+        request_metadata_from_headers(&headers),
+        Some(request_metadata)
+    );
+    Ok(())
+}
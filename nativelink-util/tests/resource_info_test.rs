@@ -15,6 +15,7 @@
 use std::borrow::Cow;
 
 use nativelink_macro::nativelink_test;
+use nativelink_util::digest_hasher::DigestHasherFunc;
 use nativelink_util::resource_info::ResourceInfo;
 use pretty_assertions::assert_eq;
 
@@ -707,3 +708,32 @@ async fn write_invalid_size_test() -> Result<(), Box<dyn std::error::Error>> {
     assert!(ResourceInfo::new(RESOURCE_NAME, true).is_err());
     Ok(())
 }
+
+#[nativelink_test]
+async fn digest_hasher_func_from_hash_length_test() -> Result<(), Box<dyn std::error::Error>> {
+    let sha256_hash = "a".repeat(64);
+    let sha384_hash = "a".repeat(96);
+    let sha512_hash = "a".repeat(128);
+    for (resource_name, expected) in [
+        (
+            format!("instance_name/blobs/{sha256_hash}/12345"),
+            DigestHasherFunc::Sha256,
+        ),
+        (
+            format!("instance_name/blobs/{sha384_hash}/12345"),
+            DigestHasherFunc::Sha384,
+        ),
+        (
+            format!("instance_name/blobs/{sha512_hash}/12345"),
+            DigestHasherFunc::Sha512,
+        ),
+        (
+            format!("instance_name/blobs/sha256tree/{sha256_hash}/12345"),
+            DigestHasherFunc::Sha256Tree,
+        ),
+    ] {
+        let resource_info = ResourceInfo::new(&resource_name, false)?;
+        assert_eq!(resource_info.digest_hasher_func()?, expected);
+    }
+    Ok(())
+}
@@ -36,6 +36,7 @@ use nativelink_util::common::fs;
 use nativelink_util::digest_hasher::{DigestHasherFunc, ACTIVE_HASHER_FUNC};
 use nativelink_util::metrics_utils::{AsyncCounterWrapper, CounterWithTime};
 use nativelink_util::origin_context::ActiveOriginContext;
+use nativelink_util::request_metadata::ACTIVE_REQUEST_METADATA;
 use nativelink_util::store_trait::Store;
 use nativelink_util::{spawn, tls_utils};
 use tokio::process;
@@ -46,7 +47,7 @@ use tonic::Streaming;
 use tracing::{event, info_span, instrument, Level};
 
 use crate::running_actions_manager::{
-    ExecutionConfiguration, Metrics as RunningActionManagerMetrics, RunningAction,
+    CachePolicy, ExecutionConfiguration, Metrics as RunningActionManagerMetrics, RunningAction,
     RunningActionsManager, RunningActionsManagerArgs, RunningActionsManagerImpl,
 };
 use crate::worker_api_client_wrapper::{WorkerApiClientTrait, WorkerApiClientWrapper};
@@ -232,6 +233,7 @@ impl<'a, T: WorkerApiClientTrait, U: RunningActionsManager> LocalWorkerImpl<'a,
                                 .ok_or(make_input_err!("Expected execute_request to be set"))
                                 .and_then(|v| DigestHasherFunc::try_from(v.digest_function))
                                 .err_tip(|| "In LocalWorkerImpl::new()")?;
+                            let request_metadata = start_execute.request_metadata.clone();
 
                             let start_action_fut = {
                                 let precondition_script_cfg = self.config.experimental_precondition_script.clone();
@@ -253,16 +255,18 @@ impl<'a, T: WorkerApiClientTrait, U: RunningActionsManager> LocalWorkerImpl<'a,
                                             operation_id = ?action.get_operation_id(),
                                             "Received request to run action"
                                         );
+                                        let cache_policy = action.get_cache_policy();
                                         action
                                             .clone()
                                             .prepare_action()
                                             .and_then(RunningAction::execute)
                                             .and_then(RunningAction::upload_results)
                                             .and_then(RunningAction::get_finished_result)
+                                            .map_ok(move |action_result| (action_result, cache_policy))
                                             // Note: We need ensure we run cleanup even if one of the other steps fail.
                                             .then(|result| async move {
                                                 if let Err(e) = action.cleanup().await {
-                                                    return Result::<ActionResult, Error>::Err(e).merge(result);
+                                                    return Result::<(ActionResult, CachePolicy), Error>::Err(e).merge(result);
                                                 }
                                                 result
                                             })
@@ -275,14 +279,14 @@ impl<'a, T: WorkerApiClientTrait, U: RunningActionsManager> LocalWorkerImpl<'a,
 
                                 let worker_id = self.worker_id.clone();
                                 let running_actions_manager = self.running_actions_manager.clone();
-                                move |res: Result<ActionResult, Error>| async move {
+                                move |res: Result<(ActionResult, CachePolicy), Error>| async move {
                                     let instance_name = maybe_instance_name
                                         .err_tip(|| "`instance_name` could not be resolved; this is likely an internal error in local_worker.")?;
                                     match res {
-                                        Ok(mut action_result) => {
+                                        Ok((mut action_result, cache_policy)) => {
                                             // Save in the action cache before notifying the scheduler that we've completed.
                                             if let Some(digest_info) = action_digest.clone().and_then(|action_digest| action_digest.try_into().ok()) {
-                                                if let Err(err) = running_actions_manager.cache_action_result(digest_info, &mut action_result, digest_hasher).await {
+                                                if let Err(err) = running_actions_manager.cache_action_result(digest_info, &mut action_result, digest_hasher, cache_policy).await {
                                                     event!(
                                                         Level::ERROR,
                                                         ?err,
@@ -322,7 +326,16 @@ impl<'a, T: WorkerApiClientTrait, U: RunningActionsManager> LocalWorkerImpl<'a,
                             let add_future_channel = add_future_channel.clone();
                             let mut ctx = ActiveOriginContext::fork().err_tip(|| "Expected ActiveOriginContext to be set in local_worker::run")?;
                             ctx.set_value(&ACTIVE_HASHER_FUNC, Arc::new(digest_hasher));
-                            ctx.run(info_span!("worker_start_action_ctx"), move || {
+                            // Uploads and downloads made for this action are
+                            // attributed to the client invocation that sent it.
+                            let (tool_invocation_id, action_mnemonic, target_id) = request_metadata
+                                .as_ref()
+                                .map(|v| (v.tool_invocation_id.clone(), v.action_mnemonic.clone(), v.target_id.clone()))
+                                .unwrap_or_default();
+                            if let Some(request_metadata) = request_metadata {
+                                ctx.set_value(&ACTIVE_REQUEST_METADATA, Arc::new(request_metadata));
+                            }
+                            ctx.run(info_span!("worker_start_action_ctx", tool_invocation_id, action_mnemonic, target_id), move || {
                                 futures_ref.push(
                                     spawn!("worker_start_action", start_action_fut).map(move |res| {
                                         let res = res.err_tip(|| "Failed to launch spawn")?;
@@ -417,6 +430,7 @@ pub async fn new_local_worker(
             upload_action_result_config: &config.upload_action_result,
             max_action_timeout,
             timeout_handled_externally: config.timeout_handled_externally,
+            input_file_materialization: config.input_file_materialization,
         })?);
     let local_worker = LocalWorker::new_with_connection_factory_and_actions_manager(
         config.clone(),
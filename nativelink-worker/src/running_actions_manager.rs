@@ -38,14 +38,15 @@ use futures::future::{
 };
 use futures::stream::{FuturesUnordered, StreamExt, TryStreamExt};
 use nativelink_config::cas_server::{
-    EnvironmentSource, UploadActionResultConfig, UploadCacheResultsStrategy,
+    EnvironmentSource, InputFileMaterialization, UploadActionResultConfig,
+    UploadCacheResultsStrategy,
 };
 use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
 use nativelink_metric::MetricsComponent;
 use nativelink_proto::build::bazel::remote::execution::v2::{
     Action, ActionResult as ProtoActionResult, Command as ProtoCommand,
-    Directory as ProtoDirectory, Directory, DirectoryNode, ExecuteResponse, FileNode, SymlinkNode,
-    Tree as ProtoTree, UpdateActionResultRequest,
+    Directory as ProtoDirectory, Directory, DirectoryNode, ExecuteResponse, FileNode,
+    ResultsCachePolicy, SymlinkNode, Tree as ProtoTree, UpdateActionResultRequest,
 };
 use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::{
     HistoricalExecuteResponse, StartExecute,
@@ -112,15 +113,19 @@ struct SideChannelInfo {
 /// should be rate limited if spawning too many requests at once is an issue.
 /// We require the `FilesystemStore` to be the `fast` store of `FastSlowStore`. This is for
 /// efficiency reasons. We will request the `FastSlowStore` to populate the entry then we will
-/// assume the `FilesystemStore` has the file available immediately after and hardlink the file
-/// to a new location.
+/// assume the `FilesystemStore` has the file available immediately after and use `file_cloner`
+/// to hardlink, reflink or copy the file to a new location.
+/// The files are remembered as hashed with `hasher`, so unchanged outputs do not need to be
+/// hashed again.
 // Sadly we cannot use `async fn` here because the rust compiler cannot determine the auto traits
 // of the future. So we need to force this function to return a dynamic future instead.
 // see: https://github.com/rust-lang/rust/issues/78649
 pub fn download_to_directory<'a>(
     cas_store: &'a FastSlowStore,
     filesystem_store: Pin<&'a FilesystemStore>,
+    file_cloner: &'a fs::FileCloner,
     digest: &'a DigestInfo,
+    hasher: DigestHasherFunc,
     current_directory: &'a str,
 ) -> BoxFuture<'a, Result<(), Error>> {
     async move {
@@ -149,14 +154,14 @@ pub fn download_to_directory<'a>(
                     .populate_fast_store(digest.into())
                     .and_then(move |_| async move {
                         let file_entry = filesystem_store
-                            .get_file_entry_for_digest(&digest)
+                            .get_file_entry_for_hard_link(&digest, current_directory)
                             .await
                             .err_tip(|| "During hard link")?;
                         file_entry
-                            .get_file_path_locked(|src| fs::hard_link(src, &dest))
+                            .get_file_path_locked(|src| file_cloner.clone_file(src, &dest))
                             .await
                             .map_err(|e| {
-                                make_err!(Code::Internal, "Could not make hardlink, {e:?} : {dest}")
+                                make_err!(Code::Internal, "Could not place file, {e:?} : {dest}")
                             })?;
                         #[cfg(target_family = "unix")]
                         if let Some(unix_mode) = unix_mode {
@@ -169,6 +174,7 @@ pub fn download_to_directory<'a>(
                                 })?;
                         }
                         if let Some(mtime) = mtime {
+                            let dest = dest.clone();
                             spawn_blocking!("download_to_directory_set_mtime", move || {
                                 set_file_mtime(
                                     &dest,
@@ -183,6 +189,14 @@ pub fn download_to_directory<'a>(
                                 "Failed to launch spawn_blocking in download_to_directory"
                             })??;
                         }
+                        // This must happen after the mode and mtime are set, because
+                        // changing them also changes the ctime of the file.
+                        filesystem_store
+                            .record_file_digest(&dest, digest, hasher)
+                            .await
+                            .err_tip(|| {
+                                format!("Could not record digest in download_to_directory {dest}")
+                            })?;
                         Ok(())
                     })
                     .map_err(move |e| e.append(format!("for digest {digest}")))
@@ -205,7 +219,9 @@ pub fn download_to_directory<'a>(
                     download_to_directory(
                         cas_store,
                         filesystem_store,
+                        file_cloner,
                         &digest,
+                        hasher,
                         &new_directory_path,
                     )
                     .await
@@ -254,44 +270,72 @@ fn is_executable(metadata: &std::fs::Metadata, _full_path: &impl AsRef<Path>) ->
 
 async fn upload_file(
     cas_store: Pin<&impl StoreLike>,
+    filesystem_store: Pin<&FilesystemStore>,
     full_path: impl AsRef<Path> + Debug,
     hasher: DigestHasherFunc,
     metadata: std::fs::Metadata,
 ) -> Result<FileInfo, Error> {
     let is_executable = is_executable(&metadata, &full_path);
     let file_size = metadata.len();
-    let resumeable_file = fs::open_file(&full_path, u64::MAX)
-        .await
-        .err_tip(|| format!("Could not open file {full_path:?}"))?;
 
-    let (digest, mut resumeable_file) = hasher
-        .hasher()
-        .digest_for_file(resumeable_file, Some(file_size))
-        .await
-        .err_tip(|| format!("Failed to hash file in digest_for_file failed for {full_path:?}"))?;
+    // If the file is an untouched hardlink out of the filesystem store we
+    // already know its digest, and it is very likely already in the CAS.
+    let cached_digest = filesystem_store.cached_file_digest(&metadata, hasher).await;
+    let already_uploaded = match cached_digest {
+        Some(digest) => cas_store
+            .has(digest)
+            .await
+            .err_tip(|| format!("Checking existence of cached digest for {full_path:?}"))?
+            .is_some(),
+        None => false,
+    };
 
-    resumeable_file
-        .as_reader()
-        .await
-        .err_tip(|| "Could not get reader from file slot in RunningActionsManager::upload_file()")?
-        .get_mut()
-        .rewind()
-        .await
-        .err_tip(|| "Could not rewind file")?;
-
-    // Note: For unknown reasons we appear to be hitting:
-    // https://github.com/rust-lang/rust/issues/92096
-    // or a smiliar issue if we try to use the non-store driver function, so we
-    // are using the store driver function here.
-    cas_store
-        .as_store_driver_pin()
-        .update_with_whole_file(
-            digest.into(),
-            resumeable_file,
-            UploadSizeInfo::ExactSize(digest.size_bytes()),
-        )
-        .await
-        .err_tip(|| format!("for {full_path:?}"))?;
+    let digest = match cached_digest {
+        Some(digest) if already_uploaded => digest,
+        _ => {
+            let resumeable_file = fs::open_file(&full_path, u64::MAX)
+                .await
+                .err_tip(|| format!("Could not open file {full_path:?}"))?;
+
+            let (digest, mut resumeable_file) = if let Some(digest) = cached_digest {
+                (digest, resumeable_file)
+            } else {
+                hasher
+                    .hasher()
+                    .digest_for_file(resumeable_file, Some(file_size))
+                    .await
+                    .err_tip(|| {
+                        format!("Failed to hash file in digest_for_file failed for {full_path:?}")
+                    })?
+            };
+
+            resumeable_file
+                .as_reader()
+                .await
+                .err_tip(|| {
+                    "Could not get reader from file slot in RunningActionsManager::upload_file()"
+                })?
+                .get_mut()
+                .rewind()
+                .await
+                .err_tip(|| "Could not rewind file")?;
+
+            // Note: For unknown reasons we appear to be hitting:
+            // https://github.com/rust-lang/rust/issues/92096
+            // or a smiliar issue if we try to use the non-store driver function, so we
+            // are using the store driver function here.
+            cas_store
+                .as_store_driver_pin()
+                .update_with_whole_file(
+                    digest.into(),
+                    resumeable_file,
+                    UploadSizeInfo::ExactSize(digest.size_bytes()),
+                )
+                .await
+                .err_tip(|| format!("for {full_path:?}"))?;
+            digest
+        }
+    };
 
     let name = full_path
         .as_ref()
@@ -367,6 +411,7 @@ async fn upload_symlink(
 
 fn upload_directory<'a, P: AsRef<Path> + Debug + Send + Sync + Clone + 'a>(
     cas_store: Pin<&'a impl StoreLike>,
+    filesystem_store: Pin<&'a FilesystemStore>,
     full_dir_path: P,
     full_work_directory: &'a str,
     hasher: DigestHasherFunc,
@@ -395,47 +440,50 @@ fn upload_directory<'a, P: AsRef<Path> + Debug + Send + Sync + Clone + 'a>(
                 if file_type.is_dir() {
                     let full_dir_path = full_dir_path.clone();
                     dir_futures.push(
-                        upload_directory(cas_store, full_path.clone(), full_work_directory, hasher)
-                            .and_then(|(dir, all_dirs)| async move {
-                                let directory_name = full_path
-                                    .file_name()
-                                    .err_tip(|| {
-                                        format!("Expected file_name to exist on {full_dir_path:?}")
-                                    })?
-                                    .to_str()
-                                    .err_tip(|| {
-                                        make_err!(
-                                            Code::Internal,
-                                            "Could not convert {:?} to string",
-                                            full_dir_path
-                                        )
-                                    })?
-                                    .to_string();
-
-                                let digest = serialize_and_upload_message(
-                                    &dir,
-                                    cas_store,
-                                    &mut hasher.hasher(),
-                                )
-                                .await
-                                .err_tip(|| format!("for {full_path:?}"))?;
-
-                                Result::<(DirectoryNode, VecDeque<Directory>), Error>::Ok((
-                                    DirectoryNode {
-                                        name: directory_name,
-                                        digest: Some(digest.into()),
-                                    },
-                                    all_dirs,
-                                ))
-                            })
-                            .boxed(),
+                        upload_directory(
+                            cas_store,
+                            filesystem_store,
+                            full_path.clone(),
+                            full_work_directory,
+                            hasher,
+                        )
+                        .and_then(|(dir, all_dirs)| async move {
+                            let directory_name = full_path
+                                .file_name()
+                                .err_tip(|| {
+                                    format!("Expected file_name to exist on {full_dir_path:?}")
+                                })?
+                                .to_str()
+                                .err_tip(|| {
+                                    make_err!(
+                                        Code::Internal,
+                                        "Could not convert {:?} to string",
+                                        full_dir_path
+                                    )
+                                })?
+                                .to_string();
+
+                            let digest =
+                                serialize_and_upload_message(&dir, cas_store, &mut hasher.hasher())
+                                    .await
+                                    .err_tip(|| format!("for {full_path:?}"))?;
+
+                            Result::<(DirectoryNode, VecDeque<Directory>), Error>::Ok((
+                                DirectoryNode {
+                                    name: directory_name,
+                                    digest: Some(digest.into()),
+                                },
+                                all_dirs,
+                            ))
+                        })
+                        .boxed(),
                     );
                 } else if file_type.is_file() {
                     file_futures.push(async move {
                         let metadata = fs::metadata(&full_path)
                             .await
                             .err_tip(|| format!("Could not open file {full_path:?}"))?;
-                        upload_file(cas_store, &full_path, hasher, metadata)
+                        upload_file(cas_store, filesystem_store, &full_path, hasher, metadata)
                             .map_ok(Into::into)
                             .await
                     });
@@ -558,10 +606,24 @@ async fn do_cleanup(
     Ok(())
 }
 
+/// How the result of an action may be stored in the action cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CachePolicy {
+    /// Set if the `Action` was marked `do_not_cache` or the scheduler asked
+    /// for the result not to be uploaded, in which case the result must not
+    /// be uploaded to the action cache.
+    pub do_not_cache: bool,
+    /// Priority the client asked the result to be cached with.
+    pub priority: i32,
+}
+
 pub trait RunningAction: Sync + Send + Sized + Unpin + 'static {
     /// Returns the action id of the action.
     fn get_operation_id(&self) -> &OperationId;
 
+    /// Returns how the result of the action may be cached.
+    fn get_cache_policy(&self) -> CachePolicy;
+
     /// Anything that needs to execute before the actions is actually executed should happen here.
     fn prepare_action(self: Arc<Self>) -> impl Future<Output = Result<Arc<Self>, Error>> + Send;
 
@@ -613,6 +675,7 @@ pub struct RunningActionImpl {
     action_directory: String,
     work_directory: String,
     action_info: ActionInfo,
+    cache_policy: CachePolicy,
     timeout: Duration,
     running_actions_manager: Arc<RunningActionsManagerImpl>,
     state: Mutex<RunningActionImplState>,
@@ -625,6 +688,7 @@ impl RunningActionImpl {
         operation_id: OperationId,
         action_directory: String,
         action_info: ActionInfo,
+        cache_policy: CachePolicy,
         timeout: Duration,
         running_actions_manager: Arc<RunningActionsManagerImpl>,
     ) -> Self {
@@ -635,6 +699,7 @@ impl RunningActionImpl {
             action_directory,
             work_directory,
             action_info,
+            cache_policy,
             timeout,
             running_actions_manager,
             state: Mutex::new(RunningActionImplState {
@@ -689,7 +754,9 @@ impl RunningActionImpl {
                     .wrap(download_to_directory(
                         &self.running_actions_manager.cas_store,
                         filesystem_store_pin,
+                        &self.running_actions_manager.file_cloner,
                         &self.action_info.input_root_digest,
+                        self.action_info.unique_qualifier.digest_function(),
                         &self.work_directory,
                     ))
                     .await
@@ -1033,6 +1100,7 @@ impl RunningActionImpl {
             )
         };
         let cas_store = self.running_actions_manager.cas_store.as_ref();
+        let filesystem_store = self.running_actions_manager.filesystem_store.as_ref();
         let hasher = self.action_info.unique_qualifier.digest_function();
         enum OutputType {
             None,
@@ -1076,39 +1144,51 @@ impl RunningActionImpl {
 
                     if metadata.is_file() {
                         return Ok(OutputType::File(
-                            upload_file(cas_store.as_pin(), &full_path, hasher, metadata)
-                                .await
-                                .map(|mut file_info| {
-                                    file_info.name_or_path = NameOrPath::Path(entry);
-                                    file_info
-                                })
-                                .err_tip(|| format!("Uploading file {full_path:?}"))?,
+                            upload_file(
+                                cas_store.as_pin(),
+                                Pin::new(filesystem_store),
+                                &full_path,
+                                hasher,
+                                metadata,
+                            )
+                            .await
+                            .map(|mut file_info| {
+                                file_info.name_or_path = NameOrPath::Path(entry);
+                                file_info
+                            })
+                            .err_tip(|| format!("Uploading file {full_path:?}"))?,
                         ));
                     }
                     metadata
                 };
                 if metadata.is_dir() {
                     Ok(OutputType::Directory(
-                        upload_directory(cas_store.as_pin(), &full_path, work_directory, hasher)
-                            .and_then(|(root_dir, children)| async move {
-                                let tree = ProtoTree {
-                                    root: Some(root_dir),
-                                    children: children.into(),
-                                };
-                                let tree_digest = serialize_and_upload_message(
-                                    &tree,
-                                    cas_store.as_pin(),
-                                    &mut hasher.hasher(),
-                                )
-                                .await
-                                .err_tip(|| format!("While processing {entry}"))?;
-                                Ok(DirectoryInfo {
-                                    path: entry,
-                                    tree_digest,
-                                })
-                            })
+                        upload_directory(
+                            cas_store.as_pin(),
+                            Pin::new(filesystem_store),
+                            &full_path,
+                            work_directory,
+                            hasher,
+                        )
+                        .and_then(|(root_dir, children)| async move {
+                            let tree = ProtoTree {
+                                root: Some(root_dir),
+                                children: children.into(),
+                            };
+                            let tree_digest = serialize_and_upload_message(
+                                &tree,
+                                cas_store.as_pin(),
+                                &mut hasher.hasher(),
+                            )
                             .await
-                            .err_tip(|| format!("Uploading directory {full_path:?}"))?,
+                            .err_tip(|| format!("While processing {entry}"))?;
+                            Ok(DirectoryInfo {
+                                path: entry,
+                                tree_digest,
+                            })
+                        })
+                        .await
+                        .err_tip(|| format!("Uploading directory {full_path:?}"))?,
                     ))
                 } else if metadata.is_symlink() {
                     let output_symlink = upload_symlink(&full_path, work_directory)
@@ -1279,6 +1359,10 @@ impl RunningAction for RunningActionImpl {
         &self.operation_id
     }
 
+    fn get_cache_policy(&self) -> CachePolicy {
+        self.cache_policy
+    }
+
     async fn prepare_action(self: Arc<Self>) -> Result<Arc<Self>, Error> {
         self.metrics()
             .clone()
@@ -1347,6 +1431,7 @@ pub trait RunningActionsManager: Sync + Send + Sized + Unpin + 'static {
         action_digest: DigestInfo,
         action_result: &mut ActionResult,
         hasher: DigestHasherFunc,
+        cache_policy: CachePolicy,
     ) -> impl Future<Output = Result<(), Error>> + Send;
 
     fn kill_all(&self) -> impl Future<Output = ()> + Send;
@@ -1501,6 +1586,7 @@ impl UploadActionResults {
         action_digest: DigestInfo,
         action_result: ProtoActionResult,
         hasher: DigestHasherFunc,
+        priority: i32,
     ) -> Result<(), Error> {
         let Some(ac_store) = self.ac_store.as_ref() else {
             return Ok(());
@@ -1512,7 +1598,7 @@ impl UploadActionResults {
                 instance_name: String::new(),
                 action_digest: Some(action_digest.into()),
                 action_result: Some(action_result),
-                results_cache_policy: None,
+                results_cache_policy: Some(ResultsCachePolicy { priority }),
                 digest_function: hasher.proto_digest_func().into(),
             };
             return grpc_store
@@ -1565,11 +1651,14 @@ impl UploadActionResults {
         action_info: DigestInfo,
         action_result: &mut ActionResult,
         hasher: DigestHasherFunc,
+        cache_policy: CachePolicy,
     ) -> Result<(), Error> {
         let should_upload_historical_results =
             Self::should_cache_result(self.upload_historical_results_strategy, action_result, true);
-        let should_upload_ac_results =
-            Self::should_cache_result(self.upload_ac_results_strategy, action_result, false);
+        // Historical results are still uploaded for `do_not_cache` actions,
+        // since they are only used for debugging and never served as a cache hit.
+        let should_upload_ac_results = !cache_policy.do_not_cache
+            && Self::should_cache_result(self.upload_ac_results_strategy, action_result, false);
         // Shortcut so we don't need to convert to proto if not needed.
         if !should_upload_ac_results && !should_upload_historical_results {
             return Ok(());
@@ -1624,6 +1713,7 @@ impl UploadActionResults {
                     .result
                     .err_tip(|| "No result set in cache_action_result")?,
                 hasher,
+                cache_policy.priority,
             )
             .await
         } else {
@@ -1642,6 +1732,7 @@ pub struct RunningActionsManagerArgs<'a> {
     pub upload_action_result_config: &'a UploadActionResultConfig,
     pub max_action_timeout: Duration,
     pub timeout_handled_externally: bool,
+    pub input_file_materialization: InputFileMaterialization,
 }
 
 /// Holds state info about what is being executed and the interface for interacting
@@ -1651,6 +1742,7 @@ pub struct RunningActionsManagerImpl {
     execution_configuration: ExecutionConfiguration,
     cas_store: Arc<FastSlowStore>,
     filesystem_store: Arc<FilesystemStore>,
+    file_cloner: fs::FileCloner,
     upload_action_results: UploadActionResults,
     max_action_timeout: Duration,
     timeout_handled_externally: bool,
@@ -1677,12 +1769,17 @@ impl RunningActionsManagerImpl {
             })?
             .get_arc()
             .err_tip(|| "FilesystemStore's internal Arc was lost")?;
+        let file_cloner = fs::FileCloner::new(match args.input_file_materialization {
+            InputFileMaterialization::hardlink => fs::FileCloneMethod::HardLink,
+            InputFileMaterialization::reflink_or_copy => fs::FileCloneMethod::Reflink,
+        });
         let (action_done_tx, _) = watch::channel(());
         Ok(Self {
             root_action_directory: args.root_action_directory,
             execution_configuration: args.execution_configuration,
             cas_store: args.cas_store,
             filesystem_store,
+            file_cloner,
             upload_action_results: UploadActionResults::new(
                 args.upload_action_result_config,
                 args.ac_store,
@@ -1725,8 +1822,10 @@ impl RunningActionsManagerImpl {
         &self,
         start_execute: StartExecute,
         queued_timestamp: SystemTime,
-    ) -> impl Future<Output = Result<ActionInfo, Error>> + '_ {
+    ) -> impl Future<Output = Result<(ActionInfo, CachePolicy), Error>> + '_ {
         self.metrics.create_action_info.wrap(async move {
+            let request_metadata = start_execute.request_metadata.map(Into::into);
+            let skip_action_cache_upload = start_execute.skip_action_cache_upload;
             let execute_request = start_execute
                 .execute_request
                 .err_tip(|| "Expected execute_request to exist in StartExecute")?;
@@ -1740,14 +1839,21 @@ impl RunningActionsManagerImpl {
                 get_and_decode_digest::<Action>(self.cas_store.as_ref(), action_digest.into())
                     .await
                     .err_tip(|| "During start_action")?;
-            let action_info = ActionInfo::try_from_action_and_execute_request(
+            let do_not_cache = action.do_not_cache;
+            let mut action_info = ActionInfo::try_from_action_and_execute_request(
                 execute_request,
                 action,
                 load_start_timestamp,
                 queued_timestamp,
             )
             .err_tip(|| "Could not create ActionInfo in create_and_add_action()")?;
-            Ok(action_info)
+            action_info.request_metadata = request_metadata;
+            action_info.skip_action_cache_upload = skip_action_cache_upload;
+            let cache_policy = CachePolicy {
+                do_not_cache: do_not_cache || skip_action_cache_upload,
+                priority: action_info.results_cache_priority,
+            };
+            Ok((action_info, cache_policy))
         })
     }
 
@@ -1802,7 +1908,7 @@ impl RunningActionsManager for RunningActionsManagerImpl {
                     .unwrap_or(SystemTime::UNIX_EPOCH);
                 let operation_id = start_execute
                     .operation_id.as_str().into();
-                let action_info = self.create_action_info(start_execute, queued_timestamp).await?;
+                let (action_info, cache_policy) = self.create_action_info(start_execute, queued_timestamp).await?;
                 event!(
                     Level::INFO,
                     ?action_info,
@@ -1839,6 +1945,7 @@ impl RunningActionsManager for RunningActionsManagerImpl {
                     operation_id.clone(),
                     action_directory,
                     action_info,
+                    cache_policy,
                     timeout,
                     self.clone(),
                 ));
@@ -1856,6 +1963,7 @@ impl RunningActionsManager for RunningActionsManagerImpl {
         action_info: DigestInfo,
         action_result: &mut ActionResult,
         hasher: DigestHasherFunc,
+        cache_policy: CachePolicy,
     ) -> Result<(), Error> {
         self.metrics
             .cache_action_result
@@ -1863,6 +1971,7 @@ impl RunningActionsManager for RunningActionsManagerImpl {
                 action_info,
                 action_result,
                 hasher,
+                cache_policy,
             ))
             .await
     }
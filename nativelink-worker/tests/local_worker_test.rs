@@ -237,6 +237,9 @@ async fn blake3_digest_function_registerd_properly() -> Result<(), Box<dyn std::
             digest_function: DigestHasherFunc::Blake3,
             digest: action_digest,
         }),
+        request_metadata: None,
+        results_cache_priority: 0,
+        skip_action_cache_upload: false,
     };
 
     {
@@ -247,6 +250,8 @@ async fn blake3_digest_function_registerd_properly() -> Result<(), Box<dyn std::
                     execute_request: Some((&action_info).into()),
                     operation_id: String::new(),
                     queued_timestamp: None,
+                    request_metadata: None,
+                    skip_action_cache_upload: false,
                 })),
             })?))
             .await
@@ -319,6 +324,9 @@ async fn simple_worker_start_action_test() -> Result<(), Box<dyn std::error::Err
             digest_function: DigestHasherFunc::Sha256,
             digest: action_digest,
         }),
+        request_metadata: None,
+        results_cache_priority: 0,
+        skip_action_cache_upload: false,
     };
 
     {
@@ -329,6 +337,8 @@ async fn simple_worker_start_action_test() -> Result<(), Box<dyn std::error::Err
                     execute_request: Some((&action_info).into()),
                     operation_id: String::new(),
                     queued_timestamp: None,
+                    request_metadata: None,
+                    skip_action_cache_upload: false,
                 })),
             })?))
             .await
@@ -581,6 +591,9 @@ async fn experimental_precondition_script_fails() -> Result<(), Box<dyn std::err
             digest_function: DigestHasherFunc::Sha256,
             digest: action_digest,
         }),
+        request_metadata: None,
+        results_cache_priority: 0,
+        skip_action_cache_upload: false,
     };
 
     {
@@ -591,6 +604,8 @@ async fn experimental_precondition_script_fails() -> Result<(), Box<dyn std::err
                     execute_request: Some((&action_info).into()),
                     operation_id: String::new(),
                     queued_timestamp: None,
+                    request_metadata: None,
+                    skip_action_cache_upload: false,
                 })),
             })?))
             .await
@@ -666,6 +681,9 @@ async fn kill_action_request_kills_action() -> Result<(), Box<dyn std::error::Er
             digest_function: DigestHasherFunc::Blake3,
             digest: action_digest,
         }),
+        request_metadata: None,
+        results_cache_priority: 0,
+        skip_action_cache_upload: false,
     };
 
     let operation_id = OperationId::default();
@@ -677,6 +695,8 @@ async fn kill_action_request_kills_action() -> Result<(), Box<dyn std::error::Er
                     execute_request: Some((&action_info).into()),
                     operation_id: operation_id.to_string(),
                     queued_timestamp: None,
+                    request_metadata: None,
+                    skip_action_cache_upload: false,
                 })),
             })?))
             .await
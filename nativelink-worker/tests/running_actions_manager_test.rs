@@ -26,7 +26,7 @@ use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use futures::{FutureExt, StreamExt, TryFutureExt, TryStreamExt};
-use nativelink_config::cas_server::EnvironmentSource;
+use nativelink_config::cas_server::{EnvironmentSource, InputFileMaterialization};
 use nativelink_error::{make_input_err, Code, Error, ResultExt};
 use nativelink_macro::nativelink_test;
 #[cfg_attr(target_family = "windows", allow(unused_imports))]
@@ -51,8 +51,8 @@ use nativelink_util::common::{fs, DigestInfo};
 use nativelink_util::digest_hasher::{DigestHasher, DigestHasherFunc};
 use nativelink_util::store_trait::{Store, StoreLike};
 use nativelink_worker::running_actions_manager::{
-    download_to_directory, Callbacks, ExecutionConfiguration, RunningAction, RunningActionImpl,
-    RunningActionsManager, RunningActionsManagerArgs, RunningActionsManagerImpl,
+    download_to_directory, CachePolicy, Callbacks, ExecutionConfiguration, RunningAction,
+    RunningActionImpl, RunningActionsManager, RunningActionsManagerArgs, RunningActionsManagerImpl,
 };
 use once_cell::sync::Lazy;
 use pretty_assertions::assert_eq;
@@ -210,7 +210,9 @@ async fn download_to_directory_file_download_test() -> Result<(), Box<dyn std::e
         download_to_directory(
             cas_store.as_ref(),
             fast_store.as_pin(),
+            &fs::FileCloner::new(fs::FileCloneMethod::HardLink),
             &root_directory_digest,
+            DigestHasherFunc::Sha256,
             &download_dir,
         )
         .await?;
@@ -314,7 +316,9 @@ async fn download_to_directory_folder_download_test() -> Result<(), Box<dyn std:
         download_to_directory(
             cas_store.as_ref(),
             fast_store.as_pin(),
+            &fs::FileCloner::new(fs::FileCloneMethod::HardLink),
             &root_directory_digest,
+            DigestHasherFunc::Sha256,
             &download_dir,
         )
         .await?;
@@ -387,7 +391,9 @@ async fn download_to_directory_symlink_download_test() -> Result<(), Box<dyn std
         download_to_directory(
             cas_store.as_ref(),
             fast_store.as_pin(),
+            &fs::FileCloner::new(fs::FileCloneMethod::HardLink),
             &root_directory_digest,
+            DigestHasherFunc::Sha256,
             &download_dir,
         )
         .await?;
@@ -437,6 +443,7 @@ async fn ensure_output_files_full_directories_are_created_no_working_directory_t
             },
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
         },
         Callbacks {
             now_fn: test_monotonic_clock,
@@ -500,6 +507,8 @@ async fn ensure_output_files_full_directories_are_created_no_working_directory_t
                     execute_request: Some(execute_request),
                     operation_id,
                     queued_timestamp: None,
+                    request_metadata: None,
+                    skip_action_cache_upload: false,
                 },
             )
             .await?;
@@ -552,6 +561,7 @@ async fn ensure_output_files_full_directories_are_created_test(
             },
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
         },
         Callbacks {
             now_fn: test_monotonic_clock,
@@ -617,6 +627,8 @@ async fn ensure_output_files_full_directories_are_created_test(
                     execute_request: Some(execute_request),
                     operation_id,
                     queued_timestamp: None,
+                    request_metadata: None,
+                    skip_action_cache_upload: false,
                 },
             )
             .await?;
@@ -669,6 +681,7 @@ async fn blake3_upload_files() -> Result<(), Box<dyn std::error::Error>> {
             },
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
         },
         Callbacks {
             now_fn: test_monotonic_clock,
@@ -750,6 +763,8 @@ async fn blake3_upload_files() -> Result<(), Box<dyn std::error::Error>> {
                     execute_request: Some(execute_request),
                     operation_id,
                     queued_timestamp: None,
+                    request_metadata: None,
+                    skip_action_cache_upload: false,
                 },
             )
             .await?;
@@ -842,6 +857,7 @@ async fn upload_files_from_above_cwd_test() -> Result<(), Box<dyn std::error::Er
             },
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
         },
         Callbacks {
             now_fn: test_monotonic_clock,
@@ -922,6 +938,8 @@ async fn upload_files_from_above_cwd_test() -> Result<(), Box<dyn std::error::Er
                     execute_request: Some(execute_request),
                     operation_id,
                     queued_timestamp: None,
+                    request_metadata: None,
+                    skip_action_cache_upload: false,
                 },
             )
             .await?;
@@ -1017,6 +1035,7 @@ async fn upload_dir_and_symlink_test() -> Result<(), Box<dyn std::error::Error>>
             },
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
         },
         Callbacks {
             now_fn: test_monotonic_clock,
@@ -1079,6 +1098,8 @@ async fn upload_dir_and_symlink_test() -> Result<(), Box<dyn std::error::Error>>
                     execute_request: Some(execute_request),
                     operation_id,
                     queued_timestamp: Some(queued_timestamp.into()),
+                    request_metadata: None,
+                    skip_action_cache_upload: false,
                 },
             )
             .await?;
@@ -1217,6 +1238,7 @@ async fn cleanup_happens_on_job_failure() -> Result<(), Box<dyn std::error::Erro
             },
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
         },
         Callbacks {
             now_fn: test_monotonic_clock,
@@ -1274,6 +1296,8 @@ async fn cleanup_happens_on_job_failure() -> Result<(), Box<dyn std::error::Erro
                     execute_request: Some(execute_request),
                     operation_id,
                     queued_timestamp: Some(queued_timestamp.into()),
+                    request_metadata: None,
+                    skip_action_cache_upload: false,
                 },
             )
             .await?;
@@ -1345,6 +1369,7 @@ async fn kill_ends_action() -> Result<(), Box<dyn std::error::Error>> {
             },
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
         })?);
 
     #[cfg(target_family = "unix")]
@@ -1406,6 +1431,8 @@ async fn kill_ends_action() -> Result<(), Box<dyn std::error::Error>> {
                 execute_request: Some(execute_request),
                 operation_id,
                 queued_timestamp: Some(make_system_time(1000).into()),
+                request_metadata: None,
+                skip_action_cache_upload: false,
             },
         )
         .await?;
@@ -1505,6 +1532,7 @@ exit 0
             },
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
         })?);
     #[cfg(target_family = "unix")]
     let arguments = vec!["printf".to_string(), EXPECTED_STDOUT.to_string()];
@@ -1553,6 +1581,8 @@ exit 0
                 execute_request: Some(execute_request),
                 operation_id,
                 queued_timestamp: Some(make_system_time(1000).into()),
+                request_metadata: None,
+                skip_action_cache_upload: false,
             },
         )
         .await?;
@@ -1662,6 +1692,7 @@ exit 0
             },
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
         })?);
     #[cfg(target_family = "unix")]
     let arguments = vec!["printf".to_string(), EXPECTED_STDOUT.to_string()];
@@ -1721,6 +1752,8 @@ exit 0
                 execute_request: Some(execute_request),
                 operation_id,
                 queued_timestamp: Some(make_system_time(1000).into()),
+                request_metadata: None,
+                skip_action_cache_upload: false,
             },
         )
         .await?;
@@ -1817,6 +1850,7 @@ exit 1
             },
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
         })?);
     let arguments = vec!["true".to_string()];
     let command = Command {
@@ -1862,6 +1896,8 @@ exit 1
                 execute_request: Some(execute_request),
                 operation_id,
                 queued_timestamp: Some(make_system_time(1000).into()),
+                request_metadata: None,
+                skip_action_cache_upload: false,
             },
         )
         .await?;
@@ -1893,6 +1929,7 @@ async fn caches_results_in_action_cache_store() -> Result<(), Box<dyn std::error
             },
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
         })?);
 
     let action_digest = DigestInfo::new([2u8; 32], 32);
@@ -1934,7 +1971,12 @@ async fn caches_results_in_action_cache_store() -> Result<(), Box<dyn std::error
         message: String::new(),
     };
     running_actions_manager
-        .cache_action_result(action_digest, &mut action_result, DigestHasherFunc::Sha256)
+        .cache_action_result(
+            action_digest,
+            &mut action_result,
+            DigestHasherFunc::Sha256,
+            CachePolicy::default(),
+        )
         .await?;
 
     let retrieved_result =
@@ -1946,6 +1988,301 @@ async fn caches_results_in_action_cache_store() -> Result<(), Box<dyn std::error
     Ok(())
 }
 
+#[nativelink_test]
+async fn do_not_cache_action_is_not_cached_in_action_cache(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (_, _, cas_store, ac_store) = setup_stores().await?;
+
+    let running_actions_manager =
+        Arc::new(RunningActionsManagerImpl::new(RunningActionsManagerArgs {
+            root_action_directory: String::new(),
+            execution_configuration: ExecutionConfiguration::default(),
+            cas_store: cas_store.clone(),
+            ac_store: Some(Store::new(ac_store.clone())),
+            historical_store: Store::new(cas_store.clone()),
+            upload_action_result_config: &nativelink_config::cas_server::UploadActionResultConfig {
+                upload_ac_results_strategy:
+                    nativelink_config::cas_server::UploadCacheResultsStrategy::everything,
+                ..Default::default()
+            },
+            max_action_timeout: Duration::MAX,
+            timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
+        })?);
+
+    let action_digest = DigestInfo::new([2u8; 32], 32);
+    let mut action_result = ActionResult {
+        output_files: vec![],
+        stdout_digest: DigestInfo::try_new(
+            "426afaf613d8cfdd9fa8addcc030ae6c95a7950ae0301164af1d5851012081d5",
+            10,
+        )?,
+        stderr_digest: DigestInfo::try_new(
+            "7b2e400d08b8e334e3172d105be308b506c6036c62a9bde5c509d7808b28b213",
+            10,
+        )?,
+        exit_code: 0,
+        output_folders: vec![],
+        output_file_symlinks: vec![],
+        output_directory_symlinks: vec![],
+        server_logs: HashMap::new(),
+        execution_metadata: ExecutionMetadata {
+            worker: "WORKER_ID".to_string(),
+            queued_timestamp: SystemTime::UNIX_EPOCH,
+            worker_start_timestamp: make_system_time(0),
+            input_fetch_start_timestamp: make_system_time(1),
+            input_fetch_completed_timestamp: make_system_time(2),
+            execution_start_timestamp: make_system_time(3),
+            execution_completed_timestamp: make_system_time(4),
+            output_upload_start_timestamp: make_system_time(5),
+            output_upload_completed_timestamp: make_system_time(6),
+            worker_completed_timestamp: make_system_time(7),
+        },
+        error: None,
+        message: String::new(),
+    };
+    running_actions_manager
+        .cache_action_result(
+            action_digest,
+            &mut action_result,
+            DigestHasherFunc::Sha256,
+            CachePolicy {
+                do_not_cache: true,
+                priority: 0,
+            },
+        )
+        .await?;
+
+    let retrieved_result =
+        get_and_decode_digest::<ProtoActionResult>(ac_store.as_ref(), action_digest.into()).await;
+    assert_eq!(
+        retrieved_result.map_err(|e| e.code),
+        Err(Code::NotFound),
+        "Expected do_not_cache action to not be in the action cache"
+    );
+
+    Ok(())
+}
+
+#[nativelink_test]
+async fn skip_action_cache_upload_test() -> Result<(), Box<dyn std::error::Error>> {
+    const WORKER_ID: &str = "foo_worker_id";
+
+    let (_, _, cas_store, ac_store) = setup_stores().await?;
+    let root_action_directory = make_temp_path("root_action_directory");
+    fs::create_dir_all(&root_action_directory).await?;
+
+    let running_actions_manager =
+        Arc::new(RunningActionsManagerImpl::new(RunningActionsManagerArgs {
+            root_action_directory,
+            execution_configuration: ExecutionConfiguration::default(),
+            cas_store: cas_store.clone(),
+            ac_store: Some(Store::new(ac_store.clone())),
+            historical_store: Store::new(cas_store.clone()),
+            upload_action_result_config: &nativelink_config::cas_server::UploadActionResultConfig {
+                upload_ac_results_strategy:
+                    nativelink_config::cas_server::UploadCacheResultsStrategy::everything,
+                ..Default::default()
+            },
+            max_action_timeout: Duration::MAX,
+            timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
+        })?);
+
+    let command_digest = serialize_and_upload_message(
+        &Command {
+            arguments: vec!["true".to_string()],
+            ..Default::default()
+        },
+        cas_store.as_pin(),
+        &mut DigestHasherFunc::Sha256.hasher(),
+    )
+    .await?;
+    let input_root_digest = serialize_and_upload_message(
+        &Directory::default(),
+        cas_store.as_pin(),
+        &mut DigestHasherFunc::Sha256.hasher(),
+    )
+    .await?;
+    let action_digest = serialize_and_upload_message(
+        &Action {
+            command_digest: Some(command_digest.into()),
+            input_root_digest: Some(input_root_digest.into()),
+            ..Default::default()
+        },
+        cas_store.as_pin(),
+        &mut DigestHasherFunc::Sha256.hasher(),
+    )
+    .await?;
+
+    let running_action = running_actions_manager
+        .create_and_add_action(
+            WORKER_ID.to_string(),
+            StartExecute {
+                execute_request: Some(ExecuteRequest {
+                    action_digest: Some(action_digest.into()),
+                    ..Default::default()
+                }),
+                operation_id: OperationId::default().to_string(),
+                queued_timestamp: None,
+                request_metadata: None,
+                skip_action_cache_upload: true,
+            },
+        )
+        .await?;
+
+    // The action itself may be cached, but the scheduler asked for its
+    // result not to be uploaded.
+    assert_eq!(
+        running_action.get_cache_policy(),
+        CachePolicy {
+            do_not_cache: true,
+            priority: 0,
+        }
+    );
+
+    Ok(())
+}
+
+// Windows has no stable file identity, so nothing is cached there.
+#[cfg(target_family = "unix")]
+#[cfg_attr(feature = "nix", ignore)]
+#[nativelink_test]
+async fn unchanged_input_file_output_is_not_hashed_or_uploaded_test(
+) -> Result<(), Box<dyn std::error::Error>> {
+    const WORKER_ID: &str = "foo_worker_id";
+    const FILE_NAME: &str = "file1.txt";
+    const FILE_CONTENT: &str = "HELLOFILE1";
+    const REPLACED_CONTENT: &str = "REPLACED_1";
+
+    let fast_config = nativelink_config::stores::FilesystemStore {
+        content_path: make_temp_path("content_path"),
+        temp_path: make_temp_path("temp_path"),
+        hash_cache_max_entries: 10,
+        ..Default::default()
+    };
+    let slow_config = nativelink_config::stores::MemoryStore::default();
+    let fast_store = FilesystemStore::new(&fast_config).await?;
+    let slow_store = MemoryStore::new(&slow_config);
+    let cas_store = FastSlowStore::new(
+        &nativelink_config::stores::FastSlowStore {
+            fast: nativelink_config::stores::StoreConfig::filesystem(fast_config),
+            slow: nativelink_config::stores::StoreConfig::memory(slow_config),
+        },
+        Store::new(fast_store),
+        Store::new(slow_store.clone()),
+    );
+    let root_action_directory = make_temp_path("root_action_directory");
+    fs::create_dir_all(&root_action_directory).await?;
+
+    let running_actions_manager =
+        Arc::new(RunningActionsManagerImpl::new(RunningActionsManagerArgs {
+            root_action_directory,
+            execution_configuration: ExecutionConfiguration::default(),
+            cas_store: cas_store.clone(),
+            ac_store: None,
+            historical_store: Store::new(cas_store.clone()),
+            upload_action_result_config: &nativelink_config::cas_server::UploadActionResultConfig {
+                upload_ac_results_strategy:
+                    nativelink_config::cas_server::UploadCacheResultsStrategy::never,
+                ..Default::default()
+            },
+            max_action_timeout: Duration::MAX,
+            timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
+        })?);
+
+    // Not the real digest of the content, so hashing the output would be
+    // noticed.
+    let file_digest = DigestInfo::new([2u8; 32], FILE_CONTENT.len() as u64);
+    slow_store
+        .as_ref()
+        .update_oneshot(file_digest, FILE_CONTENT.into())
+        .await?;
+    let command_digest = serialize_and_upload_message(
+        &Command {
+            arguments: vec!["true".to_string()],
+            output_paths: vec![FILE_NAME.to_string()],
+            ..Default::default()
+        },
+        cas_store.as_pin(),
+        &mut DigestHasherFunc::Sha256.hasher(),
+    )
+    .await?;
+    let input_root_digest = serialize_and_upload_message(
+        &Directory {
+            files: vec![FileNode {
+                name: FILE_NAME.to_string(),
+                digest: Some(file_digest.into()),
+                is_executable: false,
+                node_properties: None,
+            }],
+            ..Default::default()
+        },
+        cas_store.as_pin(),
+        &mut DigestHasherFunc::Sha256.hasher(),
+    )
+    .await?;
+    let action_digest = serialize_and_upload_message(
+        &Action {
+            command_digest: Some(command_digest.into()),
+            input_root_digest: Some(input_root_digest.into()),
+            ..Default::default()
+        },
+        cas_store.as_pin(),
+        &mut DigestHasherFunc::Sha256.hasher(),
+    )
+    .await?;
+
+    let running_action_impl = running_actions_manager
+        .create_and_add_action(
+            WORKER_ID.to_string(),
+            StartExecute {
+                execute_request: Some(ExecuteRequest {
+                    action_digest: Some(action_digest.into()),
+                    digest_function: ProtoDigestFunction::Sha256.into(),
+                    ..Default::default()
+                }),
+                operation_id: OperationId::default().to_string(),
+                queued_timestamp: None,
+                request_metadata: None,
+                skip_action_cache_upload: false,
+            },
+        )
+        .await?;
+    let running_action = running_action_impl
+        .clone()
+        .prepare_action()
+        .and_then(RunningAction::execute)
+        .await?;
+    // Uploading the output would overwrite this.
+    slow_store
+        .as_ref()
+        .update_oneshot(file_digest, REPLACED_CONTENT.into())
+        .await?;
+    let action_result = running_action
+        .upload_results()
+        .and_then(RunningAction::get_finished_result)
+        .await;
+    running_action_impl.cleanup().await?;
+    let action_result = action_result?;
+
+    assert_eq!(
+        action_result.output_files[0].digest, file_digest,
+        "Expected the recorded digest to be used instead of hashing the file"
+    );
+    let file_content = slow_store
+        .as_ref()
+        .get_part_unchunked(file_digest, 0, None)
+        .await?;
+    assert_eq!(
+        from_utf8(&file_content)?,
+        REPLACED_CONTENT,
+        "Expected the file to not be uploaded again"
+    );
+    Ok(())
+}
+
 #[nativelink_test]
 async fn failed_action_does_not_cache_in_action_cache() -> Result<(), Box<dyn std::error::Error>> {
     let (_, _, cas_store, ac_store) = setup_stores().await?;
@@ -1964,6 +2301,7 @@ async fn failed_action_does_not_cache_in_action_cache() -> Result<(), Box<dyn st
             },
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
         })?);
 
     let action_digest = DigestInfo::new([2u8; 32], 32);
@@ -2005,7 +2343,12 @@ async fn failed_action_does_not_cache_in_action_cache() -> Result<(), Box<dyn st
         message: String::new(),
     };
     running_actions_manager
-        .cache_action_result(action_digest, &mut action_result, DigestHasherFunc::Sha256)
+        .cache_action_result(
+            action_digest,
+            &mut action_result,
+            DigestHasherFunc::Sha256,
+            CachePolicy::default(),
+        )
         .await?;
 
     let retrieved_result =
@@ -2038,6 +2381,7 @@ async fn success_does_cache_in_historical_results() -> Result<(), Box<dyn std::e
             },
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
         })?);
 
     let action_digest = DigestInfo::new([2u8; 32], 32);
@@ -2079,7 +2423,12 @@ async fn success_does_cache_in_historical_results() -> Result<(), Box<dyn std::e
         message: String::new(),
     };
     running_actions_manager
-        .cache_action_result(action_digest, &mut action_result, DigestHasherFunc::Sha256)
+        .cache_action_result(
+            action_digest,
+            &mut action_result,
+            DigestHasherFunc::Sha256,
+            CachePolicy::default(),
+        )
         .await?;
 
     assert!(!action_result.message.is_empty(), "Message should be set");
@@ -2137,6 +2486,7 @@ async fn failure_does_not_cache_in_historical_results() -> Result<(), Box<dyn st
             },
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
         })?);
 
     let action_digest = DigestInfo::new([2u8; 32], 32);
@@ -2145,7 +2495,12 @@ async fn failure_does_not_cache_in_historical_results() -> Result<(), Box<dyn st
         ..Default::default()
     };
     running_actions_manager
-        .cache_action_result(action_digest, &mut action_result, DigestHasherFunc::Sha256)
+        .cache_action_result(
+            action_digest,
+            &mut action_result,
+            DigestHasherFunc::Sha256,
+            CachePolicy::default(),
+        )
         .await?;
 
     assert!(
@@ -2177,6 +2532,7 @@ async fn infra_failure_does_cache_in_historical_results() -> Result<(), Box<dyn
             },
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
         })?);
 
     let action_digest = DigestInfo::new([2u8; 32], 32);
@@ -2186,7 +2542,12 @@ async fn infra_failure_does_cache_in_historical_results() -> Result<(), Box<dyn
         ..Default::default()
     };
     running_actions_manager
-        .cache_action_result(action_digest, &mut action_result, DigestHasherFunc::Sha256)
+        .cache_action_result(
+            action_digest,
+            &mut action_result,
+            DigestHasherFunc::Sha256,
+            CachePolicy::default(),
+        )
         .await?;
 
     assert!(!action_result.message.is_empty(), "Message should be set");
@@ -2242,6 +2603,7 @@ async fn action_result_has_used_in_message() -> Result<(), Box<dyn std::error::E
             },
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
         })?);
 
     let action_digest = DigestInfo::new([2u8; 32], 32);
@@ -2250,7 +2612,12 @@ async fn action_result_has_used_in_message() -> Result<(), Box<dyn std::error::E
         ..Default::default()
     };
     running_actions_manager
-        .cache_action_result(action_digest, &mut action_result, DigestHasherFunc::Sha256)
+        .cache_action_result(
+            action_digest,
+            &mut action_result,
+            DigestHasherFunc::Sha256,
+            CachePolicy::default(),
+        )
         .await?;
 
     assert!(!action_result.message.is_empty(), "Message should be set");
@@ -2354,6 +2721,7 @@ async fn ensure_worker_timeout_chooses_correct_values() -> Result<(), Box<dyn st
                     },
                 max_action_timeout: MAX_TIMEOUT_DURATION,
                 timeout_handled_externally: false,
+                input_file_materialization: InputFileMaterialization::hardlink,
             },
             Callbacks {
                 now_fn: test_monotonic_clock,
@@ -2377,6 +2745,8 @@ async fn ensure_worker_timeout_chooses_correct_values() -> Result<(), Box<dyn st
                     execute_request: Some(execute_request),
                     operation_id,
                     queued_timestamp: Some(make_system_time(1000).into()),
+                    request_metadata: None,
+                    skip_action_cache_upload: false,
                 },
             )
             .and_then(|action| {
@@ -2434,6 +2804,7 @@ async fn ensure_worker_timeout_chooses_correct_values() -> Result<(), Box<dyn st
                     },
                 max_action_timeout: MAX_TIMEOUT_DURATION,
                 timeout_handled_externally: false,
+                input_file_materialization: InputFileMaterialization::hardlink,
             },
             Callbacks {
                 now_fn: test_monotonic_clock,
@@ -2457,6 +2828,8 @@ async fn ensure_worker_timeout_chooses_correct_values() -> Result<(), Box<dyn st
                     execute_request: Some(execute_request),
                     operation_id,
                     queued_timestamp: Some(make_system_time(1000).into()),
+                    request_metadata: None,
+                    skip_action_cache_upload: false,
                 },
             )
             .and_then(|action| {
@@ -2514,6 +2887,7 @@ async fn ensure_worker_timeout_chooses_correct_values() -> Result<(), Box<dyn st
                     },
                 max_action_timeout: MAX_TIMEOUT_DURATION,
                 timeout_handled_externally: false,
+                input_file_materialization: InputFileMaterialization::hardlink,
             },
             Callbacks {
                 now_fn: test_monotonic_clock,
@@ -2537,6 +2911,8 @@ async fn ensure_worker_timeout_chooses_correct_values() -> Result<(), Box<dyn st
                     execute_request: Some(execute_request),
                     operation_id,
                     queued_timestamp: Some(make_system_time(1000).into()),
+                    request_metadata: None,
+                    skip_action_cache_upload: false,
                 },
             )
             .and_then(|action| {
@@ -2590,6 +2966,7 @@ async fn worker_times_out() -> Result<(), Box<dyn std::error::Error>> {
             },
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
         },
         Callbacks {
             now_fn: test_monotonic_clock,
@@ -2660,6 +3037,8 @@ async fn worker_times_out() -> Result<(), Box<dyn std::error::Error>> {
                 execute_request: Some(execute_request),
                 operation_id,
                 queued_timestamp: Some(make_system_time(1000).into()),
+                request_metadata: None,
+                skip_action_cache_upload: false,
             },
         )
         .and_then(|action| {
@@ -2715,6 +3094,7 @@ async fn kill_all_waits_for_all_tasks_to_finish() -> Result<(), Box<dyn std::err
             },
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
         },
         Callbacks {
             now_fn: test_monotonic_clock,
@@ -2782,6 +3162,8 @@ async fn kill_all_waits_for_all_tasks_to_finish() -> Result<(), Box<dyn std::err
                 execute_request: Some(execute_request),
                 operation_id,
                 queued_timestamp: Some(make_system_time(1000).into()),
+                request_metadata: None,
+                skip_action_cache_upload: false,
             },
         )
         .await?;
@@ -2877,6 +3259,7 @@ async fn unix_executable_file_test() -> Result<(), Box<dyn std::error::Error>> {
             },
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
         },
         Callbacks {
             now_fn: test_monotonic_clock,
@@ -2932,6 +3315,8 @@ async fn unix_executable_file_test() -> Result<(), Box<dyn std::error::Error>> {
                 StartExecute {
                     execute_request: Some(execute_request),
                     operation_id,
+                    request_metadata: None,
+                    skip_action_cache_upload: false,
                     ..Default::default()
                 },
             )
@@ -2971,6 +3356,7 @@ async fn action_directory_contents_are_cleaned() -> Result<(), Box<dyn std::erro
             },
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
         })?);
     let queued_timestamp = make_system_time(1000);
 
@@ -3022,6 +3408,8 @@ async fn action_directory_contents_are_cleaned() -> Result<(), Box<dyn std::erro
                 execute_request: Some(execute_request),
                 operation_id,
                 queued_timestamp: Some(queued_timestamp.into()),
+                request_metadata: None,
+                skip_action_cache_upload: false,
             },
         )
         .await?;
@@ -3076,6 +3464,7 @@ async fn upload_with_single_permit() -> Result<(), Box<dyn std::error::Error>> {
             },
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
         },
         Callbacks {
             now_fn: test_monotonic_clock,
@@ -3156,6 +3545,8 @@ async fn upload_with_single_permit() -> Result<(), Box<dyn std::error::Error>> {
                     execute_request: Some(execute_request),
                     operation_id,
                     queued_timestamp: None,
+                    request_metadata: None,
+                    skip_action_cache_upload: false,
                 },
             )
             .await?;
@@ -3258,6 +3649,7 @@ async fn running_actions_manager_respects_action_timeout() -> Result<(), Box<dyn
             },
             max_action_timeout: Duration::MAX,
             timeout_handled_externally: false,
+            input_file_materialization: InputFileMaterialization::hardlink,
         },
         Callbacks {
             now_fn: test_monotonic_clock,
@@ -3331,6 +3723,8 @@ async fn running_actions_manager_respects_action_timeout() -> Result<(), Box<dyn
                 execute_request: Some(execute_request),
                 operation_id,
                 queued_timestamp: Some(make_system_time(1000).into()),
+                request_metadata: None,
+                skip_action_cache_upload: false,
             },
         )
         .await?;
@@ -20,7 +20,9 @@ use nativelink_proto::com::github::trace_machina::nativelink::remote_execution::
 use nativelink_util::action_messages::{ActionResult, OperationId};
 use nativelink_util::common::DigestInfo;
 use nativelink_util::digest_hasher::DigestHasherFunc;
-use nativelink_worker::running_actions_manager::{Metrics, RunningAction, RunningActionsManager};
+use nativelink_worker::running_actions_manager::{
+    CachePolicy, Metrics, RunningAction, RunningActionsManager,
+};
 use tokio::sync::mpsc;
 
 #[derive(Debug)]
@@ -152,6 +154,7 @@ impl RunningActionsManager for MockRunningActionsManager {
         action_digest: DigestInfo,
         action_result: &mut ActionResult,
         digest_function: DigestHasherFunc,
+        _cache_policy: CachePolicy,
     ) -> Result<(), Error> {
         self.tx_call
             .send(RunningActionManagerCalls::CacheActionResult(Box::new((
@@ -346,6 +349,10 @@ impl RunningAction for MockRunningAction {
         unreachable!("not implemented for tests");
     }
 
+    fn get_cache_policy(&self) -> CachePolicy {
+        CachePolicy::default()
+    }
+
     async fn prepare_action(self: Arc<Self>) -> Result<Arc<Self>, Error> {
         self.tx_call
             .send(RunningActionCalls::PrepareAction)
@@ -0,0 +1,163 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use clap::Parser;
+use mimalloc::MiMalloc;
+use nativelink_config::cas_server::CasConfig;
+use nativelink_config::stores::{ConfigDigestHashFunction, StoreConfig};
+use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
+use nativelink_store::filesystem_fsck::{
+    fsck_filesystem_store, FsckOptions, DEFAULT_MAX_CONCURRENT_CHECKS,
+};
+use nativelink_util::common::fs::set_open_file_limit;
+use nativelink_util::digest_hasher::DigestHasherFunc;
+use nativelink_util::init_tracing;
+use nativelink_util::origin_context::OriginContext;
+use tracing::{event, trace_span, Level};
+
+#[global_allocator]
+static GLOBAL: MiMalloc = MiMalloc;
+
+/// Note: This should match the default used by the main `nativelink` binary.
+const DEFAULT_MAX_OPEN_FILES: usize = 512;
+
+/// Checks the files of a filesystem store in a NativeLink config against
+/// their digests. The store must not be in use while this runs.
+#[derive(Parser, Debug)]
+#[clap(
+    author = "Trace Machina, Inc. <nativelink@tracemachina.com>",
+    version,
+    about,
+    long_about = None
+)]
+struct Args {
+    /// Config file to load the store from.
+    #[clap(value_parser)]
+    config_file: String,
+
+    /// Name of the filesystem store in the config to check.
+    #[clap(long)]
+    store: String,
+
+    /// Directory to move bad and orphaned files into. If not set,
+    /// problems are only reported.
+    #[clap(long)]
+    quarantine_path: Option<PathBuf>,
+
+    /// Only check file sizes. Use this for stores used as an action cache.
+    #[clap(long)]
+    skip_hash: bool,
+
+    /// Maximum number of files to check concurrently.
+    #[clap(long, default_value_t = DEFAULT_MAX_CONCURRENT_CHECKS)]
+    concurrency: usize,
+}
+
+async fn inner_main(
+    store_config: nativelink_config::stores::FilesystemStore,
+    args: Args,
+    hasher: DigestHasherFunc,
+) -> Result<(), Error> {
+    let report = fsck_filesystem_store(
+        &store_config,
+        &FsckOptions {
+            hasher,
+            verify_size: true,
+            verify_hash: !args.skip_hash,
+            quarantine_path: args.quarantine_path,
+            max_concurrent_checks: args.concurrency,
+        },
+    )
+    .await
+    .err_tip(|| format!("Failed to check store '{}'", args.store))?;
+    for problem in &report.problems {
+        event!(
+            Level::WARN,
+            path = ?problem.path,
+            digest = ?problem.digest,
+            kind = ?problem.kind,
+            "Found bad file",
+        );
+    }
+    event!(
+        Level::WARN,
+        files_checked = report.files_checked,
+        problems = report.problems.len(),
+        files_quarantined = report.files_quarantined,
+        data_size = report.data_size,
+        accounted_size_on_disk = report.accounted_size_on_disk,
+        actual_size_on_disk = report.actual_size_on_disk,
+        "Filesystem store check finished",
+    );
+    if !report.problems.is_empty() {
+        return Err(make_err!(
+            Code::DataLoss,
+            "{} problems found, see logs for details",
+            report.problems.len()
+        ));
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    init_tracing()?;
+
+    let args = Args::parse();
+    let json_contents = String::from_utf8(
+        std::fs::read(&args.config_file)
+            .err_tip(|| format!("Could not open config file {}", args.config_file))?,
+    )?;
+    let mut cfg: CasConfig = serde_json5::from_str(&json_contents)?;
+
+    let store_config = match cfg.stores.remove(&args.store) {
+        Some(StoreConfig::filesystem(store_config)) => store_config,
+        Some(_) => {
+            return Err(make_input_err!("Store '{}' is not a filesystem store", args.store).into())
+        }
+        None => return Err(make_input_err!("Could not find store '{}'", args.store).into()),
+    };
+
+    let (max_open_files, digest_hash_function) =
+        cfg.global
+            .as_ref()
+            .map_or((DEFAULT_MAX_OPEN_FILES, None), |global_cfg| {
+                (
+                    if global_cfg.max_open_files == 0 {
+                        DEFAULT_MAX_OPEN_FILES
+                    } else {
+                        global_cfg.max_open_files
+                    },
+                    global_cfg.default_digest_hash_function,
+                )
+            });
+    set_open_file_limit(max_open_files);
+    let hasher =
+        DigestHasherFunc::from(digest_hash_function.unwrap_or(ConfigDigestHashFunction::sha256));
+
+    #[allow(clippy::disallowed_methods)]
+    {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .max_blocking_threads(max_open_files * 10)
+            .enable_all()
+            .build()?;
+        runtime.block_on(
+            Arc::new(OriginContext::new())
+                .wrap_async(trace_span!("fsck"), inner_main(store_config, args, hasher)),
+        )?;
+    }
+    Ok(())
+}
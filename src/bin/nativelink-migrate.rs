@@ -0,0 +1,170 @@
+// Copyright 2024 The NativeLink Authors. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use clap::Parser;
+use mimalloc::MiMalloc;
+use nativelink_config::cas_server::CasConfig;
+use nativelink_config::stores::ConfigDigestHashFunction;
+use nativelink_error::{make_err, make_input_err, Code, Error, ResultExt};
+use nativelink_store::default_store_factory::store_factory;
+use nativelink_store::store_manager::StoreManager;
+use nativelink_store::store_migration::{
+    migrate_store, MigrationOptions, DEFAULT_LIST_BATCH_SIZE, DEFAULT_MAX_CONCURRENT_COPIES,
+};
+use nativelink_util::common::fs::set_open_file_limit;
+use nativelink_util::digest_hasher::{set_default_digest_hasher_func, DigestHasherFunc};
+use nativelink_util::init_tracing;
+use nativelink_util::origin_context::OriginContext;
+use tracing::{event, trace_span, Level};
+
+#[global_allocator]
+static GLOBAL: MiMalloc = MiMalloc;
+
+/// Note: This should match the default used by the main `nativelink` binary.
+const DEFAULT_MAX_OPEN_FILES: usize = 512;
+
+/// Copies every key from one store in a NativeLink config to another.
+///
+/// The source store must support listing its keys.
+#[derive(Parser, Debug)]
+#[clap(
+    author = "Trace Machina, Inc. <nativelink@tracemachina.com>",
+    version,
+    about,
+    long_about = None
+)]
+struct Args {
+    /// Config file to load the stores from.
+    #[clap(value_parser)]
+    config_file: String,
+
+    /// Name of the store in the config to copy data from.
+    #[clap(long)]
+    source: String,
+
+    /// Name of the store in the config to copy data to.
+    #[clap(long)]
+    destination: String,
+
+    /// Maximum number of keys to copy concurrently.
+    #[clap(long, default_value_t = DEFAULT_MAX_CONCURRENT_COPIES)]
+    concurrency: usize,
+
+    /// Number of keys to list from the source store at a time.
+    #[clap(long, default_value_t = DEFAULT_LIST_BATCH_SIZE)]
+    batch_size: usize,
+
+    /// File to record progress in. If the file exists, the migration
+    /// resumes after the last recorded key.
+    #[clap(long)]
+    checkpoint_file: Option<PathBuf>,
+
+    /// List and count the keys, but do not write anything.
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Verify the size and hash of every digest while copying it.
+    #[clap(long)]
+    verify: bool,
+}
+
+async fn inner_main(cfg: CasConfig, args: Args) -> Result<(), Error> {
+    let store_manager = Arc::new(StoreManager::new());
+    // All stores are created so `ref_store` entries can be resolved.
+    for (name, store_cfg) in cfg.stores {
+        let store = store_factory(&store_cfg, &store_manager, None)
+            .await
+            .err_tip(|| format!("Failed to create store '{name}'"))?;
+        store_manager.add_store(&name, store);
+    }
+    let source = store_manager
+        .get_store(&args.source)
+        .ok_or_else(|| make_input_err!("Could not find source store '{}'", args.source))?;
+    let destination = store_manager.get_store(&args.destination).ok_or_else(|| {
+        make_input_err!("Could not find destination store '{}'", args.destination)
+    })?;
+
+    let stats = migrate_store(
+        &source,
+        &destination,
+        &MigrationOptions {
+            max_concurrent_copies: args.concurrency,
+            list_batch_size: args.batch_size,
+            dry_run: args.dry_run,
+            verify: args.verify,
+            checkpoint_path: args.checkpoint_file,
+        },
+    )
+    .await
+    .err_tip(|| {
+        format!(
+            "Failed to migrate '{}' to '{}'",
+            args.source, args.destination
+        )
+    })?;
+    event!(Level::WARN, ?stats, "Store migration finished");
+    if stats.keys_failed != 0 {
+        return Err(make_err!(
+            Code::DataLoss,
+            "{} keys failed to migrate, see logs for details",
+            stats.keys_failed
+        ));
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    init_tracing()?;
+
+    let args = Args::parse();
+    let json_contents = String::from_utf8(
+        std::fs::read(&args.config_file)
+            .err_tip(|| format!("Could not open config file {}", args.config_file))?,
+    )?;
+    let cfg: CasConfig = serde_json5::from_str(&json_contents)?;
+
+    let (max_open_files, digest_hash_function) =
+        cfg.global
+            .as_ref()
+            .map_or((DEFAULT_MAX_OPEN_FILES, None), |global_cfg| {
+                (
+                    if global_cfg.max_open_files == 0 {
+                        DEFAULT_MAX_OPEN_FILES
+                    } else {
+                        global_cfg.max_open_files
+                    },
+                    global_cfg.default_digest_hash_function,
+                )
+            });
+    set_open_file_limit(max_open_files);
+    set_default_digest_hasher_func(DigestHasherFunc::from(
+        digest_hash_function.unwrap_or(ConfigDigestHashFunction::sha256),
+    ))?;
+
+    #[allow(clippy::disallowed_methods)]
+    {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .max_blocking_threads(max_open_files * 10)
+            .enable_all()
+            .build()?;
+        runtime.block_on(
+            Arc::new(OriginContext::new())
+                .wrap_async(trace_span!("migrate"), inner_main(cfg, args)),
+        )?;
+    }
+    Ok(())
+}
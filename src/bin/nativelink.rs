@@ -35,7 +35,9 @@ use nativelink_metric::{
     MetricFieldData, MetricKind, MetricPublishKnownKindData, MetricsComponent, RootMetricsComponent,
 };
 use nativelink_metric_collector::{otel_export, MetricsCollectorLayer};
+use nativelink_scheduler::autoscaling::autoscaling_signals;
 use nativelink_scheduler::default_scheduler_factory::scheduler_factory;
+use nativelink_scheduler::operation_summary::{list_operations, operation_details};
 use nativelink_service::ac_server::AcServer;
 use nativelink_service::bep_server::BepServer;
 use nativelink_service::bytestream_server::ByteStreamServer;
@@ -46,13 +48,16 @@ use nativelink_service::health_server::HealthServer;
 use nativelink_service::worker_api_server::WorkerApiServer;
 use nativelink_store::default_store_factory::store_factory;
 use nativelink_store::store_manager::StoreManager;
-use nativelink_util::action_messages::WorkerId;
+use nativelink_util::action_messages::{OperationId, WorkerId};
 use nativelink_util::common::fs::{set_idle_file_descriptor_timeout, set_open_file_limit};
 use nativelink_util::digest_hasher::{set_default_digest_hasher_func, DigestHasherFunc};
 use nativelink_util::health_utils::HealthRegistryBuilder;
 use nativelink_util::metrics_utils::{set_metrics_enabled_for_this_thread, Counter};
 use nativelink_util::operation_state_manager::ClientStateManager;
 use nativelink_util::origin_context::OriginContext;
+use nativelink_util::request_metadata::{
+    make_ctx_for_request_metadata, request_metadata_from_headers,
+};
 use nativelink_util::store_trait::{
     set_default_digest_size_health_check, DEFAULT_DIGEST_SIZE_HEALTH_CHECK_CFG,
 };
@@ -157,6 +162,45 @@ struct ConnectedClientsMetrics {
 
 impl RootMetricsComponent for ConnectedClientsMetrics {}
 
+/// Turns the output of `serde_json::to_string` into an admin API response.
+fn json_response(
+    json_data: Result<String, serde_json::Error>,
+) -> Result<Response<axum::body::Body>, Error> {
+    let json_data =
+        json_data.map_err(|e| make_err!(Code::Internal, "Could not convert to json {e:?}"))?;
+    let mut response = Response::new(axum::body::Body::from(json_data));
+    response.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        hyper::header::HeaderValue::from_static("application/json"),
+    );
+    Ok(response)
+}
+
+/// Decodes the REv2 `RequestMetadata` the client sent, if any, and runs the
+/// request with it set on the active context. Services and stores further
+/// down can then read it without decoding the header again.
+async fn request_metadata_middleware(
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let Some(request_metadata) = request_metadata_from_headers(request.headers()) else {
+        return next.run(request).await;
+    };
+    let span = error_span!(
+        "request_metadata",
+        tool_invocation_id = %request_metadata.tool_invocation_id,
+        action_mnemonic = %request_metadata.action_mnemonic,
+        target_id = %request_metadata.target_id,
+    );
+    match make_ctx_for_request_metadata(request_metadata) {
+        Ok(ctx) => ctx.wrap_async(span, next.run(request)).await,
+        Err(err) => {
+            event!(Level::WARN, ?err, "Could not attach RequestMetadata");
+            next.run(request).await
+        }
+    }
+}
+
 async fn inner_main(
     cfg: CasConfig,
     server_start_timestamp: u64,
@@ -273,8 +317,9 @@ async fn inner_main(
             .add_optional_service(
                 services
                     .cas
+                    .as_ref()
                     .map_or(Ok(None), |cfg| {
-                        CasServer::new(&cfg, &store_manager).map(|v| {
+                        CasServer::new(cfg, &store_manager).map(|v| {
                             let mut service = v.into_service();
                             let send_algo = &http_config.compression.send_compression_algorithm;
                             if let Some(encoding) =
@@ -358,6 +403,7 @@ async fn inner_main(
                             CapabilitiesServer::new(
                                 services.capabilities.as_ref().unwrap(),
                                 &action_schedulers,
+                                services.cas.as_ref(),
                             )
                         }),
                 )
@@ -442,7 +488,12 @@ async fn inner_main(
         let health_registry = health_registry_builder.lock().await.build();
 
         let mut svc = Router::new()
-            .merge(tonic_services.into_service().into_axum_router())
+            .merge(
+                tonic_services
+                    .into_service()
+                    .into_axum_router()
+                    .layer(axum::middleware::from_fn(request_metadata_middleware)),
+            )
             // This is the default service that executes if no other endpoint matches.
             .fallback((StatusCode::NOT_FOUND, "Not Found"));
 
@@ -595,51 +646,183 @@ async fn inner_main(
                 &admin_config.path
             };
             let worker_schedulers = Arc::new(worker_schedulers.clone());
+            let quarantine_worker_schedulers = worker_schedulers.clone();
+            let autoscaling_worker_schedulers = worker_schedulers.clone();
+            let autoscaling_action_schedulers = Arc::new(action_schedulers.clone());
+            let operations_action_schedulers = autoscaling_action_schedulers.clone();
+            let operation_action_schedulers = autoscaling_action_schedulers.clone();
             svc = svc.nest_service(
                 path,
-                Router::new().route(
-                    "/scheduler/:instance_name/set_drain_worker/:worker_id/:is_draining",
-                    axum::routing::post(
-                        move |params: axum::extract::Path<(String, String, String)>| async move {
-                            let (instance_name, worker_id, is_draining) = params.0;
-                            (async move {
-                                let is_draining = match is_draining.as_str() {
-                                    "0" => false,
-                                    "1" => true,
-                                    _ => {
-                                        return Err(make_err!(
-                                            Code::Internal,
-                                            "{} is neither 0 nor 1",
-                                            is_draining
-                                        ))
-                                    }
-                                };
-                                worker_schedulers
-                                    .get(&instance_name)
-                                    .err_tip(|| {
-                                        format!(
-                                            "Can not get an instance with the name of '{}'",
-                                            &instance_name
+                Router::new()
+                    .route(
+                        "/scheduler/:instance_name/set_drain_worker/:worker_id/:is_draining",
+                        axum::routing::post(
+                            move |params: axum::extract::Path<(String, String, String)>| async move {
+                                let (instance_name, worker_id, is_draining) = params.0;
+                                (async move {
+                                    let is_draining = match is_draining.as_str() {
+                                        "0" => false,
+                                        "1" => true,
+                                        _ => {
+                                            return Err(make_err!(
+                                                Code::Internal,
+                                                "{} is neither 0 nor 1",
+                                                is_draining
+                                            ))
+                                        }
+                                    };
+                                    worker_schedulers
+                                        .get(&instance_name)
+                                        .err_tip(|| {
+                                            format!(
+                                                "Can not get an instance with the name of '{}'",
+                                                &instance_name
+                                            )
+                                        })?
+                                        .clone()
+                                        .set_drain_worker(
+                                            &WorkerId::try_from(worker_id.clone())?,
+                                            is_draining,
                                         )
-                                    })?
-                                    .clone()
-                                    .set_drain_worker(
-                                        &WorkerId::try_from(worker_id.clone())?,
-                                        is_draining,
+                                        .await?;
+                                    Ok::<_, Error>(format!("Draining worker {worker_id}"))
+                                })
+                                .await
+                                .map_err(|e| {
+                                    Err::<String, _>((
+                                        axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                                        format!("Error: {e:?}"),
+                                    ))
+                                })
+                            },
+                        ),
+                    )
+                    .route(
+                        "/scheduler/:instance_name/unquarantine_worker/:worker_id",
+                        axum::routing::post(
+                            move |params: axum::extract::Path<(String, String)>| async move {
+                                let (instance_name, worker_id) = params.0;
+                                (async move {
+                                    quarantine_worker_schedulers
+                                        .get(&instance_name)
+                                        .err_tip(|| {
+                                            format!("No worker scheduler named '{instance_name}'")
+                                        })?
+                                        .unquarantine_worker(&WorkerId::try_from(
+                                            worker_id.clone(),
+                                        )?)
+                                        .await?;
+                                    Ok::<_, Error>(format!("Unquarantined worker {worker_id}"))
+                                })
+                                .await
+                                .map_err(|e| {
+                                    (
+                                        axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                                        format!("Error: {e:?}"),
+                                    )
+                                })
+                            },
+                        ),
+                    )
+                    .route(
+                        "/scheduler/:instance_name/autoscaling",
+                        axum::routing::get(
+                            move |params: axum::extract::Path<String>| async move {
+                                let instance_name = params.0;
+                                (async move {
+                                    let action_scheduler = autoscaling_action_schedulers
+                                        .get(&instance_name)
+                                        .err_tip(|| {
+                                            format!("No action scheduler named '{instance_name}'")
+                                        })?;
+                                    let worker_scheduler = autoscaling_worker_schedulers
+                                        .get(&instance_name)
+                                        .err_tip(|| {
+                                            format!("No worker scheduler named '{instance_name}'")
+                                        })?;
+                                    let signals = autoscaling_signals(
+                                        action_scheduler.as_ref(),
+                                        worker_scheduler.as_ref(),
+                                        SystemTime::now(),
                                     )
                                     .await?;
-                                Ok::<_, Error>(format!("Draining worker {worker_id}"))
-                            })
-                            .await
-                            .map_err(|e| {
-                                Err::<String, _>((
-                                    axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-                                    format!("Error: {e:?}"),
-                                ))
-                            })
-                        },
+                                    json_response(serde_json::to_string(&signals))
+                                })
+                                .await
+                                .map_err(|e| {
+                                    (
+                                        axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                                        format!("Error: {e:?}"),
+                                    )
+                                })
+                            },
+                        ),
+                    )
+                    .route(
+                        "/scheduler/:instance_name/operations",
+                        axum::routing::get(
+                            move |params: axum::extract::Path<String>| async move {
+                                let instance_name = params.0;
+                                (async move {
+                                    let action_scheduler = operations_action_schedulers
+                                        .get(&instance_name)
+                                        .err_tip(|| {
+                                            format!("No action scheduler named '{instance_name}'")
+                                        })?;
+                                    let operations = list_operations(
+                                        action_scheduler.as_ref(),
+                                        SystemTime::now(),
+                                    )
+                                    .await?;
+                                    json_response(serde_json::to_string(&operations))
+                                })
+                                .await
+                                .map_err(|e| {
+                                    (
+                                        axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                                        format!("Error: {e:?}"),
+                                    )
+                                })
+                            },
+                        ),
+                    )
+                    .route(
+                        "/scheduler/:instance_name/operations/:operation_id",
+                        axum::routing::get(
+                            move |params: axum::extract::Path<(String, String)>| async move {
+                                let (instance_name, operation_id) = params.0;
+                                (async move {
+                                    let action_scheduler = operation_action_schedulers
+                                        .get(&instance_name)
+                                        .err_tip(|| {
+                                            format!("No action scheduler named '{instance_name}'")
+                                        })?;
+                                    let details = operation_details(
+                                        action_scheduler.as_ref(),
+                                        OperationId::from(operation_id.as_str()),
+                                        SystemTime::now(),
+                                    )
+                                    .await?
+                                    .ok_or_else(|| {
+                                        make_err!(
+                                            Code::NotFound,
+                                            "No operation with id '{operation_id}'"
+                                        )
+                                    })?;
+                                    json_response(serde_json::to_string(&details))
+                                })
+                                .await
+                                .map_err(|e| {
+                                    let status_code = if e.code == Code::NotFound {
+                                        axum::http::StatusCode::NOT_FOUND
+                                    } else {
+                                        axum::http::StatusCode::INTERNAL_SERVER_ERROR
+                                    };
+                                    (status_code, format!("Error: {e:?}"))
+                                })
+                            },
+                        ),
                     ),
-                ),
             );
         }
 